@@ -0,0 +1,207 @@
+//! Generating GCODE from a project without the GUI. See [`USAGE`].
+
+
+use anyhow::{
+    Result,
+    bail,
+    Context,
+};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    path::PathBuf,
+};
+use crate::{
+    project::{
+        Project,
+        ProjectSheet,
+    },
+    laser::ConditionEditor,
+    machine::MachineProfile,
+    settings::{
+        Settings,
+        clean_file_name,
+    },
+    model::ModelStore,
+};
+
+
+pub const USAGE: &str = "\
+Usage:
+    laser_cam                                       Start the GUI
+    laser_cam PROJECT --list-sheets                 List the sheets in the project
+    laser_cam PROJECT --sheet NAME --out FILE       Write the GCODE for one sheet
+    laser_cam PROJECT --all [--out DIR]             Write `<sheet name>.gcode` for every sheet
+    laser_cam PROJECT --sheet NAME --image FILE     Write an SVG or PNG picture of one sheet
+        [--dpi DPI] [--show-order]                  The PNG resolution and if order numbers are shown
+
+The laser conditions and machine profile are the ones saved by the GUI.";
+
+
+enum Command {
+    ListSheets,
+    Sheet {
+        name: String,
+        out: PathBuf,
+    },
+    All {
+        dir: PathBuf,
+    },
+    Image {
+        name: String,
+        out: PathBuf,
+        dpi: Option<f64>,
+        show_order: bool,
+    },
+}
+
+/// Run the command line. `args` doesn't include the program name.
+pub fn run(args: Vec<String>)->Result<()> {
+    if args.iter().any(|arg|arg == "--help" || arg == "-h") {
+        println!("{USAGE}");
+        return Ok(());
+    }
+
+    let (project_path, command) = parse_args(args)?;
+    let mut project = Project::load(&project_path)?;
+    let conditions = merge_conditions(&mut project);
+
+    match command {
+        Command::ListSheets=>{
+            for sheet in project.sheets.iter() {
+                println!("{}", sheet.name);
+            }
+        },
+        Command::Sheet{name, out}=>{
+            let Some(sheet) = project.sheet(&name) else {
+                bail!("There is no sheet named `{name}` in `{}`", project_path.display());
+            };
+
+            write_sheets(&conditions, [(sheet, out)])?;
+        },
+        Command::All{dir}=>{
+            let paths = project.sheets.iter()
+                .map(|sheet|{
+                    let mut name = clean_file_name(&sheet.name);
+                    if name.is_empty() {
+                        name = "sheet".into();
+                    }
+                    (sheet, dir.join(format!("{name}.gcode")))
+                });
+            write_sheets(&conditions, paths)?;
+        },
+        Command::Image{name, out, dpi, show_order}=>{
+            let Some(sheet) = project.sheet(&name) else {
+                bail!("There is no sheet named `{name}` in `{}`", project_path.display());
+            };
+
+            let settings = Rc::new(RefCell::new(Settings::load()));
+            let dpi = dpi.unwrap_or(settings.borrow().image_dpi);
+            let loaded = sheet.to_sheet(
+                &ModelStore::new(),
+                conditions.get_store(),
+                Rc::new(RefCell::new(MachineProfile::load())),
+                settings,
+            )?;
+            loaded.image(show_order).write_file(&out, dpi)?;
+
+            eprintln!("Wrote sheet `{}` to `{}`", sheet.name, out.display());
+        },
+    }
+
+    return Ok(());
+}
+
+fn parse_args(args: Vec<String>)->Result<(PathBuf, Command)> {
+    let mut project = None;
+    let mut sheet = None;
+    let mut out = None;
+    let mut image = None;
+    let mut dpi = None;
+    let mut show_order = false;
+    let mut list_sheets = false;
+    let mut all = false;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--sheet"=>sheet = Some(args.next().context("`--sheet` needs a sheet name")?),
+            "--out"=>out = Some(PathBuf::from(args.next().context("`--out` needs a path")?)),
+            "--image"=>image = Some(PathBuf::from(args.next().context("`--image` needs a path")?)),
+            "--dpi"=>{
+                let arg = args.next().context("`--dpi` needs a number")?;
+                dpi = Some(arg.parse::<f64>().ok()
+                    .filter(|dpi|*dpi > 0.0)
+                    .with_context(||format!("`{arg}` is not a valid DPI"))?);
+            },
+            "--show-order"=>show_order = true,
+            "--list-sheets"=>list_sheets = true,
+            "--all"=>all = true,
+            _ if arg.starts_with('-')=>bail!("Unknown option `{arg}`\n\n{USAGE}"),
+            _ if project.is_none()=>project = Some(PathBuf::from(arg)),
+            _=>bail!("Unexpected argument `{arg}`\n\n{USAGE}"),
+        }
+    }
+
+    let Some(project) = project else {
+        bail!("No project file given\n\n{USAGE}");
+    };
+
+    if image.is_some() && out.is_some() {
+        bail!("Only one of `--out` and `--image` can be used\n\n{USAGE}");
+    }
+
+    let command = match (list_sheets, all, sheet) {
+        (true, false, None)=>Command::ListSheets,
+        (false, false, Some(name)) if image.is_some()=>Command::Image {
+            name,
+            out: image.unwrap(),
+            dpi,
+            show_order,
+        },
+        (false, true, None)=>Command::All {
+            dir: out.unwrap_or_default(),
+        },
+        (false, false, Some(name))=>Command::Sheet {
+            name,
+            out: out.context("`--sheet` needs `--out FILE`")?,
+        },
+        (false, false, None)=>bail!("Nothing to do. Use `--list-sheets`, `--sheet`, or `--all`\n\n{USAGE}"),
+        _=>bail!("Only one of `--list-sheets`, `--sheet`, and `--all` can be used\n\n{USAGE}"),
+    };
+
+    return Ok((project, command));
+}
+
+/// Merge the project's conditions into the ones saved by the GUI, like opening the project does,
+/// and change the sheets to use the merged conditions. Nothing is saved.
+fn merge_conditions(project: &mut Project)->ConditionEditor {
+    let mut conditions = ConditionEditor::load();
+    let remap = conditions.merge(project.conditions.subset(&project.used_conditions()));
+    for sheet in project.sheets.iter_mut() {
+        sheet.remap_conditions(&remap);
+    }
+
+    return conditions;
+}
+
+/// Write the GCODE for each sheet to its path. This stops at the first error.
+fn write_sheets<'a>(conditions: &ConditionEditor, sheets: impl IntoIterator<Item = (&'a ProjectSheet, PathBuf)>)->Result<()> {
+    let models = ModelStore::new();
+    let machine = Rc::new(RefCell::new(MachineProfile::load()));
+    let settings = Rc::new(RefCell::new(Settings::load()));
+
+    for (sheet, path) in sheets {
+        let loaded = sheet.to_sheet(&models, conditions.get_store(), machine.clone(), settings.clone())?;
+        let skipped = loaded.gcode_job(&sheet.name)
+            .write_file(&path, |_, _|{})
+            .with_context(||format!("Could not write `{}`", path.display()))?;
+
+        eprintln!("Wrote sheet `{}` to `{}`", sheet.name, path.display());
+        if skipped > 0 {
+            eprintln!("Skipped {skipped} lines in sheet `{}` that were too short to cut", sheet.name);
+        }
+    }
+
+    return Ok(());
+}
@@ -0,0 +1,263 @@
+//! Exporting a picture of a sheet. This only uses the entities' lines, so it works without the GUI.
+
+
+use anyhow::{
+    Result,
+    bail,
+    Context,
+};
+use tiny_skia::{
+    Pixmap,
+    PathBuilder as SkiaPathBuilder,
+    Paint,
+    Stroke,
+    Transform as SkiaTransform,
+    Color as SkiaColor,
+};
+use std::{
+    fmt::Write,
+    path::Path as StdPath,
+};
+use crate::{
+    laser::Color,
+    Point,
+    Vector,
+};
+
+
+/// The width of the sheet outline in mm.
+const SHEET_LINE_WIDTH: f64 = 0.5;
+
+/// The width of the entities' lines in mm. PNGs never go thinner than a pixel.
+const ENTITY_LINE_WIDTH: f64 = 0.2;
+
+/// The height of the order numbers in mm.
+const LABEL_SIZE: f64 = 6.0;
+
+const BACKGROUND: [u8; 3] = [32, 32, 32];
+const FOREGROUND: [u8; 3] = [230, 230, 230];
+
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ImageFormat {
+    Svg,
+    Png,
+}
+impl ImageFormat {
+    pub const EXTENSIONS: &[&str] = &["svg", "png"];
+
+    /// Pick the format from the file's extension.
+    pub fn from_path(path: &StdPath)->Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "svg"=>Some(Self::Svg),
+            "png"=>Some(Self::Png),
+            _=>None,
+        }
+    }
+}
+
+/// A snapshot of what is on the sheet. Everything is in mm on the sheet with Y up.
+#[derive(Debug, Clone)]
+pub struct SheetImage {
+    pub sheet_size: Vector,
+    /// Each line and its condition color.
+    pub lines: Vec<(Color, Vec<Point>)>,
+    /// The cutting order number and where it goes.
+    pub labels: Vec<(usize, Point)>,
+}
+impl SheetImage {
+    /// Write the image in the format of the path's extension. `dpi` is only used for PNGs.
+    pub fn write_file(&self, path: &StdPath, dpi: f64)->Result<()> {
+        let Some(format) = ImageFormat::from_path(path) else {
+            bail!("`{}` is not an SVG or PNG file", path.display());
+        };
+
+        match format {
+            ImageFormat::Svg=>std::fs::write(path, self.svg())
+                .with_context(||format!("Could not write `{}`", path.display()))?,
+            ImageFormat::Png=>self.png(dpi)?
+                .save_png(path)
+                .with_context(||format!("Could not write `{}`", path.display()))?,
+        }
+
+        return Ok(());
+    }
+
+    /// The SVG's units are mm. Lines are flipped to Y up in a group, but the labels are placed
+    /// outside of it so the text isn't upside down.
+    pub fn svg(&self)->String {
+        let Vector {x: width, y: height} = self.sheet_size;
+        let mut out = String::new();
+
+        // writing to a string can't fail
+        let _ = writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}mm" height="{height}mm" viewBox="0 0 {width} {height}">"#,
+        );
+        let _ = writeln!(out, r#"<rect width="{width}" height="{height}" fill="{}"/>"#, hex(BACKGROUND));
+        let _ = writeln!(out, r#"<g transform="matrix(1 0 0 -1 0 {height})" fill="none" stroke-linejoin="round">"#);
+        let _ = writeln!(
+            out,
+            r#"<rect width="{width}" height="{height}" stroke="{}" stroke-width="{SHEET_LINE_WIDTH}"/>"#,
+            hex(FOREGROUND),
+        );
+
+        for (color, points) in self.lines.iter() {
+            let mut d = String::new();
+            for (i, p) in points.iter().enumerate() {
+                let cmd = if i == 0 {'M'} else {'L'};
+                let _ = write!(d, "{cmd}{:.4} {:.4} ", p.x, p.y);
+            }
+
+            let _ = writeln!(
+                out,
+                r#"<path d="{}" stroke="{}" stroke-width="{ENTITY_LINE_WIDTH}"/>"#,
+                d.trim_end(),
+                hex(color.rgb8()),
+            );
+        }
+        let _ = writeln!(out, "</g>");
+
+        for (number, pos) in self.labels.iter() {
+            let _ = writeln!(
+                out,
+                r#"<text x="{:.4}" y="{:.4}" font-family="sans-serif" font-size="{LABEL_SIZE}" text-anchor="middle" dominant-baseline="central" fill="{}">{number}</text>"#,
+                pos.x,
+                height - pos.y,
+                hex(FOREGROUND),
+            );
+        }
+
+        let _ = writeln!(out, "</svg>");
+
+        return out;
+    }
+
+    /// Rasterize the sheet. Order numbers are drawn as seven segment digits, so no fonts are
+    /// needed.
+    pub fn png(&self, dpi: f64)->Result<Pixmap> {
+        let px_per_mm = dpi / 25.4;
+        let width = (self.sheet_size.x * px_per_mm).ceil() as u32;
+        let height = (self.sheet_size.y * px_per_mm).ceil() as u32;
+        let Some(mut pixmap) = Pixmap::new(width, height) else {
+            bail!("Can't make a {width}x{height} pixel image");
+        };
+        pixmap.fill(skia_color(BACKGROUND));
+
+        // mm with Y up to pixels with Y down
+        let transform = SkiaTransform::from_row(
+            px_per_mm as f32,
+            0.0,
+            0.0,
+            -px_per_mm as f32,
+            0.0,
+            (self.sheet_size.y * px_per_mm) as f32,
+        );
+        let min_width = 1.0 / px_per_mm;
+
+        let sheet = [
+            Point::new(0.0, 0.0),
+            Point::new(self.sheet_size.x, 0.0),
+            Point::new(self.sheet_size.x, self.sheet_size.y),
+            Point::new(0.0, self.sheet_size.y),
+            Point::new(0.0, 0.0),
+        ];
+        stroke_points(&mut pixmap, &sheet, FOREGROUND, SHEET_LINE_WIDTH.max(min_width), transform);
+
+        for (color, points) in self.lines.iter() {
+            stroke_points(&mut pixmap, points, color.rgb8(), ENTITY_LINE_WIDTH.max(min_width), transform);
+        }
+
+        for (number, pos) in self.labels.iter() {
+            for segment in number_segments(*number, *pos) {
+                stroke_points(&mut pixmap, &segment, FOREGROUND, (LABEL_SIZE / 10.0).max(min_width), transform);
+            }
+        }
+
+        return Ok(pixmap);
+    }
+}
+
+
+fn stroke_points(pixmap: &mut Pixmap, points: &[Point], color: [u8; 3], width: f64, transform: SkiaTransform) {
+    let mut builder = SkiaPathBuilder::new();
+    for (i, p) in points.iter().enumerate() {
+        if i == 0 {
+            builder.move_to(p.x as f32, p.y as f32);
+        } else {
+            builder.line_to(p.x as f32, p.y as f32);
+        }
+    }
+    let Some(path) = builder.finish() else {return};
+
+    let mut paint = Paint::default();
+    paint.set_color(skia_color(color));
+    paint.anti_alias = true;
+
+    let stroke = Stroke {
+        width: width as f32,
+        ..Stroke::default()
+    };
+
+    pixmap.stroke_path(&path, &paint, &stroke, transform, None);
+}
+
+/// The line segments of the number's digits centered on `center`.
+fn number_segments(number: usize, center: Point)->Vec<[Point; 2]> {
+    // which of the segments are on for each digit: top, top right, bottom right, bottom,
+    // bottom left, top left, middle
+    const DIGITS: [[bool; 7]; 10] = [
+        [true, true, true, true, true, true, false],
+        [false, true, true, false, false, false, false],
+        [true, true, false, true, true, false, true],
+        [true, true, true, true, false, false, true],
+        [false, true, true, false, false, true, true],
+        [true, false, true, true, false, true, true],
+        [true, false, true, true, true, true, true],
+        [true, true, true, false, false, false, false],
+        [true, true, true, true, true, true, true],
+        [true, true, true, true, false, true, true],
+    ];
+
+    let digit_width = LABEL_SIZE / 2.0;
+    let advance = digit_width * 1.5;
+    let digits = number.to_string();
+    let total_width = advance * (digits.len() - 1) as f64 + digit_width;
+
+    let mut out = Vec::new();
+    for (i, c) in digits.chars().enumerate() {
+        let Some(digit) = c.to_digit(10) else {continue};
+
+        let left = center.x - total_width / 2.0 + advance * i as f64;
+        let right = left + digit_width;
+        let top = center.y + LABEL_SIZE / 2.0;
+        let middle = center.y;
+        let bottom = center.y - LABEL_SIZE / 2.0;
+
+        let segments = [
+            [Point::new(left, top), Point::new(right, top)],
+            [Point::new(right, top), Point::new(right, middle)],
+            [Point::new(right, middle), Point::new(right, bottom)],
+            [Point::new(left, bottom), Point::new(right, bottom)],
+            [Point::new(left, middle), Point::new(left, bottom)],
+            [Point::new(left, top), Point::new(left, middle)],
+            [Point::new(left, middle), Point::new(right, middle)],
+        ];
+        for (segment, on) in segments.into_iter().zip(DIGITS[digit as usize]) {
+            if on {
+                out.push(segment);
+            }
+        }
+    }
+
+    return out;
+}
+
+fn hex([r, g, b]: [u8; 3])->String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+fn skia_color([r, g, b]: [u8; 3])->SkiaColor {
+    SkiaColor::from_rgba8(r, g, b, 255)
+}
@@ -0,0 +1,354 @@
+//! Text models. The glyph outlines of a system font are turned into lines, so text can be
+//! engraved without drawing it in CAD first.
+
+
+use anyhow::{
+    Result,
+    anyhow,
+    bail,
+};
+use fontdb::{
+    Database,
+    Family,
+    Query,
+};
+use geo::{
+    Coord,
+    LineString,
+};
+use rustybuzz::{
+    ttf_parser::{
+        GlyphId,
+        OutlineBuilder,
+    },
+    Face,
+    UnicodeBuffer,
+};
+use serde::{Serialize, Deserialize};
+use std::sync::OnceLock;
+use crate::model::{
+    Model,
+    ModelLoadWarning,
+    DEFAULT_ARC_TOLERANCE,
+};
+
+
+/// The most lines a single curve in a glyph is split into.
+const MAX_CURVE_SEGMENTS: usize = 1000;
+
+
+/// What a text model is made from. Projects save this instead of a model path, so the model is
+/// made again when they are loaded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextParams {
+    /// Lines are split at `\n`.
+    pub text: String,
+    /// The font family name.
+    pub font: String,
+    /// The height of capital letters in mm.
+    pub size: f64,
+    /// The distance between baselines as a multiple of `size`.
+    pub line_spacing: f64,
+}
+impl TextParams {
+    /// The model's name. This is the text on one line.
+    pub fn name(&self)->String {
+        self.text.lines()
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Make the model with the glyph outlines. Kerning from the font is used. Characters the font
+    /// doesn't have are drawn as boxes and listed in a [`ModelLoadWarning::MissingGlyphs`].
+    pub fn to_model(&self)->Result<Model> {
+        if self.size <= 0.0 || self.line_spacing <= 0.0 {
+            bail!("The text size and line spacing have to be more than 0");
+        }
+
+        let db = system_fonts();
+        let id = db.query(&Query {
+            families: &[Family::Name(&self.font)],
+            ..Query::default()
+        })
+            .ok_or_else(||anyhow!("The font `{}` is not installed", self.font))?;
+
+        let (lines, missing) = db.with_face_data(id, |data, index|{
+            let face = Face::from_slice(data, index)
+                .ok_or_else(||anyhow!("The font `{}` could not be read", self.font))?;
+            Ok::<_, anyhow::Error>(self.glyph_lines(&face))
+        })
+            .ok_or_else(||anyhow!("The font `{}` could not be loaded", self.font))??;
+
+        if lines.is_empty() {
+            bail!("The text doesn't have anything to cut");
+        }
+
+        let mut model = Model::new(lines, self.name());
+        if !missing.is_empty() {
+            model.warnings.push(ModelLoadWarning::MissingGlyphs {
+                chars: missing,
+                font: self.font.clone(),
+            });
+        }
+        model.text = Some(self.clone());
+
+        return Ok(model);
+    }
+
+    /// The outlines of every glyph in mm, with the first baseline at Y=0, and the characters the
+    /// font doesn't have.
+    fn glyph_lines(&self, face: &Face)->(Vec<LineString>, String) {
+        // older fonts don't have the capital height, so `H` is measured instead
+        let cap_height = face.capital_height()
+            .or_else(||face.glyph_bounding_box(face.glyph_index('H')?).map(|b|b.y_max))
+            .filter(|h|*h > 0)
+            .unwrap_or(face.ascender()) as f64;
+        let scale = self.size / cap_height;
+
+        let mut lines = Vec::new();
+        let mut missing = String::new();
+        for (i, line) in self.text.lines().enumerate() {
+            let mut buffer = UnicodeBuffer::new();
+            buffer.push_str(line);
+            let glyphs = rustybuzz::shape(face, &[], buffer);
+
+            let baseline = -(i as f64) * self.line_spacing * self.size;
+            let mut pen = 0.0;
+            for (info, pos) in glyphs.glyph_infos().iter().zip(glyphs.glyph_positions()) {
+                let x = pen + pos.x_offset as f64 * scale;
+                let y = baseline + pos.y_offset as f64 * scale;
+                let advance = pos.x_advance as f64 * scale;
+                pen += advance;
+
+                let c = line[info.cluster as usize..].chars().next().unwrap_or(' ');
+                if c.is_whitespace() {
+                    continue;
+                }
+
+                if info.glyph_id == 0 {
+                    if !missing.contains(c) {
+                        missing.push(c);
+                    }
+                    let width = if advance > 0.0 {advance} else {self.size * 0.6};
+                    lines.push(placeholder_box(x + width * 0.1, y, width * 0.8, self.size));
+                    continue;
+                }
+
+                let mut builder = GlyphBuilder {
+                    lines: Vec::new(),
+                    current: Vec::new(),
+                    origin: Coord {x, y},
+                    scale,
+                };
+                face.outline_glyph(GlyphId(info.glyph_id as u16), &mut builder);
+                builder.finish_contour();
+                lines.extend(builder.lines);
+            }
+        }
+
+        return (lines, missing);
+    }
+}
+impl Default for TextParams {
+    fn default()->Self {
+        TextParams {
+            text: String::new(),
+            font: String::new(),
+            size: 10.0,
+            line_spacing: 1.6,
+        }
+    }
+}
+
+/// The fonts installed on the system. They are found the first time this is called.
+fn system_fonts()->&'static Database {
+    static FONTS: OnceLock<Database> = OnceLock::new();
+    FONTS.get_or_init(||{
+        let mut db = Database::new();
+        db.load_system_fonts();
+        eprintln!("Found {} system fonts", db.len());
+        db
+    })
+}
+
+/// The names of the installed font families, sorted and without duplicates.
+pub fn font_families()->Vec<String> {
+    let mut families = system_fonts().faces()
+        .filter_map(|face|face.families.first())
+        .map(|(name, _)|name.clone())
+        .collect::<Vec<_>>();
+    families.sort();
+    families.dedup();
+
+    return families;
+}
+
+/// A closed rectangle for a character the font doesn't have.
+fn placeholder_box(x: f64, y: f64, width: f64, height: f64)->LineString {
+    LineString::from(vec![
+        (x, y),
+        (x + width, y),
+        (x + width, y + height),
+        (x, y + height),
+        (x, y),
+    ])
+}
+
+/// Collects a glyph's contours as lines in mm. Curves are split into enough lines that they are
+/// within [`DEFAULT_ARC_TOLERANCE`] of the real curve.
+struct GlyphBuilder {
+    lines: Vec<LineString>,
+    current: Vec<Coord>,
+    /// Where the glyph's origin is in mm.
+    origin: Coord,
+    /// mm per font unit.
+    scale: f64,
+}
+impl GlyphBuilder {
+    fn point(&self, x: f32, y: f32)->Coord {
+        Coord {
+            x: self.origin.x + x as f64 * self.scale,
+            y: self.origin.y + y as f64 * self.scale,
+        }
+    }
+
+    fn last(&self)->Coord {
+        self.current.last().copied().unwrap_or(self.origin)
+    }
+
+    /// How many lines a curve needs when `dd` is the most its second derivative can be. The
+    /// chords are at most `dd / (8 * n²)` from the curve.
+    fn segments(dd: f64)->usize {
+        let n = (dd / (8.0 * DEFAULT_ARC_TOLERANCE)).sqrt().ceil() as usize;
+        n.clamp(1, MAX_CURVE_SEGMENTS)
+    }
+
+    fn finish_contour(&mut self) {
+        let mut coords = std::mem::take(&mut self.current);
+        if coords.len() < 3 {
+            return;
+        }
+        if coords.first() != coords.last() {
+            coords.push(coords[0]);
+        }
+        self.lines.push(LineString::new(coords));
+    }
+}
+impl OutlineBuilder for GlyphBuilder {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.finish_contour();
+        let p = self.point(x, y);
+        self.current.push(p);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let p = self.point(x, y);
+        self.current.push(p);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let p0 = self.last();
+        let p1 = self.point(x1, y1);
+        let p2 = self.point(x, y);
+
+        let d = p0 - p1 * 2.0 + p2;
+        let dd = d.x.hypot(d.y) * 2.0;
+        let n = Self::segments(dd);
+        for i in 1..=n {
+            let t = i as f64 / n as f64;
+            let u = 1.0 - t;
+            self.current.push(p0 * (u * u) + p1 * (2.0 * u * t) + p2 * (t * t));
+        }
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let p0 = self.last();
+        let p1 = self.point(x1, y1);
+        let p2 = self.point(x2, y2);
+        let p3 = self.point(x, y);
+
+        let d1 = p0 - p1 * 2.0 + p2;
+        let d2 = p1 - p2 * 2.0 + p3;
+        let dd = d1.x.hypot(d1.y).max(d2.x.hypot(d2.y)) * 6.0;
+        let n = Self::segments(dd);
+        for i in 1..=n {
+            let t = i as f64 / n as f64;
+            let u = 1.0 - t;
+            self.current.push(
+                p0 * (u * u * u) + p1 * (3.0 * u * u * t) + p2 * (3.0 * u * t * t) + p3 * (t * t * t)
+            );
+        }
+    }
+
+    fn close(&mut self) {
+        self.finish_contour();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    /// The farthest the curve is from the lines, where `curve` gives the point at `t`. The points
+    /// are at evenly spaced `t`, so each line is checked against its part of the curve.
+    fn max_deviation(points: &[Coord], curve: impl Fn(f64)->Coord)->f64 {
+        let n = points.len() - 1;
+        let mut max = 0.0f64;
+        for i in 0..n {
+            let (a, b) = (points[i], points[i + 1]);
+            let ab = b - a;
+            for j in 0..=20 {
+                let p = curve((i as f64 + j as f64 / 20.0) / n as f64);
+                let t = ((p - a).x * ab.x + (p - a).y * ab.y) / (ab.x * ab.x + ab.y * ab.y);
+                let closest = a + ab * t.clamp(0.0, 1.0);
+                max = max.max((p - closest).x.hypot((p - closest).y));
+            }
+        }
+
+        return max;
+    }
+
+    fn builder()->GlyphBuilder {
+        GlyphBuilder {
+            lines: Vec::new(),
+            current: Vec::new(),
+            origin: Coord {x: 5.0, y: -2.0},
+            scale: 0.01,
+        }
+    }
+
+    #[test]
+    fn curves_are_within_tolerance() {
+        let mut quad = builder();
+        quad.move_to(0.0, 0.0);
+        quad.quad_to(500.0, 2000.0, 1000.0, 0.0);
+        let [p0, p1, p2] = [quad.point(0.0, 0.0), quad.point(500.0, 2000.0), quad.point(1000.0, 0.0)];
+        let deviation = max_deviation(&quad.current, |t|{
+            let u = 1.0 - t;
+            p0 * (u * u) + p1 * (2.0 * u * t) + p2 * (t * t)
+        });
+        assert!(quad.current.len() > 3);
+        assert!(deviation <= DEFAULT_ARC_TOLERANCE, "{deviation}");
+
+        let mut cubic = builder();
+        cubic.move_to(0.0, 0.0);
+        cubic.curve_to(0.0, 1500.0, 1200.0, -800.0, 1000.0, 600.0);
+        let [p0, p1, p2, p3] = [(0.0, 0.0), (0.0, 1500.0), (1200.0, -800.0), (1000.0, 600.0)]
+            .map(|(x, y)|cubic.point(x, y));
+        let deviation = max_deviation(&cubic.current, |t|{
+            let u = 1.0 - t;
+            p0 * (u * u * u) + p1 * (3.0 * u * u * t) + p2 * (3.0 * u * t * t) + p3 * (t * t * t)
+        });
+        assert!(cubic.current.len() > 3);
+        assert!(deviation <= DEFAULT_ARC_TOLERANCE, "{deviation}");
+
+        // a straight curve is a single line
+        let mut straight = builder();
+        straight.move_to(0.0, 0.0);
+        straight.quad_to(50.0, 50.0, 100.0, 100.0);
+        assert_eq!(straight.current.len(), 2);
+    }
+}
@@ -1,22 +1,76 @@
 use smallvec::SmallVec;
-use std::fmt::{
-    Display,
-    Formatter,
-    Result as FmtResult,
-    Write,
+use serde::{Serialize, Deserialize};
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+        Result as FmtResult,
+        Write,
+    },
+    io,
+};
+use crate::{
+    laser::SequenceItem as Seq,
+    machine::MachineProfile,
+    settings::MM_PER_INCH,
+    Point,
+    Vector,
 };
 use GcodeInstruction as Ins;
 
 
+/// The longest line GRBL takes. Comments in GRBL mode are cut short to fit.
+pub const GRBL_LINE_LENGTH: usize = 80;
+
+
+/// How much of the GCODE is commented. The header is always commented.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+pub enum CommentLevel {
+    None,
+    /// The start and end of each entity.
+    Entity,
+    /// Each sequence item and pass too.
+    Pass,
+    /// Each line too.
+    #[default]
+    Line,
+}
+impl CommentLevel {
+    pub const LIST: &[Self] = &[
+        Self::None,
+        Self::Entity,
+        Self::Pass,
+        Self::Line,
+    ];
+}
+impl Display for CommentLevel {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        match self {
+            Self::None=>write!(f, "None"),
+            Self::Entity=>write!(f, "Per entity"),
+            Self::Pass=>write!(f, "Per pass"),
+            Self::Line=>write!(f, "Per line"),
+        }
+    }
+}
+
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum GcodeInstruction {
     G(u16),
     S(u16),
     M(u16),
-    F(u16),
+    F(f64),
+    O(u16),
+    P(u16),
     X(f64),
     Y(f64),
+    /// The rotary axis in degrees.
+    A(f64),
     Custom(String),
+    /// A comment between the words, or on its own line if it is the only one in the block. The
+    /// alternate flag (`{:#}`) writes it for GRBL, the same as the block's comment.
+    Comment(String),
 }
 impl Display for GcodeInstruction {
     fn fmt(&self, f: &mut Formatter)->FmtResult {
@@ -26,34 +80,232 @@ impl Display for GcodeInstruction {
             S(n)=>write!(f,"S{n}"),
             M(n)=>write!(f,"M{n}"),
             F(n)=>write!(f,"F{n}"),
-            X(flt)=>write!(f,"X{flt:.6}"),
-            Y(flt)=>write!(f,"Y{flt:.6}"),
-            Custom(s)=>s.fmt(f),
+            O(n)=>write!(f,"O{n}"),
+            P(n)=>write!(f,"P{n}"),
+            X(flt)=>write!(f,"X{flt:.prec$}", prec = f.precision().unwrap_or(6)),
+            Y(flt)=>write!(f,"Y{flt:.prec$}", prec = f.precision().unwrap_or(6)),
+            A(flt)=>write!(f,"A{flt:.prec$}", prec = f.precision().unwrap_or(6)),
+            // don't use `s.fmt(f)` since the precision would truncate the string
+            Custom(s)=>f.write_str(s),
+            Comment(s)=>write!(f, "({})", clean_comment(s, f.alternate())),
+        }
+    }
+}
+impl GcodeInstruction {
+    /// If both words can't be in the same block. Only one motion (`G0`-`G3`), laser state
+    /// (`M3`-`M5`), feed, and power is allowed, and the same `G` or `M` word can't be repeated.
+    fn conflicts(&self, other: &Self)->bool {
+        use GcodeInstruction::*;
+
+        let motion = |n: u16|n <= 3;
+        let laser = |n: u16|(3..=5).contains(&n);
+
+        match (self, other) {
+            (G(a), G(b))=>a == b || (motion(*a) && motion(*b)),
+            (M(a), M(b))=>a == b || (laser(*a) && laser(*b)),
+            (F(_), F(_))|(S(_), S(_))=>true,
+            _=>false,
         }
     }
 }
 
 
-#[derive(Default)]
 pub struct GcodeBuilder {
     grbl_comments: bool,
+    comment_level: CommentLevel,
+    /// Put a blank line after each entity
+    blank_lines: bool,
+    /// How many decimal places X and Y have
+    coord_precision: usize,
+    /// Use inches instead of mm. Everything given to the builder is still in mm.
+    inches: bool,
+    /// Laser powers are lowered to this.
+    max_power: u16,
+    /// Laser powers are multiplied by this for controllers that don't use 0-1000.
+    s_scale: f64,
+    /// If set, travel moves are `G1` at this feed instead of `G0`.
+    travel_feed: Option<u16>,
+    /// If set, Y is written as the A axis turning a cylinder with this diameter.
+    rotary_diameter: Option<f64>,
+    /// Lines shorter than this are skipped. See [`GcodeBuilder::skip_line`].
+    min_line_length: f64,
+    /// How many lines were too short to cut.
+    skipped_lines: usize,
+    /// Where the machine's (0, 0) is in the coordinates given to the builder.
+    origin: Point,
+    /// The direction of the machine's X and Y axes. Each is `1.0` or `-1.0`.
+    axes: Vector,
     inner: Vec<GcodeBlock>,
     current_block: GcodeBlock,
+    /// If set, blocks are written here as they are finished instead of being kept in `inner`.
+    sink: Option<Box<dyn io::Write + Send>>,
+    /// The first error from writing to the `sink`. Everything after it is dropped.
+    sink_error: Option<io::Error>,
+    /// Blocks from [`GcodeBuilder::define_subprogram`]. These go after the end of the main program.
+    subprograms: Vec<GcodeBlock>,
+}
+impl Default for GcodeBuilder {
+    fn default()->Self {
+        GcodeBuilder {
+            grbl_comments: false,
+            comment_level: CommentLevel::default(),
+            blank_lines: false,
+            coord_precision: 3,
+            inches: false,
+            max_power: u16::MAX,
+            s_scale: 1.0,
+            travel_feed: None,
+            rotary_diameter: None,
+            min_line_length: 0.0,
+            skipped_lines: 0,
+            origin: Point::zero(),
+            axes: Vector::one(),
+            inner: Vec::new(),
+            current_block: GcodeBlock::default(),
+            sink: None,
+            sink_error: None,
+            subprograms: Vec::new(),
+        }
+    }
 }
 impl GcodeBuilder {
+    /// Create a builder that writes each block to `writer` as soon as it is finished, so the
+    /// whole file is never in memory. Use [`GcodeBuilder::finish_writer`] instead of
+    /// [`GcodeBuilder::finish`] with this.
+    pub fn write_to<W: io::Write + Send + 'static>(writer: W)->Self {
+        GcodeBuilder {
+            sink: Some(Box::new(writer)),
+            ..Self::default()
+        }
+    }
+
+    /// Write comments that GRBL takes: in parentheses without any inside them, and cut short so
+    /// the line fits in GRBL's buffer.
     pub fn set_grbl_mode(&mut self) {
         self.grbl_comments = true;
     }
 
-    /// This inserts a header with G54, G17, G21, G90, G94
+    /// Only keep the comments from [`GcodeBuilder::comment_block_at`] up to `level`.
+    pub fn set_comment_level(&mut self, level: CommentLevel) {
+        self.comment_level = level;
+    }
+
+    /// Put a blank line between each entity to make the file easier to edit by hand.
+    pub fn set_blank_lines(&mut self, blank_lines: bool) {
+        self.blank_lines = blank_lines;
+    }
+
+    /// Set how many decimal places X and Y have. This is clamped to 1..=6.
+    pub fn set_coord_precision(&mut self, precision: usize) {
+        self.coord_precision = precision.clamp(1, 6);
+    }
+
+    /// Output inches with `G20` instead of mm. Coordinates and feed rates given to the builder
+    /// are still in mm and are converted.
+    pub fn set_inches(&mut self, inches: bool) {
+        self.inches = inches;
+    }
+
+    /// Lower any laser power above `max_power` to it. Custom sequence items are used as written.
+    pub fn set_max_power(&mut self, max_power: u16) {
+        self.max_power = max_power;
+    }
+
+    /// Multiply laser powers by this before they are written, like `0.255` for a controller that
+    /// takes 0-255. Powers are still limited to `max_power` after they are scaled.
+    pub fn set_s_scale(&mut self, factor: f64) {
+        self.s_scale = factor;
+    }
+
+    /// Make [`GcodeBuilder::travel_motion`] use `G1` at the feed instead of `G0`.
+    pub fn set_travel_feed(&mut self, feed: Option<u16>) {
+        self.travel_feed = feed;
+    }
+
+    /// Write Y as degrees on the A axis for a rotary attachment turning a cylinder with this
+    /// diameter in mm. X stays the same.
+    pub fn set_rotary_diameter(&mut self, diameter: Option<f64>) {
+        self.rotary_diameter = diameter;
+    }
+
+    /// Lines shorter than this in mm are skipped.
+    pub fn set_min_line_length(&mut self, length: f64) {
+        self.min_line_length = length;
+    }
+
+    /// If a line is too short to cut. Lines with less than two points never are.
+    pub fn is_line_too_short(&self, length: f64)->bool {
+        length <= 0.0 || length < self.min_line_length
+    }
+
+    /// Count a line that isn't cut and say why in a comment.
+    pub fn skip_line(&mut self, reason: impl Display) {
+        self.skipped_lines += 1;
+        self.comment_block_at(CommentLevel::Entity, reason);
+    }
+
+    /// How many lines were skipped with [`GcodeBuilder::skip_line`], including in sub-programs.
+    pub fn skipped_lines(&self)->usize {
+        self.skipped_lines
+    }
+
+    /// Use the machine profile's comment style, units, precision, power limit, travel feed, and
+    /// shortest line.
+    pub fn set_machine(&mut self, machine: &MachineProfile) {
+        if machine.grbl_comments {
+            self.set_grbl_mode();
+        }
+        self.set_coord_precision(machine.coord_precision);
+        self.set_inches(machine.imperial_gcode);
+        self.set_max_power(machine.max_power);
+        self.set_s_scale(machine.s_scale);
+        self.set_travel_feed(machine.travel_feed);
+        self.set_min_line_length(machine.min_line_length);
+    }
+
+    /// Move and flip the coordinates so `origin` is the machine's (0, 0) and the axes point in the
+    /// directions of `axes`. Everything given to the builder is still in the old coordinates.
+    pub fn set_origin(&mut self, origin: Point, axes: Vector) {
+        self.origin = origin;
+        self.axes = axes;
+    }
+
+    /// This inserts a header with G54, G17, G21 (or G20 for inches), G90, G94
     pub fn default_header(&mut self) {
         let mut block = GcodeBlock::default();
         block.push(Ins::G(54));
         block.push(Ins::G(17));
-        block.push(Ins::G(21));
+        block.push(Ins::G(if self.inches {20} else {21}));
         block.push(Ins::G(90));
         block.push(Ins::G(94));
-        self.inner.push(block);
+        self.push_block(block);
+    }
+
+    /// This inserts a block with the given homing command. Usually this is `G28`.
+    pub fn home(&mut self, command: &str) {
+        let mut block = GcodeBlock::default();
+        block.push(Ins::Custom(command.to_string()));
+        self.push_block(block);
+    }
+
+    /// Home the machine if the profile wants it. Call this before adding anything else, so homing
+    /// is the very first block of the file.
+    pub fn machine_home(&mut self, machine: &MachineProfile) {
+        if machine.home_at_start {
+            self.home(&machine.homing_command);
+        }
+    }
+
+    /// Comment on the profile's settings, then insert the [`GcodeBuilder::default_header`].
+    pub fn machine_header(&mut self, machine: &MachineProfile) {
+        if let Some(feed) = machine.travel_feed {
+            self.comment_block(format!("Safe travel: moves between cuts are G1 at F{feed} instead of G0"));
+        }
+        if machine.s_scale != 1.0 {
+            self.comment_block(format!("Laser powers are scaled by {} for this controller", machine.s_scale));
+        }
+
+        self.default_header();
     }
 
     pub fn coolant(&mut self, on: bool)->&mut Self {
@@ -65,28 +317,86 @@ impl GcodeBuilder {
         return self;
     }
 
-    /// NOTE: Laser power is from 0..=1000 for GRBL software
-    pub fn laser_power(&mut self, power: u16)->&mut Self {
-        self.current_block.push(Ins::S(power));
+    /// NOTE: Laser power is from 0..=1000 for GRBL software. See [`GcodeBuilder::set_max_power`].
+    /// Powers above 0 and below `min_power` are raised to it with a comment, since the laser
+    /// doesn't fire below that.
+    pub fn laser_power(&mut self, power: u16, min_power: u16)->&mut Self {
+        let power = if power > 0 && power < min_power {
+            if self.comment_level >= CommentLevel::Line {
+                self.comment(format_args!("Power raised from S{power} to the minimum of S{min_power}"));
+            }
+            min_power
+        } else {
+            power
+        };
+
+        let scaled = (power as f64 * self.s_scale).round().clamp(0.0, u16::MAX as f64) as u16;
+        self.current_block.push(Ins::S(scaled.min(self.max_power)));
+        return self;
+    }
+
+    /// Wait in place. GRBL takes the time in seconds.
+    pub fn dwell(&mut self, ms: u16)->&mut Self {
+        self.current_block.push(Ins::G(4));
+        self.current_block.push(Ins::Custom(format!("P{}", ms as f64 / 1000.0)));
         return self;
     }
 
     pub fn x(&mut self, x: f64)->&mut Self {
-        self.current_block.push(Ins::X(x));
+        self.current_block.push(Ins::X(self.length((x - self.origin.x) * self.axes.x)));
         return self;
     }
 
     pub fn y(&mut self, y: f64)->&mut Self {
-        self.current_block.push(Ins::Y(y));
+        let y = (y - self.origin.y) * self.axes.y;
+        let ins = match self.rotary_diameter {
+            Some(diameter)=>Ins::A(rotary_angle(y, diameter)),
+            None=>Ins::Y(self.length(y)),
+        };
+        self.current_block.push(ins);
+        return self;
+    }
+
+    /// A distance along X, like the offset of [`GcodeBuilder::local_offset`]. This is only
+    /// flipped by the origin, not moved.
+    pub fn offset_x(&mut self, x: f64)->&mut Self {
+        self.current_block.push(Ins::X(self.length(x * self.axes.x)));
+        return self;
+    }
+
+    /// A distance along Y. See [`GcodeBuilder::offset_x`].
+    pub fn offset_y(&mut self, y: f64)->&mut Self {
+        let y = y * self.axes.y;
+        let ins = match self.rotary_diameter {
+            Some(diameter)=>Ins::A(rotary_angle(y, diameter)),
+            None=>Ins::Y(self.length(y)),
+        };
+        self.current_block.push(ins);
         return self;
     }
 
-    /// NOTE: Feedrates are in mm/min for GRBL
+    /// NOTE: Feedrates are in mm/min for GRBL. In inches they are rounded to 0.01in/min.
     pub fn feed(&mut self, feed: u16)->&mut Self {
+        let feed = if self.inches {
+            (self.length(feed as f64) * 100.0).round() / 100.0
+        } else {
+            feed as f64
+        };
         self.current_block.push(Ins::F(feed));
         return self;
     }
 
+    /// Convert a length in mm to the output units. Flipped axes can make `-0.0`, which is written
+    /// as `0.0`.
+    fn length(&self, mm: f64)->f64 {
+        let mm = mm + 0.0;
+        if self.inches {
+            mm / MM_PER_INCH
+        } else {
+            mm
+        }
+    }
+
     pub fn laser_on_const(&mut self)->&mut Self {
         self.current_block.push(Ins::M(3));
         return self;
@@ -107,13 +417,105 @@ impl GcodeBuilder {
         return self;
     }
 
+    /// A move between cuts. This is [`GcodeBuilder::rapid_motion`] unless there is a travel feed.
+    /// The laser has to be off already. Each laser on sets the cutting feed again, so the travel
+    /// feed is never used for cutting.
+    pub fn travel_motion(&mut self)->&mut Self {
+        match self.travel_feed {
+            Some(feed)=>self.cutting_motion().feed(feed),
+            None=>self.rapid_motion(),
+        }
+    }
+
     pub fn cutting_motion(&mut self)->&mut Self {
         self.current_block.push(Ins::G(1));
         return self;
     }
 
+    /// Set the power and feedrate for the sequence item and turn the laser on. Each of these is
+    /// ended with [`GcodeBuilder::eob`].
+    pub fn sequence_laser_on(&mut self, seq: &Seq) {
+        match seq {
+            Seq::GrblConst{power, feed, pierce_delay_ms, min_power, ..}=>{
+                self.cutting_motion()
+                    .laser_power(*power, *min_power)
+                    .feed(*feed)
+                    .laser_on_const()
+                    .eob();
+
+                if *pierce_delay_ms > 0 {
+                    self.dwell(*pierce_delay_ms).eob();
+                }
+            },
+            Seq::RasterEngrave{power, feed, ..}=>{
+                self.cutting_motion()
+                    .laser_power(*power, 0)
+                    .feed(*feed)
+                    .laser_on_dyn()
+                    .eob();
+            },
+            Seq::GrblDyn{power, feed, pierce_delay_ms, min_power, ..}=>{
+                self.cutting_motion()
+                    .laser_power(*power, *min_power)
+                    .feed(*feed)
+                    .laser_on_dyn()
+                    .eob();
+
+                if *pierce_delay_ms > 0 {
+                    self.dwell(*pierce_delay_ms).eob();
+                }
+            },
+            Seq::Custom{laser_on, feed, power, ..}=>{
+                self
+                    .custom(power.clone())
+                    .custom(feed.clone())
+                    .eob();
+
+                self
+                    .custom(laser_on.clone())
+                    .eob();
+            },
+        }
+    }
+
+    /// Turn the laser off for the sequence item. Each of these is ended with [`GcodeBuilder::eob`].
+    pub fn sequence_laser_off(&mut self, seq: &Seq) {
+        match seq {
+            Seq::GrblConst{..}|Seq::GrblDyn{..}|Seq::RasterEngrave{..}=>{
+                self.cutting_motion()
+                    .laser_power(0, 0)
+                    .laser_off()
+                    .eob();
+            },
+            Seq::Custom{laser_off, ..}=>{
+                self.custom(laser_off.clone())
+                    .eob();
+            },
+        }
+    }
+
+    /// Add custom GCODE to the current block. Anything after a `;` is added as an inline comment,
+    /// so a string that is only a comment is a comment-only line.
     pub fn custom(&mut self, s: String)->&mut Self {
-        self.current_block.push(Ins::Custom(s));
+        let (code, comment) = match s.split_once(';') {
+            Some((code, comment))=>(code.trim(), Some(comment.trim())),
+            None=>(s.trim(), None),
+        };
+
+        if !code.is_empty() {
+            self.current_block.push(Ins::Custom(code.to_string()));
+        }
+        if let Some(comment) = comment.filter(|c|!c.is_empty()) {
+            self.inline_comment(comment);
+        }
+
+        return self;
+    }
+
+    /// Add a comment as a word in the current block instead of at the end like
+    /// [`GcodeBuilder::comment`]. This is written no matter the comment level.
+    pub fn inline_comment(&mut self, text: impl Display)->&mut Self {
+        self.current_block.push(Ins::Comment(text.to_string()));
         return self;
     }
 
@@ -128,37 +530,164 @@ impl GcodeBuilder {
     pub fn comment_block(&mut self, text: impl Display)->&mut Self {
         let mut block = GcodeBlock::default();
         block.add_comment(text);
-        self.inner.push(block);
+        self.push_block(block);
+        return self;
+    }
+
+    /// Adds a block with the given comment if the comment level is at least `level`.
+    pub fn comment_block_at(&mut self, level: CommentLevel, text: impl Display)->&mut Self {
+        if self.comment_level >= level {
+            self.comment_block(text);
+        }
+        return self;
+    }
+
+    /// Adds an empty block if blank lines are enabled. This is called at the end of each entity.
+    pub fn entity_separator(&mut self) {
+        if self.blank_lines {
+            self.push_block(GcodeBlock::default());
+        }
+    }
+
+    /// Define sub-program `O{id}` with everything `body` adds to the builder, ending with `M99`.
+    /// Sub-programs are put after the end of the main program, so this can be called in the middle
+    /// of it. Run it with [`GcodeBuilder::call_subprogram`].
+    pub fn define_subprogram(&mut self, id: u16, body: impl FnOnce(&mut GcodeBuilder)) {
+        let mut sub = GcodeBuilder {
+            grbl_comments: self.grbl_comments,
+            comment_level: self.comment_level,
+            blank_lines: self.blank_lines,
+            coord_precision: self.coord_precision,
+            inches: self.inches,
+            max_power: self.max_power,
+            s_scale: self.s_scale,
+            travel_feed: self.travel_feed,
+            rotary_diameter: self.rotary_diameter,
+            min_line_length: self.min_line_length,
+            origin: self.origin,
+            axes: self.axes,
+            ..Self::default()
+        };
+
+        let mut start = GcodeBlock::default();
+        start.push(Ins::O(id));
+        sub.push_block(start);
+
+        body(&mut sub);
+
+        if sub.current_block.len() > 0 {
+            let block = std::mem::take(&mut sub.current_block);
+            sub.push_block(block);
+        }
+        let mut end = GcodeBlock::default();
+        end.push(Ins::M(99));
+        sub.push_block(end);
+
+        self.skipped_lines += sub.skipped_lines;
+        self.subprograms.extend(sub.inner);
+        self.subprograms.extend(sub.subprograms);
+    }
+
+    /// Run a sub-program from [`GcodeBuilder::define_subprogram`] with `M98 P{id}`.
+    pub fn call_subprogram(&mut self, id: u16)->&mut Self {
+        self.current_block.push(Ins::M(98));
+        self.current_block.push(Ins::P(id));
         return self;
     }
 
+    /// Offset everything after this by the X and Y in the block with `G52`. Set them to 0 to
+    /// remove the offset.
+    pub fn local_offset(&mut self)->&mut Self {
+        self.current_block.push(Ins::G(52));
+        return self;
+    }
+
+    /// End the block. Repeated words are removed first, so calling something like
+    /// [`GcodeBuilder::cutting_motion`] twice in one block doesn't make invalid GCODE.
     pub fn eob(&mut self) {
-        let block = std::mem::take(&mut self.current_block);
+        let mut block = std::mem::take(&mut self.current_block);
+        block.remove_overridden();
+
+        self.push_block(block);
+    }
+
+    fn push_block(&mut self, block: GcodeBlock) {
+        let Some(sink) = self.sink.as_mut() else {
+            self.inner.push(block);
+            return;
+        };
 
-        self.inner.push(block);
+        if self.sink_error.is_some() {
+            return;
+        }
+
+        let prec = self.coord_precision;
+        let res = if self.grbl_comments {
+            writeln!(sink, "{block:#.prec$}")
+        } else {
+            writeln!(sink, "{block:.prec$}")
+        };
+
+        if let Err(e) = res {
+            self.sink_error = Some(e);
+        }
     }
 
-    pub fn finish(mut self)->String {
+    /// Add the last block and the end-of-program gcode
+    fn end_program(&mut self) {
         if self.current_block.len() > 0 {
-            self.inner.push(self.current_block);
+            let block = std::mem::take(&mut self.current_block);
+            self.push_block(block);
         }
 
         // add end-of-program gcode
         let mut last_block = GcodeBlock::default();
         last_block.push(Ins::M(30));
-        self.inner.push(last_block);
+        self.push_block(last_block);
+
+        let subprograms = std::mem::take(&mut self.subprograms);
+        for block in subprograms {
+            self.push_block(block);
+        }
+    }
+
+    /// End the program and return every block, including the sub-programs after it. A builder
+    /// from [`GcodeBuilder::write_to`] has already written its blocks, so this is empty for it.
+    pub fn finish_blocks(mut self)->Vec<GcodeBlock> {
+        self.end_program();
 
+        return self.inner;
+    }
+
+    pub fn finish(self)->String {
+        let prec = self.coord_precision;
+        let grbl_comments = self.grbl_comments;
         let mut out = String::new();
-        for block in self.inner {
-            if self.grbl_comments {
-                write!(&mut out, "{block:#}\n").unwrap();
+        for block in self.finish_blocks() {
+            if grbl_comments {
+                write!(&mut out, "{block:#.prec$}\n").unwrap();
             } else {
-                write!(&mut out, "{block}\n").unwrap();
+                write!(&mut out, "{block:.prec$}\n").unwrap();
             }
         }
 
         return out;
     }
+
+    /// Finish a builder from [`GcodeBuilder::write_to`] and flush the writer. Returns the first
+    /// error from writing any of the blocks.
+    pub fn finish_writer(mut self)->io::Result<()> {
+        self.end_program();
+
+        if let Some(e) = self.sink_error.take() {
+            return Err(e);
+        }
+
+        match self.sink.as_mut() {
+            Some(sink)=>sink.flush(),
+            None=>Ok(()),
+        }
+    }
 }
 
 /// A block of gcode instructions. We don't support need many instructions, so we store them in a
@@ -168,10 +697,26 @@ pub struct GcodeBlock(SmallVec<[GcodeInstruction;6]>, Option<String>);
 impl GcodeBlock {
     pub fn len(&self)->usize {self.0.len()}
 
+    /// The words in the block without the comment.
+    pub fn codes(&self)->&[GcodeInstruction] {&self.0}
+
+    pub fn comment(&self)->Option<&str> {self.1.as_deref()}
+
     pub fn push(&mut self, code: GcodeInstruction) {
         self.0.push(code);
     }
 
+    /// Remove the `G`, `M`, `F`, and `S` words that a later word in the block overrides. The last
+    /// one wins, the same as calling the builder again.
+    fn remove_overridden(&mut self) {
+        let codes = std::mem::take(&mut self.0);
+        for (i, code) in codes.iter().enumerate() {
+            if !codes[i + 1..].iter().any(|later|code.conflicts(later)) {
+                self.0.push(code.clone());
+            }
+        }
+    }
+
     pub fn add_comment(&mut self, text: impl Display) {
         if self.1.is_none() {
             self.1 = Some(text.to_string());
@@ -181,33 +726,202 @@ impl GcodeBlock {
         }
     }
 }
+/// The alternate flag (`{:#}`) writes the comment for GRBL. See [`GcodeBuilder::set_grbl_mode`].
 impl Display for GcodeBlock {
     fn fmt(&self, f: &mut Formatter)->FmtResult {
-        if self.0.len() > 0 {
-            write!(f, "{}", self.0[0])?;
-            for code in self.0.iter().skip(1) {
-                write!(f, " {code}")?;
+        // pass the precision on to the X and Y coordinates
+        let prec = f.precision().unwrap_or(6);
+        let grbl = f.alternate();
+
+        // GRBL lines are limited, so the inline comments get whatever room the words leave, in
+        // order. Each word after the first needs a space too.
+        let words = self.0.iter()
+            .filter(|code|!matches!(code, Ins::Comment(_)))
+            .map(|code|format!("{code:#.prec$}").len() + 1)
+            .sum::<usize>();
+        let mut room = GRBL_LINE_LENGTH.saturating_sub(words.saturating_sub(1));
+
+        let mut line = String::new();
+        for code in self.0.iter() {
+            let text = match code {
+                Ins::Comment(comment) if grbl=>{
+                    // the space before it and the parentheses
+                    let comment = clean_comment(comment, true)
+                        .chars()
+                        .take(room.saturating_sub(3))
+                        .collect::<String>();
+                    if comment.is_empty() {
+                        continue;
+                    }
+                    room = room.saturating_sub(comment.len() + 3);
+                    format!("({comment})")
+                },
+                _ if grbl=>format!("{code:#.prec$}"),
+                _=>format!("{code:.prec$}"),
+            };
+            if !line.is_empty() {
+                line.push(' ');
             }
+            line.push_str(&text);
+        }
+        f.write_str(&line)?;
 
-            // we add a space before the comment to separate it from the actual gcode
-            if let Some(comment) = &self.1 {
-                if f.alternate() {
-                    write!(f, " ; {comment}")?;
-                } else {
-                    write!(f, " ({comment})")?;
-                }
+        let Some(comment) = &self.1 else {return Ok(())};
+        let comment = clean_comment(comment, grbl);
+
+        if grbl {
+            let sep = if line.is_empty() {""} else {" "};
+            let room = GRBL_LINE_LENGTH.saturating_sub(line.len() + sep.len() + 2);
+            let comment = comment.chars()
+                .take(room)
+                .collect::<String>();
+            if !comment.is_empty() {
+                write!(f, "{sep}({comment})")?;
             }
         } else {
-            if let Some(comment) = &self.1 {
-                if f.alternate() {
-                    write!(f, "; {comment}")?;
-                } else {
-                    write!(f, " ({comment})")?;
-                }
+            // we add a space before the comment to separate it from the actual gcode
+            write!(f, " ({comment})")?;
+        }
+
+        return Ok(());
+    }
+}
+
+/// The text of a comment without anything that would end it early or break the line. GRBL also
+/// only takes ASCII.
+fn clean_comment(comment: &str, grbl: bool)->String {
+    comment.chars()
+        .filter(|c|!matches!(c, '('|')') && !c.is_control() && (c.is_ascii() || !grbl))
+        .collect()
+}
+
+/// The kind of motion a [`trace`] segment is.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TraceMove {
+    /// The machine is moving with the laser off.
+    Rapid,
+    /// The machine is cutting with the laser on.
+    Cut,
+}
+
+/// Follow the motion of some GCODE starting at `start`. Consecutive moves of the same kind are
+/// joined into a single path, and each path starts where the previous one ended.
+///
+/// This only understands the GCODE we generate: `G0`/`G1` motion with absolute `X` and `Y`, `M3`
+/// and `M4` to turn the laser on, and `M5` or `S0` to turn it off. `G20` switches to inches, which
+/// are converted so the points are always in mm. Anything else is ignored.
+pub fn trace(gcode: &str, start: Point)->Vec<(TraceMove, Vec<Point>)> {
+    let mut out: Vec<(TraceMove, Vec<Point>)> = Vec::new();
+    let mut pos = start;
+    let mut cutting_motion = false;
+    let mut laser_on = false;
+    // custom sequences might never set the power, so assume it is on until told otherwise
+    let mut power_on = true;
+    let mut mm_per_unit = 1.0;
+
+    for line in gcode.lines() {
+        // strip both kinds of comments
+        let line = line.split(';').next().unwrap_or_default();
+        let mut code = String::with_capacity(line.len());
+        let mut in_comment = false;
+        for c in line.chars() {
+            match c {
+                '('=>in_comment = true,
+                ')'=>in_comment = false,
+                _ if !in_comment=>code.push(c),
+                _=>{},
             }
         }
 
+        let mut target = pos;
+        let mut moved = false;
+        for word in code.split_whitespace() {
+            let mut chars = word.chars();
+            let letter = chars.next().unwrap_or_default().to_ascii_uppercase();
+            let Ok(num) = chars.as_str().parse::<f64>() else {continue};
 
-        return Ok(());
+            match letter {
+                'G' if num == 0.0=>cutting_motion = false,
+                'G' if num == 1.0=>cutting_motion = true,
+                'G' if num == 20.0=>mm_per_unit = MM_PER_INCH,
+                'G' if num == 21.0=>mm_per_unit = 1.0,
+                'M' if num == 3.0 || num == 4.0=>laser_on = true,
+                'M' if num == 5.0=>laser_on = false,
+                'S'=>power_on = num > 0.0,
+                'X'=>{
+                    target.x = num * mm_per_unit;
+                    moved = true;
+                },
+                'Y'=>{
+                    target.y = num * mm_per_unit;
+                    moved = true;
+                },
+                _=>{},
+            }
+        }
+
+        if !moved {
+            continue;
+        }
+
+        let kind = if cutting_motion && laser_on && power_on {
+            TraceMove::Cut
+        } else {
+            TraceMove::Rapid
+        };
+
+        match out.last_mut() {
+            Some((last_kind, points)) if *last_kind == kind=>points.push(target),
+            _=>out.push((kind, vec![pos, target])),
+        }
+
+        pos = target;
+    }
+
+    return out;
+}
+
+
+/// How many degrees a cylinder with the diameter turns to move its surface `mm`. `-0.0` is
+/// written as `0.0`.
+fn rotary_angle(mm: f64, diameter: f64)->f64 {
+    mm * 360.0 / (std::f64::consts::PI * diameter) + 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn custom_comments_are_inline() {
+        let mut builder = GcodeBuilder::default();
+        builder.custom("M4 ; focus position Z=15mm".into()).eob();
+        let blocks = builder.finish_blocks();
+
+        assert!(blocks.iter().any(|block|block.codes() == [
+            GcodeInstruction::Custom("M4".into()),
+            GcodeInstruction::Comment("focus position Z=15mm".into()),
+        ]));
+        assert!(blocks.iter().any(|block|format!("{block:#}") == "M4 (focus position Z=15mm)"));
+    }
+
+    #[test]
+    fn inline_comments_are_cleaned_and_fit() {
+        let mut builder = GcodeBuilder::default();
+        let long = "x".repeat(200);
+        builder.custom(format!("M4 S1000 ; focus (Z=15mm) {long}")).eob();
+        let blocks = builder.finish_blocks();
+        let block = blocks.iter()
+            .find(|block|block.codes().first() == Some(&GcodeInstruction::Custom("M4 S1000".into())))
+            .unwrap();
+
+        let grbl = format!("{block:#}");
+        assert_eq!(grbl.len(), GRBL_LINE_LENGTH, "{grbl}");
+        assert!(grbl.starts_with("M4 S1000 (focus Z=15mm xxx"), "{grbl}");
+        assert!(grbl.ends_with("x)"), "{grbl}");
+
+        let plain = format!("{block}");
+        assert_eq!(plain, format!("M4 S1000 (focus Z=15mm {long})"));
     }
 }
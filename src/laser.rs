@@ -16,63 +16,126 @@ use iced::{
     Task,
     Length,
 };
-use serde::{Serialize, Deserialize};
+use serde::{
+    de::{
+        self,
+        Visitor,
+    },
+    Serialize,
+    Deserialize,
+    Deserializer,
+};
 use indexmap::IndexMap;
+use uuid::Uuid;
 use std::{
-    sync::atomic::{
-        Ordering,
-        AtomicUsize,
-    },
+    sync::OnceLock,
     fmt::{
         Display,
         Formatter,
         Result as FmtResult,
     },
+    collections::{
+        HashMap,
+        HashSet,
+    },
     rc::Rc,
     cell::RefCell,
 };
+use rfd::{
+    AsyncFileDialog,
+    FileHandle,
+};
+use crate::{
+    gcode::GcodeBuilder,
+    machine::MachineProfile,
+    notification::Severity,
+};
 use SequenceItem as Seq;
 
 
 #[derive(Debug, Clone)]
 pub enum Message {
     CloseEditor,
-    RecalcSheet,
+    /// Recalculate the entities using this condition
+    RecalcSheet(ConditionId),
 
     SelectCondition(ConditionId),
     DefaultCondition(ConditionId),
 
     NewCondition,
     DeleteCondition,
+    ImportConditions,
+    LoadImport(Option<FileHandle>),
+    MoveConditionUp,
+    MoveConditionDown,
+    SortConditions,
     ChangeName(String),
     ChangeColorR(f32),
     ChangeColorG(f32),
     ChangeColorB(f32),
+    ChangeOvercut(String),
 
     NewSequence,
-    DeleteSequence(usize),
-    ChangeFeed(usize, String),
-    ChangePower(usize, String),
-    ChangePasses(usize, String),
+    DeleteSequence(ConditionId, usize),
+    ChangeFeed(ConditionId, usize, String),
+    ChangePower(ConditionId, usize, String),
+    ChangePierceDelay(ConditionId, usize, String),
+    ChangeMinPower(ConditionId, usize, String),
+    ChangeLineSpacing(ConditionId, usize, String),
+    ChangePasses(ConditionId, usize, String),
 
     // For custom sequence items
-    ChangeLaserOn(usize, String),
-    ChangeLaserOff(usize, String),
-
-    ChangeSeqItemType(usize, SeqItemType),
+    ChangeLaserOn(ConditionId, usize, String),
+    ChangeLaserOff(ConditionId, usize, String),
+
+    ChangeSeqItemType(ConditionId, usize, SeqItemType),
+    ChangeSeqItemEnabled(ConditionId, usize, bool),
+
+    /// Undo the last `Change*` message.
+    UndoCondition,
+
+    CalibrationMode(CalibrationMode),
+    CalibrationStart(String),
+    CalibrationEnd(String),
+    CalibrationSteps(String),
+    CalibrationLength(String),
+    CalibrationSpacing(String),
+    /// We handle this in MainProgram since it needs the machine profile and a save dialog.
+    GenerateCalibration,
+    /// A row of squares at fractions of the condition's power. We handle this in MainProgram
+    /// since it needs a save dialog.
+    GenerateTestBurn(ConditionId),
+    /// Show a notification. We handle this in MainProgram.
+    Notify(Severity, String),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum SequenceItem {
     GrblConst {
         passes: u16,
         power: u16,
         feed: u16,
+        #[serde(default = "default_enabled")]
+        enabled: bool,
+        /// How long to wait after turning the laser on before moving.
+        #[serde(default)]
+        pierce_delay_ms: u16,
+        /// The lowest power the laser fires at. Powers above 0 are raised to this.
+        #[serde(default)]
+        min_power: u16,
     },
+    /// GRBL turns the laser off when it isn't moving in this mode, so the pierce delay doesn't
+    /// do anything on most machines.
     GrblDyn {
         passes: u16,
         power: u16,
         feed: u16,
+        #[serde(default = "default_enabled")]
+        enabled: bool,
+        #[serde(default)]
+        pierce_delay_ms: u16,
+        #[serde(default)]
+        min_power: u16,
     },
     Custom {
         passes: u16,
@@ -80,6 +143,18 @@ pub enum SequenceItem {
         laser_off: String,
         power: String,
         feed: String,
+        #[serde(default = "default_enabled")]
+        enabled: bool,
+    },
+    /// Fill the inside of the model with horizontal lines `line_spacing` mm apart instead of
+    /// cutting its lines. This uses GRBL's dynamic mode (`M4`).
+    RasterEngrave {
+        passes: u16,
+        power: u16,
+        feed: u16,
+        line_spacing: f64,
+        #[serde(default = "default_enabled")]
+        enabled: bool,
     },
 }
 impl SequenceItem {
@@ -88,32 +163,92 @@ impl SequenceItem {
             Self::GrblConst{..}=>SeqItemType::GrblConst,
             Self::GrblDyn{..}=>SeqItemType::GrblDyn,
             Self::Custom{..}=>SeqItemType::Custom,
+            Self::RasterEngrave{..}=>SeqItemType::RasterEngrave,
         }
     }
 
     pub fn passes(&self)->u16 {
         match self {
-            Self::GrblConst{passes, ..}|Self::GrblDyn{passes, ..}|Self::Custom{passes, ..}=>*passes,
+            Self::GrblConst{passes, ..}|
+                Self::GrblDyn{passes, ..}|
+                Self::Custom{passes, ..}|
+                Self::RasterEngrave{passes, ..}=>*passes,
+        }
+    }
+
+    /// Disabled items are skipped when generating GCODE.
+    pub fn enabled(&self)->bool {
+        match self {
+            Self::GrblConst{enabled, ..}|
+                Self::GrblDyn{enabled, ..}|
+                Self::Custom{enabled, ..}|
+                Self::RasterEngrave{enabled, ..}=>*enabled,
+        }
+    }
+
+    pub fn set_enabled(&mut self, value: bool) {
+        match self {
+            Self::GrblConst{enabled, ..}|
+                Self::GrblDyn{enabled, ..}|
+                Self::Custom{enabled, ..}|
+                Self::RasterEngrave{enabled, ..}=>*enabled = value,
         }
     }
 
     pub fn feed_string(&self)->String {
         match self {
-            Self::GrblConst{feed, ..}|Self::GrblDyn{feed, ..}=>feed.to_string(),
+            Self::GrblConst{feed, ..}|Self::GrblDyn{feed, ..}|Self::RasterEngrave{feed, ..}=>feed.to_string(),
             Self::Custom{feed, ..}=>feed.clone(),
         }
     }
 
+    /// The feedrate in mm/min. Custom feeds are parsed from the number after an optional `F`, and
+    /// are `None` if that isn't a number.
+    pub fn feed_rate(&self)->Option<f64> {
+        let feed = match self {
+            Self::GrblConst{feed, ..}|Self::GrblDyn{feed, ..}|Self::RasterEngrave{feed, ..}=>*feed as f64,
+            Self::Custom{feed, ..}=>{
+                let feed = feed.trim();
+                let feed = feed.strip_prefix(['F', 'f']).unwrap_or(feed);
+                feed.trim().parse().ok()?
+            },
+        };
+
+        return (feed > 0.0).then_some(feed);
+    }
+
     pub fn power_string(&self)->String {
         match self {
-            Self::GrblConst{power, ..}|Self::GrblDyn{power, ..}=>power.to_string(),
+            Self::GrblConst{power, ..}|Self::GrblDyn{power, ..}|Self::RasterEngrave{power, ..}=>power.to_string(),
             Self::Custom{power, ..}=>power.clone(),
         }
     }
 
+    /// A copy with the power multiplied by `fraction`. Custom powers are only changed if they are
+    /// a number after an optional `S`.
+    pub fn with_power_fraction(&self, fraction: f64)->SequenceItem {
+        let mut seq = self.clone();
+        match &mut seq {
+            Self::GrblConst{power, ..}|Self::GrblDyn{power, ..}|Self::RasterEngrave{power, ..}=>{
+                *power = (*power as f64 * fraction).round() as u16;
+            },
+            Self::Custom{power, ..}=>{
+                let trimmed = power.trim();
+                let number = trimmed.strip_prefix(['S', 's']).unwrap_or(trimmed);
+                if let Ok(n) = number.trim().parse::<f64>() {
+                    *power = format!("S{}", (n * fraction).round());
+                }
+            },
+        }
+
+        return seq;
+    }
+
     pub fn power_pretty_string(&self)->String {
         match self {
-            Self::GrblConst{power, ..}|Self::GrblDyn{power, ..}=>format!("{}%", (*power as f32) / 10.0),
+            Self::GrblConst{power, ..}|
+                Self::GrblDyn{power, ..}|
+                Self::RasterEngrave{power, ..}=>format!("{}%", (*power as f32) / 10.0),
             Self::Custom{power, ..}=>power.clone(),
         }
     }
@@ -124,12 +259,14 @@ pub enum SeqItemType {
     GrblConst,
     GrblDyn,
     Custom,
+    RasterEngrave,
 }
 impl SeqItemType {
     const LIST: &[Self] = &[
         Self::GrblConst,
         Self::GrblDyn,
         Self::Custom,
+        Self::RasterEngrave,
     ];
 }
 impl Display for SeqItemType {
@@ -138,6 +275,51 @@ impl Display for SeqItemType {
             Self::GrblConst=>write!(f, "GRBL Const (M3)"),
             Self::GrblDyn=>write!(f, "GRBL Dyn (M4)"),
             Self::Custom=>write!(f, "Custom"),
+            Self::RasterEngrave=>write!(f, "Raster engrave"),
+        }
+    }
+}
+
+
+/// The editable text of a [`SequenceItem`]. We keep these separate from the item so the text boxes
+/// keep whatever the user types in, even if it is not a valid number (yet).
+#[derive(Debug, Clone, Default)]
+struct SequenceStrings {
+    passes: String,
+    feed: String,
+    power: String,
+    pierce_delay: String,
+    min_power: String,
+    line_spacing: String,
+    laser_on: String,
+    laser_off: String,
+}
+impl SequenceStrings {
+    fn new(seq: &SequenceItem)->Self {
+        let (laser_on, laser_off) = match seq {
+            Seq::Custom{laser_on, laser_off, ..}=>(laser_on.clone(), laser_off.clone()),
+            _=>(String::new(), String::new()),
+        };
+        let (pierce_delay, min_power) = match seq {
+            Seq::GrblConst{pierce_delay_ms, min_power, ..}|Seq::GrblDyn{pierce_delay_ms, min_power, ..}=>{
+                (pierce_delay_ms.to_string(), min_power.to_string())
+            },
+            Seq::Custom{..}|Seq::RasterEngrave{..}=>(String::new(), String::new()),
+        };
+        let line_spacing = match seq {
+            Seq::RasterEngrave{line_spacing, ..}=>line_spacing.to_string(),
+            _=>String::new(),
+        };
+
+        SequenceStrings {
+            passes: seq.passes().to_string(),
+            feed: seq.feed_string(),
+            power: seq.power_string(),
+            pierce_delay,
+            min_power,
+            line_spacing,
+            laser_on,
+            laser_off,
         }
     }
 }
@@ -146,10 +328,17 @@ impl Display for SeqItemType {
 pub struct ConditionEditor {
     store: Rc<RefCell<ConditionStore>>,
     condition: Option<ConditionId>,
-    feed_val: Vec<String>,
-    power_val: Vec<String>,
-    passes_val: Vec<String>,
+    /// One entry for each sequence item in the selected condition.
+    seq_vals: Vec<SequenceStrings>,
+    /// The selected condition's overcut in mm.
+    overcut: String,
+    calibration: CalibrationParams,
     changed: bool,
+    /// The conditions before each `Change*` message. The last one is the most recent.
+    history: Vec<ConditionStoreSnapshot>,
+    /// What the last `Change*` message edited. Repeated edits to the same thing (typing or dragging
+    /// a slider) only save one snapshot.
+    last_edit: Option<EditKey>,
 }
 impl Default for ConditionEditor {
     fn default()->Self {
@@ -158,11 +347,13 @@ impl Default for ConditionEditor {
                 default: None,
                 conditions: IndexMap::new(),
             })),
-            feed_val: Vec::new(),
-            power_val: Vec::new(),
-            passes_val: Vec::new(),
+            seq_vals: Vec::new(),
+            overcut: String::new(),
+            calibration: CalibrationParams::default(),
             condition: None,
             changed: false,
+            history: Vec::new(),
+            last_edit: None,
         }
     }
 }
@@ -191,14 +382,6 @@ impl ConditionEditor {
             };
             eprintln!("Loaded laser conditions");
 
-            // update the condition count
-            let mut max = 0;
-            for id in store.conditions.keys() {
-                max = max.max(id.0);
-            }
-            eprintln!("DEBUG: Next ConditionId = {}", max + 1);
-            CONDITION_COUNT.store(max + 1, Ordering::Relaxed);
-
             let mut ret = ConditionEditor {
                 condition: store.default,
                 store: Rc::new(RefCell::new(store)),
@@ -244,6 +427,13 @@ impl ConditionEditor {
         }
     }
 
+    /// The default condition without making one. Use this when only showing things.
+    pub fn default_condition_opt(&self)->Option<ConditionId> {
+        self.store.borrow().default
+    }
+
+    /// The default condition. A new condition is made if there aren't any, so only use this when
+    /// an entity is being added.
     pub fn default_condition(&mut self)->ConditionId {
         let store = self.store.borrow();
         if store.conditions.len() == 0 {
@@ -263,21 +453,42 @@ impl ConditionEditor {
         return store.default.unwrap();
     }
 
-    pub fn view(&self)->Element<Message> {
+    /// Add conditions from somewhere else, like a project, to the store. Conditions with the same
+    /// name as one we already have use the existing one if they cut the same, and are added with a
+    /// new name otherwise. Returns the new ID of each condition.
+    pub fn merge(&mut self, other: ConditionStore)->HashMap<ConditionId, ConditionId> {
+        let mut remap = HashMap::new();
+        if other.conditions.is_empty() {
+            return remap;
+        }
+
+        self.changed = true;
+        self.store.borrow_mut().merge(other, &mut remap);
+
+        return remap;
+    }
+
+    /// `entity_counts` is how many entities on the current sheet use each condition. The counts
+    /// are shown in the condition list.
+    pub fn view(&self, entity_counts: &IndexMap<ConditionId, usize>)->Element<Message> {
         let mut column = Vec::new();
         let store = self.store.borrow();
 
+        let with_count = |c: &Condition|{
+            c.display().with_count(entity_counts.get(&c.id).copied().unwrap_or(0))
+        };
         let condition_list = store.conditions.values().map(Condition::display).collect::<Vec<_>>();
+        let counted_list = store.conditions.values().map(with_count).collect::<Vec<_>>();
         let condition = self.condition
             .as_ref()
-            .map(|c|store.conditions[c].display());
+            .map(|c|with_count(&store.conditions[c]));
         let default_condition = store.default
             .as_ref()
             .map(|c|store.conditions[c].display());
         column.push(
             row![
                 widget::pick_list(
-                    condition_list.clone(),
+                    counted_list,
                     condition,
                     |c|Message::SelectCondition(c.id),
                 )
@@ -296,6 +507,28 @@ impl ConditionEditor {
                     .width(Length::FillPortion(3))
                     .height(Length::Fill)
                     .on_press(Message::NewCondition),
+                column![
+                    widget::button(text!("Move up").center().width(Length::Fill))
+                        .width(Length::Fill)
+                        .on_press_maybe(self.condition.map(|_|Message::MoveConditionUp)),
+                    widget::button(text!("Move down").center().width(Length::Fill))
+                        .width(Length::Fill)
+                        .on_press_maybe(self.condition.map(|_|Message::MoveConditionDown)),
+                ]
+                    .spacing(2.0)
+                    .width(Length::FillPortion(2)),
+                widget::button(text!("Import").center())
+                    .width(Length::FillPortion(2))
+                    .height(Length::Fill)
+                    .on_press(Message::ImportConditions),
+                widget::button(text!("Sort A-Z").center())
+                    .width(Length::FillPortion(2))
+                    .height(Length::Fill)
+                    .on_press(Message::SortConditions),
+                widget::button(text!("Undo").center())
+                    .width(Length::FillPortion(2))
+                    .height(Length::Fill)
+                    .on_press_maybe(self.can_undo().then_some(Message::UndoCondition)),
                 widget::button(text!("Close editor").center())
                     .width(Length::FillPortion(2))
                     .height(Length::Fill)
@@ -370,6 +603,16 @@ impl ConditionEditor {
                         )
                             .on_input(Message::ChangeName),
 
+                        row![
+                            text!("Overcut (mm): "),
+                            widget::text_input(
+                                "0",
+                                &self.overcut,
+                            )
+                                .on_input(Message::ChangeOvercut),
+                        ]
+                            .align_y(VerticalAlign::Center),
+
                         widget::button(text!("New sequence item").center().width(Length::Fill))
                             .on_press(Message::NewSequence)
                             .width(Length::Fill),
@@ -390,18 +633,57 @@ impl ConditionEditor {
                     .into()
             );
 
+            let cal = &self.calibration;
+            column.push(
+                row![
+                    text!("Test burn: "),
+                    widget::pick_list(
+                        CalibrationMode::LIST,
+                        Some(cal.mode),
+                        Message::CalibrationMode,
+                    ),
+                    text!("From: "),
+                    widget::text_input("Start", cal.start.as_str())
+                        .on_input(Message::CalibrationStart),
+                    text!("To: "),
+                    widget::text_input("End", cal.end.as_str())
+                        .on_input(Message::CalibrationEnd),
+                    text!("Steps: "),
+                    widget::text_input("Steps", cal.steps.as_str())
+                        .on_input(Message::CalibrationSteps),
+                    text!("Length: "),
+                    widget::text_input("Length", cal.length.as_str())
+                        .on_input(Message::CalibrationLength),
+                    text!("Spacing: "),
+                    widget::text_input("Spacing", cal.spacing.as_str())
+                        .on_input(Message::CalibrationSpacing),
+                    widget::button(text!("Save test GCODE").wrapping(Wrapping::None))
+                        .on_press(Message::GenerateCalibration),
+                    widget::button(text!("Test burn squares").wrapping(Wrapping::None))
+                        .on_press(Message::GenerateTestBurn(id)),
+                ]
+                    .align_y(VerticalAlign::Center)
+                    .spacing(5.0)
+                    .into()
+            );
+
             let mut seq_column = Vec::new();
 
             seq_column.push(widget::horizontal_rule(1.0).into());
 
-            for (i, seq) in condition.sequence.iter().enumerate() {
+            for (i, (seq, vals)) in condition.sequence.iter().zip(self.seq_vals.iter()).enumerate() {
                 let mut row_items = ElementList::new();
 
+                row_items.push(
+                    widget::checkbox("", seq.enabled())
+                        .on_toggle(move|b|Message::ChangeSeqItemEnabled(id, i, b))
+                );
+
                 row_items.push(
                     widget::pick_list(
                         SeqItemType::LIST,
                         Some(seq.item_type()),
-                        move|ty|Message::ChangeSeqItemType(i, ty),
+                        move|ty|Message::ChangeSeqItemType(id, i, ty),
                     )
                         .width(Length::Shrink)
                 );
@@ -410,41 +692,60 @@ impl ConditionEditor {
                     widget::center(text!("Passes: ")).height(Length::Shrink),
                     widget::text_input(
                         "Passes",
-                        self.passes_val[i].as_str(),
+                        vals.passes.as_str(),
                     )
-                        .on_input(move|s|Message::ChangePasses(i, s))
+                        .on_input(move|s|Message::ChangePasses(id, i, s))
                 ].width(Length::FillPortion(1)));
 
                 row_items.push(column![
                     widget::center(text!("Feed: ")).height(Length::Shrink),
                     widget::text_input(
                         "Feed",
-                        self.feed_val[i].as_str(),
+                        vals.feed.as_str(),
                     )
-                        .on_input(move|s|Message::ChangeFeed(i, s))
+                        .on_input(move|s|Message::ChangeFeed(id, i, s))
                 ].width(Length::FillPortion(1)));
 
                 row_items.push(column![
                     widget::center(text!("Power: ")).height(Length::Shrink),
                     widget::text_input(
                         "Power",
-                        self.power_val[i].as_str(),
+                        vals.power.as_str(),
                     )
-                        .on_input(move|s|Message::ChangePower(i, s))
+                        .on_input(move|s|Message::ChangePower(id, i, s))
                 ].width(Length::FillPortion(1)));
 
                 match seq {
-                    Seq::Custom{laser_on, laser_off, ..}=>{
+                    Seq::GrblConst{..}|Seq::GrblDyn{..}=>{
+                        row_items.push(column![
+                            widget::center(text!("Pierce (ms): ").wrapping(Wrapping::None)).height(Length::Shrink),
+                            widget::text_input(
+                                "Pierce delay",
+                                vals.pierce_delay.as_str(),
+                            )
+                                .on_input(move|s|Message::ChangePierceDelay(id, i, s))
+                        ].width(Length::FillPortion(1)));
+
+                        row_items.push(column![
+                            widget::center(text!("Min power: ").wrapping(Wrapping::None)).height(Length::Shrink),
+                            widget::text_input(
+                                "Min power",
+                                vals.min_power.as_str(),
+                            )
+                                .on_input(move|s|Message::ChangeMinPower(id, i, s))
+                        ].width(Length::FillPortion(1)));
+                    },
+                    Seq::Custom{..}=>{
                         row_items.push(column![
                             widget::center(
                                 text!("Laser on GCODE: ").wrapping(Wrapping::None)
                             ).height(Length::Shrink).width(Length::Fill),
                             widget::text_input(
                                 "GCODE",
-                                laser_on.as_str(),
+                                vals.laser_on.as_str(),
                             )
                                 .width(Length::Fill)
-                                .on_input(move|s|Message::ChangeLaserOn(i, s))
+                                .on_input(move|s|Message::ChangeLaserOn(id, i, s))
                         ].width(Length::FillPortion(2)));
 
                         row_items.push(column![
@@ -453,13 +754,22 @@ impl ConditionEditor {
                             ).height(Length::Shrink).width(Length::Fill),
                             widget::text_input(
                                 "GCODE",
-                                laser_off.as_str(),
+                                vals.laser_off.as_str(),
                             )
                                 .width(Length::Fill)
-                                .on_input(move|s|Message::ChangeLaserOff(i, s))
+                                .on_input(move|s|Message::ChangeLaserOff(id, i, s))
                         ].width(Length::FillPortion(2)));
                     },
-                    _=>{},
+                    Seq::RasterEngrave{..}=>{
+                        row_items.push(column![
+                            widget::center(text!("Line spacing (mm): ").wrapping(Wrapping::None)).height(Length::Shrink),
+                            widget::text_input(
+                                "Line spacing",
+                                vals.line_spacing.as_str(),
+                            )
+                                .on_input(move|s|Message::ChangeLineSpacing(id, i, s))
+                        ].width(Length::FillPortion(1)));
+                    },
                 }
 
                 row_items.push(widget::Space::with_width(20.0));
@@ -468,7 +778,7 @@ impl ConditionEditor {
                     widget::button("Delete")
                         .style(crate::danger_button)
                         .width(Length::Shrink)
-                        .on_press(Message::DeleteSequence(i))
+                        .on_press(Message::DeleteSequence(id, i))
                 );
 
 
@@ -498,48 +808,161 @@ impl ConditionEditor {
             .into()
     }
 
+    /// Generate the test burn for the selected condition. Returns `None` if there is no condition
+    /// selected or the test parameters are invalid.
+    pub fn calibration_gcode(&self, machine: &MachineProfile)->Option<String> {
+        let store = self.store.borrow();
+        let condition = store.conditions.get(&self.condition?)?;
+
+        return self.calibration.generate(condition, machine);
+    }
+
     fn new_condition(&mut self) {
         let mut store = self.store.borrow_mut();
         let id = next_condition_id();
-        let name = format!("New Condition {}", id.0);
+        let name = if store.conditions.values().any(|c|c.name == "New Condition") {
+            store.unused_name("New Condition")
+        } else {
+            String::from("New Condition")
+        };
         store.conditions.insert(id, Condition {
             id,
-            name: name.clone(),
+            name,
             color: Color::WHITE,
             sequence: Vec::new(),
+            overcut_mm: 0.0,
         });
         self.condition = Some(id);
+        self.changed = true;
 
         drop(store);
         self.update_sequence_values();
     }
 
+    /// Rebuild all of the sequence strings from the selected condition.
     fn update_sequence_values(&mut self) {
-        self.power_val.clear();
-        self.feed_val.clear();
-        self.passes_val.clear();
+        self.seq_vals.clear();
 
         if let Some(id) = self.condition {
-            let mut store = self.store.borrow_mut();
-            self.changed = true;
+            let store = self.store.borrow();
 
             let condition = store.conditions
-                .get_mut(&id)
+                .get(&id)
                 .unwrap();
 
-            for seq in condition.sequence.iter() {
-                self.power_val.push(seq.power_string());
-                self.feed_val.push(seq.feed_string());
-                self.passes_val.push(seq.passes().to_string());
+            self.seq_vals.extend(condition.sequence.iter().map(SequenceStrings::new));
+            self.overcut = condition.overcut_mm.to_string();
+        }
+    }
+
+    /// Returns the selected condition's ID if it is `id` and `idx` is a valid sequence item. Messages
+    /// from the text boxes may arrive after the selection has changed, so we ignore them if they
+    /// don't belong to the selected condition.
+    fn sequence_target(&self, id: ConditionId, idx: usize)->Option<ConditionId> {
+        if self.condition != Some(id) || idx >= self.seq_vals.len() {
+            eprintln!("Ignoring stale sequence edit for {id:?} item {idx}");
+            return None;
+        }
+
+        return Some(id);
+    }
+
+    /// The sequence strings must always have one entry per sequence item in the selected condition.
+    fn check_sequence_values(&self) {
+        if let Some(id) = self.condition {
+            let store = self.store.borrow();
+            if let Some(condition) = store.conditions.get(&id) {
+                debug_assert_eq!(condition.sequence.len(), self.seq_vals.len());
+                return;
             }
         }
+
+        debug_assert!(self.seq_vals.is_empty());
+    }
+
+    fn can_undo(&self)->bool {
+        !self.history.is_empty()
+    }
+
+    /// Save a snapshot before a `Change*` message is applied. Anything that adds or removes
+    /// conditions clears the history, since restoring an old snapshot would remove conditions
+    /// entities might be using.
+    fn record_history(&mut self, msg: &Message) {
+        match msg {
+            Message::NewCondition|Message::DeleteCondition|Message::LoadImport(_)=>{
+                self.history.clear();
+                self.last_edit = None;
+                return;
+            },
+            Message::UndoCondition=>return,
+            _=>{},
+        }
+
+        let Some(key) = EditKey::new(msg, self.condition) else {
+            self.last_edit = None;
+            return;
+        };
+
+        if self.last_edit == Some(key) {
+            return;
+        }
+        self.last_edit = Some(key);
+
+        if self.history.len() >= MAX_UNDO_HISTORY {
+            self.history.remove(0);
+        }
+        self.history.push(ConditionStoreSnapshot {
+            conditions: self.store.borrow().conditions.clone(),
+            condition: self.condition,
+        });
+    }
+
+    fn undo(&mut self)->Task<Message> {
+        let Some(snapshot) = self.history.pop() else {
+            eprintln!("Nothing to undo");
+            return Task::none();
+        };
+        eprintln!("Undo laser condition change");
+
+        self.changed = true;
+        self.last_edit = None;
+        self.store.borrow_mut().conditions = snapshot.conditions;
+        self.condition = snapshot.condition;
+        self.update_sequence_values();
+
+        // the color might have changed
+        return match self.condition {
+            Some(id)=>Task::done(Message::RecalcSheet(id)),
+            None=>Task::none(),
+        };
     }
 
     pub fn update(&mut self, msg: Message)->Task<Message> {
+        self.record_history(&msg);
+        let task = self.update_inner(msg);
+
+        self.check_sequence_values();
+
+        return task;
+    }
+
+    fn update_inner(&mut self, msg: Message)->Task<Message> {
         match msg {
             // We handle this in MainProgram
             Message::CloseEditor=>{},
-            Message::RecalcSheet=>{},
+            Message::RecalcSheet(_)=>{},
+            Message::GenerateCalibration=>{},
+            Message::GenerateTestBurn(_)=>{},
+            Message::Notify(..)=>{},
+
+            Message::CalibrationMode(mode)=>self.calibration.mode = mode,
+            Message::CalibrationStart(s)=>self.calibration.start = s,
+            Message::CalibrationEnd(s)=>self.calibration.end = s,
+            Message::CalibrationSteps(s)=>self.calibration.steps = s,
+            Message::CalibrationLength(s)=>self.calibration.length = s,
+            Message::CalibrationSpacing(s)=>self.calibration.spacing = s,
+
+            Message::UndoCondition=>return self.undo(),
 
             Message::SelectCondition(id)=>{
                 self.condition = Some(id);
@@ -559,6 +982,70 @@ impl ConditionEditor {
                     self.update_sequence_values();
                 }
             },
+            Message::ImportConditions=>{
+                let future = AsyncFileDialog::new()
+                    .add_filter("Laser conditions", &["ron"])
+                    .set_title("Import laser conditions")
+                    .pick_file();
+                return Task::perform(future, Message::LoadImport);
+            },
+            Message::LoadImport(opt_file)=>if let Some(file) = opt_file {
+                let other = std::fs::read_to_string(file.path())
+                    .map_err(|e|e.to_string())
+                    .and_then(|s|ron::from_str::<ConditionStore>(&s).map_err(|e|e.to_string()));
+
+                match other {
+                    Ok(other)=>{
+                        self.changed = true;
+
+                        let count = other.conditions.len();
+                        let before = self.store.borrow().conditions.len();
+                        let mut remap = HashMap::new();
+                        self.store.borrow_mut().merge(other, &mut remap);
+
+                        // the others are the same as conditions we already have
+                        let added = self.store.borrow().conditions.len() - before;
+                        let reused = count - added;
+                        let text = match reused {
+                            0=>format!("Imported {added} laser conditions"),
+                            _=>format!("Imported {added} laser conditions and reused {reused} that are already here"),
+                        };
+                        return Task::done(Message::Notify(Severity::Info, text));
+                    },
+                    Err(e)=>return Task::done(Message::Notify(
+                        Severity::Error,
+                        format!("Could not import laser conditions: {e}"),
+                    )),
+                }
+            },
+            Message::MoveConditionUp=>{
+                if let Some(id) = self.condition {
+                    let mut store = self.store.borrow_mut();
+                    let idx = store.conditions.get_index_of(&id).unwrap();
+                    if idx > 0 {
+                        self.changed = true;
+                        store.conditions.swap_indices(idx, idx - 1);
+                    }
+                }
+            },
+            Message::MoveConditionDown=>{
+                if let Some(id) = self.condition {
+                    let mut store = self.store.borrow_mut();
+                    let idx = store.conditions.get_index_of(&id).unwrap();
+                    if idx + 1 < store.conditions.len() {
+                        self.changed = true;
+                        store.conditions.swap_indices(idx, idx + 1);
+                    }
+                }
+            },
+            // Only the order changes. Every condition keeps its ID.
+            Message::SortConditions=>{
+                self.changed = true;
+
+                self.store.borrow_mut()
+                    .conditions
+                    .sort_by(|_, a, _, b|a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+            },
             Message::ChangeName(name)=>{
                 if let Some(id) = self.condition {
                     let mut store = self.store.borrow_mut();
@@ -570,6 +1057,21 @@ impl ConditionEditor {
                     condition.name = name;
                 }
             },
+            Message::ChangeOvercut(s)=>{
+                if let Some(id) = self.condition {
+                    let overcut = if s.is_empty() {Some(0.0)} else {s.parse::<f64>().ok()};
+                    if let Some(overcut) = overcut.filter(|n|*n >= 0.0) {
+                        self.changed = true;
+
+                        let mut store = self.store.borrow_mut();
+                        let condition = store.conditions
+                            .get_mut(&id)
+                            .unwrap();
+                        condition.overcut_mm = overcut;
+                    }
+                    self.overcut = s;
+                }
+            },
             Message::ChangeColorR(n)=>{
                 if let Some(id) = self.condition {
                     self.changed = true;
@@ -580,7 +1082,7 @@ impl ConditionEditor {
                         .unwrap();
                     condition.color.r = n;
 
-                    return Task::done(Message::RecalcSheet);
+                    return Task::done(Message::RecalcSheet(id));
                 }
             },
             Message::ChangeColorG(n)=>{
@@ -593,7 +1095,7 @@ impl ConditionEditor {
                         .unwrap();
                     condition.color.g = n;
 
-                    return Task::done(Message::RecalcSheet);
+                    return Task::done(Message::RecalcSheet(id));
                 }
             },
             Message::ChangeColorB(n)=>{
@@ -606,7 +1108,7 @@ impl ConditionEditor {
                         .unwrap();
                     condition.color.b = n;
 
-                    return Task::done(Message::RecalcSheet);
+                    return Task::done(Message::RecalcSheet(id));
                 }
             },
 
@@ -618,18 +1120,20 @@ impl ConditionEditor {
                     let condition = store.conditions
                         .get_mut(&id)
                         .unwrap();
-                    condition.sequence.push(Seq::GrblConst {
+                    let seq = Seq::GrblConst {
                         passes: 1,
                         power: 300,
                         feed: 1000,
-                    });
-
-                    drop(store);
-                    self.update_sequence_values();
+                        enabled: true,
+                        pierce_delay_ms: 0,
+                        min_power: 0,
+                    };
+                    self.seq_vals.push(SequenceStrings::new(&seq));
+                    condition.sequence.push(seq);
                 }
             },
-            Message::DeleteSequence(idx)=>{
-                if let Some(id) = self.condition {
+            Message::DeleteSequence(id, idx)=>{
+                if let Some(id) = self.sequence_target(id, idx) {
                     self.changed = true;
 
                     let mut store = self.store.borrow_mut();
@@ -637,13 +1141,22 @@ impl ConditionEditor {
                         .get_mut(&id)
                         .unwrap();
                     condition.sequence.remove(idx);
+                    self.seq_vals.remove(idx);
+                }
+            },
+            Message::ChangeSeqItemEnabled(id, idx, enabled)=>{
+                if let Some(id) = self.sequence_target(id, idx) {
+                    self.changed = true;
 
-                    drop(store);
-                    self.update_sequence_values();
+                    let mut store = self.store.borrow_mut();
+                    let condition = store.conditions
+                        .get_mut(&id)
+                        .unwrap();
+                    condition.sequence[idx].set_enabled(enabled);
                 }
             },
-            Message::ChangeFeed(idx, s)=>{
-                if let Some(id) = self.condition {
+            Message::ChangeFeed(id, idx, s)=>{
+                if let Some(id) = self.sequence_target(id, idx) {
                     self.changed = true;
 
                     let mut store = self.store.borrow_mut();
@@ -652,21 +1165,21 @@ impl ConditionEditor {
                         .unwrap();
 
                     match &mut condition.sequence[idx] {
-                        Seq::GrblConst{feed, ..}|Seq::GrblDyn{feed, ..}=>{
+                        Seq::GrblConst{feed, ..}|Seq::GrblDyn{feed, ..}|Seq::RasterEngrave{feed, ..}=>{
                             if let Some(num) = crate::parse_u16(&s) {
                                 *feed = num;
-                                self.feed_val[idx] = s;
+                                self.seq_vals[idx].feed = s;
                             }
                         },
                         Seq::Custom{feed, ..}=>{
                             *feed = s.clone();
-                            self.feed_val[idx] = s;
+                            self.seq_vals[idx].feed = s;
                         },
                     }
                 }
             },
-            Message::ChangePower(idx, s)=>{
-                if let Some(id) = self.condition {
+            Message::ChangePower(id, idx, s)=>{
+                if let Some(id) = self.sequence_target(id, idx) {
                     self.changed = true;
 
                     let mut store = self.store.borrow_mut();
@@ -675,21 +1188,76 @@ impl ConditionEditor {
                         .unwrap();
 
                     match &mut condition.sequence[idx] {
-                        Seq::GrblConst{power, ..}|Seq::GrblDyn{power, ..}=>{
+                        Seq::GrblConst{power, ..}|Seq::GrblDyn{power, ..}|Seq::RasterEngrave{power, ..}=>{
                             if let Some(num) = crate::parse_u16(&s) {
                                 *power = num;
-                                self.power_val[idx] = s;
+                                self.seq_vals[idx].power = s;
                             }
                         },
                         Seq::Custom{power, ..}=>{
                             *power = s.clone();
-                            self.power_val[idx] = s;
+                            self.seq_vals[idx].power = s;
                         },
                     }
                 }
             },
-            Message::ChangePasses(idx, s)=>{
-                if let Some(id) = self.condition {
+            Message::ChangePierceDelay(id, idx, s)=>{
+                if let Some(id) = self.sequence_target(id, idx) {
+                    let mut store = self.store.borrow_mut();
+                    let condition = store.conditions
+                        .get_mut(&id)
+                        .unwrap();
+
+                    match &mut condition.sequence[idx] {
+                        Seq::GrblConst{pierce_delay_ms, ..}|Seq::GrblDyn{pierce_delay_ms, ..}=>{
+                            if let Some(num) = crate::parse_u16(&s) {
+                                self.changed = true;
+                                *pierce_delay_ms = num;
+                                self.seq_vals[idx].pierce_delay = s;
+                            }
+                        },
+                        Seq::Custom{..}|Seq::RasterEngrave{..}=>{},
+                    }
+                }
+            },
+            Message::ChangeMinPower(id, idx, s)=>{
+                if let Some(id) = self.sequence_target(id, idx) {
+                    let mut store = self.store.borrow_mut();
+                    let condition = store.conditions
+                        .get_mut(&id)
+                        .unwrap();
+
+                    match &mut condition.sequence[idx] {
+                        Seq::GrblConst{min_power, ..}|Seq::GrblDyn{min_power, ..}=>{
+                            if let Some(num) = crate::parse_u16(&s) {
+                                self.changed = true;
+                                *min_power = num;
+                                self.seq_vals[idx].min_power = s;
+                            }
+                        },
+                        Seq::Custom{..}|Seq::RasterEngrave{..}=>{},
+                    }
+                }
+            },
+            Message::ChangeLineSpacing(id, idx, s)=>{
+                if let Some(id) = self.sequence_target(id, idx) {
+                    let mut store = self.store.borrow_mut();
+                    let condition = store.conditions
+                        .get_mut(&id)
+                        .unwrap();
+
+                    if let Seq::RasterEngrave{line_spacing, ..} = &mut condition.sequence[idx] {
+                        // a spacing of 0 would never finish
+                        if let Some(num) = s.parse::<f64>().ok().filter(|n|*n > 0.0) {
+                            self.changed = true;
+                            *line_spacing = num;
+                        }
+                        self.seq_vals[idx].line_spacing = s;
+                    }
+                }
+            },
+            Message::ChangePasses(id, idx, s)=>{
+                if let Some(id) = self.sequence_target(id, idx) {
                     self.changed = true;
 
                     let mut store = self.store.borrow_mut();
@@ -698,16 +1266,17 @@ impl ConditionEditor {
                         .unwrap();
                     if let Some(num) = crate::parse_u16(&s) {
                         match &mut condition.sequence[idx] {
-                            Seq::GrblConst{passes, ..}|Seq::GrblDyn{passes, ..}|Seq::Custom{passes, ..}=>{
-                                *passes = num;
-                            },
+                            Seq::GrblConst{passes, ..}|
+                                Seq::GrblDyn{passes, ..}|
+                                Seq::Custom{passes, ..}|
+                                Seq::RasterEngrave{passes, ..}=>*passes = num,
                         }
-                        self.passes_val[idx] = s;
+                        self.seq_vals[idx].passes = s;
                     }
                 }
             },
-            Message::ChangeLaserOn(idx, s)=>{
-                if let Some(id) = self.condition {
+            Message::ChangeLaserOn(id, idx, s)=>{
+                if let Some(id) = self.sequence_target(id, idx) {
                     self.changed = true;
 
                     let mut store = self.store.borrow_mut();
@@ -716,14 +1285,15 @@ impl ConditionEditor {
                         .unwrap();
                     match &mut condition.sequence[idx] {
                         Seq::Custom{laser_on, ..}=>{
-                            *laser_on = s;
+                            *laser_on = s.clone();
+                            self.seq_vals[idx].laser_on = s;
                         },
                         _=>{},
                     }
                 }
             },
-            Message::ChangeLaserOff(idx, s)=>{
-                if let Some(id) = self.condition {
+            Message::ChangeLaserOff(id, idx, s)=>{
+                if let Some(id) = self.sequence_target(id, idx) {
                     self.changed = true;
 
                     let mut store = self.store.borrow_mut();
@@ -732,14 +1302,15 @@ impl ConditionEditor {
                         .unwrap();
                     match &mut condition.sequence[idx] {
                         Seq::Custom{laser_off, ..}=>{
-                            *laser_off = s;
+                            *laser_off = s.clone();
+                            self.seq_vals[idx].laser_off = s;
                         },
                         _=>{},
                     }
                 }
             },
-            Message::ChangeSeqItemType(idx, ty)=>{
-                if let Some(id) = self.condition {
+            Message::ChangeSeqItemType(id, idx, ty)=>{
+                if let Some(id) = self.sequence_target(id, idx) {
                     self.changed = true;
 
                     let mut store = self.store.borrow_mut();
@@ -748,45 +1319,80 @@ impl ConditionEditor {
                         .unwrap();
                     match ty {
                         SeqItemType::GrblConst=>match condition.sequence[idx] {
-                            Seq::Custom{..}=>condition.sequence[idx] = Seq::GrblConst {
+                            Seq::Custom{enabled, ..}=>condition.sequence[idx] = Seq::GrblConst {
                                 passes: 1,
                                 power: 1000,
                                 feed: 1000,
+                                enabled,
+                                pierce_delay_ms: 0,
+                                min_power: 0,
+                            },
+                            Seq::GrblDyn{passes, power, feed, enabled, pierce_delay_ms, min_power}=>{
+                                condition.sequence[idx] = Seq::GrblConst {passes, power, feed, enabled, pierce_delay_ms, min_power};
+                            },
+                            Seq::RasterEngrave{passes, power, feed, enabled, ..}=>{
+                                condition.sequence[idx] = Seq::GrblConst {passes, power, feed, enabled, pierce_delay_ms: 0, min_power: 0};
                             },
-                            Seq::GrblDyn{passes, power, feed}=>condition.sequence[idx] = Seq::GrblConst {passes, power, feed},
                             Seq::GrblConst{..}=>{},
                         },
                         SeqItemType::GrblDyn=>match condition.sequence[idx] {
-                            Seq::Custom{..}=>condition.sequence[idx] = Seq::GrblDyn {
+                            Seq::Custom{enabled, ..}=>condition.sequence[idx] = Seq::GrblDyn {
                                 passes: 1,
                                 power: 1000,
                                 feed: 1000,
+                                enabled,
+                                pierce_delay_ms: 0,
+                                min_power: 0,
+                            },
+                            Seq::GrblConst{passes, power, feed, enabled, pierce_delay_ms, min_power}=>{
+                                condition.sequence[idx] = Seq::GrblDyn {passes, power, feed, enabled, pierce_delay_ms, min_power};
+                            },
+                            Seq::RasterEngrave{passes, power, feed, enabled, ..}=>{
+                                condition.sequence[idx] = Seq::GrblDyn {passes, power, feed, enabled, pierce_delay_ms: 0, min_power: 0};
                             },
-                            Seq::GrblConst{passes, power, feed}=>condition.sequence[idx] = Seq::GrblDyn {passes, power, feed},
                             Seq::GrblDyn{..}=>{},
                         },
                         SeqItemType::Custom=>match condition.sequence[idx] {
                             Seq::Custom{..}=>{},
-                            Seq::GrblConst{passes, power, feed}=>condition.sequence[idx] = Seq::Custom {
+                            Seq::GrblConst{passes, power, feed, enabled, ..}=>condition.sequence[idx] = Seq::Custom {
                                 passes,
                                 power: format!("S{power}"),
                                 feed: format!("F{feed}"),
                                 laser_on: "M3".into(),
                                 laser_off: "M5".into(),
+                                enabled,
                             },
-                            Seq::GrblDyn{passes, power, feed}=>condition.sequence[idx] = Seq::Custom {
+                            Seq::GrblDyn{passes, power, feed, enabled, ..}|
+                                Seq::RasterEngrave{passes, power, feed, enabled, ..}=>condition.sequence[idx] = Seq::Custom {
                                 passes,
                                 power: format!("S{power}"),
                                 feed: format!("F{feed}"),
                                 laser_on: "M4".into(),
                                 laser_off: "M5".into(),
+                                enabled,
                             },
                         },
+                        SeqItemType::RasterEngrave=>match condition.sequence[idx] {
+                            Seq::Custom{enabled, ..}=>condition.sequence[idx] = Seq::RasterEngrave {
+                                passes: 1,
+                                power: 1000,
+                                feed: 1000,
+                                line_spacing: DEFAULT_LINE_SPACING,
+                                enabled,
+                            },
+                            Seq::GrblConst{passes, power, feed, enabled, ..}|
+                                Seq::GrblDyn{passes, power, feed, enabled, ..}=>condition.sequence[idx] = Seq::RasterEngrave {
+                                passes,
+                                power,
+                                feed,
+                                line_spacing: DEFAULT_LINE_SPACING,
+                                enabled,
+                            },
+                            Seq::RasterEngrave{..}=>{},
+                        },
                     }
 
-                    self.power_val[idx] = condition.sequence[idx].power_string();
-                    self.feed_val[idx] = condition.sequence[idx].feed_string();
-                    self.passes_val[idx] = condition.sequence[idx].passes().to_string();
+                    self.seq_vals[idx] = SequenceStrings::new(&condition.sequence[idx]);
                 }
             },
         }
@@ -795,8 +1401,51 @@ impl ConditionEditor {
     }
 }
 
+/// The line spacing of a new raster engrave sequence item in mm.
+const DEFAULT_LINE_SPACING: f64 = 0.1;
+
+/// The most `Change*` messages that can be undone.
+const MAX_UNDO_HISTORY: usize = 20;
+
+/// The conditions before a change. The default condition isn't included since it isn't changed by
+/// the messages that are undone.
+struct ConditionStoreSnapshot {
+    conditions: IndexMap<ConditionId, Condition>,
+    /// The selected condition, so undo shows what changed.
+    condition: Option<ConditionId>,
+}
+
+/// What a `Change*` message edits: the message kind, condition, and sequence item.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct EditKey(std::mem::Discriminant<Message>, ConditionId, usize);
+impl EditKey {
+    /// Returns `None` if the message isn't a `Change*` message. `selected` is the selected
+    /// condition, which the messages without a condition ID edit.
+    fn new(msg: &Message, selected: Option<ConditionId>)->Option<Self> {
+        let kind = std::mem::discriminant(msg);
+        match msg {
+            Message::ChangeName(_)|
+                Message::ChangeColorR(_)|
+                Message::ChangeColorG(_)|
+                Message::ChangeColorB(_)|
+                Message::ChangeOvercut(_)=>Some(EditKey(kind, selected?, 0)),
+            Message::ChangeFeed(id, idx, _)|
+                Message::ChangePower(id, idx, _)|
+                Message::ChangePierceDelay(id, idx, _)|
+                Message::ChangeMinPower(id, idx, _)|
+                Message::ChangeLineSpacing(id, idx, _)|
+                Message::ChangePasses(id, idx, _)|
+                Message::ChangeLaserOn(id, idx, _)|
+                Message::ChangeLaserOff(id, idx, _)|
+                Message::ChangeSeqItemType(id, idx, _)|
+                Message::ChangeSeqItemEnabled(id, idx, _)=>Some(EditKey(kind, *id, *idx)),
+            _=>None,
+        }
+    }
+}
+
 /// A storage medium for laser conditions
-#[derive(Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct ConditionStore {
     #[serde(default)]
     default: Option<ConditionId>,
@@ -804,18 +1453,116 @@ pub struct ConditionStore {
     conditions: IndexMap<ConditionId, Condition>,
 }
 impl ConditionStore {
-    pub fn get(&self, id: ConditionId)->&Condition {
-        self.conditions.get(&id).unwrap()
+    pub fn get_opt(&self, id: ConditionId)->Option<&Condition> {
+        self.conditions.get(&id)
+    }
+
+    /// Get the condition, or the default condition if it doesn't exist. If there is no default
+    /// either, this is the first condition or an empty one that doesn't cut anything.
+    pub fn get_or_default(&self, id: ConditionId)->&Condition {
+        static MISSING: OnceLock<Condition> = OnceLock::new();
+
+        self.conditions.get(&id)
+            .or_else(||self.default.and_then(|id|self.conditions.get(&id)))
+            .or_else(||self.conditions.values().next())
+            .unwrap_or_else(||MISSING.get_or_init(||Condition {
+                id: ConditionId(Uuid::max()),
+                color: Color::WHITE,
+                name: String::from("Missing condition"),
+                sequence: Vec::new(),
+                overcut_mm: 0.0,
+            }))
+    }
+
+    pub fn contains(&self, id: ConditionId)->bool {
+        self.conditions.contains_key(&id)
+    }
+
+    /// The default condition if it exists, otherwise the first condition.
+    pub fn default_id(&self)->Option<ConditionId> {
+        self.default
+            .filter(|id|self.contains(*id))
+            .or_else(||self.conditions.keys().next().copied())
+    }
+
+    /// Add the conditions from `other` with new IDs. `remap` gets the new ID of each condition in
+    /// `other` so the caller can fix any references to them. Conditions we already have (with the
+    /// same ID) are not added again.
+    ///
+    /// A condition with the same name as one we already have uses ours if they cut the same.
+    /// Otherwise it is added with a number after its name.
+    pub fn merge(&mut self, other: ConditionStore, remap: &mut HashMap<ConditionId, ConditionId>) {
+        for (old_id, mut condition) in other.conditions {
+            // legacy IDs were only unique in one condition store
+            if !old_id.is_legacy() && self.conditions.contains_key(&old_id) {
+                remap.insert(old_id, old_id);
+                continue;
+            }
+
+            let existing = self.conditions.values()
+                .find(|c|c.name == condition.name);
+            match existing {
+                Some(existing) if existing.cuts_same(&condition)=>{
+                    remap.insert(old_id, existing.id);
+                    continue;
+                },
+                Some(_)=>condition.name = self.unused_name(&condition.name),
+                None=>{},
+            }
+
+            let id = next_condition_id();
+            condition.id = id;
+            self.conditions.insert(id, condition);
+            remap.insert(old_id, id);
+        }
     }
 
     pub fn iter(&self)->impl Iterator<Item = &Condition> {
         self.conditions.values()
     }
+
+    /// `name` with the first number after it that no condition has, like `Cut (2)`.
+    fn unused_name(&self, name: &str)->String {
+        (2..)
+            .map(|i|format!("{name} ({i})"))
+            .find(|new|self.conditions.values().all(|c|c.name != *new))
+            .unwrap()
+    }
+
+    /// A store with only the conditions in `ids` that exist. There is no default condition.
+    pub fn subset(&self, ids: &HashSet<ConditionId>)->ConditionStore {
+        ConditionStore {
+            default: None,
+            conditions: self.conditions.iter()
+                .filter(|(id, _)|ids.contains(id))
+                .map(|(id, condition)|(*id, condition.clone()))
+                .collect(),
+        }
+    }
+
+    /// The condition after `id` in the store's order, wrapping around to the first one.
+    pub fn next_id(&self, id: ConditionId)->Option<ConditionId> {
+        let idx = self.conditions.get_index_of(&id)?;
+        let next = (idx + 1) % self.conditions.len();
+
+        return self.conditions.get_index(next).map(|(id, _)|*id);
+    }
 }
 
+/// A random ID that stays the same across saves, so projects keep pointing at the right condition.
+///
+/// Older files used a counter that started over when the conditions were loaded. Those numbers are
+/// still read, and become the UUID with the same value. Projects and condition stores saved with
+/// the same numbers still agree, and they are saved as UUIDs from then on.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[repr(transparent)]
-pub struct ConditionId(usize);
+pub struct ConditionId(#[serde(deserialize_with = "deserialize_condition_id")] Uuid);
+impl ConditionId {
+    /// If this was a counter from an older file.
+    fn is_legacy(&self)->bool {
+        self.0.as_u128() <= u64::MAX as u128
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Condition {
@@ -823,14 +1570,37 @@ pub struct Condition {
     pub color: Color,
     pub name: String,
     pub sequence: Vec<SequenceItem>,
+    /// How far in mm to keep cutting past the start of closed lines, so the laser warming up
+    /// doesn't leave a nub.
+    #[serde(default)]
+    pub overcut_mm: f64,
 }
 impl Condition {
+    /// If both make the same GCODE. The name and color don't matter.
+    pub fn cuts_same(&self, other: &Condition)->bool {
+        self.sequence == other.sequence && self.overcut_mm == other.overcut_mm
+    }
+
     pub fn display(&self)->ConditionDisplay {
         ConditionDisplay {
             name: self.name.clone(),
             id: self.id,
+            count: None,
         }
     }
+
+    /// A rough estimate of how many seconds it takes to cut `length` mm with every pass of every
+    /// sequence item. Acceleration and rapid moves are ignored, and so are items without a valid
+    /// feedrate.
+    pub fn estimate_cut_time(&self, length: f64)->f64 {
+        self.sequence.iter()
+            .filter(|seq|seq.enabled())
+            .filter_map(|seq|{
+                let feed = seq.feed_rate()?;
+                Some(length * seq.passes() as f64 / feed)
+            })
+            .sum::<f64>() * 60.0
+    }
 }
 impl PartialEq for Condition {
     fn eq(&self, other: &Self)->bool {
@@ -847,10 +1617,24 @@ impl Display for Condition {
 pub struct ConditionDisplay {
     pub id: ConditionId,
     name: String,
+    /// How many entities use the condition. Shown after the name if set.
+    count: Option<usize>,
+}
+impl ConditionDisplay {
+    /// Show how many entities use the condition after its name.
+    pub fn with_count(self, count: usize)->Self {
+        ConditionDisplay {
+            count: Some(count),
+            ..self
+        }
+    }
 }
 impl Display for ConditionDisplay {
     fn fmt(&self, f: &mut Formatter)->FmtResult {
-        self.name.fmt(f)
+        match self.count {
+            Some(count)=>write!(f, "{} ({count})", self.name),
+            None=>self.name.fmt(f),
+        }
     }
 }
 
@@ -866,6 +1650,15 @@ impl Color {
         g: 1.0,
         b: 1.0,
     };
+
+    /// The color as 8 bit RGB.
+    pub fn rgb8(&self)->[u8; 3] {
+        [
+            (self.r.clamp(0.0, 1.0) * 255.0).round() as u8,
+            (self.g.clamp(0.0, 1.0) * 255.0).round() as u8,
+            (self.b.clamp(0.0, 1.0) * 255.0).round() as u8,
+        ]
+    }
 }
 impl From<Color> for IcedColor {
     fn from(c: Color)->Self {
@@ -878,6 +1671,126 @@ impl From<Color> for IcedColor {
     }
 }
 
+/// What a test burn changes between each line.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CalibrationMode {
+    Power,
+    Feed,
+}
+impl CalibrationMode {
+    const LIST: &[Self] = &[
+        Self::Power,
+        Self::Feed,
+    ];
+}
+impl Display for CalibrationMode {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        match self {
+            Self::Power=>write!(f, "Power"),
+            Self::Feed=>write!(f, "Feed"),
+        }
+    }
+}
+
+/// The parameters for a test burn. It cuts a short line for each step between `start` and `end`
+/// using the condition's sequence with either the power or feed replaced.
+struct CalibrationParams {
+    mode: CalibrationMode,
+    start: String,
+    end: String,
+    steps: String,
+    length: String,
+    spacing: String,
+}
+impl Default for CalibrationParams {
+    fn default()->Self {
+        CalibrationParams {
+            mode: CalibrationMode::Power,
+            start: "100".into(),
+            end: "1000".into(),
+            steps: "10".into(),
+            length: "10".into(),
+            spacing: "2".into(),
+        }
+    }
+}
+impl CalibrationParams {
+    fn generate(&self, condition: &Condition, machine: &MachineProfile)->Option<String> {
+        let start = crate::parse_u16(&self.start)? as f64;
+        let end = crate::parse_u16(&self.end)? as f64;
+        let steps = crate::parse_u16(&self.steps)?.max(1);
+        let length = crate::parse_float(&self.length)?;
+        let spacing = crate::parse_float(&self.spacing)?;
+
+        let mut builder = GcodeBuilder::default();
+        builder.set_machine(machine);
+        builder.machine_home(machine);
+
+        builder.comment_block(concat!("Test burn generated by LaserCAM ", env!("CARGO_PKG_VERSION")));
+        builder.comment_block(format!(
+            "{} test for laser condition `{}` from {} to {} in {} steps",
+            self.mode,
+            condition.name,
+            start,
+            end,
+            steps,
+        ));
+        builder.machine_header(machine);
+
+        for step in 0..steps {
+            let value = if steps > 1 {
+                start + ((end - start) * (step as f64) / ((steps - 1) as f64))
+            } else {
+                start
+            };
+            let value = value.round() as u16;
+            let y = (step as f64) * spacing;
+
+            builder.comment_block(format!("Begin line {} with {} {}", step + 1, self.mode, value));
+
+            for seq in condition.sequence.iter().filter(|seq|seq.enabled()) {
+                let seq = self.apply(seq, value);
+
+                for _ in 0..seq.passes() {
+                    builder.travel_motion()
+                        .x(0.0)
+                        .y(y)
+                        .eob();
+
+                    builder.sequence_laser_on(&seq);
+
+                    builder.cutting_motion()
+                        .x(length)
+                        .y(y)
+                        .eob();
+
+                    builder.sequence_laser_off(&seq);
+                }
+            }
+        }
+
+        builder.travel_motion()
+            .x(0.0)
+            .y(0.0)
+            .eob();
+
+        return Some(builder.finish());
+    }
+
+    /// Replace the power or feed of the sequence item with the given value.
+    fn apply(&self, seq: &SequenceItem, value: u16)->SequenceItem {
+        let mut seq = seq.clone();
+        match (self.mode, &mut seq) {
+            (CalibrationMode::Power, Seq::GrblConst{power, ..}|Seq::GrblDyn{power, ..}|Seq::RasterEngrave{power, ..})=>*power = value,
+            (CalibrationMode::Feed, Seq::GrblConst{feed, ..}|Seq::GrblDyn{feed, ..}|Seq::RasterEngrave{feed, ..})=>*feed = value,
+            (CalibrationMode::Power, Seq::Custom{power, ..})=>*power = format!("S{value}"),
+            (CalibrationMode::Feed, Seq::Custom{feed, ..})=>*feed = format!("F{value}"),
+        }
+
+        return seq;
+    }
+}
+
 pub struct ElementList<'a, M>(pub Vec<Element<'a, M>>);
 impl<'a, M> ElementList<'a, M> {
     pub fn new()->Self {ElementList(Vec::new())}
@@ -888,10 +1801,151 @@ impl<'a, M> ElementList<'a, M> {
 }
 
 
-static CONDITION_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// Generate a new random condition ID
+fn next_condition_id()->ConditionId {
+    ConditionId(Uuid::new_v4())
+}
 
+/// Read a UUID string, or a number from an older file. See [`ConditionId`].
+fn deserialize_condition_id<'de, D: Deserializer<'de>>(deserializer: D)->Result<Uuid, D::Error> {
+    struct IdVisitor;
+    impl Visitor<'_> for IdVisitor {
+        type Value = Uuid;
 
-/// Generate a new, per-execution unique condition ID
-fn next_condition_id()->ConditionId {
-    ConditionId(CONDITION_COUNT.fetch_add(1, Ordering::SeqCst))
+        fn expecting(&self, f: &mut Formatter)->FmtResult {
+            write!(f, "a UUID string or a number")
+        }
+
+        fn visit_u64<E: de::Error>(self, n: u64)->Result<Uuid, E> {
+            Ok(Uuid::from_u128(n as u128))
+        }
+
+        fn visit_i64<E: de::Error>(self, n: i64)->Result<Uuid, E> {
+            let n = u64::try_from(n).map_err(|_|E::custom("condition IDs can't be negative"))?;
+            self.visit_u64(n)
+        }
+
+        fn visit_str<E: de::Error>(self, s: &str)->Result<Uuid, E> {
+            Uuid::parse_str(s).map_err(E::custom)
+        }
+    }
+
+    deserializer.deserialize_any(IdVisitor)
+}
+
+fn default_enabled()->bool {true}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    /// A store with one condition for each `(id, name, power)`.
+    fn store(conditions: &[(u64, &str, u16)])->ConditionStore {
+        let conditions = conditions.iter()
+            .map(|(id, name, power)|format!(
+                r#"({id}): (id: ({id}), color: (r: 1.0, g: 0.2, b: 0.2), name: "{name}", sequence: [GrblConst(passes: 1, power: {power}, feed: 1000)])"#,
+            ))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        ron::from_str(&format!("(conditions: {{{conditions}}})")).unwrap()
+    }
+
+    #[test]
+    fn merge_renames_different_conditions() {
+        let mut local = store(&[(1, "Cut", 500), (2, "Engrave", 100)]);
+        let other = store(&[(3, "Cut", 500), (4, "Engrave", 200)]);
+        let id = |n: u64|ron::from_str::<ConditionId>(&format!("({n})")).unwrap();
+
+        let mut remap = HashMap::new();
+        local.merge(other, &mut remap);
+
+        // the same cut uses ours
+        assert_eq!(remap[&id(3)], id(1));
+        // a different cut is added with a new name
+        let engrave = local.get_opt(remap[&id(4)]).unwrap();
+        assert_ne!(engrave.id, id(2));
+        assert_eq!(engrave.name, "Engrave (2)");
+        assert_eq!(engrave.sequence[0].power_string(), "200");
+        assert_eq!(local.iter().count(), 3);
+    }
+
+    #[test]
+    fn sequence_edits_land_on_the_selected_item() {
+        let id = |n: u64|ron::from_str::<ConditionId>(&format!("({n})")).unwrap();
+        let mut editor = ConditionEditor {
+            store: Rc::new(RefCell::new(store(&[(1, "Cut", 500), (2, "Engrave", 100)]))),
+            ..Default::default()
+        };
+        let sequence = |editor: &ConditionEditor, n: u64|editor.store.borrow().get_opt(id(n)).unwrap().sequence.clone();
+
+        let _ = editor.update(Message::SelectCondition(id(2)));
+        let _ = editor.update(Message::NewSequence);
+        let _ = editor.update(Message::ChangeFeed(id(2), 1, "1500".into()));
+        assert_eq!(sequence(&editor, 2)[1].feed_string(), "1500");
+        assert_eq!(sequence(&editor, 2)[0].feed_string(), "1000");
+
+        // the second item is gone from the other condition, so its edits are stale
+        let _ = editor.update(Message::SelectCondition(id(1)));
+        assert_eq!(editor.seq_vals.len(), 1);
+        let _ = editor.update(Message::ChangePower(id(2), 1, "900".into()));
+        let _ = editor.update(Message::ChangePower(id(1), 1, "900".into()));
+        assert_eq!(sequence(&editor, 2)[1].power_string(), "300");
+        assert_eq!(sequence(&editor, 1).len(), 1);
+
+        let _ = editor.update(Message::ChangePower(id(1), 0, "250".into()));
+        assert_eq!(sequence(&editor, 1)[0].power_string(), "250");
+        assert_eq!(sequence(&editor, 2)[0].power_string(), "100");
+
+        // custom GCODE keeps what was typed when switching back and forth
+        let _ = editor.update(Message::ChangeSeqItemType(id(1), 0, SeqItemType::Custom));
+        let _ = editor.update(Message::ChangeLaserOn(id(1), 0, "M4 S".into()));
+        let _ = editor.update(Message::SelectCondition(id(2)));
+        assert_eq!(editor.seq_vals[0].laser_on, "");
+        let _ = editor.update(Message::SelectCondition(id(1)));
+        assert_eq!(editor.seq_vals[0].laser_on, "M4 S");
+    }
+
+    #[test]
+    fn new_conditions_get_unused_names() {
+        let mut editor = ConditionEditor::default();
+        for _ in 0..3 {
+            let _ = editor.update(Message::NewCondition);
+        }
+
+        let store = editor.store.borrow();
+        let names = store.iter()
+            .map(|c|c.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["New Condition", "New Condition (2)", "New Condition (3)"]);
+    }
+
+    #[test]
+    fn legacy_condition_ids_still_match() {
+        use ron::{
+            ser::PrettyConfig,
+            extensions::Extensions,
+        };
+
+        // how older versions saved the store and referenced it in projects
+        let store = ron::from_str::<ConditionStore>(r#"#![enable(implicit_some, unwrap_newtypes)]
+            (
+                default: 3,
+                conditions: {
+                    3: (id: 3, color: (r: 1.0, g: 0.2, b: 0.2), name: "Cut", sequence: []),
+                },
+            )"#).unwrap();
+        let id = ron::from_str::<ConditionId>("(3)").unwrap();
+        assert_eq!(store.get_opt(id).map(|c|c.name.as_str()), Some("Cut"));
+
+        // saved as a UUID and read back as the same ID
+        let mut pc = PrettyConfig::default();
+        pc.extensions = Extensions::UNWRAP_NEWTYPES|Extensions::IMPLICIT_SOME;
+        let s = ron::ser::to_string_pretty(&store, pc).unwrap();
+        assert!(s.contains("00000000-0000-0000-0000-000000000003"));
+        let store = ron::from_str::<ConditionStore>(&s).unwrap();
+        assert!(store.contains(id));
+        assert_eq!(ron::from_str::<ConditionId>(&ron::to_string(&id).unwrap()).unwrap(), id);
+    }
 }
@@ -0,0 +1,198 @@
+use iced::{
+    widget::pane_grid::{
+        Configuration,
+        State as PaneState,
+        Axis,
+        Node,
+    },
+    Size,
+};
+use serde::{Serialize, Deserialize};
+use crate::ProgramPane;
+
+
+/// The pane layout and window size. This is saved when the program closes so the next launch
+/// looks the same.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowLayout {
+    pub panes: LayoutNode,
+    pub window_width: f32,
+    pub window_height: f32,
+}
+impl Default for WindowLayout {
+    fn default()->Self {
+        WindowLayout {
+            panes: LayoutNode::Split {
+                vertical: true,
+                ratio: 0.8,
+                a: Box::new(LayoutNode::Pane(ProgramPane::Sheet)),
+                b: Box::new(LayoutNode::Split {
+                    vertical: false,
+                    ratio: 0.5,
+                    a: Box::new(LayoutNode::Pane(ProgramPane::SheetList)),
+                    b: Box::new(LayoutNode::Pane(ProgramPane::ModelList)),
+                }),
+            },
+            window_width: 1024.0,
+            window_height: 768.0,
+        }
+    }
+}
+impl WindowLayout {
+    /// Save the current layout.
+    pub fn new(panes: &PaneState<ProgramPane>, window_size: Size)->Self {
+        WindowLayout {
+            panes: LayoutNode::from_node(panes, panes.layout()),
+            window_width: window_size.width,
+            window_height: window_size.height,
+        }
+    }
+
+    pub fn window_size(&self)->Size {
+        Size::new(self.window_width, self.window_height)
+    }
+
+    /// The panes as a [`Configuration`] for [`PaneState::with_configuration`].
+    pub fn pane_config(&self)->Configuration<ProgramPane> {
+        self.panes.to_config()
+    }
+
+    /// Load the layout. If it is missing or doesn't have the panes we need, then the default is
+    /// used.
+    pub fn load()->Self {
+        let config_path = directories::BaseDirs::new()
+            .unwrap()
+            .config_dir()
+            .to_path_buf()
+            .join("laser_cam")
+            .join("layout.ron");
+
+        if config_path.exists() {
+            let s = std::fs::read_to_string(config_path).expect("Could not read the layout");
+
+            match ron::from_str::<WindowLayout>(&s) {
+                Ok(mut layout)=>{
+                    match layout.panes.restorable() {
+                        Some(panes)=>{
+                            eprintln!("Loaded layout");
+                            layout.panes = panes;
+                            return layout;
+                        },
+                        None=>eprintln!("The saved layout is missing panes. Using the default"),
+                    }
+                },
+                Err(e)=>eprintln!("Error loading layout: {e}"),
+            }
+        }
+
+        return Self::default();
+    }
+
+    pub fn save(&self) {
+        use ron::ser::PrettyConfig;
+
+        let config_path = directories::BaseDirs::new()
+            .unwrap()
+            .config_dir()
+            .to_path_buf()
+            .join("laser_cam");
+        std::fs::create_dir_all(&config_path).unwrap();
+        let config_path = config_path.join("layout.ron");
+
+        let mut pc = PrettyConfig::default();
+        pc.struct_names = false;
+
+        let s = ron::ser::to_string_pretty(self, pc).unwrap();
+        std::fs::write(config_path, s).expect("Could not write layout");
+
+        eprintln!("Saved layout");
+    }
+}
+
+/// A saved [`Node`] with the panes in it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LayoutNode {
+    Split {
+        /// The panes are side by side
+        vertical: bool,
+        ratio: f32,
+        a: Box<LayoutNode>,
+        b: Box<LayoutNode>,
+    },
+    Pane(ProgramPane),
+}
+impl LayoutNode {
+    fn from_node(panes: &PaneState<ProgramPane>, node: &Node)->Self {
+        match node {
+            Node::Split{axis, ratio, a, b, ..}=>LayoutNode::Split {
+                vertical: *axis == Axis::Vertical,
+                ratio: *ratio,
+                a: Box::new(Self::from_node(panes, a)),
+                b: Box::new(Self::from_node(panes, b)),
+            },
+            Node::Pane(pane)=>LayoutNode::Pane(*panes.get(*pane).unwrap()),
+        }
+    }
+
+    fn to_config(&self)->Configuration<ProgramPane> {
+        match self {
+            Self::Split{vertical, ratio, a, b}=>Configuration::Split {
+                axis: if *vertical {Axis::Vertical} else {Axis::Horizontal},
+                ratio: ratio.clamp(0.05, 0.95),
+                a: Box::new(a.to_config()),
+                b: Box::new(b.to_config()),
+            },
+            Self::Pane(pane)=>Configuration::Pane(*pane),
+        }
+    }
+
+    /// Make the layout usable for a fresh start. The entity params go back to the model list since
+    /// nothing is selected, the sheet import dialog goes back to the sheet, and the info pane is
+    /// removed since there is nothing to show. Returns
+    /// `None` if the sheet (or condition editor or settings), sheet list, and model list aren't
+    /// there exactly once.
+    fn restorable(self)->Option<Self> {
+        let node = self.without_info()?;
+
+        let mut counts = [0; 3];
+        node.count_panes(&mut counts);
+
+        return (counts == [1; 3]).then_some(node);
+    }
+
+    fn without_info(self)->Option<Self> {
+        match self {
+            Self::Split{vertical, ratio, a, b}=>{
+                match (a.without_info(), b.without_info()) {
+                    (Some(a), Some(b))=>Some(Self::Split {
+                        vertical,
+                        ratio,
+                        a: Box::new(a),
+                        b: Box::new(b),
+                    }),
+                    // the other side takes up the whole split
+                    (Some(node), None)|(None, Some(node))=>Some(node),
+                    (None, None)=>None,
+                }
+            },
+            Self::Pane(ProgramPane::Info)=>None,
+            Self::Pane(ProgramPane::EntityParams)=>Some(Self::Pane(ProgramPane::ModelList)),
+            Self::Pane(ProgramPane::SheetImportDialog)=>Some(Self::Pane(ProgramPane::Sheet)),
+            Self::Pane(pane)=>Some(Self::Pane(pane)),
+        }
+    }
+
+    /// Count the `[sheet (or condition editor or settings), sheet list, model list]` panes.
+    fn count_panes(&self, counts: &mut [usize; 3]) {
+        match self {
+            Self::Split{a, b, ..}=>{
+                a.count_panes(counts);
+                b.count_panes(counts);
+            },
+            Self::Pane(ProgramPane::Sheet|ProgramPane::ConditionEditor|ProgramPane::Settings|ProgramPane::SheetImportDialog)=>counts[0] += 1,
+            Self::Pane(ProgramPane::SheetList)=>counts[1] += 1,
+            Self::Pane(ProgramPane::ModelList|ProgramPane::EntityParams)=>counts[2] += 1,
+            Self::Pane(ProgramPane::Info)=>{},
+        }
+    }
+}
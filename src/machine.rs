@@ -0,0 +1,117 @@
+use serde::{Serialize, Deserialize};
+
+
+/// Settings for the machine the GCODE is generated for. These are shared between all sheets.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MachineProfile {
+    /// Home the machine before anything else in the file.
+    #[serde(default)]
+    pub home_at_start: bool,
+    /// The GCODE used to home the machine. Not all controllers use `G28`.
+    #[serde(default = "default_homing_command")]
+    pub homing_command: String,
+    /// How many decimal places the X and Y coordinates have. Between 1 and 6.
+    #[serde(default = "default_coord_precision")]
+    pub coord_precision: usize,
+    /// Generate GCODE in inches with `G20` instead of mm with `G21`. Coordinates and feed rates
+    /// are converted, but custom sequence items are used as written.
+    #[serde(default)]
+    pub imperial_gcode: bool,
+    /// Cut entities that only differ by position with one `M98`/`M99` sub-program each, moved into
+    /// place with `G52`. This makes files much smaller, but GRBL doesn't support sub-programs.
+    #[serde(default)]
+    pub subprograms: bool,
+    /// Write comments the way GRBL takes them. See [`GcodeBuilder::set_grbl_mode`].
+    ///
+    /// [`GcodeBuilder::set_grbl_mode`]: crate::gcode::GcodeBuilder::set_grbl_mode
+    #[serde(default)]
+    pub grbl_comments: bool,
+    /// The highest `S` value the controller takes. This is GRBL's `$30`, which is 1000 by default.
+    /// Higher powers are lowered to this.
+    #[serde(default = "default_max_power")]
+    pub max_power: u16,
+    /// Laser powers are multiplied by this, for controllers that take a different range than
+    /// GRBL's 0-1000. Conditions are always written in 0-1000.
+    #[serde(default = "default_s_scale")]
+    pub s_scale: f64,
+    /// Move between cuts with `G1` at this feed in mm/min instead of `G0`. Some machines shake
+    /// at full rapid speed.
+    #[serde(default)]
+    pub travel_feed: Option<u16>,
+    /// Lines shorter than this in mm are skipped. Firing the laser without moving burns a hole.
+    #[serde(default = "default_min_line_length")]
+    pub min_line_length: f64,
+}
+impl Default for MachineProfile {
+    fn default()->Self {
+        MachineProfile {
+            home_at_start: false,
+            homing_command: default_homing_command(),
+            coord_precision: default_coord_precision(),
+            imperial_gcode: false,
+            subprograms: false,
+            grbl_comments: false,
+            max_power: default_max_power(),
+            s_scale: default_s_scale(),
+            travel_feed: None,
+            min_line_length: default_min_line_length(),
+        }
+    }
+}
+impl MachineProfile {
+    pub fn load()->Self {
+        let config_path = directories::BaseDirs::new()
+            .unwrap()
+            .config_dir()
+            .to_path_buf()
+            .join("laser_cam")
+            .join("machine_profile.ron");
+
+        if config_path.exists() {
+            let s = std::fs::read_to_string(config_path).expect("Could not read the machine profile");
+
+            match ron::from_str::<MachineProfile>(&s) {
+                Ok(profile)=>{
+                    eprintln!("Loaded machine profile");
+                    return profile;
+                },
+                Err(e)=>eprintln!("Error loading machine profile: {e}"),
+            }
+        }
+
+        return Self::default();
+    }
+
+    pub fn save(&self) {
+        use ron::ser::PrettyConfig;
+
+        let config_path = directories::BaseDirs::new()
+            .unwrap()
+            .config_dir()
+            .to_path_buf()
+            .join("laser_cam");
+        std::fs::create_dir_all(&config_path).unwrap();
+        let config_path = config_path.join("machine_profile.ron");
+
+        let mut pc = PrettyConfig::default();
+        pc.struct_names = false;
+
+        let s = ron::ser::to_string_pretty(self, pc).unwrap();
+        std::fs::write(config_path, s).expect("Could not write machine profile");
+
+        eprintln!("Saved machine profile");
+    }
+}
+
+
+fn default_homing_command()->String {
+    "G28".into()
+}
+
+fn default_coord_precision()->usize {3}
+
+fn default_max_power()->u16 {1000}
+
+fn default_s_scale()->f64 {1.0}
+
+fn default_min_line_length()->f64 {0.01}
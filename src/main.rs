@@ -3,6 +3,7 @@ use iced::{
         pane_grid::{
             State as PaneState,
             ResizeEvent,
+            DragEvent,
             self,
         },
         button::Status as ButtonStatus,
@@ -10,6 +11,7 @@ use iced::{
         column,
         row,
         text,
+        text_editor,
         self,
     },
     alignment::{
@@ -22,20 +24,37 @@ use iced::{
     },
     Background,
     Border,
+    Color,
     Length,
     Element,
     Theme,
     Task,
+    Size,
+    Subscription,
+    keyboard,
     window,
+    advanced::widget::operation::focusable,
 };
 use rfd::{
     AsyncFileDialog,
+    AsyncMessageDialog,
+    MessageButtons,
+    MessageDialogResult,
+    MessageLevel,
     FileHandle,
 };
-use std::fmt::{
-    Display,
-    Formatter,
-    Result as FmtResult,
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+        Result as FmtResult,
+    },
+    cell::RefCell,
+    rc::Rc,
+    path::{
+        Path as StdPath,
+        PathBuf,
+    },
 };
 use sheet::*;
 use model::*;
@@ -43,14 +62,47 @@ use laser::{
     ConditionEditor,
     Message as ConditionMessage,
     ConditionId,
+    ConditionStore,
+    ConditionDisplay,
+};
+use machine::MachineProfile;
+use gcode::CommentLevel;
+use layout::WindowLayout;
+use settings::{
+    Settings,
+    ImportOptions,
+    CanvasStyle,
+    AppTheme,
+    Units,
+};
+use notification::{
+    Notification,
+    Severity,
+    TOAST_DURATION,
+    MAX_TOASTS,
 };
+use project::{
+    Project,
+    PROJECT_EXTENSION,
+};
+use export::ImageFormat;
+use font::TextParams;
+use serde::{Serialize, Deserialize};
 
 
 mod model;
 mod sheet;
 mod gcode;
 mod laser;
+mod machine;
+mod layout;
+mod settings;
+mod notification;
+mod project;
+mod export;
+mod cli;
 mod utils;
+mod font;
 
 
 pub type Point = ultraviolet::DVec2;
@@ -61,32 +113,115 @@ pub type Transform = ultraviolet::DSimilarity2;
 pub type Translation = ultraviolet::DVec2;
 
 
+/// The sheet tabs scroll sideways when there are more than this many sheets.
+const MAX_FIXED_TABS: usize = 5;
+
+/// The color of warnings. The theme doesn't have one.
+const WARNING_COLOR: Color = Color::from_rgb(0.95, 0.65, 0.1);
+
+
 /// Main program state changes
 #[derive(Debug, Clone)]
 pub enum Message {
     Sheet(SheetMessage),
     Condition(ConditionMessage),
     Iced(Event),
+    /// Tab, Shift+Tab, or Ctrl+A was pressed. See [`Sheet::selection_shortcut`].
+    SelectionShortcut(keyboard::Key, keyboard::Modifiers),
 
     RenameSheet(String),
+    BookmarkName(String),
     SelectSheet(usize),
     NewSheet,
-    DeleteSheet,
+    DeleteSheet(usize),
     ChangeSheetWidth(String),
+    /// Select the entities that are partly or completely outside the margin or in a keep-out zone.
+    SelectOutOfBounds,
     ChangeSheetHeight(String),
+    ChangeSheetMargin(String),
+    ChangeGlobalScale(String),
+    ToggleRotary(bool),
+    ChangeMaterial(String),
+    ChangeRotaryDiameter(String),
+    ChangeOrigin(Origin),
+    /// One of the corner coordinates of the next keep-out zone. `X1`, `Y1`, `X2`, `Y2`.
+    KeepOutInput(usize, String),
+    AddKeepOut,
+    RemoveKeepOut(usize),
 
     AddModel(ModelHandle),
+    /// Remove the model from the store and every entity using it from all the sheets.
+    RemoveModel(ModelHandle),
+    /// Pick a file to replace the model with.
+    OpenReplaceModelPicker(ModelHandle),
+    /// Load the file in place of the model. Entities using it keep their placement.
+    ReplaceModel(ModelHandle, Option<FileHandle>),
+    /// Paste the entity from [`SheetMessage::CopyToSheet`] on the sheet.
+    CopyEntityToSheet(usize),
+    CancelCopyEntity,
 
     ResizePane(ResizeEvent),
+    /// Move a pane by dragging its title bar. Dropping it in the middle of another pane swaps them.
+    DragPane(DragEvent),
+    /// Maximize the sheet (or the condition editor if it is open), or restore it.
+    ToggleMaximizeSheet,
 
     ModelPaneState(ModelPaneState),
+    /// Filter the entity list by label, model, or condition.
+    EntitySearch(String),
 
     OpenFilePicker,
     LoadModel(Option<Vec<FileHandle>>),
+    ImportUnits(Units),
+    ImportScale(String),
+    ImportArcTolerance(String),
+    ImportAddToSheet(bool),
+    /// Save the import options as the defaults.
+    ImportRemember(bool),
+    /// Always import with the saved options without showing them.
+    ImportDontAsk(bool),
+    ConfirmModelImport,
+    CancelModelImport,
+
+    /// Add a text model, or edit the text model in the handle.
+    OpenTextDialog(Option<ModelHandle>),
+    TextAction(text_editor::Action),
+    TextFont(String),
+    TextSize(String),
+    TextLineSpacing(String),
+    ConfirmText,
+    CancelText,
+
+    OpenProjectPicker,
+    LoadProject(Option<FileHandle>),
+    OpenProjectSaveDialog,
+    SaveProject(Option<FileHandle>),
+    OpenSheetImportPicker,
+    ImportSheet(Option<FileHandle>),
+    ToggleImportedSheet(usize, bool),
+    ImportSelectedSheets,
+    CancelSheetImport,
 
     OpenGcodeSaveDialog,
-    SaveGcode(Option<FileHandle>),
+    /// Save the GCODE for only the selected entities.
+    OpenSelectionGcodeSaveDialog,
+    ChangeResumeFrom(String),
+    /// Save the GCODE starting at the entity in [`MainProgram::resume_from`].
+    OpenResumeGcodeSaveDialog,
+    CopyGcode,
+    SimulateGcode,
+    GcodeProgress(usize, GcodeProgress),
+    /// The sheet, which of its entities to cut, and the file.
+    SaveGcode(usize, GcodeRange, Option<FileHandle>),
+    /// Write the GCODE if the user said it can overwrite the file.
+    OverwriteGcode(usize, GcodeRange, PathBuf, bool),
+    SaveCalibration(Option<FileHandle>),
+    OverwriteCalibration(PathBuf, bool),
 
+    OpenImageSaveDialog,
+    ExportImage(Option<FileHandle>),
+
+    EntityParamsLabel(String),
     EntityParamsX(String),
     EntityParamsY(String),
     EntityParamsAngle(f64),
@@ -95,33 +230,115 @@ pub enum Message {
     EntityParamsFlip(bool),
     EntityParamsCondition(ConditionId),
     DeleteEntity,
+    ChangeEntityGapH(String),
+    ChangeEntityGapV(String),
+    /// Move the second selected entity so the gaps in [`MainProgram::entity_gap`] are between it
+    /// and the first.
+    ApplyEntityGap,
+    ChangePatternKind(PatternKind),
+    /// The columns, rows, X spacing, and Y spacing of a rectangular pattern, or the count and
+    /// diameter of a circular one.
+    PatternInput(usize, String),
+    /// Ask where to save the pattern of the entity in [`MainProgram::entity_params`].
+    OpenPatternSaveDialog,
+    /// Save the pattern in [`MainProgram::pending_pattern`] and put it in place of its entity.
+    SavePattern(Option<FileHandle>),
 
     ToggleConditionEditor,
+    ToggleSettings,
+    SettingsTheme(AppTheme),
+    SettingsUnits(Units),
+    SettingsZoomStep(f64),
+    SettingsNudgeDistance(f64),
+    SettingsEntitySnap(bool),
+    SettingsSnapDistance(f64),
+    SettingsImageDpi(f64),
+    SettingsGcodeFileName(String),
+    SettingsAskImportOptions(bool),
+    SettingsMinFeatureSize(f64),
+    SettingsMaxFitScale(f64),
+    /// Every canvas appearance setting at once. The sheets are redrawn.
+    SettingsCanvas(CanvasStyle),
 
     ClearModels,
 
-    ToggleGrblComment(bool),
+    /// Show or hide the notification log.
+    ToggleInfo,
+    /// Clear the notification log and close it.
+    DismissInfo,
+    /// Hide a toast. It stays in the log.
+    DismissToast(usize),
+    /// Answer the prompt for an entity that is bigger than the sheet.
+    FitEntity(usize, EntityId, FitChoice),
+    /// The answer to scaling an entity up more than [`Settings::max_fit_scale`] to fit the sheet.
+    ConfirmFitToSheet(usize, EntityId, bool),
+
+    ToggleGrblComments(bool),
+    ChangeCommentLevel(CommentLevel),
+    ToggleBlankLines(bool),
+
+    ToggleHomeAtStart(bool),
+    ToggleImperialGcode(bool),
+    ToggleSubprograms(bool),
+    ChangeHomingCommand(String),
+    ChangeCoordPrecision(usize),
+    ChangeMaxPower(String),
+    ChangeTravelFeed(String),
+    ChangeMinLineLength(String),
+    ChangeSScale(String),
+}
+impl Message {
+    /// If this message changes the entity in [`MainProgram::entity_params`].
+    fn is_entity_params(&self)->bool {
+        matches!(self,
+            Self::EntityParamsLabel(_)|
+                Self::EntityParamsX(_)|
+                Self::EntityParamsY(_)|
+                Self::EntityParamsAngle(_)|
+                Self::EntityParamsAngleString(_)|
+                Self::EntityParamsScale(_)|
+                Self::EntityParamsFlip(_)|
+                Self::EntityParamsCondition(_)|
+                Self::DeleteEntity
+        )
+    }
+}
+
+/// Which of a sheet's entities are saved as GCODE.
+#[derive(Debug, Clone)]
+pub enum GcodeRange {
+    All,
+    Selection(Vec<EntityId>),
+    /// The index in the cutting order to start at.
+    ResumeFrom(usize),
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ProgramPane {
     Sheet,
     SheetList,
     ModelList,
     EntityParams,
     ConditionEditor,
+    Settings,
+    Info,
+    /// Picking sheets from another project. Shown where the sheet is.
+    SheetImportDialog,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ModelPaneState {
     ActiveModels,
     AllModels,
+    /// Every entity on the active sheet.
+    Entities,
 }
 impl Display for ModelPaneState {
     fn fmt(&self, f: &mut Formatter)->FmtResult {
         match self {
             Self::ActiveModels=>write!(f, "Active Models"),
             Self::AllModels=>write!(f, "All Models"),
+            Self::Entities=>write!(f, "Entities"),
         }
     }
 }
@@ -139,8 +356,113 @@ impl Display for SheetIndex {
     }
 }
 
+/// A project the user is picking sheets to import from.
+struct SheetImport {
+    path: PathBuf,
+    project: Project,
+    /// If each of the project's sheets will be imported.
+    selected: Vec<bool>,
+}
+
+/// What to do with an entity that is bigger than the sheet.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FitChoice {
+    /// Scale it down to fit.
+    Scale,
+    /// Turn it 90°.
+    Rotate,
+    /// Leave it hanging off the sheet.
+    Leave,
+}
+
+/// DXF files waiting for the user to pick how they are imported.
+struct ModelImport {
+    paths: Vec<PathBuf>,
+    options: ImportOptions,
+    scale: String,
+    arc_tolerance: String,
+    remember: bool,
+    dont_ask: bool,
+}
+
+/// The text model being added or edited.
+struct TextDialog {
+    /// The text model being edited, or `None` for a new one.
+    editing: Option<ModelHandle>,
+    content: text_editor::Content,
+    /// The installed font families.
+    fonts: Vec<String>,
+    font: Option<String>,
+    size: String,
+    line_spacing: String,
+}
+impl TextDialog {
+    fn new(editing: Option<ModelHandle>, units: Units)->Self {
+        let params = editing.as_ref()
+            .and_then(|handle|handle.text.clone())
+            .unwrap_or_default();
+        let fonts = font::font_families();
+        let font = match params.font.is_empty() {
+            true=>fonts.first().cloned(),
+            false=>Some(params.font.clone()),
+        };
+
+        TextDialog {
+            editing,
+            content: text_editor::Content::with_text(&params.text),
+            fonts,
+            font,
+            size: units.format(params.size),
+            line_spacing: params.line_spacing.to_string(),
+        }
+    }
+
+    /// The text and its options, if they are all valid.
+    fn params(&self, units: Units)->Option<TextParams> {
+        let text = self.content.text().trim_end().to_string();
+        if text.trim().is_empty() {
+            return None;
+        }
+
+        Some(TextParams {
+            text,
+            font: self.font.clone()?,
+            size: units.parse(&self.size).filter(|s|*s > 0.0)?,
+            line_spacing: self.line_spacing.trim().parse::<f64>().ok().filter(|s|*s > 0.0)?,
+        })
+    }
+}
+
+/// A choice in the condition filter.
+#[derive(Clone, PartialEq)]
+pub enum ConditionFilterChoice {
+    All,
+    Only(ConditionDisplay),
+}
+impl Display for ConditionFilterChoice {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        match self {
+            Self::All=>write!(f, "All conditions"),
+            Self::Only(c)=>c.fmt(f),
+        }
+    }
+}
+
+/// A saved view in the bookmark list.
+#[derive(Clone, PartialEq)]
+pub struct BookmarkIndex {
+    pub name: String,
+    pub index: usize,
+}
+impl Display for BookmarkIndex {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        self.name.fmt(f)
+    }
+}
+
 struct EntityParams {
     id: EntityId,
+    label: String,
     x: String,
     y: String,
     angle: f64,
@@ -157,17 +479,69 @@ pub struct MainProgram {
     sheets: Vec<Sheet>,
     sheet_settings: Vec<SheetIndex>,
     model_pane_state: ModelPaneState,
+    entity_search: String,
     entity_params: Option<EntityParams>,
+    /// The entity waiting for a sheet to be copied to.
+    copy_entity: Option<(ModelHandle, EntityState)>,
+    sheet_import: Option<SheetImport>,
+    model_import: Option<ModelImport>,
+    text_dialog: Option<TextDialog>,
+    /// The sheets and entities that don't fit on them, waiting for the user to pick a
+    /// [`FitChoice`].
+    fit_prompts: Vec<(usize, EntityId)>,
     sheet_size: [String; 2],
+    sheet_margin: String,
+    global_scale: String,
+    rotary_diameter: String,
+    /// The machine profile's shortest line in mm.
+    min_line_length: String,
+    /// The machine profile's power scale.
+    s_scale: String,
+    /// The order number (starting at 1) of the entity to resume cutting at.
+    resume_from: String,
+    /// The horizontal and vertical gaps to put between the two selected entities. Empty ones
+    /// aren't changed.
+    entity_gap: [String; 2],
+    pattern_kind: PatternKind,
+    /// See [`Message::PatternInput`].
+    pattern_input: [String; 4],
+    /// The sheet, entity, and pattern waiting for the save dialog.
+    pending_pattern: Option<(usize, EntityId, Pattern)>,
+    /// The corners of the next keep-out zone: `X1`, `Y1`, `X2`, `Y2`.
+    keep_out_input: [String; 4],
+    /// The name for the next saved view.
+    bookmark_name: String,
     conditions: ConditionEditor,
+    machine: Rc<RefCell<MachineProfile>>,
+    settings: Rc<RefCell<Settings>>,
+    /// The test burn waiting for the save dialog.
+    calibration_gcode: Option<String>,
+    /// `(sheet, entities done, total entities)` while GCODE is being generated in the background.
+    gcode_progress: Option<(usize, usize, usize)>,
+    /// Every notification, shown in the info pane.
+    notifications: Vec<Notification>,
+    /// The IDs of the notifications shown as toasts.
+    toasts: Vec<usize>,
+    /// Saved with the pane layout when closing.
+    window_size: Size,
+    /// The files being dragged over the window.
+    hovered_files: Vec<PathBuf>,
 }
 impl MainProgram {
+    pub fn theme(&self)->Theme {
+        self.settings.borrow().theme.theme()
+    }
+
     pub fn view(&self)->Element<Message> {
-        widget::pane_grid(
+        let panes = widget::pane_grid(
             &self.panes,
-            |_pane, state, _is_maximized|{
+            |_pane, state, is_maximized|{
                 match state {
-                    ProgramPane::ConditionEditor=>pane_grid::Content::new(self.conditions.view().map(Message::Condition))
+                    ProgramPane::ConditionEditor=>pane_grid::Content::new(
+                        self.conditions
+                            .view(&self.sheets[self.active_sheet].stats().condition_counts)
+                            .map(Message::Condition)
+                    )
                         .style(|theme|{
                             Style {
                                 border: Border {
@@ -177,7 +551,12 @@ impl MainProgram {
                                 },
                                 ..Style::default()
                             }
-                        }),
+                        })
+                        .title_bar(
+                            pane_grid::TitleBar::new(widget::center(text!("Laser Conditions")).height(Length::Shrink))
+                                .controls(maximize_button(is_maximized))
+                                .padding(5.0)
+                        ),
                     ProgramPane::Sheet=>pane_grid::Content::new(self.sheet_view())
                         .style(|theme|{
                             Style {
@@ -188,7 +567,12 @@ impl MainProgram {
                                 },
                                 ..Style::default()
                             }
-                        }),
+                        })
+                        .title_bar(
+                            pane_grid::TitleBar::new(widget::center(text!("Sheet")).height(Length::Shrink))
+                                .controls(maximize_button(is_maximized))
+                                .padding(5.0)
+                        ),
                     ProgramPane::SheetList=>pane_grid::Content::new(self.sheet_list_view())
                         .style(|theme|{
                             Style {
@@ -234,610 +618,3142 @@ impl MainProgram {
                             pane_grid::TitleBar::new(widget::center(text!("Entity Settings")).height(Length::Shrink))
                                 .padding(5.0)
                         ),
+                    ProgramPane::Settings=>pane_grid::Content::new(self.settings_view())
+                        .style(|theme|{
+                            Style {
+                                border: Border {
+                                    color: theme.palette().primary,
+                                    width: 1.0,
+                                    ..Border::default()
+                                },
+                                ..Style::default()
+                            }
+                        })
+                        .title_bar(
+                            pane_grid::TitleBar::new(widget::center(text!("Settings")).height(Length::Shrink))
+                                .controls(maximize_button(is_maximized))
+                                .padding(5.0)
+                        ),
+                    ProgramPane::SheetImportDialog=>pane_grid::Content::new(self.sheet_import_view())
+                        .style(|theme|{
+                            Style {
+                                border: Border {
+                                    color: theme.palette().primary,
+                                    width: 1.0,
+                                    ..Border::default()
+                                },
+                                ..Style::default()
+                            }
+                        })
+                        .title_bar(
+                            pane_grid::TitleBar::new(widget::center(text!("Import Sheets")).height(Length::Shrink))
+                                .padding(5.0)
+                        ),
+                    ProgramPane::Info=>pane_grid::Content::new(self.info_view())
+                        .style(|theme|{
+                            Style {
+                                border: Border {
+                                    color: theme.palette().primary,
+                                    width: 1.0,
+                                    ..Border::default()
+                                },
+                                ..Style::default()
+                            }
+                        })
+                        .title_bar(
+                            pane_grid::TitleBar::new(widget::center(text!("Notifications")).height(Length::Shrink))
+                                .padding(5.0)
+                        ),
                 }
             },
         )
             .on_resize(10.0, Message::ResizePane)
-            .into()
-    }
+            .on_drag(Message::DragPane);
 
-    fn sheet_view(&self)->Element<Message> {
-        widget::container(
-            self.sheets[self.active_sheet]
-                .main_view()
-                .map(|m|Message::Sheet(m))
-        )
-            .width(Length::FillPortion(3))
-            .height(Length::Fill)
+        column![
+            widget::stack![
+                panes,
+                self.toast_view(),
+                self.model_import_view(),
+                self.text_dialog_view(),
+            ],
+            self.status_bar_view(),
+        ]
             .into()
     }
 
-    fn sheet_list_view(&self)->Element<Message> {
-        widget::scrollable(
-            column![
-                // sheet selector
-                widget::pick_list(
-                    self.sheet_settings.as_slice(),
-                    Some(&self.sheet_settings[self.active_sheet]),
-                    |named_sheet|Message::SelectSheet(named_sheet.index),
-                ),
+    /// A line across the bottom with the active sheet, the selection, the zoom, and the last
+    /// notification.
+    fn status_bar_view(&self)->Element<Message> {
+        let sheet = &self.sheets[self.active_sheet];
+        let units = self.settings.borrow().units;
 
+        let selection = match &self.entity_params {
+            _ if sheet.selection.len() > 1=>format!("{} selected", sheet.selection.len()),
+            Some(params)=>match sheet.entities.get(&params.id) {
+                Some((handle, _))=>format!("Selected: {} ({:?})", handle.name(), params.id),
+                None=>String::from("Nothing selected"),
+            },
+            None=>String::from("Nothing selected"),
+        };
 
-                row![
-                    widget::button("New sheet")
-                        .on_press(Message::NewSheet),
+        let last_notification: Element<Message> = match self.notifications.last() {
+            Some(notification)=>{
+                let style = match notification.severity {
+                    Severity::Info=>text::default,
+                    Severity::Warning=>warning_text,
+                    Severity::Error=>text::danger,
+                };
+                text!("{} {}", notification.timestamp(), notification.text)
+                    .style(style)
+                    .into()
+            },
+            None=>widget::Space::with_width(0.0).into(),
+        };
 
-                    widget::Space::with_width(Length::Fill),
+        widget::container(
+            row![
+                text!(
+                    "{} ({})",
+                    self.sheet_settings[self.active_sheet].name,
+                    units.size(sheet.sheet_size.x, sheet.sheet_size.y),
+                ),
 
-                    widget::button("Delete sheet")
-                        .style(danger_button)
-                        .on_press(Message::DeleteSheet),
-                ].spacing(5.0),
+                text!("Entities: {}", sheet.entities.len()),
 
-                widget::button("Laser condition editor")
-                    .on_press(Message::ToggleConditionEditor),
+                text!("{selection}"),
 
-                row![
-                    "Rename: ",
-                    widget::text_input(
-                        "Sheet name",
-                        self.sheet_settings[self.active_sheet].name.as_str(),
-                    )
-                        .on_input(|s|Message::RenameSheet(s)),
-                ],
+                widget::button(text!("Zoom: {:.0}%", sheet.zoom() * 100.0))
+                    .style(widget::button::text)
+                    .padding(0.0)
+                    .on_press(Message::Sheet(SheetMessage::ResetZoom)),
 
-                row![
-                    "Width: ",
-                    widget::text_input(
-                        "Width",
-                        &self.sheet_size[0],
-                    )
-                        .on_input(Message::ChangeSheetWidth),
-                ],
+                last_notification,
+            ]
+                .align_y(VerticalAlign::Center)
+                .spacing(20.0)
+        )
+            .width(Length::Fill)
+            .padding([2.0, 10.0])
+            .style(|theme: &Theme|{
+                let palette = theme.extended_palette();
+                Style {
+                    background: Some(Background::Color(palette.background.weak.color)),
+                    text_color: Some(palette.background.weak.text),
+                    ..Default::default()
+                }
+            })
+            .into()
+    }
 
-                row![
-                    "Height: ",
-                    widget::text_input(
-                        "Height",
-                        &self.sheet_size[1],
-                    )
-                        .on_input(Message::ChangeSheetHeight),
-                ],
+    /// The import options over everything else while DXF files are waiting to be imported.
+    fn model_import_view(&self)->Element<Message> {
+        let Some(import) = &self.model_import else {
+            return widget::Space::new(0.0, 0.0).into();
+        };
+        let title = match import.paths.as_slice() {
+            [path]=>format!("Import `{}`", path.file_name().unwrap_or_default().to_string_lossy()),
+            paths=>format!("Import {} DXF files", paths.len()),
+        };
 
-                widget::button("Reorder entities")
-                    .on_press(Message::Sheet(SheetMessage::StartOrder)),
+        let dialog = widget::container(
+            column![
+                text!("{title}").size(20),
 
                 row![
-                    text!("Entity order visibility"),
-                    widget::toggler(self.sheets[self.active_sheet].show_order)
-                        .on_toggle(|b|Message::Sheet(SheetMessage::SetShowOrder(b)))
+                    "Drawing units: ",
+                    widget::pick_list(
+                        Units::LIST,
+                        Some(import.options.units),
+                        Message::ImportUnits,
+                    ),
                 ]
-                    .align_y(VerticalAlign::Center)
-                    .spacing(5.0),
+                    .align_y(VerticalAlign::Center),
 
                 row![
-                    widget::button("Save GCODE")
-                        .on_press(Message::OpenGcodeSaveDialog),
+                    "Scale: ",
+                    widget::text_input("Scale", &import.scale)
+                        .on_input(Message::ImportScale),
+                ]
+                    .align_y(VerticalAlign::Center),
 
-                    column![
-                        text!("GRBL comments"),
+                row![
+                    "Arc tolerance: ",
+                    widget::text_input("Arc tolerance", &import.arc_tolerance)
+                        .on_input(Message::ImportArcTolerance),
+                ]
+                    .align_y(VerticalAlign::Center),
 
-                        widget::toggler(self.sheets[self.active_sheet].grbl_comments)
-                            .on_toggle(Message::ToggleGrblComment)
-                    ]
-                        .align_x(HorizontalAlign::Center)
-                        .spacing(5.0)
+                widget::checkbox("Add to the active sheet", import.options.add_to_sheet)
+                    .on_toggle(Message::ImportAddToSheet),
+                widget::checkbox("Remember these choices", import.remember)
+                    .on_toggle(Message::ImportRemember),
+                widget::checkbox("Don't ask again", import.dont_ask)
+                    .on_toggle(Message::ImportDontAsk),
+
+                row![
+                    widget::button("Import")
+                        .on_press_maybe((import.options.scale > 0.0).then_some(Message::ConfirmModelImport)),
+                    widget::button("Cancel")
+                        .style(widget::button::secondary)
+                        .on_press(Message::CancelModelImport),
                 ]
-                    .height(Length::Shrink)
-                    .align_y(VerticalAlign::Center)
                     .spacing(5.0),
             ]
-                .align_x(HorizontalAlign::Center)
-                .spacing(5.0)
-                .padding(5.0)
+                .spacing(10.0)
+        )
+            .width(350.0)
+            .padding(15.0)
+            .style(widget::container::rounded_box);
+
+        // block the rest of the window while the dialog is open
+        widget::opaque(
+            widget::container(widget::opaque(dialog))
+                .center(Length::Fill)
+                .style(|theme: &Theme|{
+                    let palette = theme.extended_palette();
+                    Style {
+                        background: Some(Background::Color(palette.background.base.color.scale_alpha(0.6))),
+                        ..Default::default()
+                    }
+                })
         )
-            .width(Length::Fill)
-            .into()
     }
 
-    fn model_list_view(&self)->Element<Message> {
-        let mut column_items = Vec::new();
+    /// The text options over everything else while a text model is being added or edited.
+    fn text_dialog_view(&self)->Element<Message> {
+        let Some(dialog) = &self.text_dialog else {
+            return widget::Space::new(0.0, 0.0).into();
+        };
+        let units = self.settings.borrow().units;
+        let title = match &dialog.editing {
+            Some(handle)=>format!("Edit `{}`", handle.name()),
+            None=>String::from("Add text"),
+        };
 
-        column_items.push(row![
-            widget::button(
-                text!("Load model")
-                    .center()
-                    .width(Length::Fill)
-            )
-                .width(Length::FillPortion(1))
-                .on_press(Message::OpenFilePicker),
+        let dialog_view = widget::container(
+            column![
+                text!("{title}").size(20),
 
-            widget::Space::with_width(Length::FillPortion(1)),
+                widget::text_editor(&dialog.content)
+                    .placeholder("Text")
+                    .height(100.0)
+                    .on_action(Message::TextAction),
 
-            widget::button(
-                text!("Clear models")
-                    .center()
-                    .width(Length::Fill)
-            )
-                .width(Length::FillPortion(1))
-                .style(danger_button)
-                .on_press(Message::ClearModels),
-        ].into());
+                row![
+                    "Font: ",
+                    widget::pick_list(
+                        dialog.fonts.as_slice(),
+                        dialog.font.as_ref(),
+                        Message::TextFont,
+                    ),
+                ]
+                    .align_y(VerticalAlign::Center),
 
-        column_items.push(widget::Space::with_height(10.0).into());
+                row![
+                    text!("Capital height ({}): ", units.suffix()),
+                    widget::text_input("Size", &dialog.size)
+                        .on_input(Message::TextSize),
+                ]
+                    .align_y(VerticalAlign::Center),
 
-        column_items.push(widget::pick_list(
-            [ModelPaneState::ActiveModels, ModelPaneState::AllModels],
-            Some(self.model_pane_state),
-            |state|Message::ModelPaneState(state),
-        )
-            .into());
+                row![
+                    "Line spacing: ",
+                    widget::text_input("Line spacing", &dialog.line_spacing)
+                        .on_input(Message::TextLineSpacing),
+                ]
+                    .align_y(VerticalAlign::Center),
 
-        match self.model_pane_state {
-            ModelPaneState::ActiveModels=>{
-                let active_models = &self.sheets[self.active_sheet].active_models;
+                row![
+                    widget::button(if dialog.editing.is_some() {"Save"} else {"Add"})
+                        .on_press_maybe(dialog.params(units).map(|_|Message::ConfirmText)),
+                    widget::button("Cancel")
+                        .style(widget::button::secondary)
+                        .on_press(Message::CancelText),
+                ]
+                    .spacing(5.0),
+            ]
+                .spacing(10.0)
+        )
+            .width(400.0)
+            .padding(15.0)
+            .style(widget::container::rounded_box);
 
-                // a list of active models
-                for (model, _) in active_models.iter() {
-                    column_items.push(widget::Space::with_height(10.0).into());
+        // block the rest of the window while the dialog is open
+        widget::opaque(
+            widget::container(widget::opaque(dialog_view))
+                .center(Length::Fill)
+                .style(|theme: &Theme|{
+                    let palette = theme.extended_palette();
+                    Style {
+                        background: Some(Background::Color(palette.background.base.color.scale_alpha(0.6))),
+                        ..Default::default()
+                    }
+                })
+        )
+    }
 
-                    column_items.push(widget::button(model.name())
-                        .on_press(Message::AddModel(model.clone()))
-                        .into()
-                    );
+    /// The toasts in the bottom right corner. Clicking one hides it. Prompts for entities that are
+    /// too big for the active sheet are above them until they are answered.
+    fn toast_view(&self)->Element<Message> {
+        let sheet = &self.sheets[self.active_sheet];
+        let prompts = self.fit_prompts.iter()
+            .filter(|(idx, _)|*idx == self.active_sheet)
+            .filter_map(|(idx, id)|Some((*idx, *id, sheet.oversize_fix(*id)?)))
+            .take(MAX_TOASTS)
+            .map(|(idx, id, fix)|{
+                let scale = sheet.entities[&id].1.transform.scale * fix.scale;
+                let mut buttons = row![
+                    widget::button(text!("Scale to {scale:.3}"))
+                        .on_press(Message::FitEntity(idx, id, FitChoice::Scale)),
+                ]
+                    .spacing(5.0);
+                if fix.rotate_fits {
+                    buttons = buttons.push(widget::button("Rotate 90°")
+                        .on_press(Message::FitEntity(idx, id, FitChoice::Rotate)));
                 }
-            },
-            ModelPaneState::AllModels=>{
-                let all_models = self.models.iter();
+                buttons = buttons.push(widget::button("Leave it")
+                    .style(widget::button::secondary)
+                    .on_press(Message::FitEntity(idx, id, FitChoice::Leave)));
 
-                // a list of active models
-                for handle in all_models {
-                    column_items.push(widget::Space::with_height(10.0).into());
+                widget::container(column![
+                    text!("`{}` is bigger than the sheet", sheet.entity_label(id)),
+                    buttons,
+                ].spacing(5.0))
+                    .width(300.0)
+                    .padding(10.0)
+                    .style(widget::container::rounded_box)
+                    .into()
+            });
 
-                    column_items.push(row![
-                        widget::button(widget::text(handle.name().to_string()))
-                            .on_press(Message::AddModel(handle)),
-                    ].into());
-                }
-            },
-        }
+        let toasts = prompts.chain(self.toasts.iter()
+            .filter_map(|id|self.notifications.iter().find(|n|n.id == *id))
+            .map(|notification|{
+                let style = match notification.severity {
+                    Severity::Info=>widget::button::secondary,
+                    Severity::Warning=>warning_button,
+                    Severity::Error=>danger_button,
+                };
+                widget::button(text!("{}", notification.text))
+                    .style(style)
+                    .width(300.0)
+                    .padding(10.0)
+                    .on_press(Message::DismissToast(notification.id))
+                    .into()
+            }));
 
-        widget::scrollable(
-            widget::column(column_items)
-                .padding(5.0)
+        widget::container(
+            widget::column(toasts)
+                .spacing(5.0)
         )
-            .width(Length::Fill)
+            .align_right(Length::Fill)
+            .align_bottom(Length::Fill)
+            .padding(10.0)
             .into()
     }
 
-    fn entity_params_view(&self)->Element<Message> {
-        let params = self.entity_params.as_ref().unwrap();
+    fn sheet_view(&self)->Element<Message> {
+        let sheet = self.sheets[self.active_sheet]
+            .main_view()
+            .map(|m|Message::Sheet(m));
 
-        let store = self.conditions
-            .get_store();
+        widget::container(
+            widget::stack![
+                sheet,
+                self.file_hover_view(),
+            ]
+        )
+            .width(Length::FillPortion(3))
+            .height(Length::Fill)
+            .into()
+    }
+
+    /// The sheet's keep-out zones and the inputs to add another one.
+    fn keep_out_view(&self)->Element<Message> {
+        let units = self.settings.borrow().units;
+        let sheet = &self.sheets[self.active_sheet];
+
+        let mut items: Vec<Element<Message>> = vec![text!("Keep-out zones").into()];
+        for (i, (min, max)) in sheet.keep_outs.iter().enumerate() {
+            items.push(row![
+                text!(
+                    "{}, {} to {}, {}",
+                    units.length(min.x),
+                    units.length(min.y),
+                    units.length(max.x),
+                    units.length(max.y),
+                ),
+
+                widget::Space::with_width(Length::Fill),
+
+                widget::button("Remove")
+                    .style(danger_button)
+                    .on_press(Message::RemoveKeepOut(i)),
+            ]
+                .align_y(VerticalAlign::Center)
+                .into()
+            );
+        }
+
+        let inputs = ["X1", "Y1", "X2", "Y2"].into_iter()
+            .enumerate()
+            .map(|(i, placeholder)|{
+                widget::text_input(placeholder, &self.keep_out_input[i])
+                    .on_input(move|s|Message::KeepOutInput(i, s))
+                    .into()
+            })
+            .collect::<Vec<Element<Message>>>();
+        items.push(row![
+            widget::row(inputs).spacing(2.0),
+
+            widget::button("Add")
+                .on_press_maybe(self.parse_keep_out().map(|_|Message::AddKeepOut)),
+        ]
+            .spacing(5.0)
+            .into()
+        );
+
+        widget::column(items)
+            .spacing(2.0)
+            .into()
+    }
+
+    /// Pick a condition to only show its entities.
+    fn condition_filter_view(&self)->Element<Message> {
+        let store = self.conditions.get_store();
         let store = store.borrow();
-        let conditions = store.iter()
-            .map(|c|c.display())
+
+        let choices = std::iter::once(ConditionFilterChoice::All)
+            .chain(store.iter().map(|c|ConditionFilterChoice::Only(c.display())))
             .collect::<Vec<_>>();
-        let current_condition = store.get(params.laser_condition).display();
-        drop(store);
+        let current = match self.sheets[self.active_sheet].condition_filter.and_then(|id|store.get_opt(id)) {
+            Some(condition)=>ConditionFilterChoice::Only(condition.display()),
+            None=>ConditionFilterChoice::All,
+        };
+
+        row![
+            text!("Show"),
+            widget::pick_list(
+                choices,
+                Some(current),
+                |choice|{
+                    let filter = match choice {
+                        ConditionFilterChoice::All=>None,
+                        ConditionFilterChoice::Only(c)=>Some(c.id),
+                    };
+                    Message::Sheet(SheetMessage::SetConditionFilter(filter))
+                },
+            ),
+        ]
+            .align_y(VerticalAlign::Center)
+            .spacing(5.0)
+            .into()
+    }
+
+    /// Save the current view with a name and go back to saved views.
+    fn bookmark_view(&self)->Element<Message> {
+        let sheet = &self.sheets[self.active_sheet];
+        let bookmarks = sheet.bookmarks.iter()
+            .enumerate()
+            .map(|(index, (name, _))|BookmarkIndex {
+                name: name.clone(),
+                index,
+            })
+            .collect::<Vec<_>>();
+        let save = (!self.bookmark_name.trim().is_empty())
+            .then(||Message::Sheet(SheetMessage::SaveBookmark(self.bookmark_name.trim().to_string())));
+
+        column![
+            row![
+                widget::text_input("View name", &self.bookmark_name)
+                    .on_input(Message::BookmarkName)
+                    .on_submit_maybe(save.clone()),
+
+                widget::button("Save view")
+                    .on_press_maybe(save),
+            ]
+                .spacing(5.0),
+
+            widget::pick_list(
+                bookmarks,
+                None::<BookmarkIndex>,
+                |bookmark|Message::Sheet(SheetMessage::RestoreBookmark(bookmark.index)),
+            )
+                .placeholder("Go to a saved view"),
+        ]
+            .spacing(5.0)
+            .into()
+    }
+
+    /// How much of the sheet the parts use, the cut length, and how many entities use each
+    /// condition.
+    fn sheet_stats_view(&self)->Element<Message> {
+        let stats = self.sheets[self.active_sheet].stats();
+        let units = self.settings.borrow().units;
+        let store = self.conditions.get_store();
+        let store = store.borrow();
+
+        let mut items = vec![
+            text!("Part area: {} of {}", units.area(stats.part_area), units.area(stats.sheet_area)).into(),
+            text!("Utilization: {:.1}%", stats.utilization()).into(),
+            text!("Total cut length: {}", units.length(stats.cut_length)).into(),
+        ];
+        for (id, count) in stats.condition_counts.iter() {
+            let name = store.get_opt(*id)
+                .map_or("Unknown condition", |c|c.name.as_str());
+            items.push(text!("{name}: {count}").into());
+        }
+
+        widget::column(items)
+            .spacing(2.0)
+            .into()
+    }
+
+    /// A warning with a button to select the entities that are outside the usable area. Empty if
+    /// there aren't any.
+    fn out_of_bounds_view(&self)->Element<Message> {
+        let count = self.sheets[self.active_sheet].out_of_bounds_entities().len();
+        if count == 0 {
+            return widget::Space::with_height(0.0).into();
+        }
+
+        let warning = match count {
+            1=>String::from("⚠ 1 entity is outside the usable area of the sheet"),
+            n=>format!("⚠ {n} entities are outside the usable area of the sheet"),
+        };
+
+        row![
+            text!("{warning}").style(text::danger),
+
+            widget::button("Select")
+                .on_press(Message::SelectOutOfBounds),
+        ]
+            .align_y(VerticalAlign::Center)
+            .spacing(5.0)
+            .into()
+    }
+
+    /// Shown over the sheet while files are dragged over the window.
+    fn file_hover_view(&self)->Element<Message> {
+        if self.hovered_files.is_empty() {
+            return widget::Space::new(0.0, 0.0).into();
+        }
+
+        let supported = self.hovered_files.iter()
+            .filter(|path|is_model_file(path))
+            .count();
+        let label = match supported {
+            0=>String::from("Only DXF files can be imported"),
+            1=>String::from("Drop to import 1 model"),
+            n=>format!("Drop to import {n} models"),
+        };
+
+        widget::container(text!("{label}").size(20))
+            .center(Length::Fill)
+            .style(|theme: &Theme|{
+                let palette = theme.extended_palette();
+                Style {
+                    background: Some(Background::Color(palette.primary.weak.color.scale_alpha(0.2))),
+                    text_color: Some(palette.background.base.text),
+                    ..Default::default()
+                }
+            })
+            .into()
+    }
+
+    /// A tab for each sheet with a close button, and a button to add a sheet.
+    fn sheet_tabs_view(&self)->Element<Message> {
+        let mut tabs = widget::Row::new().spacing(5.0);
+        for (i, named_sheet) in self.sheet_settings.iter().enumerate() {
+            let style = if i == self.active_sheet {
+                widget::button::primary
+            } else {
+                widget::button::secondary
+            };
+
+            tabs = tabs.push(row![
+                widget::button(text!("{}", named_sheet.name))
+                    .style(style)
+                    .on_press(Message::SelectSheet(i)),
+
+                widget::button("x")
+                    .style(danger_button)
+                    .on_press(Message::DeleteSheet(i)),
+            ]);
+        }
+        tabs = tabs.push(widget::button("+").on_press(Message::NewSheet));
+
+        if self.sheet_settings.len() > MAX_FIXED_TABS {
+            widget::scrollable(tabs)
+                .direction(widget::scrollable::Direction::Horizontal(
+                    widget::scrollable::Scrollbar::default().spacing(5.0),
+                ))
+                .width(Length::Fill)
+                .into()
+        } else {
+            tabs.wrap().into()
+        }
+    }
+
+    /// A checkbox for each sheet in the project being imported from.
+    fn sheet_import_view(&self)->Element<Message> {
+        let Some(import) = &self.sheet_import else {
+            return widget::Space::with_height(0.0).into();
+        };
+
+        let mut sheets = widget::Column::new().spacing(5.0);
+        for (i, (sheet, selected)) in import.project.sheets.iter().zip(import.selected.iter()).enumerate() {
+            sheets = sheets.push(
+                widget::checkbox(format!("{} ({} entities)", sheet.name, sheet.entities.len()), *selected)
+                    .on_toggle(move|b|Message::ToggleImportedSheet(i, b)),
+            );
+        }
+
+        column![
+            text!("Sheets in `{}`:", import.path.display()),
+
+            widget::scrollable(sheets).height(Length::Fill),
+
+            row![
+                widget::button("Import")
+                    .on_press_maybe(import.selected.contains(&true).then_some(Message::ImportSelectedSheets)),
+
+                widget::button("Cancel")
+                    .on_press(Message::CancelSheetImport),
+            ]
+                .spacing(5.0),
+        ]
+            .padding(10.0)
+            .spacing(10.0)
+            .into()
+    }
+
+    fn copy_entity_view(&self)->Element<Message> {
+        match &self.copy_entity {
+            Some((handle, _))=>column![
+                text!("Copy `{}` to:", handle.name()),
+                row![
+                    widget::pick_list(
+                        self.sheet_settings.as_slice(),
+                        None::<&SheetIndex>,
+                        |named_sheet|Message::CopyEntityToSheet(named_sheet.index),
+                    )
+                        .placeholder("Sheet"),
+
+                    widget::button("Cancel")
+                        .on_press(Message::CancelCopyEntity),
+                ]
+                    .spacing(5.0),
+            ]
+                .align_x(HorizontalAlign::Center)
+                .spacing(5.0)
+                .into(),
+            None=>widget::Space::with_height(0.0).into(),
+        }
+    }
+
+    fn gcode_progress_view(&self)->Element<Message> {
+        match self.gcode_progress {
+            Some((_, done, total))=>column![
+                text!("Generating GCODE: {done}/{total}"),
+                widget::progress_bar(0.0..=total.max(1) as f32, done as f32)
+                    .height(10.0),
+            ]
+                .align_x(HorizontalAlign::Center)
+                .spacing(5.0)
+                .into(),
+            None=>widget::Space::with_height(0.0).into(),
+        }
+    }
+
+    fn sheet_list_view(&self)->Element<Message> {
+        let machine = self.machine.borrow().clone();
+        let units = self.settings.borrow().units;
+        let selection = &self.sheets[self.active_sheet].selection;
+        let group_selection = (selection.len() >= 2)
+            .then(||Message::Sheet(SheetMessage::Group(selection.iter().copied().collect())));
+        let sheet = &self.sheets[self.active_sheet];
+        let rotary_warning: Element<Message> = if sheet.rotary_height_mismatch() {
+            text!(
+                "⚠ The height isn't the cylinder's circumference of {}",
+                units.length(std::f64::consts::PI * sheet.rotary_diameter),
+            )
+                .style(text::danger)
+                .into()
+        } else {
+            widget::Space::with_height(0.0).into()
+        };
+        let simulate_button = if self.sheets[self.active_sheet].simulation.is_empty() {
+            widget::button("Simulate")
+                .on_press(Message::SimulateGcode)
+        } else {
+            widget::button("Clear simulation")
+                .on_press(Message::Sheet(SheetMessage::SimulateGcode(Vec::new())))
+        };
 
         widget::scrollable(
             column![
+                self.sheet_tabs_view(),
+
+                self.copy_entity_view(),
+
+                row![
+                    widget::button("Open project")
+                        .on_press_maybe(self.gcode_progress.is_none().then_some(Message::OpenProjectPicker)),
+
+                    widget::button("Save project")
+                        .on_press(Message::OpenProjectSaveDialog),
+
+                    widget::button("Import sheets")
+                        .on_press(Message::OpenSheetImportPicker),
+                ].spacing(5.0),
+
+                row![
+                    widget::button("Laser condition editor")
+                        .on_press(Message::ToggleConditionEditor),
+
+                    widget::button("Settings")
+                        .on_press(Message::ToggleSettings),
+
+                    widget::button("Notifications")
+                        .on_press(Message::ToggleInfo),
+                ]
+                    .spacing(5.0),
+
                 row![
-                    text!("X: "),
+                    "Rename: ",
                     widget::text_input(
-                        "X",
-                        &params.x,
+                        "Sheet name",
+                        self.sheet_settings[self.active_sheet].name.as_str(),
                     )
-                        .on_input(Message::EntityParamsX),
+                        .on_input(|s|Message::RenameSheet(s)),
                 ],
 
                 row![
-                    text!("Y: "),
+                    text!("Width ({}): ", units.suffix()),
                     widget::text_input(
-                        "Y",
-                        &params.y,
+                        "Width",
+                        &self.sheet_size[0],
                     )
-                        .on_input(Message::EntityParamsY),
+                        .on_input(Message::ChangeSheetWidth),
                 ],
 
                 row![
-                    text!("Angle: "),
-                    column![
-                        widget::slider(
-                            0.0..=360.0,
-                            params.angle,
-                            Message::EntityParamsAngle,
-                        ).step(1.0),
-                        widget::TextInput::new(
-                            "Angle",
-                            params.angle_string.as_str(),
-                        )
-                            .on_input(Message::EntityParamsAngleString),
-                    ],
+                    text!("{} ({}): ", if sheet.rotary {"Circumference"} else {"Height"}, units.suffix()),
+                    widget::text_input(
+                        "Height",
+                        &self.sheet_size[1],
+                    )
+                        .on_input(Message::ChangeSheetHeight),
                 ],
 
                 row![
-                    text!("Scale: "),
+                    text!("Margin ({}): ", units.suffix()),
                     widget::text_input(
-                        "Scale",
-                        &params.scale,
+                        "Margin",
+                        &self.sheet_margin,
                     )
-                        .on_input(Message::EntityParamsScale),
+                        .on_input(Message::ChangeSheetMargin),
                 ],
 
                 row![
                     widget::checkbox(
-                        "Flip: ",
-                        params.flip,
+                        "Rotary axis",
+                        sheet.rotary,
                     )
-                        .on_toggle(Message::EntityParamsFlip),
+                        .on_toggle(Message::ToggleRotary),
+                    text!("Diameter ({}): ", units.suffix()),
+                    widget::text_input(
+                        "Diameter",
+                        &self.rotary_diameter,
+                    )
+                        .on_input(Message::ChangeRotaryDiameter),
+                ]
+                    .align_y(VerticalAlign::Center)
+                    .spacing(5.0),
+                rotary_warning,
+
+                row![
+                    "Material: ",
+                    widget::text_input(
+                        "Material",
+                        &self.sheets[self.active_sheet].material,
+                    )
+                        .on_input(Message::ChangeMaterial),
+                ]
+                    .align_y(VerticalAlign::Center),
+
+                row![
+                    "Scale everything: ",
+                    widget::text_input(
+                        "Scale",
+                        &self.global_scale,
+                    )
+                        .on_input(Message::ChangeGlobalScale),
                 ],
 
-                widget::pick_list(
-                    conditions,
-                    Some(current_condition),
-                    |c|Message::EntityParamsCondition(c.id),
-                ),
+                row![
+                    "Origin: ",
+                    widget::pick_list(
+                        Origin::LIST,
+                        Some(self.sheets[self.active_sheet].origin),
+                        Message::ChangeOrigin,
+                    ),
+                ],
 
-                widget::Space::with_height(25.0),
+                self.keep_out_view(),
 
-                widget::button("Delete entity")
-                    .style(danger_button)
-                    .on_press(Message::DeleteEntity),
-            ]
-                .padding(5.0)
-        )
-            .width(Length::Fill)
-            .into()
-    }
+                text!("Total cut area: {}", units.area(self.sheets[self.active_sheet].cut_area())),
+                self.sheet_stats_view(),
 
-    pub fn update(&mut self, msg: Message)->Task<Message> {
-        match msg {
-            Message::Sheet(msg)=>{
-                match msg {
-                    SheetMessage::Select(id)|SheetMessage::SelectMove(id, _)=>{
-                        if !self.sheets[self.active_sheet].reorder {
-                            let mt = &self.sheets[self.active_sheet]
-                                .entities[&id].1;
-                            let rotation = mt.transform.rotation.normalized();
-                            let mut vec = Vector::new(1.0, 0.0);
-                            rotation.rotate_vec(&mut vec);
-                            let mut angle = vec.y.atan2(vec.x).to_degrees();
-                            if angle < 0.0 {
-                                angle += 360.0;
-                            }
-                            self.entity_params = Some(EntityParams {
-                                id,
-                                x: mt.transform.translation.x.to_string(),
-                                y: mt.transform.translation.y.to_string(),
-                                angle,
-                                angle_string: angle.to_string(),
-                                scale: mt.transform.scale.to_string(),
-                                flip: mt.flip,
-                                laser_condition: mt.laser_condition,
-                            });
+                self.out_of_bounds_view(),
 
-                            self.close_entity_params();
-                            self.open_entity_params();
-                        } else {
-                            self.entity_params = None;
-                            self.close_entity_params();
-                        }
-                    },
-                    SheetMessage::Deselect(_)|SheetMessage::Delete(_)=>{
-                        self.entity_params = None;
-                        self.close_entity_params();
-                    },
-                    SheetMessage::Move(..)=>{
-                        if let Some(params) = &mut self.entity_params {
-                            let entity = self.sheets[self.active_sheet]
-                                .entities[&params.id].1;
+                widget::button("Reorder entities")
+                    .on_press(Message::Sheet(SheetMessage::StartOrder)),
 
-                            params.x = entity.transform.translation.x.to_string();
-                            params.y = entity.transform.translation.y.to_string();
-                        }
-                    },
-                    _=>{},
-                }
-                return self.sheets[self.active_sheet]
-                    .main_update(msg)
-                    .map(|m|Message::Sheet(m));
-            },
-            Message::Condition(msg)=>{
-                match msg {
-                    ConditionMessage::CloseEditor=>{
-                        self.close_condition_editor();
-                    },
-                    ConditionMessage::RecalcSheet=>{
-                        self.sheets[self.active_sheet].recalc_paths();
-                    },
-                    _=>{},
-                }
+                row![
+                    widget::button("Sort top-to-bottom")
+                        .on_press(Message::Sheet(SheetMessage::ReorderByPosition(false))),
+                    widget::button("Sort left-to-right")
+                        .on_press(Message::Sheet(SheetMessage::ReorderByPosition(true))),
+                    widget::button("Reverse order")
+                        .on_press(Message::Sheet(SheetMessage::InvertOrder)),
+                ]
+                    .spacing(5.0),
 
-                return self.conditions.update(msg).map(Message::Condition);
-            },
-            Message::RenameSheet(name)=>self.sheet_settings[self.active_sheet].name = name,
-            Message::ToggleGrblComment(b)=>self.sheets[self.active_sheet].grbl_comments = b,
-            Message::NewSheet=>{
-                self.active_sheet = self.sheets.len();
-                self.sheet_settings.push(SheetIndex {
-                    name: "New Sheet".into(),
-                    gcode: None,
-                    index: self.sheets.len(),
-                });
-                self.sheets.push(Sheet::new(self.models.clone(), self.conditions.get_store()));
+                widget::button("Group selected")
+                    .on_press_maybe(group_selection),
+
+                row![
+                    text!("Entity order visibility"),
+                    widget::toggler(self.sheets[self.active_sheet].show_order)
+                        .on_toggle(|b|Message::Sheet(SheetMessage::SetShowOrder(b)))
+                ]
+                    .align_y(VerticalAlign::Center)
+                    .spacing(5.0),
+
+                self.bookmark_view(),
+
+                self.condition_filter_view(),
+
+                row![
+                    text!("Cut direction"),
+                    widget::toggler(self.sheets[self.active_sheet].show_direction)
+                        .on_toggle(|b|Message::Sheet(SheetMessage::SetShowDirection(b)))
+                ]
+                    .align_y(VerticalAlign::Center)
+                    .spacing(5.0),
+
+                row![
+                    text!("Bounding boxes"),
+                    widget::toggler(self.sheets[self.active_sheet].show_bounding_boxes)
+                        .on_toggle(|b|Message::Sheet(SheetMessage::SetShowBoundingBoxes(b)))
+                ]
+                    .align_y(VerticalAlign::Center)
+                    .spacing(5.0),
+
+                row![
+                    column![
+                        widget::button("Save GCODE")
+                            .on_press_maybe(self.gcode_progress.is_none().then_some(Message::OpenGcodeSaveDialog)),
+
+                        widget::button("Save GCODE for selection")
+                            .on_press_maybe((self.gcode_progress.is_none() && !self.gcode_selection().is_empty())
+                                .then_some(Message::OpenSelectionGcodeSaveDialog)),
+
+                        row![
+                            widget::button("Save GCODE from #")
+                                .on_press_maybe((self.gcode_progress.is_none() && self.parse_resume_from().is_some())
+                                    .then_some(Message::OpenResumeGcodeSaveDialog)),
+                            widget::text_input("N", &self.resume_from)
+                                .on_input(Message::ChangeResumeFrom)
+                                .width(Length::Fixed(60.0)),
+                        ]
+                            .align_y(VerticalAlign::Center)
+                            .spacing(5.0),
+
+                        widget::button("Copy GCODE")
+                            .on_press(Message::CopyGcode),
+
+                        widget::button("Export image")
+                            .on_press(Message::OpenImageSaveDialog),
+
+                        simulate_button,
+                    ]
+                        .spacing(5.0),
+
+                    column![
+                        text!("Comments"),
+
+                        widget::pick_list(
+                            CommentLevel::LIST,
+                            Some(self.sheets[self.active_sheet].comment_level),
+                            Message::ChangeCommentLevel,
+                        ),
+                    ]
+                        .align_x(HorizontalAlign::Center)
+                        .spacing(5.0)
+                ]
+                    .height(Length::Shrink)
+                    .align_y(VerticalAlign::Center)
+                    .spacing(5.0),
+
+                widget::checkbox(
+                    "Blank lines between entities",
+                    self.sheets[self.active_sheet].blank_lines,
+                )
+                    .on_toggle(Message::ToggleBlankLines),
+
+                self.gcode_progress_view(),
+
+                widget::center(widget::horizontal_rule(1.0))
+                    .height(Length::Shrink),
+
+                text!("Machine profile"),
+
+                widget::checkbox(
+                    "Home at start",
+                    machine.home_at_start,
+                )
+                    .on_toggle(Message::ToggleHomeAtStart),
+
+                widget::checkbox(
+                    "Imperial GCODE (G20)",
+                    machine.imperial_gcode,
+                )
+                    .on_toggle(Message::ToggleImperialGcode),
+
+                widget::checkbox(
+                    "Sub-programs for repeated entities (not GRBL)",
+                    machine.subprograms,
+                )
+                    .on_toggle(Message::ToggleSubprograms),
+
+                widget::checkbox(
+                    "GRBL comments (short, in parentheses)",
+                    machine.grbl_comments,
+                )
+                    .on_toggle(Message::ToggleGrblComments),
+
+                row![
+                    "Homing GCODE: ",
+                    widget::text_input(
+                        "G28",
+                        machine.homing_command.as_str(),
+                    )
+                        .on_input(Message::ChangeHomingCommand),
+                ],
+
+                row![
+                    "Coordinate decimals: ",
+                    widget::pick_list(
+                        [1, 2, 3, 4, 5, 6],
+                        Some(machine.coord_precision),
+                        Message::ChangeCoordPrecision,
+                    ),
+                ]
+                    .align_y(VerticalAlign::Center),
+
+                row![
+                    "Max laser power (S): ",
+                    widget::text_input(
+                        "1000",
+                        &machine.max_power.to_string(),
+                    )
+                        .on_input(Message::ChangeMaxPower),
+                ],
+
+                row![
+                    "Power scale (S = power × scale): ",
+                    widget::text_input(
+                        "1.0",
+                        &self.s_scale,
+                    )
+                        .on_input(Message::ChangeSScale),
+                ],
+
+                row![
+                    "Travel feed (mm/min): ",
+                    widget::text_input(
+                        "Blank for G0",
+                        &machine.travel_feed.map(|feed|feed.to_string()).unwrap_or_default(),
+                    )
+                        .on_input(Message::ChangeTravelFeed),
+                ],
+
+                row![
+                    "Shortest line to cut (mm): ",
+                    widget::text_input(
+                        "0.01",
+                        &self.min_line_length,
+                    )
+                        .on_input(Message::ChangeMinLineLength),
+                ],
+            ]
+                .align_x(HorizontalAlign::Center)
+                .spacing(5.0)
+                .padding(5.0)
+        )
+            .width(Length::Fill)
+            .into()
+    }
+
+    fn model_list_view(&self)->Element<Message> {
+        let mut column_items = Vec::new();
+
+        column_items.push(row![
+            widget::button(
+                text!("Load model")
+                    .center()
+                    .width(Length::Fill)
+            )
+                .width(Length::FillPortion(1))
+                .on_press(Message::OpenFilePicker),
+
+            widget::button(
+                text!("Add text")
+                    .center()
+                    .width(Length::Fill)
+            )
+                .width(Length::FillPortion(1))
+                .on_press(Message::OpenTextDialog(None)),
+
+            widget::button(
+                text!("Clear models")
+                    .center()
+                    .width(Length::Fill)
+            )
+                .width(Length::FillPortion(1))
+                .style(danger_button)
+                .on_press(Message::ClearModels),
+        ].into());
+
+        column_items.push(widget::Space::with_height(10.0).into());
+
+        column_items.push(widget::pick_list(
+            [ModelPaneState::ActiveModels, ModelPaneState::AllModels, ModelPaneState::Entities],
+            Some(self.model_pane_state),
+            |state|Message::ModelPaneState(state),
+        )
+            .into());
+
+        column_items.push(text!(
+            "Models: {}  Entities: {}",
+            self.models.count(),
+            self.sheets[self.active_sheet].entities.len(),
+        ).into());
+
+        match self.model_pane_state {
+            ModelPaneState::ActiveModels=>{
+                let active_models = &self.sheets[self.active_sheet].active_models;
+
+                // a list of active models
+                for (model, _) in active_models.iter() {
+                    column_items.push(widget::Space::with_height(10.0).into());
+
+                    column_items.push(widget::button(text!("{} ({} segs)", model.name(), model.path_segment_count()))
+                        .on_press(Message::AddModel(model.clone()))
+                        .into()
+                    );
+                }
+            },
+            ModelPaneState::AllModels=>{
+                let all_models = self.models.iter();
+                let active_models = &self.sheets[self.active_sheet].active_models;
+                let units = self.settings.borrow().units;
+
+                // a list of active models
+                for handle in all_models {
+                    column_items.push(widget::Space::with_height(10.0).into());
+
+                    let (w, h) = handle.dimensions();
+                    let size = units.size(w, h);
+                    let segments = handle.path_segment_count();
+                    let name = match active_models.get(&handle) {
+                        Some(entities)=>format!("{} ({segments} segs) ({size}) ({} on sheet)", handle.name(), entities.len()),
+                        None=>format!("{} ({segments} segs) ({size})", handle.name()),
+                    };
+
+                    column_items.push(row![
+                        widget::button(widget::text(name))
+                            .on_press(Message::AddModel(handle.clone())),
+
+                        widget::Space::with_width(Length::Fill),
+
+                        widget::button("Replace")
+                            .on_press(Message::OpenReplaceModelPicker(handle.clone())),
+
+                        widget::button("Remove model")
+                            .style(danger_button)
+                            .on_press(Message::RemoveModel(handle)),
+                    ]
+                        .spacing(5.0)
+                        .into());
+                }
+            },
+            ModelPaneState::Entities=>{
+                column_items.push(widget::Space::with_height(10.0).into());
+                column_items.push(widget::text_input("Search", &self.entity_search)
+                    .on_input(Message::EntitySearch)
+                    .into());
+
+                for row in self.entity_list_rows() {
+                    column_items.push(widget::Space::with_height(5.0).into());
+                    column_items.push(row);
+                }
+            },
+        }
+
+        widget::scrollable(
+            widget::column(column_items)
+                .padding(5.0)
+        )
+            .width(Length::Fill)
+            .into()
+    }
+
+    /// The gaps between the two selected entities. Nothing is shown unless exactly two are
+    /// selected.
+    fn entity_gap_view(&self)->Element<Message> {
+        let sheet = &self.sheets[self.active_sheet];
+        let units = self.settings.borrow().units;
+        let &[a, b] = sheet.selection.iter().copied().collect::<Vec<_>>().as_slice() else {
+            return widget::Space::with_height(0.0).into();
+        };
+        let gap = sheet.entity_gap(a, b).unwrap_or_default();
+
+        column![
+            text!("Spacing of {} from {}", sheet.entity_label(b), sheet.entity_label(a)),
+            row![
+                text!("H gap ({}): ", units.suffix()),
+                widget::text_input(&units.format(gap.x), &self.entity_gap[0])
+                    .on_input(Message::ChangeEntityGapH)
+                    .on_submit(Message::ApplyEntityGap),
+            ]
+                .align_y(VerticalAlign::Center),
+            row![
+                text!("V gap ({}): ", units.suffix()),
+                widget::text_input(&units.format(gap.y), &self.entity_gap[1])
+                    .on_input(Message::ChangeEntityGapV)
+                    .on_submit(Message::ApplyEntityGap),
+            ]
+                .align_y(VerticalAlign::Center),
+            widget::button("Apply spacing")
+                .on_press(Message::ApplyEntityGap),
+        ]
+            .spacing(5.0)
+            .into()
+    }
+
+    /// Make a new model from copies of the selected entity's contours in a grid or around a
+    /// circle.
+    fn entity_pattern_view(&self)->Element<Message> {
+        let units = self.settings.borrow().units;
+        let labels = match self.pattern_kind {
+            PatternKind::Linear=>vec![
+                String::from("Columns"),
+                String::from("Rows"),
+                format!("X spacing ({})", units.suffix()),
+                format!("Y spacing ({})", units.suffix()),
+            ],
+            PatternKind::Polar=>vec![
+                String::from("Count"),
+                format!("Diameter ({})", units.suffix()),
+            ],
+        };
+
+        let inputs = labels.into_iter()
+            .enumerate()
+            .map(|(i, label)|{
+                row![
+                    text!("{label}: "),
+                    widget::text_input(&label, &self.pattern_input[i])
+                        .on_input(move|s|Message::PatternInput(i, s)),
+                ]
+                    .align_y(VerticalAlign::Center)
+                    .into()
+            })
+            .collect::<Vec<Element<Message>>>();
+
+        column![
+            row![
+                "Pattern: ",
+                widget::pick_list(
+                    PatternKind::LIST,
+                    Some(self.pattern_kind),
+                    Message::ChangePatternKind,
+                ),
+            ]
+                .align_y(VerticalAlign::Center),
+            widget::column(inputs).spacing(5.0),
+            widget::button("Save pattern")
+                .on_press_maybe(self.parse_pattern().map(|_|Message::OpenPatternSaveDialog)),
+        ]
+            .spacing(5.0)
+            .into()
+    }
+
+    fn entity_params_view(&self)->Element<Message> {
+        let params = self.entity_params.as_ref().unwrap();
+        let units = self.settings.borrow().units;
+
+        let default_condition = self.conditions.default_condition_opt();
+        let store = self.conditions
+            .get_store();
+        let store = store.borrow();
+        let conditions = store.iter()
+            .map(|c|c.display())
+            .collect::<Vec<_>>();
+        // nothing is picked when the store is empty
+        let current_condition = store.get_opt(params.laser_condition)
+            .or_else(||default_condition.and_then(|id|store.get_opt(id)))
+            .map(|c|c.display());
+        let next_condition = store.next_id(params.laser_condition)
+            .filter(|id|*id != params.laser_condition);
+        let sheet = &self.sheets[self.active_sheet];
+        let est_time = sheet.entities
+            .get(&params.id)
+            .map(|(_, mt)|store.get_or_default(mt.laser_condition).estimate_cut_time(sheet.path_length_for_entity(params.id)))
+            .unwrap_or(0.0);
+        drop(store);
+
+        let group = self.sheets[self.active_sheet]
+            .group_of(params.id)
+            .map(|g|g.id);
+        let text_model = sheet.entities
+            .get(&params.id)
+            .map(|(handle, _)|handle.clone())
+            .filter(|handle|handle.text.is_some());
+
+        widget::scrollable(
+            column![
+                row![
+                    "Label: ",
+                    widget::text_input(
+                        "Label",
+                        &params.label,
+                    )
+                        .on_input(Message::EntityParamsLabel),
+                ],
+
+                row![
+                    text!("X ({}): ", units.suffix()),
+                    widget::text_input(
+                        "X",
+                        &params.x,
+                    )
+                        .on_input(Message::EntityParamsX),
+                ],
+
+                row![
+                    text!("Y ({}): ", units.suffix()),
+                    widget::text_input(
+                        "Y",
+                        &params.y,
+                    )
+                        .on_input(Message::EntityParamsY),
+                ],
+
+                row![
+                    text!("Angle: "),
+                    column![
+                        widget::slider(
+                            0.0..=360.0,
+                            params.angle,
+                            Message::EntityParamsAngle,
+                        ).step(1.0),
+                        widget::TextInput::new(
+                            "Angle",
+                            params.angle_string.as_str(),
+                        )
+                            .on_input(Message::EntityParamsAngleString),
+                    ],
+                ],
+
+                row![
+                    text!("Scale: "),
+                    widget::text_input(
+                        "Scale",
+                        &params.scale,
+                    )
+                        .on_input(Message::EntityParamsScale),
+                ],
+
+                row![
+                    widget::checkbox(
+                        "Flip: ",
+                        params.flip,
+                    )
+                        .on_toggle(Message::EntityParamsFlip),
+                ],
+
+                row![
+                    widget::pick_list(
+                        conditions,
+                        current_condition,
+                        |c|Message::EntityParamsCondition(c.id),
+                    ),
+
+                    widget::button("Next condition")
+                        .on_press_maybe(next_condition.map(Message::EntityParamsCondition)),
+                ]
+                    .spacing(5.0),
+
+                text!("Path length: {}", units.length(sheet.path_length_for_entity(params.id))),
+
+                text!("Est. time: {est_time:.1}s"),
+
+                widget::Space::with_height(25.0),
+
+                widget::button("Edit text")
+                    .on_press_maybe(text_model.map(|handle|Message::OpenTextDialog(Some(handle)))),
+
+                self.entity_gap_view(),
+
+                widget::Space::with_height(25.0),
+
+                self.entity_pattern_view(),
+
+                widget::Space::with_height(25.0),
+
+                row![
+                    widget::button("Center on sheet")
+                        .on_press(Message::Sheet(SheetMessage::MoveToCenter(params.id))),
+                    widget::button("Fit to sheet")
+                        .on_press(Message::Sheet(SheetMessage::FitToSheet(params.id))),
+                ]
+                    .spacing(5.0),
+
+                widget::Space::with_height(25.0),
+
+                widget::button("Ungroup")
+                    .on_press_maybe(group.map(|id|Message::Sheet(SheetMessage::Ungroup(id)))),
+
+                widget::Space::with_height(25.0),
+
+                widget::button("Delete entity")
+                    .style(danger_button)
+                    .on_press(Message::DeleteEntity),
+            ]
+                .padding(5.0)
+        )
+            .width(Length::Fill)
+            .into()
+    }
+
+    fn settings_view(&self)->Element<Message> {
+        let settings = self.settings.borrow().clone();
+
+        widget::scrollable(
+            column![
+                row![
+                    "Theme: ",
+                    widget::pick_list(
+                        AppTheme::LIST,
+                        Some(settings.theme),
+                        Message::SettingsTheme,
+                    ),
+                ]
+                    .align_y(VerticalAlign::Center),
+
+                row![
+                    "Measurement units: ",
+                    widget::pick_list(
+                        Units::LIST,
+                        Some(settings.units),
+                        Message::SettingsUnits,
+                    ),
+                ]
+                    .align_y(VerticalAlign::Center),
+
+                text!("Zoom step: {:.0}%", settings.zoom_step * 100.0),
+                widget::slider(
+                    0.01..=0.5,
+                    settings.zoom_step,
+                    Message::SettingsZoomStep,
+                ).step(0.01),
+
+                text!("Arrow key nudge: {:.1} pixels", settings.nudge_distance),
+                widget::slider(
+                    0.1..=20.0,
+                    settings.nudge_distance,
+                    Message::SettingsNudgeDistance,
+                ).step(0.1),
+
+                widget::checkbox(
+                    "Snap to other entities and the sheet edges while dragging. Hold Alt to move freely",
+                    settings.entity_snap,
+                )
+                    .on_toggle(Message::SettingsEntitySnap),
+
+                text!("Snap distance: {:.0} pixels", settings.snap_distance),
+                widget::slider(
+                    1.0..=20.0,
+                    settings.snap_distance,
+                    Message::SettingsSnapDistance,
+                ).step(1.0),
+
+                text!("Exported PNG resolution: {:.0} DPI", settings.image_dpi),
+                widget::slider(
+                    25.0..=600.0,
+                    settings.image_dpi,
+                    Message::SettingsImageDpi,
+                ).step(25.0),
+
+                text!("GCODE file name"),
+                widget::text_input("{sheet}", &settings.gcode_file_name)
+                    .on_input(Message::SettingsGcodeFileName),
+                text!("{{sheet}}, {{date}}, {{material}}, and {{condition}} are filled in").size(12.0),
+
+                widget::checkbox(
+                    "Ask how to import DXF files",
+                    settings.ask_import_options,
+                )
+                    .on_toggle(Message::SettingsAskImportOptions),
+
+                text!("Warn about DXF features smaller than {}", settings.units.length(settings.min_feature_size)),
+                widget::slider(
+                    0.0..=2.0,
+                    settings.min_feature_size,
+                    Message::SettingsMinFeatureSize,
+                ).step(0.01),
+
+                text!("Ask before fitting to the sheet scales up more than {:.1}x", settings.max_fit_scale),
+                widget::slider(
+                    1.0..=10.0,
+                    settings.max_fit_scale,
+                    Message::SettingsMaxFitScale,
+                ).step(0.1),
+
+                widget::Space::with_height(10.0),
+
+                self.canvas_style_view(settings.canvas),
+
+                widget::Space::with_height(25.0),
+
+                widget::button("Close settings")
+                    .on_press(Message::ToggleSettings),
+            ]
+                .spacing(5.0)
+                .padding(5.0)
+        )
+            .width(Length::Fill)
+            .into()
+    }
+
+    fn canvas_style_view(&self, style: CanvasStyle)->Element<Message> {
+        let width_units = if style.widths_in_mm {"mm"} else {"pixels"};
+
+        column![
+            text!("Canvas").size(20.0),
+
+            color_setting("Background", style.background, [0, 0, 0], move|background|{
+                Message::SettingsCanvas(CanvasStyle {background, ..style})
+            }),
+
+            color_setting("Sheet outline", style.sheet_color, [255, 255, 255], move|sheet_color|{
+                Message::SettingsCanvas(CanvasStyle {sheet_color, ..style})
+            }),
+
+            widget::checkbox(
+                "Line widths in mm instead of screen pixels",
+                style.widths_in_mm,
+            )
+                .on_toggle(move|widths_in_mm|Message::SettingsCanvas(CanvasStyle {widths_in_mm, ..style})),
+
+            text!("Sheet outline width: {:.1} {width_units}", style.sheet_width),
+            widget::slider(
+                0.5..=10.0,
+                style.sheet_width,
+                move|sheet_width|Message::SettingsCanvas(CanvasStyle {sheet_width, ..style}),
+            ).step(0.5),
+
+            text!("Entity line width: {:.1} {width_units}", style.entity_width),
+            widget::slider(
+                0.5..=10.0,
+                style.entity_width,
+                move|entity_width|Message::SettingsCanvas(CanvasStyle {entity_width, ..style}),
+            ).step(0.5),
+
+            text!("Selected outline width: {:.1} {width_units}", style.selected_width),
+            widget::slider(
+                0.5..=10.0,
+                style.selected_width,
+                move|selected_width|Message::SettingsCanvas(CanvasStyle {selected_width, ..style}),
+            ).step(0.5),
+
+            text!("Order number size: {:.0}", style.order_text_size),
+            widget::slider(
+                8.0..=48.0,
+                style.order_text_size,
+                move|order_text_size|Message::SettingsCanvas(CanvasStyle {order_text_size, ..style}),
+            ).step(1.0),
+
+            widget::button("Reset canvas style")
+                .on_press(Message::SettingsCanvas(CanvasStyle::default())),
+        ]
+            .spacing(5.0)
+            .into()
+    }
+
+    fn info_view(&self)->Element<Message> {
+        let mut column_items = Vec::new();
+
+        for notification in self.notifications.iter() {
+            let style = match notification.severity {
+                Severity::Info=>widget::text::default,
+                Severity::Warning=>warning_text,
+                Severity::Error=>widget::text::danger,
+            };
+            column_items.push(
+                text!("[{}] {}", notification.timestamp(), notification.text)
+                    .style(style)
+                    .into()
+            );
+        }
+
+        column![
+            widget::scrollable(
+                widget::column(column_items)
+                    .spacing(5.0)
+                    .padding(5.0)
+            )
+                .width(Length::Fill)
+                .height(Length::Fill),
+
+            widget::button("Clear")
+                .on_press(Message::DismissInfo),
+        ]
+            .align_x(HorizontalAlign::Center)
+            .padding(5.0)
+            .into()
+    }
+
+    pub fn update(&mut self, msg: Message)->Task<Message> {
+        // The entity might have been deleted since the params pane was opened.
+        if msg.is_entity_params() && !self.check_entity_params() {
+            return Task::none();
+        }
+
+        match msg {
+            Message::Sheet(msg)=>{
+                match msg {
+                    SheetMessage::Select(id)|SheetMessage::SelectEntity(id)|SheetMessage::SelectMove(id, _)|SheetMessage::Focus(id)=>{
+                        if !self.sheets[self.active_sheet].reorder {
+                            let sheet = &self.sheets[self.active_sheet];
+                            let mt = &sheet.entities[&id].1;
+                            let pos = sheet.entity_position(id).unwrap_or_default();
+                            let angle = entity_angle(mt);
+                            let units = self.settings.borrow().units;
+                            self.entity_params = Some(EntityParams {
+                                id,
+                                label: sheet.entity_label(id).to_string(),
+                                x: units.format(pos.x),
+                                y: units.format(pos.y),
+                                angle,
+                                angle_string: angle.to_string(),
+                                scale: mt.transform.scale.to_string(),
+                                flip: mt.flip,
+                                laser_condition: mt.laser_condition,
+                            });
+
+                            // a single click only selects. The settings open on a double-click.
+                            if let SheetMessage::Focus(_) = msg {
+                                self.close_entity_params();
+                                self.open_entity_params();
+                            }
+                        } else {
+                            self.entity_params = None;
+                            self.close_entity_params();
+                        }
+                    },
+                    SheetMessage::Deselect(_)|SheetMessage::Delete(_)=>{
+                        self.entity_params = None;
+                        self.close_entity_params();
+                    },
+                    SheetMessage::CopyToSheet(id)=>{
+                        self.copy_entity = self.sheets[self.active_sheet].entities.get(&id).cloned();
+                    },
+                    SheetMessage::FitToSheet(id)=>return self.fit_to_sheet(self.active_sheet, id, false),
+                    SheetMessage::SaveBookmark(_)=>self.bookmark_name.clear(),
+                    _=>{},
+                }
+                let moved = matches!(msg, SheetMessage::Move(..)|SheetMessage::MoveToCenter(_));
+                let task = self.sheets[self.active_sheet]
+                    .main_update(msg)
+                    .map(|m|Message::Sheet(m));
+
+                if moved {
+                    self.format_entity_position();
+                }
+
+                return task;
+            },
+            Message::Condition(msg)=>{
+                match msg {
+                    ConditionMessage::CloseEditor=>{
+                        self.close_condition_editor();
+                    },
+                    ConditionMessage::Notify(severity, text)=>return self.notify(severity, text),
+                    // the shortcut only undoes condition changes while the editor is open
+                    ConditionMessage::UndoCondition if !self.condition_editor_open()=>return Task::none(),
+                    ConditionMessage::RecalcSheet(id)=>{
+                        // conditions are shared by every sheet, so the other sheets would keep the
+                        // old color until they are changed
+                        for sheet in self.sheets.iter_mut() {
+                            sheet.recalc_paths_condition(id);
+                        }
+                    },
+                    ConditionMessage::GenerateCalibration=>{
+                        let gcode = self.conditions
+                            .calibration_gcode(&self.machine.borrow());
+                        let Some(gcode) = gcode else {
+                            return self.notify(Severity::Error, "Invalid test burn parameters");
+                        };
+                        self.calibration_gcode = Some(gcode);
+
+                        let future = self.gcode_dialog("Save test burn GCODE file")
+                            .set_file_name("test_burn.gcode")
+                            .save_file();
+                        return Task::perform(future, Message::SaveCalibration);
+                    },
+                    ConditionMessage::GenerateTestBurn(id)=>{
+                        let gcode = self.sheets[self.active_sheet].generate_test_burn_gcode(id);
+                        self.calibration_gcode = Some(gcode);
+
+                        let future = self.gcode_dialog("Save test burn GCODE file")
+                            .set_file_name("test_burn_squares.gcode")
+                            .save_file();
+                        return Task::perform(future, Message::SaveCalibration);
+                    },
+                    _=>{},
+                }
+
+                return self.conditions.update(msg).map(Message::Condition);
+            },
+            Message::SelectOutOfBounds=>{
+                let sheet = &mut self.sheets[self.active_sheet];
+                let ids = sheet.out_of_bounds_entities();
+
+                return sheet.main_update(SheetMessage::SelectMultiple(ids)).map(Message::Sheet);
+            },
+            Message::RenameSheet(name)=>self.sheet_settings[self.active_sheet].name = name,
+            Message::BookmarkName(name)=>self.bookmark_name = name,
+            Message::ChangeCommentLevel(level)=>self.sheets[self.active_sheet].comment_level = level,
+            Message::ToggleBlankLines(b)=>self.sheets[self.active_sheet].blank_lines = b,
+            Message::ToggleHomeAtStart(b)=>self.machine.borrow_mut().home_at_start = b,
+            Message::ToggleImperialGcode(b)=>self.machine.borrow_mut().imperial_gcode = b,
+            Message::ToggleSubprograms(b)=>self.machine.borrow_mut().subprograms = b,
+            Message::ToggleGrblComments(b)=>self.machine.borrow_mut().grbl_comments = b,
+            Message::ChangeHomingCommand(s)=>self.machine.borrow_mut().homing_command = s,
+            Message::ChangeCoordPrecision(n)=>self.machine.borrow_mut().coord_precision = n,
+            Message::ChangeMaxPower(s)=>if let Some(n) = parse_u16(&s) {
+                self.machine.borrow_mut().max_power = n.max(1);
+            },
+            Message::ChangeTravelFeed(s)=>if let Some(n) = parse_u16(&s) {
+                // 0 (or a blank box) goes back to G0
+                self.machine.borrow_mut().travel_feed = Some(n).filter(|n|*n > 0);
+            },
+            Message::ChangeSScale(s)=>{
+                // keep what was typed even if it isn't valid yet, like `0.`
+                if let Some(f) = s.parse::<f64>().ok().filter(|f|*f > 0.0) {
+                    self.machine.borrow_mut().s_scale = f;
+                }
+                self.s_scale = s;
+            },
+            Message::ChangeMinLineLength(s)=>if let Some(f) = parse_float(&s).filter(|f|*f >= 0.0) {
+                self.min_line_length = s;
+                self.machine.borrow_mut().min_line_length = f;
+            },
+            Message::NewSheet=>{
+                self.active_sheet = self.sheets.len();
+                self.sheet_settings.push(SheetIndex {
+                    name: "New Sheet".into(),
+                    gcode: None,
+                    index: self.sheets.len(),
+                });
+                self.sheets.push(Sheet::new(self.models.clone(), self.conditions.get_store(), self.machine.clone(), self.settings.clone()));
+
+                self.entity_params = None;
+                self.close_entity_params();
+
+                self.format_sheet_size();
+            },
+            Message::DeleteSheet(idx)=>{
+                if idx >= self.sheets.len() {
+                    eprintln!("Tried to delete sheet {idx}, but it doesn't exist");
+                    return Task::none();
+                }
+
+                // ensure there is at least 1 sheet so we don't have errors
+                if self.sheets.len() == 1 {
+                    self.sheets.clear();
+                    self.sheet_settings.clear();
+
+                    self.sheet_settings.push(SheetIndex {
+                        name: "New Sheet".into(),
+                        gcode: None,
+                        index: self.sheets.len(),
+                    });
+                    self.sheets.push(Sheet::new(self.models.clone(), self.conditions.get_store(), self.machine.clone(), self.settings.clone()));
+                } else {
+                    self.sheets.remove(idx);
+                    self.sheet_settings.remove(idx);
+                    for (i, named_sheet) in self.sheet_settings.iter_mut().enumerate() {
+                        named_sheet.index = i;
+                    }
+
+                    // keep the same sheet open if it's still there
+                    if self.active_sheet > idx || self.active_sheet == self.sheets.len() {
+                        self.active_sheet -= 1;
+                    }
+                }
+
+                // the sheet indices changed, so stop tracking the progress of the running job
+                self.gcode_progress = None;
+                self.fit_prompts.retain(|(i, _)|*i != idx);
+                for (i, _) in self.fit_prompts.iter_mut() {
+                    if *i > idx {
+                        *i -= 1;
+                    }
+                }
+
+                self.entity_params = None;
+                self.close_entity_params();
+
+                self.format_sheet_size();
+            },
+            Message::SelectSheet(idx)=>{
+                self.active_sheet = idx;
+
+                // the entity is on the other sheet
+                self.entity_params = None;
+                self.close_entity_params();
+
+                self.format_sheet_size();
+            },
+            Message::CopyEntityToSheet(idx)=>{
+                let Some((handle, mt)) = self.copy_entity.take() else {
+                    return Task::none();
+                };
+                match self.sheets.get_mut(idx) {
+                    Some(sheet)=>{
+                        sheet.add_model_from_handle_with_transform(handle, mt, 1);
+                    },
+                    None=>eprintln!("Tried to copy to sheet {idx}, but it doesn't exist"),
+                }
+            },
+            Message::CancelCopyEntity=>self.copy_entity = None,
+            Message::ResizePane(event)=>self.panes.resize(event.split, event.ratio),
+            Message::DragPane(event)=>{
+                if let DragEvent::Dropped{pane, target} = event {
+                    self.panes.drop(pane, target);
+                }
+            },
+            Message::ToggleMaximizeSheet=>{
+                if self.panes.maximized().is_some() {
+                    self.panes.restore();
+                } else {
+                    let pane = self.panes.iter()
+                        .map(|(p,s)|(*p,*s))
+                        .find(|(_,state)|matches!(state, ProgramPane::Sheet|ProgramPane::ConditionEditor|ProgramPane::Settings|ProgramPane::SheetImportDialog));
+                    if let Some((pane, _)) = pane {
+                        self.panes.maximize(pane);
+                    }
+                }
+            },
+            Message::AddModel(handle)=>{
+                let ids = self.sheets[self.active_sheet]
+                    .add_model_from_handle(handle, 1, self.conditions.default_condition());
+                return self.prompt_oversized(ids, true);
+            },
+            Message::OpenReplaceModelPicker(handle)=>{
+                let future = AsyncFileDialog::new()
+                    .add_filter("DXF Files", MODEL_EXTENSIONS)
+                    .set_title(format!("Replace `{}`", handle.name()))
+                    .pick_file();
+                return Task::perform(future, move|file|Message::ReplaceModel(handle.clone(), file));
+            },
+            Message::ReplaceModel(handle, opt_file)=>if let Some(file) = opt_file {
+                let new_handle = match self.models.replace(&handle, file.path()) {
+                    Ok(new_handle)=>new_handle,
+                    Err(e)=>{
+                        return self.notify(Severity::Error, format!("Could not load `{}`: {e}", file.path().display()));
+                    },
+                };
+
+                for sheet in self.sheets.iter_mut() {
+                    sheet.replace_model(new_handle.clone());
+                }
+                if let Some((copy, _)) = &mut self.copy_entity {
+                    if *copy == new_handle {
+                        *copy = new_handle.clone();
+                    }
+                }
+
+                let mut tasks = Vec::new();
+                for warning in new_handle.warnings.iter() {
+                    tasks.push(self.notify(Severity::Error, format!("Model `{}`: {warning}", new_handle.name())));
+                }
+                tasks.push(self.notify(Severity::Info, format!("Replaced `{}` with `{}`", handle.name(), file.path().display())));
+
+                return Task::batch(tasks);
+            },
+            Message::ModelPaneState(state)=>self.model_pane_state = state,
+            Message::EntitySearch(search)=>self.entity_search = search,
+            Message::OpenFilePicker=>{
+                let future = AsyncFileDialog::new()
+                    .add_filter("DXF Files", MODEL_EXTENSIONS)
+                    .set_title("Load DXF files")
+                    .pick_files();
+                return Task::perform(future,Message::LoadModel);
+            },
+            Message::LoadModel(opt_files)=>if let Some(files) = opt_files {
+                let paths = files.iter()
+                    .map(|file|file.path().to_path_buf())
+                    .collect();
+                return self.import_models(paths);
+            },
+            Message::ImportUnits(units)=>if let Some(import) = &mut self.model_import {
+                import.options.units = units;
+            },
+            Message::ImportScale(s)=>if let Some(import) = &mut self.model_import {
+                if let Some(f) = s.parse::<f64>().ok().filter(|f|*f > 0.0) {
+                    import.options.scale = f;
+                }
+                import.scale = s;
+            },
+            Message::ImportArcTolerance(s)=>if let Some(import) = &mut self.model_import {
+                if let Some(f) = s.parse::<f64>().ok().filter(|f|*f > 0.0) {
+                    import.options.arc_tolerance = f;
+                }
+                import.arc_tolerance = s;
+            },
+            Message::ImportAddToSheet(b)=>if let Some(import) = &mut self.model_import {
+                import.options.add_to_sheet = b;
+            },
+            Message::ImportRemember(b)=>if let Some(import) = &mut self.model_import {
+                import.remember = b;
+            },
+            Message::ImportDontAsk(b)=>if let Some(import) = &mut self.model_import {
+                import.dont_ask = b;
+            },
+            Message::ConfirmModelImport=>if let Some(import) = self.model_import.take() {
+                // not asking again only makes sense if the choices are kept
+                if import.remember || import.dont_ask {
+                    let mut settings = self.settings.borrow_mut();
+                    settings.import_options = import.options;
+                    settings.ask_import_options = !import.dont_ask;
+                    settings.save();
+                }
+
+                let tasks = import.paths.iter()
+                    .map(|path|self.load_model_file(path, import.options))
+                    .collect::<Vec<_>>();
+                return Task::batch(tasks);
+            },
+            Message::CancelModelImport=>self.model_import = None,
+            Message::OpenTextDialog(editing)=>{
+                let units = self.settings.borrow().units;
+                self.text_dialog = Some(TextDialog::new(editing, units));
+            },
+            Message::TextAction(action)=>if let Some(dialog) = &mut self.text_dialog {
+                dialog.content.perform(action);
+            },
+            Message::TextFont(font)=>if let Some(dialog) = &mut self.text_dialog {
+                dialog.font = Some(font);
+            },
+            Message::TextSize(s)=>if let Some(dialog) = &mut self.text_dialog {
+                dialog.size = s;
+            },
+            Message::TextLineSpacing(s)=>if let Some(dialog) = &mut self.text_dialog {
+                dialog.line_spacing = s;
+            },
+            Message::ConfirmText=>{
+                let units = self.settings.borrow().units;
+                let Some(params) = self.text_dialog.as_ref().and_then(|dialog|dialog.params(units)) else {
+                    return Task::none();
+                };
+                let editing = self.text_dialog.take().and_then(|dialog|dialog.editing);
+
+                let result = match &editing {
+                    Some(handle)=>self.models.replace_text(handle, &params),
+                    None=>self.models.add_text(&params),
+                };
+                let handle = match result {
+                    Ok(handle)=>handle,
+                    Err(e)=>return self.notify(Severity::Error, format!("Could not make the text `{}`: {e}", params.name())),
+                };
+
+                let mut tasks = Vec::new();
+                for warning in handle.warnings.iter() {
+                    tasks.push(self.notify(Severity::Error, format!("Model `{}`: {warning}", handle.name())));
+                }
+                if editing.is_some() {
+                    // the entities keep their transforms
+                    for sheet in self.sheets.iter_mut() {
+                        sheet.replace_model(handle.clone());
+                    }
+                    if let Some((copy, _)) = &mut self.copy_entity {
+                        if *copy == handle {
+                            *copy = handle.clone();
+                        }
+                    }
+                } else {
+                    let ids = self.sheets[self.active_sheet]
+                        .add_model_from_handle(handle, 1, self.conditions.default_condition());
+                    tasks.push(self.prompt_oversized(ids, true));
+                }
+
+                return Task::batch(tasks);
+            },
+            Message::CancelText=>self.text_dialog = None,
+            Message::EntityParamsLabel(val)=>{
+                let Some(params) = self.entity_params
+                    .as_mut() else {return Task::none()};
+
+                params.label = val.clone();
+                self.sheets[self.active_sheet].set_entity_label(params.id, val);
+            },
+            Message::EntityParamsX(val)=>{
+                let units = self.settings.borrow().units;
+                if let Some(f) = units.parse(&val) {
+                    let Some(params) = self.entity_params
+                        .as_mut() else {return Task::none()};
+
+                    params.x = val;
+                    let sheet = &mut self.sheets[self.active_sheet];
+                    if let Some(pos) = sheet.entity_position(params.id) {
+                        sheet.set_entity_position(params.id, Point::new(f, pos.y));
+                    }
+                }
+            },
+            Message::EntityParamsY(val)=>{
+                let units = self.settings.borrow().units;
+                if let Some(f) = units.parse(&val) {
+                    let Some(params) = self.entity_params
+                        .as_mut() else {return Task::none()};
+
+                    params.y = val;
+                    let sheet = &mut self.sheets[self.active_sheet];
+                    if let Some(pos) = sheet.entity_position(params.id) {
+                        sheet.set_entity_position(params.id, Point::new(pos.x, f));
+                    }
+                }
+            },
+            Message::EntityParamsAngle(val)=>{
+                let Some(params) = self.entity_params
+                    .as_mut() else {return Task::none()};
+
+                params.angle = val;
+                params.angle_string = val.to_string();
+                self.sheets[self.active_sheet].set_entity_angle(params.id, val.to_radians());
+            },
+            Message::EntityParamsAngleString(val)=>{
+                if let Some(f) = parse_float(&val) {
+                    let Some(params) = self.entity_params
+                        .as_mut() else {return Task::none()};
+
+                    params.angle = f;
+                    params.angle_string = val;
+                    self.sheets[self.active_sheet].set_entity_angle(params.id, f.to_radians());
+                }
+            },
+            Message::EntityParamsScale(val)=>{
+                if let Some(f) = parse_float(&val) {
+                    let Some(params) = self.entity_params
+                        .as_mut() else {return Task::none()};
+
+                    // a scale of 0 can't be undone for the rest of the group, so we ignore it
+                    if val.len() > 0 && f > 0.0 {
+                        let sheet = &mut self.sheets[self.active_sheet];
+                        let scale = sheet.entities[&params.id].1.transform.scale;
+                        sheet.rotate_scale_entity(params.id, Rotation::identity(), f / scale);
+                    }
+
+                    params.scale = val;
+                }
+            },
+            Message::EntityParamsFlip(val)=>{
+                let Some(params) = self.entity_params
+                    .as_mut() else {return Task::none()};
+
+                params.flip = val;
+                self.sheets[self.active_sheet]
+                    .entities.get_mut(&params.id)
+                    .unwrap().1
+                    .flip = val;
+
+                self.sheets[self.active_sheet].recalc_paths_id(params.id);
+            },
+            Message::EntityParamsCondition(id)=>{
+                let Some(params) = self.entity_params
+                    .as_mut() else {return Task::none()};
+
+                params.laser_condition = id;
+                self.sheets[self.active_sheet]
+                    .entities.get_mut(&params.id)
+                    .unwrap().1
+                    .laser_condition = id;
+
+                self.sheets[self.active_sheet].recalc_paths_id(params.id);
+            },
+            Message::DeleteEntity=>{
+                let Some(params) = self.entity_params
+                    .as_mut() else {return Task::none()};
+
+                self.sheets[self.active_sheet]
+                    .delete_entity(params.id);
+
+                self.entity_params = None;
+                self.close_entity_params();
+            },
+            Message::ChangeSheetWidth(val)=>{
+                let units = self.settings.borrow().units;
+                if let Some(f) = units.parse(&val) {
+                    self.sheet_size[0] = val;
+
+                    let before = self.oversized_entities();
+                    self.sheets[self.active_sheet].change_width(f);
+                    return self.prompt_oversized(self.newly_oversized(before), false);
+                }
+            },
+            Message::ChangeSheetHeight(val)=>{
+                let units = self.settings.borrow().units;
+                if let Some(f) = units.parse(&val) {
+                    self.sheet_size[1] = val;
+
+                    let before = self.oversized_entities();
+                    self.sheets[self.active_sheet].change_height(f);
+                    return self.prompt_oversized(self.newly_oversized(before), false);
+                }
+            },
+            Message::ChangeSheetMargin(val)=>{
+                let units = self.settings.borrow().units;
+                if let Some(f) = units.parse(&val) {
+                    self.sheet_margin = val;
+
+                    self.sheets[self.active_sheet].change_margin(f);
+                }
+            },
+            Message::ChangeMaterial(material)=>self.sheets[self.active_sheet].material = material,
+            Message::ToggleRotary(rotary)=>{
+                let sheet = &mut self.sheets[self.active_sheet];
+                sheet.set_rotary(rotary, sheet.rotary_diameter);
+                self.format_sheet_size();
+            },
+            Message::ChangeRotaryDiameter(val)=>{
+                let units = self.settings.borrow().units;
+                if let Some(f) = units.parse(&val).filter(|f|*f > 0.0) {
+                    let sheet = &mut self.sheets[self.active_sheet];
+                    sheet.set_rotary(sheet.rotary, f);
+                    self.format_sheet_size();
+                }
+                self.rotary_diameter = val;
+            },
+            Message::ChangeGlobalScale(val)=>{
+                if let Some(f) = parse_float(&val) {
+                    self.global_scale = val;
+
+                    let sheet = &mut self.sheets[self.active_sheet];
+                    sheet.set_global_scale(f);
+
+                    // the selected entity was scaled and moved too
+                    if let Some(params) = &mut self.entity_params {
+                        params.scale = sheet.entities[&params.id].1.transform.scale.to_string();
+                    }
+                    self.format_entity_position();
+                }
+            },
+            Message::ChangeOrigin(origin)=>{
+                self.sheets[self.active_sheet].set_origin(origin);
+                self.format_entity_position();
+            },
+            Message::KeepOutInput(i, val)=>self.keep_out_input[i] = val,
+            Message::AddKeepOut=>if let Some([x1, y1, x2, y2]) = self.parse_keep_out() {
+                self.sheets[self.active_sheet].add_keep_out(Point::new(x1, y1), Point::new(x2, y2));
+                self.keep_out_input = Default::default();
+            },
+            Message::RemoveKeepOut(i)=>self.sheets[self.active_sheet].remove_keep_out(i),
+            Message::SaveGcode(idx, range, opt_file)=>{
+                let Some(file) = opt_file else {return Task::none()};
+
+                let path = self.gcode_file_path(&file);
+                // the dialog only asked about the path without the extension
+                if path != file.path() && path.exists() {
+                    return Task::perform(confirm_overwrite(&path), move|yes|Message::OverwriteGcode(idx, range.clone(), path.clone(), yes));
+                }
+                return self.save_gcode(idx, range, path);
+            },
+            Message::OverwriteGcode(idx, range, path, yes)=>if yes {
+                return self.save_gcode(idx, range, path);
+            },
+            Message::GcodeProgress(idx, progress)=>{
+                // the job was dropped, probably because the sheet was deleted
+                if self.gcode_progress.is_none_or(|(job_idx, ..)|job_idx != idx) {
+                    return Task::none();
+                }
+
+                match progress {
+                    GcodeProgress::Progress(done, total)=>{
+                        self.gcode_progress = Some((idx, done, total));
+                    },
+                    GcodeProgress::Done(Ok(skipped))=>{
+                        self.gcode_progress = None;
+                        let name = &self.sheet_settings[idx].name;
+                        if skipped > 0 {
+                            let text = format!("Saved the GCODE for `{name}`, but skipped {skipped} lines that were too short to cut");
+                            return self.notify(Severity::Warning, text);
+                        }
+                        let text = format!("Saved the GCODE for `{name}`");
+                        return self.notify(Severity::Info, text);
+                    },
+                    GcodeProgress::Done(Err(e))=>{
+                        self.gcode_progress = None;
+                        let text = format!("Could not save the GCODE for `{}`: {e}", self.sheet_settings[idx].name);
+                        return self.notify(Severity::Error, text);
+                    },
+                }
+            },
+            Message::SaveCalibration(opt_file)=>{
+                let Some(file) = opt_file else {
+                    self.calibration_gcode = None;
+                    return Task::none();
+                };
+
+                let path = self.gcode_file_path(&file);
+                if path != file.path() && path.exists() {
+                    return Task::perform(confirm_overwrite(&path), move|yes|Message::OverwriteCalibration(path.clone(), yes));
+                }
+                return self.save_calibration(path);
+            },
+            Message::OverwriteCalibration(path, yes)=>{
+                if yes {
+                    return self.save_calibration(path);
+                }
+                self.calibration_gcode = None;
+            },
+            Message::OpenProjectPicker=>{
+                let future = AsyncFileDialog::new()
+                    .add_filter("LaserCAM Projects", &[PROJECT_EXTENSION])
+                    .set_title("Open project")
+                    .pick_file();
+                return Task::perform(future, Message::LoadProject);
+            },
+            Message::LoadProject(opt_file)=>if let Some(file) = opt_file {
+                return self.load_project_file(file.path());
+            },
+            Message::OpenSheetImportPicker=>{
+                let future = AsyncFileDialog::new()
+                    .add_filter("LaserCAM Projects", &[PROJECT_EXTENSION])
+                    .set_title("Import sheets from a project")
+                    .pick_file();
+                return Task::perform(future, Message::ImportSheet);
+            },
+            Message::ImportSheet(opt_file)=>if let Some(file) = opt_file {
+                let path = file.path().to_path_buf();
+                let project = match Project::load(&path) {
+                    Ok(project)=>project,
+                    Err(e)=>return self.notify(Severity::Error, format!("{e:#}")),
+                };
+                if project.sheets.is_empty() {
+                    return self.notify(Severity::Error, format!("`{}` doesn't have any sheets", path.display()));
+                }
+
+                self.sheet_import = Some(SheetImport {
+                    path,
+                    selected: vec![false; project.sheets.len()],
+                    project,
+                });
+                self.open_sheet_import();
+            },
+            Message::ToggleImportedSheet(idx, b)=>if let Some(import) = &mut self.sheet_import {
+                if let Some(selected) = import.selected.get_mut(idx) {
+                    *selected = b;
+                }
+            },
+            Message::ImportSelectedSheets=>{
+                let Some(import) = self.sheet_import.take() else {
+                    return Task::none();
+                };
+                self.close_sheet_import();
+
+                return self.import_sheets(import);
+            },
+            Message::CancelSheetImport=>{
+                self.sheet_import = None;
+                self.close_sheet_import();
+            },
+            Message::OpenProjectSaveDialog=>{
+                let future = AsyncFileDialog::new()
+                    .add_filter("LaserCAM Projects", &[PROJECT_EXTENSION])
+                    .set_title("Save project")
+                    .set_file_name(format!("project.{PROJECT_EXTENSION}"))
+                    .save_file();
+                return Task::perform(future, Message::SaveProject);
+            },
+            Message::SaveProject(opt_file)=>if let Some(file) = opt_file {
+                let mut path = file.path().to_path_buf();
+                if path.extension().is_none() {
+                    path.set_extension(PROJECT_EXTENSION);
+                }
+
+                let dir = path.parent().unwrap_or(StdPath::new(""));
+                let project = Project::new(
+                    self.sheet_settings.iter()
+                        .map(|settings|settings.name.as_str())
+                        .zip(self.sheets.iter()),
+                    &self.conditions.get_store().borrow(),
+                    dir,
+                );
+
+                return match project.save(&path) {
+                    Ok(_)=>self.notify(Severity::Info, format!("Saved `{}`", path.display())),
+                    Err(e)=>self.notify(Severity::Error, format!("{e:#}")),
+                };
+            },
+            Message::OpenImageSaveDialog=>{
+                let future = AsyncFileDialog::new()
+                    .add_filter("Images", ImageFormat::EXTENSIONS)
+                    .set_title("Export image")
+                    .set_file_name(format!("{}.svg", self.sheet_settings[self.active_sheet].name))
+                    .save_file();
+                return Task::perform(future, Message::ExportImage);
+            },
+            Message::ExportImage(opt_file)=>if let Some(file) = opt_file {
+                let mut path = file.path().to_path_buf();
+                if ImageFormat::from_path(&path).is_none() {
+                    path.set_extension("svg");
+                }
+
+                let sheet = &self.sheets[self.active_sheet];
+                let image = sheet.image(sheet.show_order);
+                let dpi = self.settings.borrow().image_dpi;
+
+                return match image.write_file(&path, dpi) {
+                    Ok(_)=>self.notify(Severity::Info, format!("Exported `{}`", path.display())),
+                    Err(e)=>self.notify(Severity::Error, format!("{e:#}")),
+                };
+            },
+            Message::OpenGcodeSaveDialog=>{
+                if self.gcode_progress.is_some() {
+                    return Task::none();
+                }
+
+                let idx = self.active_sheet;
+                let future = self.sheet_gcode_dialog(idx, "Save GCODE file", "")
+                    .save_file();
+                return Task::perform(future, move|file|Message::SaveGcode(idx, GcodeRange::All, file));
+            },
+            Message::OpenSelectionGcodeSaveDialog=>{
+                let idx = self.active_sheet;
+                let ids = self.gcode_selection();
+                if self.gcode_progress.is_some() || ids.is_empty() {
+                    return Task::none();
+                }
+
+                let future = self.sheet_gcode_dialog(idx, "Save GCODE file for the selection", " selection")
+                    .save_file();
+                return Task::perform(future, move|file|Message::SaveGcode(idx, GcodeRange::Selection(ids.clone()), file));
+            },
+            Message::ChangeResumeFrom(s)=>self.resume_from = s,
+            Message::ChangeEntityGapH(s)=>self.entity_gap[0] = s,
+            Message::ChangeEntityGapV(s)=>self.entity_gap[1] = s,
+            Message::ApplyEntityGap=>{
+                let units = self.settings.borrow().units;
+                let sheet = &mut self.sheets[self.active_sheet];
+                let &[a, b] = sheet.selection.iter().copied().collect::<Vec<_>>().as_slice() else {
+                    return Task::none();
+                };
+
+                for (s, vertical) in self.entity_gap.iter().zip([false, true]) {
+                    if s.trim().is_empty() {
+                        continue;
+                    }
+                    match units.parse(s.trim()) {
+                        Some(gap)=>sheet.set_entity_gap(a, b, gap, vertical),
+                        None=>return self.notify(Severity::Error, format!("`{s}` is not a valid gap")),
+                    }
+                }
+                self.entity_gap = Default::default();
+                self.refresh_entity_params();
+            },
+            Message::ChangePatternKind(kind)=>{
+                // the inputs mean different things for each kind
+                if kind != self.pattern_kind {
+                    self.pattern_input = Default::default();
+                }
+                self.pattern_kind = kind;
+            },
+            Message::PatternInput(i, s)=>self.pattern_input[i] = s,
+            Message::OpenPatternSaveDialog=>{
+                let Some(params) = &self.entity_params else {return Task::none()};
+                let Some(pattern) = self.parse_pattern() else {return Task::none()};
+                let Some((handle, _)) = self.sheets[self.active_sheet].entities.get(&params.id) else {
+                    return Task::none();
+                };
+
+                let future = AsyncFileDialog::new()
+                    .add_filter("DXF Files", MODEL_EXTENSIONS)
+                    .set_title("Save pattern")
+                    .set_file_name(format!("{} pattern.dxf", handle.name()))
+                    .save_file();
+                self.pending_pattern = Some((self.active_sheet, params.id, pattern));
+                return Task::perform(future, Message::SavePattern);
+            },
+            Message::SavePattern(opt_file)=>{
+                let Some((idx, id, pattern)) = self.pending_pattern.take() else {return Task::none()};
+                let Some(file) = opt_file else {return Task::none()};
+                let Some((handle, mt)) = self.sheets.get(idx).and_then(|sheet|sheet.entities.get(&id)).cloned() else {
+                    return self.notify(Severity::Error, "The entity was deleted before the pattern was saved");
+                };
+
+                // the pattern is in mm on the sheet, but the model is scaled by the entity
+                let model = handle.pattern(pattern.scaled(1.0 / mt.transform.scale));
+                if let Err(e) = model.save_dxf(file.path()) {
+                    return self.notify(Severity::Error, format!("Could not save `{}`: {e}", file.path().display()));
+                }
+
+                // load it back, so it is the same as when a project with it is opened
+                let min_feature_size = self.settings.borrow().min_feature_size;
+                let new_handle = match self.models.import(file.path(), handle.import_scale, handle.arc_tolerance, min_feature_size) {
+                    Ok(new_handle)=>new_handle,
+                    Err(e)=>{
+                        return self.notify(Severity::Error, format!("Could not load `{}`: {e}", file.path().display()));
+                    },
+                };
+
+                let sheet = &mut self.sheets[idx];
+                sheet.delete_entity(id);
+                sheet.add_model_from_handle_with_transform(new_handle.clone(), mt, 1);
+                if self.entity_params.as_ref().is_some_and(|params|params.id == id) {
+                    self.entity_params = None;
+                    self.close_entity_params();
+                }
+
+                let mut tasks = Vec::new();
+                for warning in new_handle.warnings.iter() {
+                    tasks.push(self.notify(Severity::Error, format!("Model `{}`: {warning}", new_handle.name())));
+                }
+                tasks.push(self.notify(Severity::Info, format!("Replaced `{}` with the pattern `{}`", handle.name(), new_handle.name())));
+
+                return Task::batch(tasks);
+            },
+            Message::OpenResumeGcodeSaveDialog=>{
+                let idx = self.active_sheet;
+                let Some(from) = self.parse_resume_from() else {return Task::none()};
+                if self.gcode_progress.is_some() {
+                    return Task::none();
+                }
+
+                let future = self.sheet_gcode_dialog(idx, "Save GCODE file to resume from", &format!(" from {}", from + 1))
+                    .save_file();
+                return Task::perform(future, move|file|Message::SaveGcode(idx, GcodeRange::ResumeFrom(from), file));
+            },
+            Message::CopyGcode=>{
+                let skipped = self.generate_active_gcode();
+
+                let gcode = self.sheet_settings[self.active_sheet]
+                    .gcode
+                    .clone()
+                    .unwrap_or_default();
+                let notification = if skipped > 0 {
+                    self.notify(Severity::Warning, format!("Copied GCODE to the clipboard, but skipped {skipped} lines that were too short to cut"))
+                } else {
+                    self.notify(Severity::Info, "Copied GCODE to the clipboard")
+                };
+                return Task::batch([
+                    iced::clipboard::write(gcode),
+                    notification,
+                ]);
+            },
+            Message::SimulateGcode=>{
+                let start = std::time::Instant::now();
+
+                let sheet = &mut self.sheets[self.active_sheet];
+                let paths = sheet.gcode_job(&self.sheet_settings[self.active_sheet].name)
+                    .simulate();
+
+                eprintln!("GCODE simulated in {:?}", start.elapsed());
+                return sheet.main_update(SheetMessage::SimulateGcode(paths)).map(Message::Sheet);
+            },
+            Message::ToggleSettings=>{
+                if !self.open_settings() {
+                    self.close_settings();
+                }
+            },
+            Message::SettingsTheme(theme)=>self.settings.borrow_mut().theme = theme,
+            Message::SettingsUnits(units)=>{
+                self.settings.borrow_mut().units = units;
+
+                // only the text changes. The sheet and entities stay in mm.
+                self.format_sheet_size();
+                self.format_entity_position();
+            },
+            Message::SettingsZoomStep(step)=>self.settings.borrow_mut().zoom_step = step,
+            Message::SettingsNudgeDistance(dist)=>self.settings.borrow_mut().nudge_distance = dist,
+            Message::SettingsEntitySnap(b)=>self.settings.borrow_mut().entity_snap = b,
+            Message::SettingsSnapDistance(dist)=>self.settings.borrow_mut().snap_distance = dist,
+            Message::SettingsImageDpi(dpi)=>self.settings.borrow_mut().image_dpi = dpi,
+            Message::SettingsGcodeFileName(name)=>self.settings.borrow_mut().gcode_file_name = name,
+            Message::SettingsAskImportOptions(b)=>self.settings.borrow_mut().ask_import_options = b,
+            Message::SettingsMinFeatureSize(size)=>self.settings.borrow_mut().min_feature_size = size,
+            Message::SettingsMaxFitScale(scale)=>self.settings.borrow_mut().max_fit_scale = scale,
+            Message::SettingsCanvas(style)=>{
+                self.settings.borrow_mut().canvas = style;
+                for sheet in self.sheets.iter() {
+                    sheet.clear_cache();
+                }
+            },
+            Message::ToggleConditionEditor=>{
+                if !self.open_condition_editor() {
+                    self.close_condition_editor();
+                }
+            },
+            Message::SelectionShortcut(key, modifiers)=>{
+                let current = self.entity_params.as_ref().map(|params|params.id);
+                let Some(msg) = self.sheets[self.active_sheet].selection_shortcut(current, &key, modifiers) else {
+                    return Task::none();
+                };
+
+                return iced::advanced::widget::operate(focusable::count())
+                    .map(move|count|count.focused.is_none().then(||Message::Sheet(msg.clone())))
+                    .and_then(Task::done);
+            },
+            Message::Iced(event)=>{
+                match event {
+                    Event::Window(window::Event::Resized(size))=>self.window_size = size,
+                    Event::Window(window::Event::FileHovered(path))=>self.hovered_files.push(path),
+                    Event::Window(window::Event::FilesHoveredLeft)=>self.hovered_files.clear(),
+                    // each dropped file is a separate event
+                    Event::Window(window::Event::FileDropped(path))=>{
+                        self.hovered_files.clear();
+                        if is_model_file(&path) {
+                            return self.import_models(vec![path]);
+                        } else {
+                            let text = format!("Skipped `{}`: only DXF files can be imported", path.display());
+                            return self.notify(Severity::Error, text);
+                        }
+                    },
+                    // Ctrl+M maximizes the sheet
+                    Event::Keyboard(keyboard::Event::KeyPressed{key: keyboard::Key::Character(c), modifiers, ..})
+                        if modifiers.command() && c.as_str() == "m"=>{
+                        return self.update(Message::ToggleMaximizeSheet);
+                    },
+                    Event::Window(window::Event::CloseRequested)=>{
+                        self.conditions.save();
+                        self.machine.borrow().save();
+                        self.settings.borrow().save();
+                        WindowLayout::new(&self.panes, self.window_size).save();
+                        return window::get_latest().and_then(window::close);
+                    },
+                    _=>{},
+                }
+            }
+            Message::ClearModels=>self.models.clear(),
+            Message::RemoveModel(handle)=>{
+                for sheet in self.sheets.iter_mut() {
+                    let Some(ids) = sheet.active_models.get(&handle) else {continue};
+
+                    for id in ids.clone() {
+                        sheet.delete_entity(id);
+                    }
+                }
+                self.models.remove(&handle);
+
+                if self.copy_entity.as_ref().is_some_and(|(copy, _)|*copy == handle) {
+                    self.copy_entity = None;
+                }
+                self.check_entity_params();
+
+                return self.notify(Severity::Info, format!("Removed model `{}`", handle.name()));
+            },
+            Message::ToggleInfo=>{
+                if self.panes.iter().any(|(_, state)|*state == ProgramPane::Info) {
+                    self.close_info();
+                } else {
+                    self.open_info();
+                }
+            },
+            Message::DismissInfo=>{
+                self.notifications.clear();
+                self.toasts.clear();
+                self.close_info();
+            },
+            Message::DismissToast(id)=>self.toasts.retain(|t|*t != id),
+            Message::FitEntity(idx, id, choice)=>{
+                self.fit_prompts.retain(|prompt|*prompt != (idx, id));
+                if idx >= self.sheets.len() {
+                    return Task::none();
+                }
+
+                match choice {
+                    FitChoice::Scale=>self.sheets[idx].fit_entity(id, false),
+                    FitChoice::Rotate=>self.sheets[idx].fit_entity(id, true),
+                    FitChoice::Leave=>{},
+                }
+                if idx == self.active_sheet {
+                    self.refresh_entity_params();
+                }
+            },
+            Message::ConfirmFitToSheet(idx, id, yes)=>{
+                if yes && idx < self.sheets.len() {
+                    return self.fit_to_sheet(idx, id, true);
+                }
+            },
+        }
+
+        return Task::none();
+    }
+
+    /// Fit the entity to the sheet. If it would be scaled up more than
+    /// [`Settings::max_fit_scale`] the user is asked first, unless `confirmed`.
+    fn fit_to_sheet(&mut self, idx: usize, id: EntityId, confirmed: bool)->Task<Message> {
+        let Some((scale, _)) = self.sheets[idx].fit_to_sheet_target(id) else {
+            return self.notify(Severity::Error, "There is no room on the sheet to fit the entity");
+        };
+
+        let max_scale = self.settings.borrow().max_fit_scale;
+        if !confirmed && scale > max_scale {
+            let future = AsyncMessageDialog::new()
+                .set_level(MessageLevel::Warning)
+                .set_title("Scale up?")
+                .set_description(format!("Fitting the entity to the sheet scales it up {scale:.2}x. Do you want to continue?"))
+                .set_buttons(MessageButtons::YesNo)
+                .show();
+
+            return Task::perform(future, move|res|Message::ConfirmFitToSheet(idx, id, res == MessageDialogResult::Yes));
+        }
+
+        self.sheets[idx].fit_to_sheet(id);
+        if idx == self.active_sheet {
+            self.refresh_entity_params();
+        }
+
+        return Task::none();
+    }
+
+    /// Generate the GCODE for the active sheet and store it in its [`SheetIndex`].
+    /// Returns how many lines were too short to cut.
+    fn generate_active_gcode(&mut self)->usize {
+        let start = std::time::Instant::now();
+
+        let settings = &mut self.sheet_settings[self.active_sheet];
+        let (gcode, skipped) = self.sheets[self.active_sheet]
+            .generate_gcode(settings.name.as_str());
+        settings.gcode = Some(gcode);
+
+        let elapsed = start.elapsed();
+        eprintln!("GCODE Generated in {elapsed:?}");
+
+        return skipped;
+    }
+
+    fn open_condition_editor(&mut self)->bool {
+        let pane = self.panes.iter()
+            .map(|(p,s)|(*p,*s))
+            .find(|(_,state)|*state==ProgramPane::Sheet||*state==ProgramPane::Settings);
+        if let Some((pane, _)) = pane {
+            *self.panes
+                .get_mut(pane)
+                .unwrap() = ProgramPane::ConditionEditor;
+            return true;
+        }
+
+        return false;
+    }
+
+    fn condition_editor_open(&self)->bool {
+        self.panes.iter().any(|(_, state)|*state == ProgramPane::ConditionEditor)
+    }
+
+    fn close_condition_editor(&mut self)->bool {
+        let pane = self.panes.iter()
+            .map(|(p,s)|(*p,*s))
+            .find(|(_,state)|*state==ProgramPane::ConditionEditor);
+        if let Some((pane, _)) = pane {
+            *self.panes
+                .get_mut(pane)
+                .unwrap() = ProgramPane::Sheet;
+            return true;
+        }
+
+        return false;
+    }
+
+    /// Show the settings where the sheet (or condition editor) is.
+    fn open_settings(&mut self)->bool {
+        let pane = self.panes.iter()
+            .map(|(p,s)|(*p,*s))
+            .find(|(_,state)|*state==ProgramPane::Sheet||*state==ProgramPane::ConditionEditor);
+        if let Some((pane, _)) = pane {
+            *self.panes
+                .get_mut(pane)
+                .unwrap() = ProgramPane::Settings;
+            return true;
+        }
+
+        return false;
+    }
+
+    fn close_settings(&mut self)->bool {
+        let pane = self.panes.iter()
+            .map(|(p,s)|(*p,*s))
+            .find(|(_,state)|*state==ProgramPane::Settings);
+        if let Some((pane, _)) = pane {
+            *self.panes
+                .get_mut(pane)
+                .unwrap() = ProgramPane::Sheet;
+            return true;
+        }
+
+        return false;
+    }
+
+    /// Show the sheet import dialog where the sheet (or condition editor or settings) is.
+    fn open_sheet_import(&mut self) {
+        let pane = self.panes.iter()
+            .map(|(p,s)|(*p,*s))
+            .find(|(_,state)|matches!(state, ProgramPane::Sheet|ProgramPane::ConditionEditor|ProgramPane::Settings|ProgramPane::SheetImportDialog));
+        if let Some((pane, _)) = pane {
+            *self.panes
+                .get_mut(pane)
+                .unwrap() = ProgramPane::SheetImportDialog;
+        }
+    }
+
+    fn close_sheet_import(&mut self) {
+        let pane = self.panes.iter()
+            .map(|(p,s)|(*p,*s))
+            .find(|(_,state)|*state==ProgramPane::SheetImportDialog);
+        if let Some((pane, _)) = pane {
+            *self.panes
+                .get_mut(pane)
+                .unwrap() = ProgramPane::Sheet;
+        }
+    }
+
+    /// Open the info pane below the sheet if it isn't already open.
+    fn open_info(&mut self) {
+        if self.panes.iter().any(|(_, state)|*state == ProgramPane::Info) {
+            return;
+        }
+
+        let pane = self.panes.iter()
+            .map(|(p,s)|(*p,*s))
+            .find(|(_,state)|matches!(state, ProgramPane::Sheet|ProgramPane::ConditionEditor|ProgramPane::Settings|ProgramPane::SheetImportDialog));
+        if let Some((pane, _)) = pane {
+            if let Some((_, split)) = self.panes.split(pane_grid::Axis::Horizontal, pane, ProgramPane::Info) {
+                self.panes.resize(split, 0.8);
+            }
+        }
+    }
+
+    fn close_info(&mut self) {
+        let pane = self.panes.iter()
+            .map(|(p,s)|(*p,*s))
+            .find(|(_,state)|*state==ProgramPane::Info);
+        if let Some((pane, _)) = pane {
+            self.panes.close(pane);
+        }
+    }
+
+    /// Ask how to import the files, or import them with the saved options if the user doesn't want
+    /// to be asked. Files dropped while the options are open are added to them.
+    fn import_models(&mut self, paths: Vec<PathBuf>)->Task<Message> {
+        if let Some(import) = &mut self.model_import {
+            import.paths.extend(paths);
+            return Task::none();
+        }
+
+        let settings = self.settings.borrow();
+        let options = settings.import_options;
+        if !settings.ask_import_options {
+            drop(settings);
+            let tasks = paths.iter()
+                .map(|path|self.load_model_file(path, options))
+                .collect::<Vec<_>>();
+            return Task::batch(tasks);
+        }
+        drop(settings);
+
+        self.model_import = Some(ModelImport {
+            paths,
+            options,
+            scale: options.scale.to_string(),
+            arc_tolerance: options.arc_tolerance.to_string(),
+            remember: false,
+            dont_ask: false,
+        });
+
+        return Task::none();
+    }
+
+    /// Load a model and add it to the active sheet. Any errors or warnings are shown as
+    /// notifications.
+    fn load_model_file(&mut self, path: &StdPath, options: ImportOptions)->Task<Message> {
+        let min_feature_size = self.settings.borrow().min_feature_size;
+        let handle = match self.models.import(path, options.entity_scale(), options.arc_tolerance, min_feature_size) {
+            Ok(handle)=>handle,
+            Err(e)=>{
+                return self.notify(Severity::Error, format!("Could not load `{}`: {e}", path.display()));
+            },
+        };
+
+        let mut tasks = Vec::new();
+        for warning in handle.warnings.iter() {
+            tasks.push(self.notify(Severity::Error, format!("Model `{}`: {warning}", handle.name())));
+        }
+        if options.add_to_sheet {
+            let ids = self.sheets[self.active_sheet]
+                .add_model_from_handle(handle, 1, self.conditions.default_condition());
+            tasks.push(self.prompt_oversized(ids, true));
+        }
+
+        return Task::batch(tasks);
+    }
+
+    /// Replace all the sheets with the ones in the project. If anything in the project can't be
+    /// loaded, then the current sheets are kept.
+    fn load_project_file(&mut self, path: &StdPath)->Task<Message> {
+        // the job refers to a sheet by index, which is about to change
+        if self.gcode_progress.is_some() {
+            return self.notify(Severity::Error, "Wait for the GCODE to finish saving before opening a project");
+        }
+
+        let mut project = match Project::load(path) {
+            Ok(project)=>project,
+            Err(e)=>return self.notify(Severity::Error, format!("{e:#}")),
+        };
+
+        // the project's conditions might not be in our store, or have different IDs in it
+        let remap = self.conditions.merge(project.conditions.subset(&project.used_conditions()));
+
+        let sheets = project.sheets.iter_mut()
+            .map(|sheet|{
+                sheet.remap_conditions(&remap);
+                let loaded = sheet.to_sheet(&self.models, self.conditions.get_store(), self.machine.clone(), self.settings.clone())?;
+                Ok((sheet.name.clone(), loaded))
+            })
+            .collect::<anyhow::Result<Vec<_>>>();
+        let mut sheets = match sheets {
+            Ok(sheets)=>sheets,
+            Err(e)=>return self.notify(Severity::Error, format!("{e:#}")),
+        };
+        if sheets.is_empty() {
+            return self.notify(Severity::Error, format!("`{}` doesn't have any sheets", path.display()));
+        }
+
+        let store = self.conditions.get_store();
+        for (_, sheet) in sheets.iter_mut() {
+            Self::validate_sheet(sheet, &store.borrow());
+        }
+
+        self.sheets.clear();
+        self.sheet_settings.clear();
+        self.fit_prompts.clear();
+        for (index, (name, sheet)) in sheets.into_iter().enumerate() {
+            self.sheet_settings.push(SheetIndex {
+                name,
+                gcode: None,
+                index,
+            });
+            self.sheets.push(sheet);
+        }
+        self.active_sheet = 0;
+
+        self.copy_entity = None;
+        self.entity_params = None;
+        self.close_entity_params();
+
+        self.format_sheet_size();
+
+        return self.notify(Severity::Info, format!("Opened `{}`", path.display()));
+    }
+
+    /// Fix references in a loaded sheet that would cause problems later. Entities with a condition
+    /// that doesn't exist get the default condition, and IDs in the cutting order that aren't
+    /// entities are removed.
+    fn validate_sheet(sheet: &mut Sheet, store: &ConditionStore) {
+        let missing_conditions = sheet.entities.iter()
+            .filter(|(_, (_, mt))|!store.contains(mt.laser_condition))
+            .map(|(id, _)|*id)
+            .collect::<Vec<_>>();
+        for id in missing_conditions {
+            let Some(default) = store.default_id() else {
+                eprintln!("Entity {id:?} uses a condition that doesn't exist, and there is no default condition");
+                continue;
+            };
+            if let Some((_, mt)) = sheet.entities.get_mut(&id) {
+                eprintln!("Entity {id:?} uses condition {:?}, which doesn't exist. Using the default condition", mt.laser_condition);
+                mt.laser_condition = default;
+            }
+            sheet.recalc_paths_id(id);
+        }
+
+        for id in sheet.prune_order() {
+            eprintln!("Removing entity {id:?} from the cutting order since it doesn't exist");
+        }
+    }
+
+    /// Add the selected sheets of the project after the open ones. The conditions they use are
+    /// merged into ours, and any models that aren't loaded yet are loaded.
+    fn import_sheets(&mut self, import: SheetImport)->Task<Message> {
+        let SheetImport {path, project, selected} = import;
+
+        let mut project_sheets = project.sheets.into_iter()
+            .zip(selected)
+            .filter_map(|(sheet, selected)|selected.then_some(sheet))
+            .collect::<Vec<_>>();
+        if project_sheets.is_empty() {
+            return Task::none();
+        }
+
+        // only the conditions the picked sheets use
+        let used = project_sheets.iter()
+            .flat_map(|sheet|sheet.entities.iter())
+            .map(|entity|entity.laser_condition)
+            .collect();
+        let remap = self.conditions.merge(project.conditions.subset(&used));
+
+        let sheets = project_sheets.iter_mut()
+            .map(|sheet|{
+                sheet.remap_conditions(&remap);
+                let loaded = sheet.to_sheet(&self.models, self.conditions.get_store(), self.machine.clone(), self.settings.clone())?;
+                Ok((sheet.name.clone(), loaded))
+            })
+            .collect::<anyhow::Result<Vec<_>>>();
+        let mut sheets = match sheets {
+            Ok(sheets)=>sheets,
+            Err(e)=>return self.notify(Severity::Error, format!("{e:#}")),
+        };
+
+        let store = self.conditions.get_store();
+        for (_, sheet) in sheets.iter_mut() {
+            Self::validate_sheet(sheet, &store.borrow());
+        }
+
+        let count = sheets.len();
+        self.active_sheet = self.sheets.len();
+        for (name, sheet) in sheets {
+            self.sheet_settings.push(SheetIndex {
+                name,
+                gcode: None,
+                index: self.sheets.len(),
+            });
+            self.sheets.push(sheet);
+        }
+
+        self.entity_params = None;
+        self.close_entity_params();
+
+        self.format_sheet_size();
+
+        return self.notify(Severity::Info, format!("Imported {count} sheets from `{}`", path.display()));
+    }
+
+    /// Show a notification as a toast and add it to the log. The toast goes away after
+    /// [`TOAST_DURATION`], so the returned task has to be run.
+    fn notify(&mut self, severity: Severity, text: impl Into<String>)->Task<Message> {
+        let notification = Notification::new(severity, text.into());
+        let id = notification.id;
+        eprintln!("{}", notification.text);
+
+        self.notifications.push(notification);
+        self.toasts.push(id);
+        if self.toasts.len() > MAX_TOASTS {
+            self.toasts.remove(0);
+        }
+
+        return Task::perform(notification::wait(TOAST_DURATION), move|_|Message::DismissToast(id));
+    }
+
+    /// Write the GCODE to the file, making sure it has an extension.
+    fn save_calibration(&mut self, path: PathBuf)->Task<Message> {
+        let Some(gcode) = self.calibration_gcode.take() else {return Task::none()};
+
+        match std::fs::write(&path, gcode) {
+            Err(e)=>self.notify(Severity::Error, format!("Could not save `{}`: {e}", path.display())),
+            _=>self.notify(Severity::Info, format!("Saved `{}`", path.display())),
+        }
+    }
+
+    /// Write the sheet's GCODE in the background. Progress is sent as [`Message::GcodeProgress`].
+    fn save_gcode(&mut self, idx: usize, range: GcodeRange, path: PathBuf)->Task<Message> {
+        // the sheet might have been deleted while the dialog was open
+        if idx >= self.sheets.len() || self.gcode_progress.is_some() {
+            return Task::none();
+        }
 
-                self.sheet_size = [
-                    format!("{}", self.sheets[self.active_sheet].sheet_size.x),
-                    format!("{}", self.sheets[self.active_sheet].sheet_size.y),
-                ];
-            },
-            Message::DeleteSheet=>{
-                // ensure there is at least 1 sheet so we don't have errors
-                if self.sheets.len() == 1 {
-                    self.sheets.clear();
-                    self.sheet_settings.clear();
+        let name = &self.sheet_settings[idx].name;
+        let job = match range {
+            GcodeRange::All=>self.sheets[idx].gcode_job(name),
+            GcodeRange::Selection(ids)=>self.sheets[idx].gcode_job_for(name, &ids),
+            GcodeRange::ResumeFrom(from)=>self.sheets[idx].gcode_job_resumed(name, from),
+        };
+        self.gcode_progress = Some((idx, 0, job.len()));
 
-                    self.sheet_settings.push(SheetIndex {
-                        name: "New Sheet".into(),
-                        gcode: None,
-                        index: self.sheets.len(),
-                    });
-                    self.sheets.push(Sheet::new(self.models.clone(), self.conditions.get_store()));
-                } else {
-                    self.sheets.remove(self.active_sheet);
-                    self.sheet_settings.remove(self.active_sheet);
-                    self.active_sheet = 0;
-                }
+        let stream = iced::stream::channel(100, move|mut sender|async move {
+            use iced::futures::SinkExt;
 
-                self.sheet_size = [
-                    format!("{}", self.sheets[self.active_sheet].sheet_size.x),
-                    format!("{}", self.sheets[self.active_sheet].sheet_size.y),
-                ];
-            },
-            Message::SelectSheet(idx)=>{
-                self.active_sheet = idx;
+            let start = std::time::Instant::now();
+            let res = job.write_file(&path, |done, total|{
+                // progress is only informational, so it is fine to drop some of it
+                let _ = sender.try_send(GcodeProgress::Progress(done, total));
+            });
+            eprintln!("GCODE Generated in {:?}", start.elapsed());
 
-                self.sheet_size = [
-                    format!("{}", self.sheets[self.active_sheet].sheet_size.x),
-                    format!("{}", self.sheets[self.active_sheet].sheet_size.y),
-                ];
-            },
-            Message::ResizePane(event)=>self.panes.resize(event.split, event.ratio),
-            Message::AddModel(handle)=>{
+            let _ = sender.send(GcodeProgress::Done(res.map_err(|e|e.to_string()))).await;
+        });
+        return Task::run(stream, move|progress|Message::GcodeProgress(idx, progress));
+    }
 
-                self.sheets[self.active_sheet]
-                    .add_model_from_handle(handle, 1, self.conditions.default_condition());
-            },
-            Message::ModelPaneState(state)=>self.model_pane_state = state,
-            Message::OpenFilePicker=>{
-                let future = AsyncFileDialog::new()
-                    .add_filter("DXF Files", &["dxf"])
-                    .set_title("Load DXF files")
-                    .pick_files();
-                return Task::perform(future,Message::LoadModel);
-            },
-            Message::LoadModel(opt_files)=>if let Some(files) = opt_files {
-                for file in files {
-                    // TODO(error handling): Make this not crash when we have an error
+    /// A GCODE save dialog that starts in the folder GCODE was last saved in.
+    fn gcode_dialog(&self, title: &str)->AsyncFileDialog {
+        let mut dialog = AsyncFileDialog::new()
+            .add_filter("GCODE Files", &["gcode", "nc"])
+            .set_title(title);
+        if let Some(dir) = &self.settings.borrow().gcode_dir {
+            dialog = dialog.set_directory(dir);
+        }
 
-                    let model = Model::load(file.path())
-                        .expect("Could not load files");
+        return dialog;
+    }
 
-                    let handle = self.models.add(model);
-                    self.sheets[self.active_sheet]
-                        .add_model_from_handle(handle, 1, self.conditions.default_condition());
+    /// A [`MainProgram::gcode_dialog`] for the sheet with the file name from the settings'
+    /// template. `suffix` goes after the name, before the extension.
+    fn sheet_gcode_dialog(&self, idx: usize, title: &str, suffix: &str)->AsyncFileDialog {
+        let sheet = &self.sheets[idx];
+        let mut conditions = Vec::new();
+        {
+            let store = self.conditions.get_store();
+            let store = store.borrow();
+            for (_, (_, mt)) in sheet.ordered_entities() {
+                let name = &store.get_or_default(mt.laser_condition).name;
+                if !conditions.contains(name) {
+                    conditions.push(name.clone());
                 }
-            },
-            Message::EntityParamsX(val)=>{
-                if let Some(f) = parse_float(&val) {
-                    let Some(params) = self.entity_params
-                        .as_mut() else {return Task::none()};
+            }
+        }
 
-                    params.x = val;
-                    self.sheets[self.active_sheet]
-                        .entities.get_mut(&params.id)
-                        .unwrap().1
-                        .transform
-                        .translation.x = f;
+        let name = self.settings.borrow().gcode_file_name(
+            &self.sheet_settings[idx].name,
+            &sheet.material,
+            &conditions.join("+"),
+        );
 
-                    self.sheets[self.active_sheet].recalc_paths();
-                }
-            },
-            Message::EntityParamsY(val)=>{
-                if let Some(f) = parse_float(&val) {
-                    let Some(params) = self.entity_params
-                        .as_mut() else {return Task::none()};
+        return self.gcode_dialog(title)
+            .set_file_name(format!("{name}{suffix}.gcode"));
+    }
 
-                    params.y = val;
-                    self.sheets[self.active_sheet]
-                        .entities.get_mut(&params.id)
-                        .unwrap().1
-                        .transform
-                        .translation.y = f;
+    /// The path of the file with an extension. Its folder is remembered for the next GCODE save
+    /// dialog.
+    fn gcode_file_path(&mut self, file: &FileHandle)->PathBuf {
+        let path = gcode_file_path(file);
+        if let Some(dir) = path.parent() {
+            self.settings.borrow_mut().gcode_dir = Some(dir.to_path_buf());
+        }
 
-                    self.sheets[self.active_sheet].recalc_paths();
-                }
-            },
-            Message::EntityParamsAngle(val)=>{
-                let Some(params) = self.entity_params
-                    .as_mut() else {return Task::none()};
+        return path;
+    }
 
-                params.angle = val;
-                params.angle_string = val.to_string();
-                self.sheets[self.active_sheet]
-                    .entities.get_mut(&params.id)
-                    .unwrap().1
-                    .transform
-                    .rotation = Rotation::from_angle(val.to_radians());
+    /// Returns `true` if the entity in the params pane is still on the active sheet. If it isn't,
+    /// then the pane is closed.
+    fn check_entity_params(&mut self)->bool {
+        let Some(params) = &self.entity_params else {return false};
 
-                self.sheets[self.active_sheet].recalc_paths();
-            },
-            Message::EntityParamsAngleString(val)=>{
-                if let Some(f) = parse_float(&val) {
-                    let Some(params) = self.entity_params
-                        .as_mut() else {return Task::none()};
+        if self.sheets[self.active_sheet].entities.contains_key(&params.id) {
+            return true;
+        }
 
-                    params.angle = f;
-                    params.angle_string = val;
-                    self.sheets[self.active_sheet]
-                        .entities.get_mut(&params.id)
-                        .unwrap().1
-                        .transform
-                        .rotation = Rotation::from_angle(f.to_radians());
+        eprintln!("Entity {:?} is not on the sheet anymore. Closing its params.", params.id);
+        self.entity_params = None;
+        self.close_entity_params();
 
-                    self.sheets[self.active_sheet].recalc_paths();
-                }
-            },
-            Message::EntityParamsScale(val)=>{
-                if let Some(f) = parse_float(&val) {
-                    let Some(params) = self.entity_params
-                        .as_mut() else {return Task::none()};
+        return false;
+    }
 
-                    if val.len() > 0 {
-                        self.sheets[self.active_sheet]
-                            .entities.get_mut(&params.id)
-                            .unwrap().1
-                            .transform
-                            .scale = f;
-                    }
+    /// Fill the sheet size inputs from the active sheet in the current units.
+    fn format_sheet_size(&mut self) {
+        let units = self.settings.borrow().units;
+        let size = self.sheets[self.active_sheet].sheet_size;
 
-                    params.scale = val;
+        self.sheet_size = [
+            units.format(size.x),
+            units.format(size.y),
+        ];
+        self.sheet_margin = units.format(self.sheets[self.active_sheet].margin);
+        self.global_scale = self.sheets[self.active_sheet].global_scale.to_string();
+        self.rotary_diameter = units.format(self.sheets[self.active_sheet].rotary_diameter);
+    }
 
-                    self.sheets[self.active_sheet].recalc_paths();
-                }
-            },
-            Message::EntityParamsFlip(val)=>{
-                let Some(params) = self.entity_params
-                    .as_mut() else {return Task::none()};
+    /// The corners of the next keep-out zone in mm if they are all valid.
+    fn parse_keep_out(&self)->Option<[f64; 4]> {
+        let units = self.settings.borrow().units;
+        let mut out = [0.0; 4];
+        for (val, input) in out.iter_mut().zip(self.keep_out_input.iter()) {
+            *val = units.parse(input)?;
+        }
 
-                params.flip = val;
-                self.sheets[self.active_sheet]
-                    .entities.get_mut(&params.id)
-                    .unwrap().1
-                    .flip = val;
+        return Some(out);
+    }
 
-                self.sheets[self.active_sheet].recalc_paths();
-            },
-            Message::EntityParamsCondition(id)=>{
-                let Some(params) = self.entity_params
-                    .as_mut() else {return Task::none()};
+    /// The entities on the active sheet that "Save GCODE for selection" cuts: the shift+clicked
+    /// ones and the one that was clicked normally.
+    fn gcode_selection(&self)->Vec<EntityId> {
+        let sheet = &self.sheets[self.active_sheet];
+        let mut ids = sheet.selection.iter().copied().collect::<Vec<_>>();
+        if let Some(params) = &self.entity_params {
+            if sheet.entities.contains_key(&params.id) && !ids.contains(&params.id) {
+                ids.push(params.id);
+            }
+        }
 
-                params.laser_condition = id;
-                self.sheets[self.active_sheet]
-                    .entities.get_mut(&params.id)
-                    .unwrap().1
-                    .laser_condition = id;
+        return ids;
+    }
+
+    /// The pattern in [`MainProgram::pattern_input`] in mm, if every input is valid. There has to
+    /// be more than one copy.
+    fn parse_pattern(&self)->Option<Pattern> {
+        let units = self.settings.borrow().units;
+        let count = |s: &String|s.trim().parse::<usize>().ok().filter(|n|*n > 0);
+        let input = &self.pattern_input;
 
-                self.sheets[self.active_sheet].recalc_paths();
+        let pattern = match self.pattern_kind {
+            PatternKind::Linear=>Pattern::Linear {
+                columns: count(&input[0])?,
+                rows: count(&input[1])?,
+                spacing: Vector::new(units.parse(&input[2])?, units.parse(&input[3])?),
             },
-            Message::DeleteEntity=>{
-                let Some(params) = self.entity_params
-                    .as_mut() else {return Task::none()};
+            PatternKind::Polar=>Pattern::Polar {
+                count: count(&input[0])?,
+                diameter: units.parse(&input[1]).filter(|d|*d > 0.0)?,
+            },
+        };
 
-                self.sheets[self.active_sheet]
-                    .delete_entity(params.id);
+        let copies = match pattern {
+            Pattern::Linear{columns, rows, ..}=>columns * rows,
+            Pattern::Polar{count, ..}=>count,
+        };
+        return (copies > 1).then_some(pattern);
+    }
 
-                self.entity_params = None;
-                self.close_entity_params();
-            },
-            Message::ChangeSheetWidth(val)=>{
-                if let Some(f) = parse_float(&val) {
-                    self.sheet_size[0] = val;
+    /// The index in the active sheet's cutting order of [`MainProgram::resume_from`], if it is one
+    /// of the entities.
+    fn parse_resume_from(&self)->Option<usize> {
+        let number = self.resume_from.trim().parse::<usize>().ok()?;
+        let count = self.sheets[self.active_sheet].entities.len();
 
-                    self.sheets[self.active_sheet].change_width(f);
-                }
-            },
-            Message::ChangeSheetHeight(val)=>{
-                if let Some(f) = parse_float(&val) {
-                    self.sheet_size[1] = val;
+        return (1..=count).contains(&number).then(||number - 1);
+    }
 
-                    self.sheets[self.active_sheet].change_height(f);
+    /// A button for each entity on the active sheet in cutting order with its label, model,
+    /// condition, and position. Only entities matching [`MainProgram::entity_search`] are
+    /// included.
+    fn entity_list_rows(&self)->Vec<Element<Message>> {
+        let sheet = &self.sheets[self.active_sheet];
+        let units = self.settings.borrow().units;
+        let store = self.conditions.get_store();
+        let store = store.borrow();
+        let search = self.entity_search.to_lowercase();
+        let selected = self.entity_params.as_ref().map(|params|params.id);
+
+        sheet.ordered_entities()
+            .filter_map(|(id, (handle, mt))|{
+                let label = sheet.entity_label(id);
+                let condition = &store.get_or_default(mt.laser_condition).name;
+                let matches = [label, handle.name(), condition.as_str()]
+                    .iter()
+                    .any(|s|s.to_lowercase().contains(&search));
+                if !matches {
+                    return None;
                 }
-            },
-            Message::SaveGcode(opt_file)=>{
-                if let Some(file) = opt_file {
-                    let mut path = file.path().to_path_buf();
 
-                    // ensure there is a file extension
-                    if path.extension().is_none() {
-                        path.set_extension(".gcode");
-                    }
+                let pos = sheet.entity_position(id).unwrap_or_default();
+                let style = if selected == Some(id) {
+                    widget::button::primary
+                } else {
+                    widget::button::secondary
+                };
 
-                    let gcode = self.sheet_settings[self.active_sheet]
-                        .gcode
-                        .take()
-                        .unwrap_or(String::new());
+                Some(widget::button(column![
+                    text!("{label}"),
+                    text!(
+                        "{} - {condition} - X: {} Y: {}",
+                        handle.name(),
+                        units.length(pos.x),
+                        units.length(pos.y),
+                    ).size(12.0),
+                ])
+                    .width(Length::Fill)
+                    .style(style)
+                    .on_press(Message::Sheet(SheetMessage::SelectEntity(id)))
+                    .into())
+            })
+            .collect()
+    }
 
-                    match std::fs::write(path, gcode) {
-                        Err(e)=>eprintln!("Error saving GCODE file: {e}"),
-                        _=>eprintln!("Saved GCODE file"),
-                    }
-                }
-            },
-            Message::OpenGcodeSaveDialog=>{
-                let start = std::time::Instant::now();
+    /// The entities on the active sheet that don't fit on it.
+    fn oversized_entities(&self)->Vec<EntityId> {
+        let sheet = &self.sheets[self.active_sheet];
+
+        sheet.entities.keys()
+            .copied()
+            .filter(|id|sheet.oversize_fix(*id).is_some())
+            .collect()
+    }
 
-                let settings = &mut self.sheet_settings[self.active_sheet];
-                let gcode = self.sheets[self.active_sheet]
-                    .generate_gcode(settings.name.as_str());
-                settings.gcode = Some(gcode);
+    /// The entities on the active sheet that don't fit on it, but did `before`.
+    fn newly_oversized(&self, before: Vec<EntityId>)->Vec<EntityId> {
+        self.oversized_entities()
+            .into_iter()
+            .filter(|id|!before.contains(id))
+            .collect()
+    }
 
-                let elapsed = start.elapsed();
-                eprintln!("GCODE Generated in {elapsed:?}");
+    /// Ask what to do with each of the entities on the active sheet that doesn't fit. `notify`
+    /// also adds it to the notification log.
+    fn prompt_oversized(&mut self, ids: Vec<EntityId>, notify: bool)->Task<Message> {
+        let mut tasks = Vec::new();
+        for id in ids {
+            let sheet = &self.sheets[self.active_sheet];
+            if sheet.oversize_fix(id).is_none() || self.fit_prompts.contains(&(self.active_sheet, id)) {
+                continue;
+            }
 
-                let future = AsyncFileDialog::new()
-                    .add_filter("GCODE Files", &["gcode", "nc"])
-                    .set_title("Save GCODE file")
-                    .set_file_name(format!("{}.gcode", self.sheet_settings[self.active_sheet].name))
-                    .save_file();
-                return Task::perform(future, Message::SaveGcode);
-            },
-            Message::ToggleConditionEditor=>{
-                if !self.open_condition_editor() {
-                    self.close_condition_editor();
-                }
-            },
-            Message::Iced(event)=>{
-                if let Event::Window(window::Event::CloseRequested) = event {
-                    self.conditions.save();
-                    return window::get_latest().and_then(window::close);
-                }
+            if notify {
+                let text = format!("`{}` is bigger than the sheet", sheet.entity_label(id));
+                tasks.push(self.notify(Severity::Error, text));
             }
-            Message::ClearModels=>self.models.clear(),
+            self.fit_prompts.push((self.active_sheet, id));
         }
 
-        return Task::none();
+        return Task::batch(tasks);
     }
 
-    fn open_condition_editor(&mut self)->bool {
-        let pane = self.panes.iter()
-            .map(|(p,s)|(*p,*s))
-            .find(|(_,state)|*state==ProgramPane::Sheet);
-        if let Some((pane, _)) = pane {
-            *self.panes
-                .get_mut(pane)
-                .unwrap() = ProgramPane::ConditionEditor;
-            return true;
-        }
+    /// Fill every input of the entity params from the entity after it was changed somewhere else.
+    fn refresh_entity_params(&mut self) {
+        let sheet = &self.sheets[self.active_sheet];
+        let Some(params) = &mut self.entity_params else {return};
+        let Some((_, mt)) = sheet.entities.get(&params.id) else {return};
 
-        return false;
+        params.angle = entity_angle(mt);
+        params.angle_string = params.angle.to_string();
+        params.scale = mt.transform.scale.to_string();
+        self.format_entity_position();
     }
 
-    fn close_condition_editor(&mut self)->bool {
-        let pane = self.panes.iter()
-            .map(|(p,s)|(*p,*s))
-            .find(|(_,state)|*state==ProgramPane::ConditionEditor);
-        if let Some((pane, _)) = pane {
-            *self.panes
-                .get_mut(pane)
-                .unwrap() = ProgramPane::Sheet;
-            return true;
-        }
+    /// Fill the position inputs of the entity params from the entity in the current units.
+    fn format_entity_position(&mut self) {
+        let units = self.settings.borrow().units;
+        let sheet = &self.sheets[self.active_sheet];
 
-        return false;
+        if let Some(params) = &mut self.entity_params {
+            if let Some(pos) = sheet.entity_position(params.id) {
+                params.x = units.format(pos.x);
+                params.y = units.format(pos.y);
+            }
+        }
     }
 
     fn close_entity_params(&mut self) {
@@ -862,32 +3778,35 @@ impl MainProgram {
         }
     }
 }
-impl Default for MainProgram {
-    fn default()->Self {
-        use pane_grid::{
-            Configuration,
-            Axis,
-        };
+impl MainProgram {
+    pub fn new(layout: &WindowLayout)->Self {
         let conditions = ConditionEditor::load();
         let models = ModelStore::new();
-        let sheet = Sheet::new(models.clone(), conditions.get_store());
+        let machine = Rc::new(RefCell::new(MachineProfile::load()));
+        let settings = Rc::new(RefCell::new(Settings::load()));
+        let sheet = Sheet::new(models.clone(), conditions.get_store(), machine.clone(), settings.clone());
+        let units = settings.borrow().units;
+        let min_line_length = machine.borrow().min_line_length.to_string();
+        let s_scale = machine.borrow().s_scale.to_string();
 
         MainProgram {
             sheet_size: [
-                format!("{}", sheet.sheet_size.x),
-                format!("{}", sheet.sheet_size.y),
+                units.format(sheet.sheet_size.x),
+                units.format(sheet.sheet_size.y),
             ],
-            panes: PaneState::with_configuration(Configuration::Split {
-                axis: Axis::Vertical,
-                ratio: 0.8,
-                a: Box::new(Configuration::Pane(ProgramPane::Sheet)),
-                b: Box::new(Configuration::Split {
-                    axis: Axis::Horizontal,
-                    ratio: 0.5,
-                    a: Box::new(Configuration::Pane(ProgramPane::SheetList)),
-                    b: Box::new(Configuration::Pane(ProgramPane::ModelList)),
-                }),
-            }),
+            sheet_margin: units.format(sheet.margin),
+            global_scale: sheet.global_scale.to_string(),
+            rotary_diameter: units.format(sheet.rotary_diameter),
+            min_line_length,
+            s_scale,
+            resume_from: String::new(),
+            entity_gap: Default::default(),
+            pattern_kind: PatternKind::default(),
+            pattern_input: Default::default(),
+            pending_pattern: None,
+            keep_out_input: Default::default(),
+            bookmark_name: String::new(),
+            panes: PaneState::with_configuration(layout.pane_config()),
             models,
             active_sheet: 0,
             sheets: vec![sheet],
@@ -897,26 +3816,130 @@ impl Default for MainProgram {
                 index: 0,
             }],
             model_pane_state: ModelPaneState::AllModels,
+            entity_search: String::new(),
             entity_params: None,
+            copy_entity: None,
+            sheet_import: None,
+            model_import: None,
+            text_dialog: None,
+            fit_prompts: Vec::new(),
             conditions,
+            machine,
+            settings,
+            calibration_gcode: None,
+            gcode_progress: None,
+            notifications: Vec::new(),
+            toasts: Vec::new(),
+            window_size: layout.window_size(),
+            hovered_files: Vec::new(),
         }
     }
 }
 
 
 fn main()->iced::Result {
+    // with any arguments we generate GCODE without the GUI
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if !args.is_empty() {
+        if let Err(e) = cli::run(args) {
+            eprintln!("Error: {e:#}");
+            std::process::exit(1);
+        }
+
+        return Ok(());
+    }
+
+    let layout = WindowLayout::load();
+
     iced::application(
         "LaserCAM",
         MainProgram::update,
         MainProgram::view,
     )
-        .subscription(|_|event::listen().map(Message::Iced))
+        .subscription(|_|Subscription::batch([
+            event::listen().map(Message::Iced),
+            event::listen_with(undo_shortcut),
+            event::listen_with(selection_shortcut),
+        ]))
         .exit_on_close_request(false)
         .centered()
-        .theme(|_|Theme::Dark)
-        .run()
+        .window_size(layout.window_size())
+        .theme(MainProgram::theme)
+        .run_with(move||(MainProgram::new(&layout), Task::none()))
+}
+
+/// If the file has an extension in [`MODEL_EXTENSIONS`].
+fn is_model_file(path: &StdPath)->bool {
+    path.extension()
+        .and_then(|ext|ext.to_str())
+        .map(|ext|MODEL_EXTENSIONS.iter().any(|e|e.eq_ignore_ascii_case(ext)))
+        .unwrap_or(false)
+}
+
+/// Ctrl+Z undoes laser condition changes. Text inputs capture every key press, so this listens to
+/// captured events too.
+fn undo_shortcut(event: Event, _status: event::Status, _window: window::Id)->Option<Message> {
+    match event {
+        Event::Keyboard(keyboard::Event::KeyPressed{key: keyboard::Key::Character(c), modifiers, ..})
+            if modifiers.command() && c.as_str() == "z"=>Some(Message::Condition(ConditionMessage::UndoCondition)),
+        _=>None,
+    }
+}
+
+/// Tab and Ctrl+A change the selection on the sheet. Text inputs capture Ctrl+A, but not Tab, so
+/// [`Message::SelectionShortcut`] checks that none of them have focus.
+fn selection_shortcut(event: Event, status: event::Status, _window: window::Id)->Option<Message> {
+    if status == event::Status::Captured {
+        return None;
+    }
+
+    match event {
+        Event::Keyboard(keyboard::Event::KeyPressed{key, modifiers, ..}) if key == keyboard::Key::Named(keyboard::key::Named::Tab)=>{
+            Some(Message::SelectionShortcut(key, modifiers))
+        },
+        Event::Keyboard(keyboard::Event::KeyPressed{key: keyboard::Key::Character(c), modifiers, ..})
+            if modifiers.command() && c.as_str() == "a"=>Some(Message::SelectionShortcut(keyboard::Key::Character(c), modifiers)),
+        _=>None,
+    }
+}
+
+/// The entity's angle in degrees from 0 to 360.
+fn entity_angle(mt: &EntityState)->f64 {
+    let angle = mt.angle().to_degrees();
+    if angle < 0.0 {
+        return angle + 360.0;
+    }
+
+    return angle;
+}
+
+/// The path of the file, making sure it has an extension.
+fn gcode_file_path(file: &FileHandle)->PathBuf {
+    let mut path = file.path().to_path_buf();
+
+    // ensure there is a file extension
+    if path.extension().is_none() {
+        path.set_extension("gcode");
+    }
+
+    return path;
+}
+
+/// Ask if the file can be replaced.
+fn confirm_overwrite(path: &StdPath)->impl std::future::Future<Output = bool> {
+    let future = AsyncMessageDialog::new()
+        .set_level(MessageLevel::Warning)
+        .set_title("Replace file?")
+        .set_description(format!("`{}` already exists. Do you want to replace it?", path.display()))
+        .set_buttons(MessageButtons::YesNo)
+        .show();
+
+    async move {
+        future.await == MessageDialogResult::Yes
+    }
 }
 
+
 pub fn parse_float(s: &str)->Option<f64> {
     if s.len() == 0 {
         return Some(0.0);
@@ -934,6 +3957,74 @@ pub fn parse_u16(s: &str)->Option<u16> {
     num.map(|n|if n > u16::MAX as u32 {u16::MAX} else {n as u16})
 }
 
+/// The title bar control to maximize or restore a pane.
+fn maximize_button<'a>(is_maximized: bool)->Element<'a, Message> {
+    let label = if is_maximized {"Restore"} else {"Maximize"};
+
+    widget::button(text(label).size(12.0))
+        .padding([2.0, 5.0])
+        .on_press(Message::ToggleMaximizeSheet)
+        .into()
+}
+
+/// A color that can use the theme's color instead. `default` is used when switching from the
+/// theme's color.
+fn color_setting<'a>(
+    label: &'a str,
+    color: Option<[u8; 3]>,
+    default: [u8; 3],
+    on_change: impl Fn(Option<[u8; 3]>)->Message + Copy + 'a,
+)->Element<'a, Message> {
+    let mut items = vec![
+        widget::checkbox(format!("{label}: use the theme's color"), color.is_none())
+            .on_toggle(move|b|on_change(if b {None} else {Some(default)}))
+            .into(),
+    ];
+
+    if let Some([r, g, b]) = color {
+        let channels = [
+            ("R: ", r, 0),
+            ("G: ", g, 1),
+            ("B: ", b, 2),
+        ];
+        for (name, value, i) in channels {
+            items.push(row![
+                text(name),
+                widget::slider(0..=255u8, value, move|value|{
+                    let mut rgb = [r, g, b];
+                    rgb[i] = value;
+                    on_change(Some(rgb))
+                }),
+            ]
+                .align_y(VerticalAlign::Center)
+                .into());
+        }
+    }
+
+    widget::column(items)
+        .spacing(5.0)
+        .into()
+}
+
+pub fn warning_text(_theme: &Theme)->widget::text::Style {
+    widget::text::Style {
+        color: Some(WARNING_COLOR),
+    }
+}
+
+pub fn warning_button(theme: &Theme, status: ButtonStatus)->widget::button::Style {
+    let alpha = match status {
+        ButtonStatus::Active|ButtonStatus::Pressed=>1.0,
+        ButtonStatus::Hovered=>0.8,
+        ButtonStatus::Disabled=>0.5,
+    };
+    widget::button::Style {
+        background: Some(Background::Color(WARNING_COLOR.scale_alpha(alpha))),
+        text_color: Color::BLACK,
+        ..widget::button::secondary(theme, status)
+    }
+}
+
 pub fn danger_button(theme: &Theme, status: ButtonStatus)->widget::button::Style {
     let palette = theme.extended_palette();
     let danger = palette.danger;
@@ -960,3 +4051,78 @@ pub fn danger_button(theme: &Theme, status: ButtonStatus)->widget::button::Style
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::LineString;
+
+
+    /// A program with a 10mm square on the first sheet and its params pane open.
+    fn program_with_params()->(MainProgram, EntityId) {
+        let mut program = MainProgram::new(&WindowLayout::default());
+        let square = LineString::from(vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0), (0.0, 0.0)]);
+        let handle = program.models.add(Model::new(vec![square], "square".into()));
+        let condition = ron::from_str::<ConditionId>("(0)").unwrap();
+        let id = program.sheets[0].add_model_from_handle(handle, 1, condition)[0];
+
+        let _ = program.update(Message::Sheet(SheetMessage::Focus(id)));
+        assert!(program.entity_params.is_some());
+        assert!(program.params_open());
+
+        return (program, id);
+    }
+
+    impl MainProgram {
+        fn params_open(&self)->bool {
+            self.panes.iter().any(|(_, state)|*state == ProgramPane::EntityParams)
+        }
+    }
+
+    #[test]
+    fn params_for_a_deleted_entity_are_closed() {
+        let (mut program, id) = program_with_params();
+        program.sheets[0].delete_entity(id);
+
+        let _ = program.update(Message::EntityParamsX("5".into()));
+        assert!(program.entity_params.is_none());
+        assert!(!program.params_open());
+
+        // nothing to delete anymore
+        let _ = program.update(Message::DeleteEntity);
+        assert!(program.sheets[0].entities.is_empty());
+    }
+
+    #[test]
+    fn params_close_when_switching_sheets() {
+        let (mut program, id) = program_with_params();
+        let _ = program.update(Message::NewSheet);
+        assert!(program.entity_params.is_none());
+        assert!(!program.params_open());
+
+        // the entity isn't on the new sheet, so this can't move it
+        let _ = program.update(Message::EntityParamsY("50".into()));
+        let _ = program.update(Message::SelectSheet(0));
+        assert_eq!(program.sheets[0].entity_position(id), Some(Point::zero()));
+    }
+
+    #[test]
+    fn missing_conditions_get_the_default() {
+        let id = |n: u64|ron::from_str::<ConditionId>(&format!("({n})")).unwrap();
+        let store = ron::from_str::<ConditionStore>(
+            r#"(default: Some((1)), conditions: {(1): (id: (1), color: (r: 1.0, g: 0.2, b: 0.2), name: "Cut", sequence: [])})"#,
+        ).unwrap();
+        let store = Rc::new(RefCell::new(store));
+
+        let models = ModelStore::new();
+        let square = LineString::from(vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0), (0.0, 0.0)]);
+        let handle = models.add(Model::new(vec![square], "square".into()));
+        let mut sheet = Sheet::new(models, store.clone(), Rc::default(), Rc::default());
+        let ids = sheet.add_model_from_handle(handle, 2, id(2));
+
+        MainProgram::validate_sheet(&mut sheet, &store.borrow());
+        for entity in ids {
+            assert_eq!(sheet.entities[&entity].1.laser_condition, id(1));
+        }
+    }
+}
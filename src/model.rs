@@ -1,6 +1,11 @@
 use dxf::{
-    entities::EntityType,
+    entities::{
+        Entity as DxfEntity,
+        EntityType,
+        Line as DxfLine,
+    },
     Drawing,
+    Point as DxfPoint,
 };
 use geo::{
     Centroid,
@@ -11,6 +16,8 @@ use geo::{
     Contains,
     Area,
     ConvexHull,
+    Intersects,
+    Rect,
 };
 use iced::widget::canvas::path::{
     Builder as PathBuilder,
@@ -20,6 +27,10 @@ use anyhow::{
     Result,
     bail,
 };
+use serde::{
+    Serialize,
+    Deserialize,
+};
 use std::{
     fmt::{
         Display,
@@ -27,6 +38,7 @@ use std::{
         Result as FmtResult,
     },
     hash::{
+        DefaultHasher,
         Hash,
         Hasher,
     },
@@ -35,10 +47,21 @@ use std::{
         Ref,
     },
     cmp::PartialOrd,
+    collections::{
+        HashMap,
+        VecDeque,
+    },
     ops::Deref,
     rc::Rc,
-    sync::Arc,
-    path::Path as StdPath,
+    sync::{
+        Arc,
+        OnceLock,
+    },
+    path::{
+        Path as StdPath,
+        PathBuf,
+    },
+    time::UNIX_EPOCH,
     result::Result as StdResult,
 };
 use crate::{
@@ -49,12 +72,15 @@ use crate::{
     sheet::EntityState,
     utils::*,
     gcode::*,
+    font::TextParams,
     Point,
     Rotation,
+    Vector,
 };
 
 
 /// Which axis is "up" in the model so we can rotate it.
+#[derive(Copy, Clone, PartialEq)]
 enum ModelMode {
     ZUp,
     XUp,
@@ -77,21 +103,65 @@ impl Display for ModelLoadError {
     }
 }
 
+/// Problems with a model that don't stop it from loading, but the user should know about.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ModelLoadWarning {
+    /// The DXF has entities other than lines, arcs, and circles. These are ignored.
+    UnsupportedEntities,
+    /// The contour at the index does not end where it starts. `gap` is the distance between the
+    /// start and end. Open contours give incorrect hit-testing and GCODE.
+    OpenContour {
+        index: usize,
+        gap: f64,
+    },
+    /// A line segment or a contour's area is smaller than the laser can cut cleanly, usually from
+    /// digitizing. See [`Model::small_feature_warning`].
+    SmallFeature {
+        min_segment_mm: f64,
+        min_area_mm2: f64,
+    },
+    /// The font doesn't have these characters, so they are boxes in a text model.
+    MissingGlyphs {
+        chars: String,
+        font: String,
+    },
+}
+impl Display for ModelLoadWarning {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        use ModelLoadWarning::*;
+        match self {
+            UnsupportedEntities=>write!(f,"We only support lines, arcs, and circles in DXF files. Anything else is IGNORED!"),
+            OpenContour{index, gap}=>write!(f,"Contour {index} is open with a gap of {gap:.4}"),
+            SmallFeature{min_segment_mm, min_area_mm2}=>write!(
+                f,
+                "The shortest segment is {min_segment_mm:.4}mm and the smallest contour is {min_area_mm2:.4}mm². Features this small might not cut cleanly",
+            ),
+            MissingGlyphs{chars, font}=>write!(f,"The font `{font}` doesn't have `{chars}`. They are drawn as boxes"),
+        }
+    }
+}
+
 
 /// A closed shape with one polygon or more polygons that may have holes.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Shape {
     parts: MultiPolygon,
     hull: Polygon,
+    /// If each line in [`Model::lines_iter`] order was open when loaded. Polygons always close
+    /// their lines, so open lines end with a closing point that isn't cut. Models cached before
+    /// this was added don't have it, so their lines are all closed.
+    #[serde(default)]
+    open_lines: Vec<bool>,
     pub min: Point,
     pub max: Point,
 }
 impl Shape {
-    /// Creates a clockwise circle
+    /// Creates a clockwise circle. No point on the circle is more than `tolerance` away from the
+    /// polygon. [`DEFAULT_ARC_TOLERANCE`] is a good default.
     #[allow(unused)]
-    pub fn circle(r: f64, min_points: usize, max_dist: f64)->Self {
+    pub fn circle(r: f64, min_points: usize, tolerance: f64)->Self {
         let mut line = LineString::from(
-            ArcToPoints::new_circle(r, min_points, max_dist, true)
+            ArcToPoints::new_circle(r, min_points, tolerance, true, 0.0)
                 .map(|p|p.to_geo())
                 .collect::<Vec<_>>()
         );
@@ -102,6 +172,7 @@ impl Shape {
         return Self {
             parts: outline.clone().into(),
             hull: outline,
+            open_lines: vec![false],
             min: Point::new(-r, -r),
             max: Point::new(r, r),
         };
@@ -109,7 +180,7 @@ impl Shape {
 
     /// NOTE: We sort the lines by area, so holes are more likely to be put into an outline instead
     /// of by themselves. We also assume the outline has a larger area than its holes, which makes
-    /// sense.
+    /// sense. Outlines are made counter-clockwise and holes clockwise.
     pub fn from_lines(lines: Vec<LineString>)->Self {
         let mut min = Point::new(f64::MAX, f64::MAX);
         let mut max = Point::new(f64::MIN, f64::MIN);
@@ -140,42 +211,68 @@ impl Shape {
                 max.x = max.x.max(max_x);
                 max.y = max.y.max(max_y);
 
+                let open = match (l.0.first(), l.0.last()) {
+                    (Some(first), Some(last))=>(last.to_uv() - first.to_uv()).mag() > CLOSED_TOLERANCE,
+                    _=>false,
+                };
+
                 let p = Polygon::new(l, Vec::new());
                 let a = p.unsigned_area();
-                (p, a)
+                (p, a, open)
             })
             .collect::<Vec<_>>();
 
-        polys.sort_by(|(_, a1), (_, a2)|a1.partial_cmp(a2).unwrap());
+        // largest first, so outlines are found before their holes
+        polys.sort_by(|(_, a1, _), (_, a2, _)|a2.partial_cmp(a1).unwrap());
 
-        let (largest_idx, _) = polys.iter()
-            .map(|(_, area)|*area)
-            .enumerate()
-            .min_by(|(_, a1), (_, a2)|a1.partial_cmp(a2).unwrap())
-            .unwrap();
-
-        let mut top_level = vec![polys.remove(largest_idx).0];
+        // each polygon with its area, if its outline is open, and if each of its holes are open
+        let mut top_level: Vec<(Polygon, f64, bool, Vec<bool>)> = Vec::new();
 
-        'poly_iter:for (poly, _) in polys {
-            for outline in top_level.iter_mut() {
+        'poly_iter:for (poly, area, open) in polys {
+            for (outline, _, _, holes_open) in top_level.iter_mut() {
                 if outline.contains(&poly) {
                     let line = poly.into_inner().0;
                     outline.interiors_push(line);
+                    holes_open.push(open);
 
                     continue 'poly_iter;
                 }
             }
 
-            top_level.push(poly);
+            top_level.push((poly, area, open, Vec::new()));
         }
 
-        let parts = MultiPolygon::new(top_level);
+        // cut the smallest parts first, so parts inside another part's hole are cut before it
+        // falls out
+        top_level.sort_by(|(_, a1, ..), (_, a2, ..)|a1.partial_cmp(a2).unwrap());
+
+        // outlines go counter-clockwise and holes go clockwise. Open lines aren't real rings, so
+        // they are left alone.
+        for (poly, _, open, holes_open) in top_level.iter_mut() {
+            if !*open {
+                poly.exterior_mut(|line|if signed_area(&line.0) < 0.0 {line.0.reverse()});
+            }
+            poly.interiors_mut(|holes|{
+                for (hole, open) in holes.iter_mut().zip(holes_open.iter()) {
+                    if !*open && signed_area(&hole.0) > 0.0 {
+                        hole.0.reverse();
+                    }
+                }
+            });
+        }
+
+        // the same order as `Model::lines_iter`
+        let open_lines = top_level.iter()
+            .flat_map(|(_, _, open, holes_open)|holes_open.iter().copied().chain(std::iter::once(*open)))
+            .collect();
+        let parts = MultiPolygon::new(top_level.into_iter().map(|(poly, ..)|poly).collect());
 
         let hull = parts.convex_hull();
 
         return Shape {
             parts,
             hull,
+            open_lines,
             min,
             max,
         };
@@ -204,28 +301,172 @@ impl Shape {
     }
 }
 
+/// The file extensions models can be loaded from.
+pub const MODEL_EXTENSIONS: &[&str] = &["dxf"];
+
+/// Which kind of [`Pattern`] to make.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum PatternKind {
+    #[default]
+    Linear,
+    Polar,
+}
+impl PatternKind {
+    pub const LIST: &[Self] = &[
+        Self::Linear,
+        Self::Polar,
+    ];
+}
+impl Display for PatternKind {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        match self {
+            Self::Linear=>write!(f, "Rectangular"),
+            Self::Polar=>write!(f, "Circular"),
+        }
+    }
+}
+
+/// Copies of a model's contours arranged in a grid or around a circle. See [`Model::pattern`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Pattern {
+    /// A grid of copies. `spacing` is from the center of one copy to the next.
+    Linear {
+        columns: usize,
+        rows: usize,
+        spacing: Vector,
+    },
+    /// Copies evenly spaced on a circle around the model's center. Each copy is turned to face the
+    /// center the same way the first one does.
+    Polar {
+        count: usize,
+        diameter: f64,
+    },
+}
+impl Pattern {
+    /// Scale the distances, so a pattern in mm on the sheet can be made in the model's units.
+    pub fn scaled(self, factor: f64)->Self {
+        match self {
+            Self::Linear{columns, rows, spacing}=>Self::Linear {
+                columns,
+                rows,
+                spacing: spacing * factor,
+            },
+            Self::Polar{count, diameter}=>Self::Polar {
+                count,
+                diameter: diameter * factor,
+            },
+        }
+    }
+
+    /// Where each copy goes, as the rotation around the model's center and then the offset.
+    fn placements(&self)->Vec<(Rotation, Vector)> {
+        match *self {
+            Self::Linear{columns, rows, spacing}=>(0..rows)
+                .flat_map(|row|(0..columns).map(move|column|{
+                    (Rotation::identity(), Vector::new(column as f64 * spacing.x, row as f64 * spacing.y))
+                }))
+                .collect(),
+            Self::Polar{count, diameter}=>{
+                use std::f64::consts::TAU;
+
+                let radius = diameter / 2.0;
+                (0..count)
+                    .map(|i|{
+                        let angle = i as f64 * TAU / count as f64;
+                        (Rotation::from_angle(angle), Vector::new(angle.cos(), angle.sin()) * radius)
+                    })
+                    .collect()
+            },
+        }
+    }
+}
+
 /// A model loaded from a DXF. We take in a list of lines from the DXF and process it to extract
 /// the outline and AABB. Once created, nothing can change. Transforms are stored externally.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Model {
     shape: Shape,
     pub name: String,
+    /// Any problems found when loading the model.
+    #[serde(default)]
+    pub warnings: Vec<ModelLoadWarning>,
+    /// The file the model was loaded from. This is what projects save to find the model again.
+    #[serde(skip)]
+    pub path: PathBuf,
+    /// The scale new entities of this model get, from the import options. Projects save each
+    /// entity's scale, so this isn't saved.
+    #[serde(skip, default = "default_import_scale")]
+    pub import_scale: f64,
+    /// The arc tolerance the model was loaded with, so it is loaded the same way when replaced.
+    #[serde(skip, default = "default_arc_tolerance")]
+    pub arc_tolerance: f64,
+    /// What the model was made from if it is text instead of a DXF. Projects save this.
+    #[serde(skip)]
+    pub text: Option<TextParams>,
+    #[serde(skip)]
+    local_paths: LocalPaths,
 }
 impl Model {
-    /// Load a new model from a file path. See [`Model::new`] and [`load_model`] for more information.
-    pub fn load<P: AsRef<StdPath>>(path: P)->Result<Self> {
-        load_model(path)
+    /// Load a new model from a file path. Arcs and circles are made into lines at most
+    /// `arc_tolerance` from the curve. See [`Model::new`] and [`load_model`] for more information.
+    pub fn load<P: AsRef<StdPath>>(path: P, arc_tolerance: f64)->Result<Self> {
+        load_model(path, arc_tolerance)
     }
 
     /// Create a new model from a list of lines. The largest one is assumed to be the outline. Each
     /// other line is tested to see if it contains the other line, then they are inserted as holes.
-    fn new(lines: Vec<LineString>, name: String)->Self {
+    pub fn new(lines: Vec<LineString>, name: String)->Self {
         let shape = Shape::from_lines(lines);
 
         Model {
             shape,
             name,
+            warnings: Vec::new(),
+            path: PathBuf::new(),
+            import_scale: default_import_scale(),
+            arc_tolerance: default_arc_tolerance(),
+            text: None,
+            local_paths: LocalPaths::default(),
+        }
+    }
+
+    /// A new model with a copy of every line of this one at each place in the pattern. Polar
+    /// patterns are centered on this model's center, and the first copy of a linear pattern is
+    /// where this model is.
+    pub fn pattern(&self, pattern: Pattern)->Self {
+        let center = (self.shape.min + self.shape.max) / 2.0;
+
+        let mut lines = Vec::new();
+        for (rotation, offset) in pattern.placements() {
+            for line in self.lines_iter() {
+                let coords = line.coords()
+                    .map(|c|{
+                        let mut p = c.to_uv() - center;
+                        rotation.rotate_vec(&mut p);
+                        (p + center + offset).to_geo()
+                    })
+                    .collect::<Vec<_>>();
+                lines.push(LineString::new(coords));
+            }
+        }
+
+        return Model::new(lines, format!("{} pattern", self.name));
+    }
+
+    /// Write the lines as DXF `LINE`s, so [`Model::load`] reads back exactly these lines.
+    pub fn save_dxf(&self, path: &StdPath)->Result<()> {
+        let mut drawing = Drawing::new();
+        for line in self.lines_iter() {
+            for segment in line.lines() {
+                drawing.add_entity(DxfEntity::new(EntityType::Line(DxfLine::new(
+                    DxfPoint::new(segment.start.x, segment.start.y, 0.0),
+                    DxfPoint::new(segment.end.x, segment.end.y, 0.0),
+                ))));
+            }
         }
+
+        drawing.save_file(path)?;
+        return Ok(());
     }
 
     /// Generate the gcode for this model with the given transform, laser power, and feedrate.
@@ -233,19 +474,42 @@ impl Model {
     /// The generated code includes laser on const, laser off, and proper feeds and speeds for
     /// safety. After each line we set laser power to 0 and rapid move to the next line. After all
     /// lines are done, we turn the laser off.
-    pub fn generate_gcode(&self, mt: &EntityState, builder: &mut GcodeBuilder, laser_condition: &Condition) {
-        builder.comment_block(format!(
-            "Start model `{}` with laser condition `{}` and {} sequence items",
-            self.name,
+    ///
+    /// `label` is the entity's name for the comments. Sub-programs are shared, so they don't have
+    /// one.
+    pub fn generate_gcode(&self, mt: &EntityState, builder: &mut GcodeBuilder, laser_condition: &Condition, label: Option<&str>) {
+        let start = match label {
+            Some(label)=>format!("Start entity `{label}` (model `{}`)", self.name),
+            None=>format!("Start model `{}`", self.name),
+        };
+        builder.comment_block_at(CommentLevel::Entity, format!(
+            "{start} with laser condition `{}` and {} sequence items",
             laser_condition.name,
             laser_condition.sequence.len(),
         ));
 
+        // lines are checked after the transform, since a tiny scale can make them too short
+        let lines = self.cut_lines(mt).collect::<Vec<_>>();
+        let mut cut = Vec::with_capacity(lines.len());
+        for (i, line) in lines.iter().enumerate() {
+            let length = line_length(line);
+            let too_short = builder.is_line_too_short(length);
+            if too_short {
+                builder.skip_line(format!("WARNING: Skipped line {i} because it is only {length:.4}mm long"));
+            }
+            cut.push(!too_short);
+        }
+
         for (i, seq) in laser_condition.sequence.iter().enumerate() {
+            if !seq.enabled() {
+                builder.comment_block_at(CommentLevel::Pass, format!("- Skip disabled sequence {}", i + 1));
+                continue;
+            }
+
             let passes_str = if seq.passes() > 1 {"passes"} else {"pass"};
             match seq {
-                Seq::GrblConst{passes, feed, power}|Seq::GrblDyn{passes, feed, power}=>{
-                    builder.comment_block(format!(
+                Seq::GrblConst{passes, feed, power, ..}|Seq::GrblDyn{passes, feed, power, ..}=>{
+                    builder.comment_block_at(CommentLevel::Pass, format!(
                         "- Begin GRBL sequence {} with {} {passes_str} at {}mm/min and {}% power",
                         i + 1,
                         passes,
@@ -254,26 +518,73 @@ impl Model {
                     ));
                 },
                 Seq::Custom{passes, ..}=>{
-                    builder.comment_block(format!(
+                    builder.comment_block_at(CommentLevel::Pass, format!(
                         "- Begin Custom sequence {} with {} {passes_str}",
                         i + 1,
                         passes,
                     ));
                 },
+                Seq::RasterEngrave{passes, feed, power, line_spacing, ..}=>{
+                    builder.comment_block_at(CommentLevel::Pass, format!(
+                        "- Begin raster engrave sequence {} with {} {passes_str} at {}mm/min and {}% power, {}mm apart",
+                        i + 1,
+                        passes,
+                        feed,
+                        (*power as f32) / 10.0,
+                        line_spacing,
+                    ));
+                },
             }
 
             for pass in 0..seq.passes() {
-                builder.comment_block(format!("-- Begin pass {}", pass + 1));
+                builder.comment_block_at(CommentLevel::Pass, format!("-- Begin pass {}", pass + 1));
 
-                self.generate_gcode_lines(builder, mt, &seq);
+                match seq {
+                    Seq::RasterEngrave{line_spacing, ..}=>self.generate_gcode_raster(builder, &lines, seq, *line_spacing),
+                    _=>self.generate_gcode_lines(builder, &lines, &cut, &seq, laser_condition.overcut_mm),
+                }
             }
         }
 
-        builder.comment_block(format!("End model `{}`", self.name));
+        builder.comment_block_at(CommentLevel::Entity, format!("End model `{}`", self.name));
+        builder.entity_separator();
+    }
+
+    /// The area of the convex hull after the entity's transform is applied.
+    pub fn hull_area(&self, mt: &EntityState)->f64 {
+        let scale = mt.transform.scale;
+
+        // rotating and flipping don't change the area
+        return self.shape.hull.unsigned_area() * scale * scale;
+    }
+
+    /// The area of the parts without their holes after the entity's transform is applied.
+    pub fn part_area(&self, mt: &EntityState)->f64 {
+        let scale = mt.transform.scale;
+
+        // rotating and flipping don't change the area
+        return self.shape.parts.unsigned_area() * scale * scale;
+    }
+
+    /// The total length of every line after the entity's transform is applied. This is how far the
+    /// laser travels while cutting in a single pass.
+    pub fn path_length(&self, mt: &EntityState)->f64 {
+        let length = self.lines_iter()
+            .enumerate()
+            .map(|(i, line)|self.cut_coords(i, line)
+                .windows(2)
+                .map(|pair|(pair[1].to_uv() - pair[0].to_uv()).mag())
+                .sum::<f64>()
+            )
+            .sum::<f64>();
+
+        // rotating and flipping don't change the length
+        return length * mt.transform.scale;
     }
 
     /// Iterate over the lines. Do the interior lines first, then the outline for each polygon.
-    fn lines_iter(&self)->impl Iterator<Item = &LineString> {
+    /// This is the order the lines are cut in, and the laser follows the order of their points.
+    pub fn lines_iter(&self)->impl Iterator<Item = &LineString> {
         self.shape.parts.iter()
             .map(|p|{
                 let ext = p.exterior();
@@ -286,50 +597,85 @@ impl Model {
             .flatten()
     }
 
-    /// For each line we move to the start, turn on the laser, set the power and feedrate, perform
-    /// the cutting motion, turn off the laser, and repeat.
-    fn generate_gcode_lines(&self, builder: &mut GcodeBuilder, mt: &EntityState, seq: &Seq) {
-        let iter = self.lines_iter().enumerate();
+    /// A warning if a segment is shorter than `min_size` mm, or a closed contour is smaller than a
+    /// `min_size` square, after the [`Model::import_scale`]. A `min_size` of 0 never warns.
+    pub fn small_feature_warning(&self, min_size: f64)->Option<ModelLoadWarning> {
+        let scale = self.import_scale;
+        let min_segment_mm = self.lines_iter()
+            .flat_map(|line|line.0.windows(2))
+            .map(|pair|(pair[1].to_uv() - pair[0].to_uv()).mag() * scale)
+            .fold(f64::INFINITY, f64::min);
+        let min_area_mm2 = self.lines_iter()
+            .enumerate()
+            .filter(|(i, _)|!self.is_line_open(*i))
+            .map(|(_, line)|signed_area(&line.0).abs() * scale * scale)
+            .fold(f64::INFINITY, f64::min);
+
+        if min_segment_mm < min_size || min_area_mm2 < min_size * min_size {
+            return Some(ModelLoadWarning::SmallFeature {min_segment_mm, min_area_mm2});
+        }
 
-        for (i, line) in iter {
-            builder.comment_block(format!("--- Start line {i}"));
+        return None;
+    }
+
+    /// If the line at index `i` of [`Model::lines_iter`] was open when it was loaded.
+    pub fn is_line_open(&self, i: usize)->bool {
+        self.shape.open_lines.get(i).copied().unwrap_or(false)
+    }
+
+    /// The points of the line at index `i` of [`Model::lines_iter`] that are cut. Closed lines end
+    /// on their first point, but open lines don't get a move back to the start.
+    fn cut_coords<'a>(&self, i: usize, line: &'a LineString)->&'a [Coord] {
+        let coords = &line.0;
+        if self.is_line_open(i) && coords.len() > 2 {
+            return &coords[..coords.len() - 1];
+        }
+
+        return coords;
+    }
+
+    /// Each line's points on the sheet in the order the laser follows them. This is the same
+    /// order as the GCODE, including the entity's start vertex.
+    pub fn cut_lines<'a>(&'a self, mt: &'a EntityState)->impl Iterator<Item = Vec<Point>> + 'a {
+        let start_vertex = mt.start_vertex
+            .and_then(|p|self.closest_vertex(p));
 
-            // create an iterator of the points and transform them
-            let mut points_iter = line.coords()
-                .map(|p|mt.transform(p.to_uv()));
+        self.lines_iter()
+            .enumerate()
+            .map(move|(i, line)|{
+                let coords = self.cut_coords(i, line);
+                let mut points = ordered_coords(coords, !self.is_line_open(i), i, start_vertex)
+                    .map(|p|mt.transform(p.to_uv()))
+                    .collect::<Vec<_>>();
+
+                // make sure closed lines finish exactly where they started
+                if !self.is_line_open(i) && coords.first() != coords.last() {
+                    if let Some(first) = points.first().copied() {
+                        points.push(first);
+                    }
+                }
+
+                points
+            })
+    }
+
+    /// For each line we move to the start, turn on the laser, set the power and feedrate, perform
+    /// the cutting motion, turn off the laser, and repeat. `lines` are from [`Model::cut_lines`]
+    /// and lines that are `false` in `cut` are skipped. Closed lines are cut again for `overcut`
+    /// mm past their start.
+    fn generate_gcode_lines(&self, builder: &mut GcodeBuilder, lines: &[Vec<Point>], cut: &[bool], seq: &Seq, overcut: f64) {
+        for (i, (line, _)) in lines.iter().zip(cut).enumerate().filter(|(_, (_, cut))|**cut) {
+            builder.comment_block_at(CommentLevel::Line, format!("--- Start line {i}"));
+
+            let mut points_iter = line.iter();
 
             let start = points_iter.next().unwrap();
-            builder.rapid_motion()
+            builder.travel_motion()
                 .x(start.x)
                 .y(start.y)
                 .eob();
 
-            match seq {
-                Seq::GrblConst{power, feed, ..}=>{
-                    builder.cutting_motion()
-                        .laser_power(*power)
-                        .feed(*feed)
-                        .laser_on_const()
-                        .eob();
-                },
-                Seq::GrblDyn{power, feed, ..}=>{
-                    builder.cutting_motion()
-                        .laser_power(*power)
-                        .feed(*feed)
-                        .laser_on_dyn()
-                        .eob();
-                },
-                Seq::Custom{laser_on, feed, power, ..}=>{
-                    builder
-                        .custom(power.clone())
-                        .custom(feed.clone())
-                        .eob();
-
-                    builder
-                        .custom(laser_on.clone())
-                        .eob();
-                },
-            }
+            builder.sequence_laser_on(seq);
 
             for point in points_iter {
                 builder.cutting_motion()
@@ -338,21 +684,87 @@ impl Model {
                     .eob();
             }
 
-            match seq {
-                Seq::GrblConst{..}|Seq::GrblDyn{..}=>{
-                    builder.cutting_motion()
-                        .laser_power(0)
-                        .laser_off()
-                        .eob();
-                },
-                Seq::Custom{laser_off, ..}=>{
-                    builder.custom(laser_off.clone())
-                        .eob();
-                },
+            let overcut_points = overcut_points(line, overcut);
+            if !overcut_points.is_empty() {
+                builder.comment_block_at(CommentLevel::Line, format!("--- Overcut {overcut}mm"));
+            }
+            for point in overcut_points {
+                builder.cutting_motion()
+                    .x(point.x)
+                    .y(point.y)
+                    .eob();
+            }
+
+            builder.sequence_laser_off(seq);
+        }
+    }
+
+    /// Fill the inside of the lines with horizontal lines `spacing` apart from the bottom of the
+    /// model to the top. Every other line goes right to left so the laser doesn't travel back
+    /// across the model each time. `lines` are from [`Model::cut_lines`].
+    fn generate_gcode_raster(&self, builder: &mut GcodeBuilder, lines: &[Vec<Point>], seq: &Seq, spacing: f64) {
+        // the spacing is read from files without being checked, and the rows would never end
+        if spacing.is_nan() || spacing <= 0.0 {
+            builder.comment_block_at(CommentLevel::Entity, format!("WARNING: Skipped raster engrave because the line spacing is {spacing}"));
+            return;
+        }
+
+        let (min, max) = lines.iter()
+            .flatten()
+            .fold(
+                (Point::new(f64::MAX, f64::MAX), Point::new(f64::MIN, f64::MIN)),
+                |(min, max), p|(min.min_by_component(*p), max.max_by_component(*p)),
+            );
+
+        let mut y = min.y + spacing / 2.0;
+        let mut row = 0;
+        while y < max.y {
+            let mut spans = scan_line_spans(lines, y);
+            if row % 2 == 1 {
+                spans.reverse();
+                for span in spans.iter_mut() {
+                    *span = (span.1, span.0);
+                }
             }
+
+            for (start, end) in spans {
+                builder.comment_block_at(CommentLevel::Line, format!("--- Raster line {row}"));
+                builder.travel_motion()
+                    .x(start)
+                    .y(y)
+                    .eob();
+                builder.sequence_laser_on(seq);
+                builder.cutting_motion()
+                    .x(end)
+                    .y(y)
+                    .eob();
+                builder.sequence_laser_off(seq);
+            }
+
+            y += spacing;
+            row += 1;
         }
     }
 
+    /// Find the vertex closest to the point in model space. Returns the index of the line in
+    /// [`Model::lines_iter`] and the index of the vertex in the line.
+    pub fn closest_vertex(&self, point: Point)->Option<(usize, usize)> {
+        let mut closest = None;
+        let mut closest_dist = f64::MAX;
+
+        for (i, line) in self.lines_iter().enumerate() {
+            for (j, p) in line.coords().enumerate() {
+                let dist = (p.to_uv() - point).mag_sq();
+                if dist < closest_dist {
+                    closest_dist = dist;
+                    closest = Some((i, j));
+                }
+            }
+        }
+
+        return closest;
+    }
+
     /// Check if a point is within the outline of this model.
     /// We assume the given point is in model space and any transforms are performed prior to
     /// receiving it.
@@ -366,58 +778,94 @@ impl Model {
         return self.shape.hull.contains(&Coord{x:point.x,y:point.y});
     }
 
-    /// Build the [`iced::Path`]s from this model and a transform.
-    /// TODO(optimization): Reuse built paths and transform them instead of creating new ones every
-    /// time.
-    pub fn paths(&self, mt: EntityState, height: f64)->ModelPaths {
-        let mut paths = Vec::new();
+    /// The [`iced::Path`]s of this model in model space (Y up). These are only built once and the
+    /// entity's transform is applied when drawing.
+    pub fn local_paths(&self)->&[Path] {
+        self.local_paths.0.get_or_init(||{
+            let mut paths = Vec::new();
 
-        let mut min = Point::new(f64::MAX, f64::MAX);
-        let mut max = Point::new(-f64::MAX, -f64::MAX);
+            for (i, line) in self.lines_iter().enumerate() {
+                // build the line based on the points
+                let mut builder = PathBuilder::new();
+                let mut points_iter = self.cut_coords(i, line)
+                    .iter()
+                    .map(|p|p.to_iced());
 
-        for line in self.lines_iter() {
-            // build the line based on the points
-            let mut builder = PathBuilder::new();
-            let mut points_iter = line.coords()
-                .copied()
-                .map(|p|{
-                    let p = mt.transform(p.to_uv());
-                    min.x = min.x.min(p.x);
-                    min.y = min.y.min(p.y);
-                    max.x = max.x.max(p.x);
-                    max.y = max.y.max(p.y);
-
-                    p.to_ydown(height).to_iced()
-                });
+                let start = points_iter.next().unwrap();
+                builder.move_to(start);
 
-            let start = points_iter.next().unwrap();
-            builder.move_to(start);
+                for point in points_iter {
+                    builder.line_to(point);
+                }
 
-            for point in points_iter {
-                builder.line_to(point);
+                if !self.is_line_open(i) {
+                    builder.close();
+                }
+
+                paths.push(builder.build());
             }
 
-            builder.close();
+            paths
+        })
+    }
+
+    /// The `(min, max)` corners of the AABB after the entity's transform is applied.
+    pub fn bounds(&self, mt: &EntityState)->(Point, Point) {
+        let mut min = Point::new(f64::MAX, f64::MAX);
+        let mut max = Point::new(-f64::MAX, -f64::MAX);
 
-            paths.push(builder.build());
+        // The AABB of the transformed hull is the same as the AABB of all the transformed points,
+        // and the hull has far fewer points.
+        for p in self.shape.hull.exterior().coords() {
+            let p = mt.transform(p.to_uv());
+            min.x = min.x.min(p.x);
+            min.y = min.y.min(p.y);
+            max.x = max.x.max(p.x);
+            max.y = max.y.max(p.y);
+        }
+
+        return (min, max);
+    }
+
+    /// If the convex hull touches the rectangle on the sheet after the entity's transform is
+    /// applied.
+    pub fn hull_intersects_rect(&self, mt: &EntityState, min: Point, max: Point)->bool {
+        // the hull can only touch the rectangle if the bounding box around it does
+        let (box_min, box_max) = mt.apply_to_bounds(self.shape.min, self.shape.max);
+        if box_max.x < min.x || box_max.y < min.y || box_min.x > max.x || box_min.y > max.y {
+            return false;
         }
 
+        let exterior = self.shape.hull.exterior()
+            .coords()
+            .map(|p|mt.transform(p.to_uv()).to_geo())
+            .collect::<Vec<_>>();
+        let hull = Polygon::new(LineString::new(exterior), Vec::new());
+
+        return hull.intersects(&Rect::new(min.to_geo(), max.to_geo()));
+    }
+
+    /// Build the outline and label position from this model and a transform. The lines
+    /// themselves are in [`Model::local_paths`].
+    pub fn paths(&self, mt: EntityState)->ModelPaths {
+        let (min, max) = self.bounds(&mt);
+
         // Build the outline as a rectangle based on the AABB
         let mut builder = PathBuilder::new();
-        builder.move_to(Point::new(min.x, min.y).to_ydown(height).to_iced());
-        builder.line_to(Point::new(max.x, min.y).to_ydown(height).to_iced());
-        builder.line_to(Point::new(max.x, max.y).to_ydown(height).to_iced());
-        builder.line_to(Point::new(min.x, max.y).to_ydown(height).to_iced());
+        builder.move_to(Point::new(min.x, min.y).to_iced());
+        builder.line_to(Point::new(max.x, min.y).to_iced());
+        builder.line_to(Point::new(max.x, max.y).to_iced());
+        builder.line_to(Point::new(min.x, max.y).to_iced());
         builder.close();
 
         let ret = ModelPaths {
             outline: builder.build(),
-            lines: paths,
             display_center: self.shape.hull.centroid()
                 .unwrap().0
                 .transformed(mt.transform)
-                .to_ydown(height)
-                .to_iced(),
+                .to_uv(),
+            min,
+            max,
         };
 
         return ret;
@@ -429,20 +877,31 @@ impl Model {
 struct LineBuilder(Vec<Coord>);
 impl LineBuilder {
     /// Try to add a segment to the line. If the first point in the segment is the same as the last
-    /// point in the line, then add it. If not then return it in a `Result::Err`. This signals the
-    /// caller to finish this line and start a new one.
+    /// point in the line, then add it. DXFs don't keep the direction of segments, so a segment
+    /// whose last point matches is added reversed. If neither matches then return it in a
+    /// `Result::Err`. This signals the caller to finish this line and start a new one.
     pub fn try_add(&mut self, seg: Segment)->StdResult<(), Segment> {
         let seg2 = (seg.0.to_geo(), seg.1.to_geo());
         if self.0.is_empty() {
             self.0.push(seg2.0);
             self.0.push(seg2.1);
+            return Ok(());
+        }
+
+        // with only one segment we don't know its direction yet, so it can be flipped too
+        if self.0.len() == 2 && self.0[1] != seg2.0 && self.0[1] != seg2.1
+            && (self.0[0] == seg2.0 || self.0[0] == seg2.1)
+        {
+            self.0.reverse();
+        }
+
+        let last = *self.0.last().unwrap();
+        if last == seg2.0 {
+            self.0.push(seg2.1);
+        } else if last == seg2.1 {
+            self.0.push(seg2.0);
         } else {
-            let last = self.0.last().unwrap();
-            if *last == seg2.0 {
-                self.0.push(seg2.1);
-            } else {
-                return Err(seg);
-            }
+            return Err(seg);
         }
 
         return Ok(());
@@ -462,11 +921,22 @@ impl LineBuilder {
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Segment(pub Point, pub Point);
 
-/// The [`iced::Path`]s created from a [`Model`].
+/// The transformed outline and label position of a [`Model`]. These are on the sheet in Y up, so
+/// they have to be projected to the screen when drawn.
 pub struct ModelPaths {
     pub outline: Path,
-    pub lines: Vec<Path>,
-    pub display_center: iced::Point,
+    pub display_center: Point,
+    /// The corners of the bounding box on the sheet. The same as [`Model::bounds`].
+    pub min: Point,
+    pub max: Point,
+}
+
+/// Lazily built [`Model::local_paths`]. These are derived from the shape, so they are ignored when
+/// comparing models.
+#[derive(Debug, Clone, Default)]
+struct LocalPaths(OnceLock<Vec<Path>>);
+impl PartialEq for LocalPaths {
+    fn eq(&self, _: &Self)->bool {true}
 }
 
 /// The ID of a [`Model`] stored in a [`ModelStore`].
@@ -476,6 +946,20 @@ impl ModelHandle {
     pub fn name(&self)->&str {
         self.1.name.as_str()
     }
+
+    /// The `(width, height)` of the model's bounding box before it is transformed.
+    pub fn dimensions(&self)->(f64, f64) {
+        let shape = &self.1.shape;
+        (shape.max.x - shape.min.x, shape.max.y - shape.min.y)
+    }
+
+    /// How many points there are in all of the model's lines. Models with a lot of them are slow
+    /// to draw and make big GCODE files.
+    pub fn path_segment_count(&self)->usize {
+        self.lines_iter()
+            .map(|line|line.0.len())
+            .sum()
+    }
 }
 impl Deref for ModelHandle {
     type Target = Model;
@@ -505,8 +989,10 @@ impl Display for ModelHandle {
 ///
 /// When cloned, this refers to the same model store. It is cheap to clone being just an
 /// `Rc<RefCell>`.
+///
+/// Removed models leave an empty slot so the IDs of the other models don't change.
 #[derive(Debug, Clone, PartialEq)]
-pub struct ModelStore(Rc<RefCell<Vec<Arc<Model>>>>);
+pub struct ModelStore(Rc<RefCell<Vec<Option<Arc<Model>>>>>);
 impl ModelStore {
     pub fn new()->Self {
         ModelStore(Rc::new(RefCell::new(Vec::new())))
@@ -517,13 +1003,22 @@ impl ModelStore {
         let mut models = self.0.borrow_mut();
         let model = Arc::new(model);
         let id = ModelHandle(models.len(), model.clone());
-        models.push(model);
+        models.push(Some(model));
         return id;
     }
 
+    /// Remove a model from the store. Entities using it have to be removed from the sheets
+    /// separately.
+    pub fn remove(&self, handle: &ModelHandle) {
+        if let Some(slot) = self.0.borrow_mut().get_mut(handle.0) {
+            *slot = None;
+        }
+    }
+
     /// How many models do we have stored?
-    #[allow(unused)]
-    pub fn count(&self)->usize {self.0.borrow().len()}
+    pub fn count(&self)->usize {
+        self.0.borrow().iter().filter(|model|model.is_some()).count()
+    }
 
     /// Create an iterator over all the models
     pub fn iter<'a>(&'a self)->ModelIter<'a> {
@@ -533,41 +1028,122 @@ impl ModelStore {
     pub fn clear(&self) {
         self.0.borrow_mut().clear();
     }
+
+    /// Load a model and add it to the store. If we have already parsed this file and it has not
+    /// been modified since, then we load the parsed model from the cache instead of the DXF.
+    ///
+    /// Any errors with the cache are logged and we fall back to loading the DXF.
+    pub fn load_cached(&self, path: &StdPath, arc_tolerance: f64)->Result<ModelHandle> {
+        return Ok(self.add(load_model_cached(path, arc_tolerance)?));
+    }
+
+    /// [`ModelStore::load_cached`], but new entities of the model are added with `scale`. Models
+    /// with features smaller than `min_feature_size` mm get a warning.
+    pub fn import(&self, path: &StdPath, scale: f64, arc_tolerance: f64, min_feature_size: f64)->Result<ModelHandle> {
+        let mut model = load_model_cached(path, arc_tolerance)?;
+        model.import_scale = scale;
+        // this isn't cached since the size can change
+        model.warnings.extend(model.small_feature_warning(min_feature_size));
+
+        return Ok(self.add(model));
+    }
+
+    /// Load the model at `path` in place of `handle`'s model. The ID stays the same, so the
+    /// returned handle is equal to `handle`. Sheets keep their own handles, so they have to be
+    /// given the new one with [`Sheet::replace_model`](crate::sheet::Sheet::replace_model).
+    pub fn replace(&self, handle: &ModelHandle, path: &StdPath)->Result<ModelHandle> {
+        let mut model = load_model_cached(path, handle.arc_tolerance)?;
+        model.import_scale = handle.import_scale;
+        let model = Arc::new(model);
+
+        let mut models = self.0.borrow_mut();
+        match models.get_mut(handle.0) {
+            Some(slot @ Some(_))=>*slot = Some(model.clone()),
+            _=>bail!("The model `{}` was removed", handle.name()),
+        }
+
+        return Ok(ModelHandle(handle.0, model));
+    }
+
+    /// Make a model from text and add it to the store.
+    pub fn add_text(&self, params: &TextParams)->Result<ModelHandle> {
+        return Ok(self.add(params.to_model()?));
+    }
+
+    /// Make the model again from the changed text in place of `handle`'s model. Like
+    /// [`ModelStore::replace`], the ID stays the same and sheets have to be given the new handle.
+    pub fn replace_text(&self, handle: &ModelHandle, params: &TextParams)->Result<ModelHandle> {
+        let mut model = params.to_model()?;
+        model.import_scale = handle.import_scale;
+        let model = Arc::new(model);
+
+        let mut models = self.0.borrow_mut();
+        match models.get_mut(handle.0) {
+            Some(slot @ Some(_))=>*slot = Some(model.clone()),
+            _=>bail!("The model `{}` was removed", handle.name()),
+        }
+
+        return Ok(ModelHandle(handle.0, model));
+    }
+
+    /// Get the model made from the same text, or make it with [`ModelStore::add_text`].
+    pub fn get_or_add_text(&self, params: &TextParams)->Result<ModelHandle> {
+        if let Some(handle) = self.iter().find(|handle|handle.text.as_ref() == Some(params)) {
+            return Ok(handle);
+        }
+
+        return self.add_text(params);
+    }
+
+    /// Get the model loaded from `path`, or load it with [`ModelStore::load_cached`] if we don't
+    /// have it yet.
+    pub fn get_or_load(&self, path: &StdPath, arc_tolerance: f64)->Result<ModelHandle> {
+        if let Some(handle) = self.iter().find(|handle|handle.path == path) {
+            return Ok(handle);
+        }
+
+        return self.load_cached(path, arc_tolerance);
+    }
 }
-pub struct ModelIter<'a>(usize, Ref<'a, Vec<Arc<Model>>>);
-impl<'a> ExactSizeIterator for ModelIter<'a> {}
+pub struct ModelIter<'a>(usize, Ref<'a, Vec<Option<Arc<Model>>>>);
 impl<'a> Iterator for ModelIter<'a> {
     type Item = ModelHandle;
 
     fn size_hint(&self)->(usize, Option<usize>) {
         let len = self.1.len() - self.0;
 
-        (len, Some(len))
+        (0, Some(len))
     }
     fn next(&mut self)->Option<ModelHandle> {
-        if self.0 == self.1.len() {
-            return None;
-        }
-
-        let idx = self.0;
-        self.0 += 1;
+        while self.0 < self.1.len() {
+            let idx = self.0;
+            self.0 += 1;
 
-        let model = self.1[idx].clone();
+            // skip removed models
+            if let Some(model) = &self.1[idx] {
+                return Some(ModelHandle(idx, model.clone()));
+            }
+        }
 
-        Some(ModelHandle(idx, model))
+        return None;
     }
 }
 
+/// The default distance (in mm) an arc tessellated with [`ArcToPoints`] may be from the real arc.
+pub const DEFAULT_ARC_TOLERANCE: f64 = 0.01;
+/// The smallest tolerance [`ArcToPoints`] will use.
+const MIN_ARC_TOLERANCE: f64 = 0.000_1;
+/// The most points [`ArcToPoints`] will make for a single arc.
+pub const MAX_ARC_POINTS: usize = 100_000;
+
 /// An iterator returning points along an arc. Might be a circle.
 ///
-/// The points are returned in either clockwise or counter-clockwise order. The arc always starts
-/// on Y=0, X=r and goes "up" or "down" depending on {counter,}-clockwise
-///
-/// Attempts to create an iterator of points with an equal spacing of about `max_dist`. If the
-/// count is lower than `min_points`, then it will use that number of points.
+/// The points are returned in either clockwise or counter-clockwise order. The arc starts at
+/// `start_angle` (`0.0` is Y=0, X=r) and goes "up" or "down" depending on {counter,}-clockwise
 ///
-/// NOTE: The `max_dist` uses the arc distance NOT the point-to-point distance to calculate the
-/// point count.
+/// Uses as few points as it can while keeping the chords within `tolerance` of the real arc, so
+/// small arcs get more points per mm than large ones. If the count is lower than `min_points`,
+/// then it will use that number of points. The count is never more than [`MAX_ARC_POINTS`].
 pub struct ArcToPoints {
     start: Point,
     i: usize,
@@ -577,10 +1153,10 @@ pub struct ArcToPoints {
 impl ArcToPoints {
     /// Minor optimization to make things slightly more accurate
     #[inline]
-    pub fn new_circle(r: f64, min_points: usize, max_dist: f64, clockwise: bool)->Self {
+    pub fn new_circle(r: f64, min_points: usize, tolerance: f64, clockwise: bool, start_angle: f64)->Self {
         use std::f64::consts::TAU;
 
-        Self::new_arc(r, min_points, max_dist, clockwise, TAU)
+        Self::new_arc(r, min_points, tolerance, clockwise, start_angle, TAU)
     }
 
     /// Returns if the arc is clockwise or not.
@@ -590,16 +1166,16 @@ impl ArcToPoints {
         self.step > 0.0
     }
 
-    /// NOTE: Angle is in Radians
-    pub fn new_arc(r: f64, min_points: usize, max_dist: f64, clockwise: bool, angle: f64)->Self {
+    /// NOTE: Angles are in Radians. `angle` is how far the arc goes from `start_angle`.
+    pub fn new_arc(r: f64, min_points: usize, tolerance: f64, clockwise: bool, start_angle: f64, angle: f64)->Self {
         let clockwise = if clockwise {1.0} else {-1.0};
 
-        let length = angle * r;
-        let points = ((length / max_dist).ceil() as usize)
-            .max(min_points);
+        let points = (Self::point_count(r, tolerance, angle.abs()) as usize)
+            .max(min_points)
+            .min(MAX_ARC_POINTS);
         let step = (angle / (points as f64)) * clockwise;
 
-        let start = Point{x: r, y: 0.0};
+        let start = Point{x: r * start_angle.cos(), y: r * start_angle.sin()};
 
         return ArcToPoints {
             start,
@@ -608,6 +1184,28 @@ impl ArcToPoints {
             points,
         };
     }
+
+    /// How many chords the arc needs so the sagitta (the distance from the middle of the chord to
+    /// the arc) of each is at most `tolerance`.
+    fn point_count(r: f64, tolerance: f64, angle: f64)->f64 {
+        // a tolerance of 0 would need infinite points
+        let tolerance = tolerance.max(MIN_ARC_TOLERANCE);
+
+        // Any chord of a tiny arc is within the tolerance, so the largest step is half a turn.
+        // This also keeps `acos` in its domain.
+        if r <= tolerance {
+            return (angle / std::f64::consts::PI).ceil();
+        }
+
+        let max_step = 2.0 * (1.0 - tolerance / r).acos();
+        let count = (angle / max_step).ceil();
+
+        if count.is_nan() {
+            return 0.0;
+        }
+
+        return count;
+    }
 }
 impl Iterator for ArcToPoints {
     type Item = Point;
@@ -625,7 +1223,264 @@ impl Iterator for ArcToPoints {
 }
 
 
-fn load_model<P: AsRef<StdPath>>(path: P)->Result<Model> {
+/// The coordinates of the line at index `i` of [`Model::lines_iter`] in cutting order. A closed line
+/// can start at any vertex, so if `start_vertex` is on this line we skip the duplicate end point
+/// and wrap around to finish where we started.
+fn ordered_coords(coords: &[Coord], closed: bool, i: usize, start_vertex: Option<(usize, usize)>)->impl Iterator<Item = &Coord> {
+    let first = match start_vertex {
+        Some((line_idx, vertex)) if line_idx == i && closed && vertex < coords.len()=>vertex,
+        _=>0,
+    };
+    let wrapped = if first > 0 {&coords[1..=first]} else {&[]};
+
+    coords[first..].iter()
+        .chain(wrapped)
+}
+
+/// Connect the segments end-to-end into chains, flipping them when needed. Ends within `tolerance`
+/// of each other are connected, and the segment is moved so its start is exactly where the chain
+/// ends. Chains are grown from both ends until nothing else touches them.
+fn chain_segments(segments: Vec<Segment>, tolerance: f64)->Vec<Vec<Segment>> {
+    let grid = SegmentGrid::new(&segments, tolerance);
+    let mut used = vec![false; segments.len()];
+    let mut chains = Vec::new();
+
+    for first in 0..segments.len() {
+        if used[first] {
+            continue;
+        }
+        used[first] = true;
+        let mut chain = VecDeque::from([segments[first]]);
+
+        loop {
+            let end = chain.back().unwrap().1;
+            if let Some(idx) = grid.touching(&segments, &used, end) {
+                used[idx] = true;
+                let seg = segments[idx];
+                let other = if grid.touches(seg.0, end) {seg.1} else {seg.0};
+                chain.push_back(Segment(end, other));
+                continue;
+            }
+
+            let start = chain.front().unwrap().0;
+            if let Some(idx) = grid.touching(&segments, &used, start) {
+                used[idx] = true;
+                let seg = segments[idx];
+                let other = if grid.touches(seg.1, start) {seg.0} else {seg.1};
+                chain.push_front(Segment(other, start));
+                continue;
+            }
+
+            break;
+        }
+
+        chains.push(chain.into());
+    }
+
+    return chains;
+}
+
+/// The indices of segments bucketed by their ends in cells `tolerance` wide, so the segments
+/// touching a point are found without looking at all of them.
+struct SegmentGrid {
+    cells: HashMap<(i64, i64), Vec<usize>>,
+    tolerance: f64,
+}
+impl SegmentGrid {
+    fn new(segments: &[Segment], tolerance: f64)->Self {
+        let mut grid = SegmentGrid {
+            cells: HashMap::new(),
+            tolerance: tolerance.max(f64::EPSILON),
+        };
+        for (i, seg) in segments.iter().enumerate() {
+            grid.cells.entry(grid.cell(seg.0)).or_default().push(i);
+            if grid.cell(seg.1) != grid.cell(seg.0) {
+                grid.cells.entry(grid.cell(seg.1)).or_default().push(i);
+            }
+        }
+
+        return grid;
+    }
+
+    fn cell(&self, p: Point)->(i64, i64) {
+        ((p.x / self.tolerance).floor() as i64, (p.y / self.tolerance).floor() as i64)
+    }
+
+    fn touches(&self, a: Point, b: Point)->bool {
+        (a - b).mag() <= self.tolerance
+    }
+
+    /// The first unused segment with an end touching `p`. Touching ends are at most one cell
+    /// away.
+    fn touching(&self, segments: &[Segment], used: &[bool], p: Point)->Option<usize> {
+        let (x, y) = self.cell(p);
+        (x - 1..=x + 1)
+            .flat_map(|cx|(y - 1..=y + 1).map(move|cy|(cx, cy)))
+            .filter_map(|cell|self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .filter(|i|!used[*i])
+            .filter(|i|self.touches(segments[*i].0, p) || self.touches(segments[*i].1, p))
+            .min()
+    }
+}
+
+/// The points to cut after finishing a closed line to go `distance` past its start, following the
+/// line. Open lines and lines shorter than `distance` don't get an overcut.
+fn overcut_points(line: &[Point], distance: f64)->Vec<Point> {
+    let closed = line.len() > 2 && line.first() == line.last();
+    if !closed || distance <= 0.0 || line_length(line) < distance {
+        return Vec::new();
+    }
+
+    let mut out = Vec::new();
+    let mut left = distance;
+    for pair in line.windows(2) {
+        let seg = pair[1] - pair[0];
+        let len = seg.mag();
+        if len >= left {
+            out.push(pair[0] + seg * (left / len));
+            break;
+        }
+
+        left -= len;
+        out.push(pair[1]);
+    }
+
+    return out;
+}
+
+/// The parts of the horizontal line at `y` that are inside the lines, from left to right. Each
+/// line is treated as closed, and a point is inside if it is inside an odd number of them, so holes
+/// are left out.
+fn scan_line_spans(lines: &[Vec<Point>], y: f64)->Vec<(f64, f64)> {
+    let mut crossings = Vec::new();
+    for line in lines.iter().filter(|line|line.len() > 1) {
+        let closing = [*line.last().unwrap(), line[0]];
+        for pair in line.windows(2).chain(std::iter::once(&closing[..])) {
+            let (a, b) = (pair[0], pair[1]);
+            // half-open so a vertex on the scan line is only counted once
+            if (a.y <= y) != (b.y <= y) {
+                crossings.push(a.x + (y - a.y) / (b.y - a.y) * (b.x - a.x));
+            }
+        }
+    }
+
+    crossings.sort_by(|a, b|a.partial_cmp(b).unwrap());
+
+    return crossings.chunks_exact(2)
+        .map(|pair|(pair[0], pair[1]))
+        .filter(|(start, end)|end > start)
+        .collect();
+}
+
+/// The shoelace formula. Counter-clockwise lines are positive and clockwise lines are negative.
+fn signed_area(coords: &[Coord])->f64 {
+    let twice = coords.windows(2)
+        .map(|pair|pair[0].x * pair[1].y - pair[1].x * pair[0].y)
+        .sum::<f64>();
+
+    // the line might not repeat the first point
+    let closing = match (coords.first(), coords.last()) {
+        (Some(first), Some(last))=>last.x * first.y - first.x * last.y,
+        _=>0.0,
+    };
+
+    return (twice + closing) / 2.0;
+}
+
+/// The length of a line through the points.
+fn line_length(points: &[Point])->f64 {
+    points.windows(2)
+        .map(|pair|(pair[1] - pair[0]).mag())
+        .sum()
+}
+
+/// Load a model, using the cache if we have already parsed this file and it has not been modified
+/// since. See [`ModelStore::load_cached`].
+fn load_model_cached(path: &StdPath, arc_tolerance: f64)->Result<Model> {
+    let cache_path = match model_cache_path(path, arc_tolerance) {
+        Ok(p)=>p,
+        Err(e)=>{
+            eprintln!("Could not get model cache path: {e}");
+            return Model::load(path, arc_tolerance);
+        },
+    };
+
+    if cache_path.exists() {
+        match std::fs::read_to_string(&cache_path).map(|s|ron::from_str::<Model>(&s)) {
+            Ok(Ok(mut model))=>{
+                eprintln!("Loaded model `{}` from cache", model.name);
+                model.path = path.to_path_buf();
+                model.arc_tolerance = arc_tolerance;
+                return Ok(model);
+            },
+            Ok(Err(e))=>eprintln!("Error parsing cached model: {e}"),
+            Err(e)=>eprintln!("Error reading cached model: {e}"),
+        }
+    }
+
+    let model = Model::load(path, arc_tolerance)?;
+
+    let write_cache = ron::to_string(&model)
+        .map_err(anyhow::Error::from)
+        .and_then(|s|{
+            std::fs::create_dir_all(cache_path.parent().unwrap())?;
+            std::fs::write(&cache_path, s)?;
+            Ok(())
+        });
+    if let Err(e) = write_cache {
+        eprintln!("Error writing cached model: {e}");
+    }
+
+    return Ok(model);
+}
+
+/// The cache file for a model is named from a hash of its path, modification time and arc
+/// tolerance, so a modified DXF or a different tolerance never matches an old cache file.
+fn model_cache_path(path: &StdPath, arc_tolerance: f64)->Result<PathBuf> {
+    let path = path.canonicalize()?;
+    let mtime = std::fs::metadata(&path)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)?;
+
+    let mut hasher = DefaultHasher::new();
+    MODEL_CACHE_VERSION.hash(&mut hasher);
+    path.hash(&mut hasher);
+    mtime.hash(&mut hasher);
+    arc_tolerance.to_bits().hash(&mut hasher);
+
+    let cache_path = directories::BaseDirs::new()
+        .unwrap()
+        .cache_dir()
+        .to_path_buf()
+        .join("laser_cam")
+        .join("model_cache")
+        .join(format!("{:016x}.ron", hasher.finish()));
+
+    return Ok(cache_path);
+}
+
+fn default_import_scale()->f64 {1.0}
+
+fn default_arc_tolerance()->f64 {DEFAULT_ARC_TOLERANCE}
+
+/// Change this when models are built differently, so old cached models are loaded again.
+const MODEL_CACHE_VERSION: u32 = 2;
+
+/// The segments between each point and the next.
+fn arc_segments(points: impl Iterator<Item = Point>)->Vec<Segment> {
+    let points = points.collect::<Vec<_>>();
+
+    points.windows(2)
+        .map(|pair|Segment(pair[0], pair[1]))
+        .collect()
+}
+
+/// How far apart the start and end of a contour can be while still being closed.
+const CLOSED_TOLERANCE: f64 = 0.001;
+
+fn load_model<P: AsRef<StdPath>>(path: P, arc_tolerance: f64)->Result<Model> {
     let path = path.as_ref();
     let name = path.file_stem()
         .expect("File does not have a name")
@@ -635,83 +1490,281 @@ fn load_model<P: AsRef<StdPath>>(path: P)->Result<Model> {
 
     let mut lines = Vec::new();
 
+    let mut warnings = Vec::new();
     let mut line_warning = false;
-    let mut mode = ModelMode::ZUp;
+    let mut mode = None;
 
-    let mut line_builder = LineBuilder::default();
+    let mut segments = Vec::new();
 
-    for (i, entity) in drawing.entities().enumerate() {
+    for entity in drawing.entities() {
         use ModelMode::*;
 
-        let EntityType::Line(line)=&entity.specific else {line_warning=true;continue};
-
-        if i==0 {
-            let up = &line.extrusion_direction;
-            if up.x == 1.0 {
-                mode = XUp;
-            } else if up.y == 1.0 {
-                mode = YUp;
-            } else if up.z == 1.0 {
-                mode = ZUp;
-            } else {
-                bail!(ModelLoadError::ModelNotInPlane);
-            }
-        }
-
-        let p1;
-        let p2;
+        let up = match &entity.specific {
+            EntityType::Line(line)=>&line.extrusion_direction,
+            EntityType::Arc(arc)=>&arc.normal,
+            EntityType::Circle(circle)=>&circle.normal,
+            _=>{line_warning=true;continue},
+        };
 
-        match mode {
-            ZUp=>{
-                p1 = Point {
-                    x: line.p1.x,
-                    y: line.p1.y,
-                };
-                p2 = Point {
-                    x: line.p2.x,
-                    y: line.p2.y,
+        // the first entity decides which plane the model is in
+        let mode = match mode {
+            Some(mode)=>mode,
+            None=>{
+                let first = if up.x == 1.0 {
+                    XUp
+                } else if up.y == 1.0 {
+                    YUp
+                } else if up.z == 1.0 {
+                    ZUp
+                } else {
+                    bail!(ModelLoadError::ModelNotInPlane);
                 };
+                mode = Some(first);
+                first
             },
-            XUp=>{
-                p1 = Point {
-                    x: line.p1.y,
-                    y: line.p1.z,
-                };
-                p2 = Point {
-                    x: line.p2.y,
-                    y: line.p2.z,
-                };
+        };
+
+        let to_point = |p: &DxfPoint|match mode {
+            ZUp=>Point {x: p.x, y: p.y},
+            XUp=>Point {x: p.y, y: p.z},
+            YUp=>Point {x: p.x, y: p.z},
+        };
+
+        match &entity.specific {
+            EntityType::Line(line)=>segments.push(Segment(to_point(&line.p1), to_point(&line.p2))),
+            // arcs are in the entity's own coordinates, which only match the drawing's when it
+            // faces straight up
+            EntityType::Arc(arc) if mode == ZUp && arc.normal.z == 1.0=>{
+                let start = arc.start_angle.to_radians();
+                let mut sweep = (arc.end_angle - arc.start_angle).rem_euclid(360.0).to_radians();
+                if sweep == 0.0 {
+                    sweep = std::f64::consts::TAU;
+                }
+
+                let center = to_point(&arc.center);
+                let end = center + Vector::new(arc.radius * (start + sweep).cos(), arc.radius * (start + sweep).sin());
+                // DXF arcs go counter-clockwise, which is a positive step for `ArcToPoints`
+                let points = ArcToPoints::new_arc(arc.radius, 1, arc_tolerance, true, start, sweep)
+                    .map(|p|p + center)
+                    .chain(std::iter::once(end));
+                segments.extend(arc_segments(points));
             },
-            YUp=>{
-                p1 = Point {
-                    x: line.p1.x,
-                    y: line.p1.z,
-                };
-                p2 = Point {
-                    x: line.p2.x,
-                    y: line.p2.z,
-                };
+            EntityType::Circle(circle) if mode == ZUp && circle.normal.z == 1.0=>{
+                let center = to_point(&circle.center);
+                let points = ArcToPoints::new_circle(circle.radius, 3, arc_tolerance, true, 0.0)
+                    .map(|p|p + center)
+                    .collect::<Vec<_>>();
+                let first = points[0];
+                segments.extend(arc_segments(points.into_iter().chain(std::iter::once(first))));
             },
+            _=>line_warning = true,
         }
+    }
+
+    // segments can be in any order in the file, so connect them first
+    for chain in chain_segments(segments, CLOSED_TOLERANCE) {
+        let mut line_builder = LineBuilder::default();
 
         // Logic determining when we start a new line
-        match line_builder.try_add(Segment(p1, p2)) {
-            Err(seg)=>{
-                lines.push(line_builder.finish());
-                line_builder = LineBuilder::default();
-                line_builder.try_add(seg).unwrap();
-            },
-            Ok(())=>{},
+        for seg in chain {
+            match line_builder.try_add(seg) {
+                Err(seg)=>{
+                    lines.push(line_builder.finish());
+                    line_builder = LineBuilder::default();
+                    line_builder.try_add(seg).unwrap();
+                },
+                Ok(())=>{},
+            }
         }
-    }
 
-    if !line_builder.is_empty() {
-        lines.push(line_builder.finish());
+        if !line_builder.is_empty() {
+            lines.push(line_builder.finish());
+        }
     }
 
     if line_warning {
-        eprintln!("WARNING: We only support lines in DXF files. Anything else is IGNORED!");
+        warnings.push(ModelLoadWarning::UnsupportedEntities);
+    }
+
+    for (index, line) in lines.iter().enumerate() {
+        let start = line.0.first().unwrap().to_uv();
+        let end = line.0.last().unwrap().to_uv();
+        let gap = (end - start).mag();
+
+        if gap > CLOSED_TOLERANCE {
+            warnings.push(ModelLoadWarning::OpenContour {index, gap});
+        }
+    }
+
+    for warning in warnings.iter() {
+        eprintln!("WARNING: Model `{name}`: {warning}");
+    }
+
+    let mut model = Model::new(lines, name.into());
+    model.warnings = warnings;
+    model.path = path.to_path_buf();
+    model.arc_tolerance = arc_tolerance;
+
+    return Ok(model);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    /// A model from lines given as points.
+    fn model(name: &str, lines: &[&[(f64, f64)]])->Model {
+        let lines = lines.iter()
+            .map(|points|LineString::from(points.to_vec()))
+            .collect();
+
+        Model::new(lines, name.into())
+    }
+
+    fn square()->Model {
+        model("square", &[&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0), (0.0, 0.0)]])
     }
 
-    return Ok(Model::new(lines, name.into()));
+    fn plate_with_hole()->Model {
+        model("plate", &[
+            &[(0.0, 0.0), (40.0, 0.0), (40.0, 20.0), (0.0, 20.0), (0.0, 0.0)],
+            &[(15.0, 5.0), (25.0, 5.0), (25.0, 15.0), (15.0, 15.0), (15.0, 5.0)],
+        ])
+    }
+
+    #[test]
+    fn chain_segments_joins_shuffled_segments() {
+        // a square with its sides out of order, one flipped, and one end a little off
+        let segments = vec![
+            Segment(Point::new(10.0, 10.0), Point::new(0.0, 10.0)),
+            Segment(Point::new(0.0, 0.0), Point::new(10.0, 0.0)),
+            Segment(Point::new(0.0, 0.0), Point::new(0.0, 10.0)),
+            Segment(Point::new(10.0, 0.0), Point::new(10.0, 10.000_5)),
+            // far away from the square
+            Segment(Point::new(50.0, 50.0), Point::new(60.0, 50.0)),
+        ];
+
+        let chains = chain_segments(segments, CLOSED_TOLERANCE);
+        assert_eq!(chains.len(), 2);
+        assert_eq!(chains[0].len(), 4);
+        for pair in chains[0].windows(2) {
+            assert_eq!(pair[0].1, pair[1].0);
+        }
+        assert_eq!(chains[1], vec![Segment(Point::new(50.0, 50.0), Point::new(60.0, 50.0))]);
+    }
+
+    /// The furthest the middle of any chord between the points (and back to the first) is from
+    /// a circle of radius `r` around the origin.
+    fn max_sagitta(points: &[Point], r: f64)->f64 {
+        points.iter()
+            .zip(points.iter().cycle().skip(1))
+            .map(|(a, b)|r - ((*a + *b) / 2.0).mag())
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn arc_chords_are_within_tolerance() {
+        for r in [0.5, 3.0, 25.0, 400.0] {
+            for tolerance in [DEFAULT_ARC_TOLERANCE, 0.1, 1.0] {
+                let points = ArcToPoints::new_circle(r, 3, tolerance, false, 0.3).collect::<Vec<_>>();
+                assert!(max_sagitta(&points, r) <= tolerance + 1e-9, "r {r}, tolerance {tolerance}");
+                assert!(points.iter().all(|p|(p.mag() - r).abs() < 1e-9));
+
+                // one fewer point is too coarse, unless the minimum was used
+                if points.len() > 3 {
+                    let fewer = ArcToPoints::new_circle(r, points.len() - 1, r, false, 0.3).collect::<Vec<_>>();
+                    assert_eq!(fewer.len(), points.len() - 1);
+                    assert!(max_sagitta(&fewer, r) > tolerance, "r {r}, tolerance {tolerance}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn arc_point_count_limits() {
+        // small arcs get more points per mm than large ones
+        let small = ArcToPoints::new_circle(1.0, 0, DEFAULT_ARC_TOLERANCE, true, 0.0).count();
+        let large = ArcToPoints::new_circle(100.0, 0, DEFAULT_ARC_TOLERANCE, true, 0.0).count();
+        assert!(small < large);
+        assert!((small as f64 / 1.0) > (large as f64 / 100.0));
+
+        // a radius under the tolerance uses half turns, and the minimum still applies
+        assert_eq!(ArcToPoints::new_circle(0.001, 0, DEFAULT_ARC_TOLERANCE, true, 0.0).count(), 2);
+        assert_eq!(ArcToPoints::new_circle(0.001, 8, DEFAULT_ARC_TOLERANCE, true, 0.0).count(), 8);
+
+        // a tolerance of 0 doesn't need infinite points
+        assert_eq!(ArcToPoints::new_circle(1e6, 0, 0.0, true, 0.0).count(), MAX_ARC_POINTS);
+
+        // a quarter arc has about a quarter of the circle's points
+        let quarter = ArcToPoints::new_arc(10.0, 0, DEFAULT_ARC_TOLERANCE, false, 0.0, std::f64::consts::FRAC_PI_2).count();
+        let circle = ArcToPoints::new_circle(10.0, 0, DEFAULT_ARC_TOLERANCE, false, 0.0).count();
+        assert!(quarter.abs_diff(circle / 4) <= 1);
+    }
+
+    #[test]
+    fn small_features_warn() {
+        assert_eq!(square().small_feature_warning(0.1), None);
+
+        let jagged = model("jagged", &[&[(0.0, 0.0), (10.0, 0.0), (10.0, 0.05), (10.0, 10.0), (0.0, 10.0), (0.0, 0.0)]]);
+        let Some(ModelLoadWarning::SmallFeature{min_segment_mm, ..}) = jagged.small_feature_warning(0.1) else {
+            panic!("no warning for a 0.05mm segment");
+        };
+        assert!((min_segment_mm - 0.05).abs() < 1e-9);
+        assert_eq!(jagged.small_feature_warning(0.0), None);
+    }
+
+    #[test]
+    fn patterns_copy_every_contour() {
+        let grid = plate_with_hole().pattern(Pattern::Linear {
+            columns: 3,
+            rows: 2,
+            spacing: Vector::new(50.0, 30.0),
+        });
+        assert_eq!(grid.lines_iter().count(), 12);
+        assert_eq!((grid.shape.min, grid.shape.max), (Point::new(0.0, 0.0), Point::new(140.0, 50.0)));
+
+        // a 10mm square centered on (5, 5), so each copy is 20mm from it
+        let circle = square().pattern(Pattern::Polar {
+            count: 4,
+            diameter: 40.0,
+        });
+        assert_eq!(circle.lines_iter().count(), 4);
+        let (min, max) = (circle.shape.min, circle.shape.max);
+        assert!((min - Point::new(-20.0, -20.0)).mag() < 1e-9, "{min:?}");
+        assert!((max - Point::new(30.0, 30.0)).mag() < 1e-9, "{max:?}");
+    }
+
+    #[test]
+    fn arcs_and_circles_load_within_the_tolerance() {
+        use dxf::entities::{Arc as DxfArc, Circle as DxfCircle};
+
+        // a half circle closed by a line, and a circle inside it
+        let mut drawing = Drawing::new();
+        drawing.add_entity(DxfEntity::new(EntityType::Arc(DxfArc::new(DxfPoint::new(0.0, 0.0, 0.0), 20.0, 0.0, 180.0))));
+        drawing.add_entity(DxfEntity::new(EntityType::Line(DxfLine::new(DxfPoint::new(-20.0, 0.0, 0.0), DxfPoint::new(20.0, 0.0, 0.0)))));
+        drawing.add_entity(DxfEntity::new(EntityType::Circle(DxfCircle::new(DxfPoint::new(0.0, 10.0, 0.0), 5.0))));
+        let path = std::env::temp_dir().join(format!("laser_cam_arcs_{}.dxf", std::process::id()));
+        drawing.save_file(&path).unwrap();
+
+        let fine = Model::load(&path, 0.01).unwrap();
+        let coarse = Model::load(&path, 1.0).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(fine.warnings.is_empty(), "{:?}", fine.warnings);
+        assert_eq!(fine.lines_iter().count(), 2);
+        assert!(coarse.lines_iter().map(|l|l.0.len()).sum::<usize>() < fine.lines_iter().map(|l|l.0.len()).sum::<usize>());
+
+        let (min, max) = (fine.shape.min, fine.shape.max);
+        assert!((min - Point::new(-20.0, 0.0)).mag() < 1e-9, "{min:?}");
+        assert!((max - Point::new(20.0, 20.0)).mag() < 1e-9, "{max:?}");
+
+        // every point is on the half circle, its closing line, or the circle
+        for p in fine.lines_iter().flat_map(|l|l.coords()).map(|c|c.to_uv()) {
+            let on_arc = (p.mag() - 20.0).abs() < 1e-9;
+            let on_line = p.y.abs() < 1e-9;
+            let on_circle = ((p - Point::new(0.0, 10.0)).mag() - 5.0).abs() < 1e-9;
+            assert!(on_arc || on_line || on_circle, "{p:?}");
+        }
+    }
 }
@@ -0,0 +1,70 @@
+use time::OffsetDateTime;
+use std::{
+    sync::atomic::{
+        Ordering,
+        AtomicUsize,
+    },
+    time::Duration,
+    future::Future,
+};
+
+
+/// How long a toast is shown before it goes away on its own.
+pub const TOAST_DURATION: Duration = Duration::from_secs(5);
+
+/// The most toasts shown at once. Older ones are still in the notification log.
+pub const MAX_TOASTS: usize = 5;
+
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Severity {
+    Info,
+    /// Something worked, but not completely.
+    Warning,
+    Error,
+}
+
+/// A message for the user. These are shown as toasts and kept in the notification log.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub id: usize,
+    pub text: String,
+    pub severity: Severity,
+    pub time: OffsetDateTime,
+}
+impl Notification {
+    pub fn new(severity: Severity, text: String)->Self {
+        Notification {
+            id: next_notification_id(),
+            text,
+            severity,
+            time: OffsetDateTime::now_local()
+                .unwrap_or(OffsetDateTime::now_utc()),
+        }
+    }
+
+    /// The time as `HH:MM:SS`.
+    pub fn timestamp(&self)->String {
+        format!("{:02}:{:02}:{:02}", self.time.hour(), self.time.minute(), self.time.second())
+    }
+}
+
+
+/// Finish after `duration`. The waiting happens on its own thread so it doesn't hold up the
+/// executor, which also runs GCODE generation.
+pub fn wait(duration: Duration)->impl Future<Output = ()> {
+    let (sender, receiver) = iced::futures::channel::oneshot::channel();
+    std::thread::spawn(move||{
+        std::thread::sleep(duration);
+        let _ = sender.send(());
+    });
+
+    async move {
+        let _ = receiver.await;
+    }
+}
+
+fn next_notification_id()->usize {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    COUNT.fetch_add(1, Ordering::SeqCst)
+}
@@ -0,0 +1,388 @@
+//! Saving and loading sheets as a project file. Projects only reference the model files, so the
+//! DXFs have to be available when loading. Text models are saved as their text and made again.
+//! The laser conditions the sheets use are saved with the project so its sheets can be imported
+//! into another condition store.
+
+
+use anyhow::{
+    Result,
+    Context,
+};
+use serde::{Serialize, Deserialize};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    path::{
+        Path as StdPath,
+        PathBuf,
+    },
+};
+use crate::{
+    laser::{
+        ConditionId,
+        ConditionStore,
+    },
+    machine::MachineProfile,
+    gcode::CommentLevel,
+    settings::Settings,
+    sheet::{
+        EntityState,
+        Origin,
+        Sheet,
+        DEFAULT_ROTARY_DIAMETER,
+        rotation_angle,
+    },
+    model::ModelStore,
+    font::TextParams,
+    Point,
+    Rotation,
+    Transform,
+    Translation,
+    Vector,
+};
+
+
+/// The file extension for projects.
+pub const PROJECT_EXTENSION: &str = "lcam";
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Project {
+    pub sheets: Vec<ProjectSheet>,
+    /// The conditions used by the sheets. Older projects don't have these.
+    #[serde(default)]
+    pub conditions: ConditionStore,
+}
+impl Project {
+    /// Save the sheets with their names. Model paths are made relative to `dir` (the folder the
+    /// project is saved in) when they are inside it, so a project can be moved along with its
+    /// models.
+    pub fn new<'a>(
+        sheets: impl IntoIterator<Item = (&'a str, &'a Sheet)>,
+        conditions: &ConditionStore,
+        dir: &StdPath,
+    )->Self {
+        let sheets = sheets.into_iter()
+            .map(|(name, sheet)|ProjectSheet::new(name, sheet, dir))
+            .collect::<Vec<_>>();
+        let used = sheets.iter()
+            .flat_map(|sheet|sheet.entities.iter())
+            .map(|entity|entity.laser_condition)
+            .collect::<HashSet<_>>();
+
+        Project {
+            sheets,
+            conditions: conditions.subset(&used),
+        }
+    }
+
+    pub fn load(path: &StdPath)->Result<Self> {
+        let s = std::fs::read_to_string(path)
+            .with_context(||format!("Could not read `{}`", path.display()))?;
+        let mut project = ron::from_str::<Project>(&s)
+            .with_context(||format!("Could not parse `{}`", path.display()))?;
+
+        // relative model paths are from the project's folder
+        let dir = path.parent().unwrap_or(StdPath::new(""));
+        for entity in project.sheets.iter_mut().flat_map(|sheet|sheet.entities.iter_mut()) {
+            if entity.text.is_none() && entity.model.is_relative() {
+                entity.model = dir.join(&entity.model);
+            }
+        }
+
+        return Ok(project);
+    }
+
+    pub fn save(&self, path: &StdPath)->Result<()> {
+        use ron::ser::PrettyConfig;
+
+        let mut pc = PrettyConfig::default();
+        pc.struct_names = false;
+
+        let s = ron::ser::to_string_pretty(self, pc)?;
+        std::fs::write(path, s)
+            .with_context(||format!("Could not write `{}`", path.display()))?;
+
+        return Ok(());
+    }
+
+    pub fn sheet(&self, name: &str)->Option<&ProjectSheet> {
+        self.sheets.iter().find(|sheet|sheet.name == name)
+    }
+
+    /// The conditions the sheets' entities use.
+    pub fn used_conditions(&self)->HashSet<ConditionId> {
+        self.sheets.iter()
+            .flat_map(|sheet|sheet.entities.iter())
+            .map(|entity|entity.laser_condition)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectSheet {
+    pub name: String,
+    pub width: f64,
+    pub height: f64,
+    #[serde(default)]
+    pub comment_level: CommentLevel,
+    #[serde(default)]
+    pub blank_lines: bool,
+    #[serde(default)]
+    pub margin: f64,
+    /// `[min x, min y, max x, max y]` in mm.
+    #[serde(default)]
+    pub keep_outs: Vec<[f64; 4]>,
+    #[serde(default)]
+    pub origin: Origin,
+    #[serde(default = "default_global_scale")]
+    pub global_scale: f64,
+    #[serde(default)]
+    pub rotary: bool,
+    #[serde(default = "default_rotary_diameter")]
+    pub rotary_diameter: f64,
+    #[serde(default)]
+    pub material: String,
+    /// In cutting order.
+    pub entities: Vec<ProjectEntity>,
+    /// Groups of the entities. Older projects don't have these.
+    #[serde(default)]
+    pub groups: Vec<ProjectGroup>,
+}
+impl ProjectSheet {
+    fn new(name: &str, sheet: &Sheet, dir: &StdPath)->Self {
+        let ids = sheet.ordered_entities()
+            .map(|(id, _)|id)
+            .collect::<Vec<_>>();
+        let groups = sheet.groups.iter()
+            .map(|group|ProjectGroup {
+                members: group.members.iter()
+                    .filter_map(|member|ids.iter().position(|id|id == member))
+                    .collect(),
+                x: group.transform.translation.x,
+                y: group.transform.translation.y,
+                angle: rotation_angle(group.transform.rotation),
+                scale: group.transform.scale,
+            })
+            .collect();
+
+        let entities = sheet.ordered_entities()
+            .map(|(id, (handle, mt))|{
+                let model = handle.path.strip_prefix(dir)
+                    .unwrap_or(&handle.path)
+                    .to_path_buf();
+
+                ProjectEntity {
+                    model,
+                    x: mt.transform.translation.x,
+                    y: mt.transform.translation.y,
+                    angle: mt.angle(),
+                    scale: mt.transform.scale,
+                    flip: mt.flip,
+                    laser_condition: mt.laser_condition,
+                    start_vertex: mt.start_vertex.map(|p|[p.x, p.y]),
+                    label: Some(sheet.entity_label(id).to_string()),
+                    text: handle.text.clone(),
+                }
+            })
+            .collect();
+
+        ProjectSheet {
+            name: name.to_string(),
+            width: sheet.sheet_size.x,
+            height: sheet.sheet_size.y,
+            comment_level: sheet.comment_level,
+            blank_lines: sheet.blank_lines,
+            margin: sheet.margin,
+            keep_outs: sheet.keep_outs.iter()
+                .map(|(min, max)|[min.x, min.y, max.x, max.y])
+                .collect(),
+            origin: sheet.origin,
+            global_scale: sheet.global_scale,
+            rotary: sheet.rotary,
+            rotary_diameter: sheet.rotary_diameter,
+            material: sheet.material.clone(),
+            entities,
+            groups,
+        }
+    }
+
+    /// Change the entities' laser conditions to their new IDs after the project's conditions were
+    /// merged into another store. Conditions that aren't in `remap` are kept.
+    pub fn remap_conditions(&mut self, remap: &HashMap<ConditionId, ConditionId>) {
+        for entity in self.entities.iter_mut() {
+            if let Some(id) = remap.get(&entity.laser_condition) {
+                entity.laser_condition = *id;
+            }
+        }
+    }
+
+    /// Make a sheet from this. Models that aren't in `models` yet are loaded. Fails if a model
+    /// can't be loaded. Entities keep their laser condition even if it isn't in the store, so
+    /// merge the project's conditions first and check the sheet after.
+    pub fn to_sheet(
+        &self,
+        models: &ModelStore,
+        laser_conditions: Rc<RefCell<ConditionStore>>,
+        machine: Rc<RefCell<MachineProfile>>,
+        settings: Rc<RefCell<Settings>>,
+    )->Result<Sheet> {
+        let mut sheet = Sheet::new(models.clone(), laser_conditions.clone(), machine, settings);
+        sheet.sheet_size = Vector::new(self.width, self.height);
+        sheet.comment_level = self.comment_level;
+        sheet.blank_lines = self.blank_lines;
+        sheet.margin = self.margin;
+        sheet.keep_outs = self.keep_outs.iter()
+            .map(|[x1, y1, x2, y2]|(Point::new(*x1, *y1), Point::new(*x2, *y2)))
+            .collect();
+        sheet.origin = self.origin;
+        // the entities are saved already scaled
+        sheet.global_scale = self.global_scale;
+        // the height is already the circumference
+        sheet.rotary = self.rotary;
+        sheet.rotary_diameter = self.rotary_diameter;
+        sheet.material = self.material.clone();
+
+        let arc_tolerance = sheet.settings.borrow().import_options.arc_tolerance;
+        let mut ids = Vec::new();
+        for entity in self.entities.iter() {
+            let handle = match &entity.text {
+                Some(text)=>models.get_or_add_text(text),
+                None=>models.get_or_load(&entity.model, arc_tolerance),
+            }
+                .with_context(||format!("Sheet `{}`: could not load `{}`", self.name, entity.name()))?;
+
+            let new_ids = sheet.add_model_from_handle_with_transform(handle, entity.state(), 1);
+            if let Some(label) = &entity.label {
+                for id in new_ids.iter() {
+                    sheet.set_entity_label(*id, label.clone());
+                }
+            }
+            ids.extend(new_ids);
+        }
+
+        for group in self.groups.iter() {
+            let members = group.members.iter()
+                .filter_map(|i|ids.get(*i).copied())
+                .collect();
+            let Some(id) = sheet.group(members) else {continue};
+            if let Some(new_group) = sheet.groups.iter_mut().find(|g|g.id == id) {
+                new_group.transform = Transform::new(
+                    Translation::new(group.x, group.y),
+                    Rotation::from_angle(group.angle),
+                    group.scale,
+                );
+            }
+        }
+
+        return Ok(sheet);
+    }
+}
+
+/// A placed model. Positions are in mm and the angle is in radians, the same as [`EntityState`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectEntity {
+    pub model: PathBuf,
+    pub x: f64,
+    pub y: f64,
+    pub angle: f64,
+    pub scale: f64,
+    pub flip: bool,
+    pub laser_condition: ConditionId,
+    #[serde(default)]
+    pub start_vertex: Option<[f64; 2]>,
+    /// The default label is used if this isn't set.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Text models are made from this instead of loading `model`.
+    #[serde(default)]
+    pub text: Option<TextParams>,
+}
+impl ProjectEntity {
+    /// The model's file, or the text for text models.
+    fn name(&self)->String {
+        match &self.text {
+            Some(text)=>text.name(),
+            None=>self.model.display().to_string(),
+        }
+    }
+
+    fn state(&self)->EntityState {
+        EntityState {
+            transform: Transform::new(
+                Translation::new(self.x, self.y),
+                Rotation::from_angle(self.angle),
+                self.scale,
+            ),
+            flip: self.flip,
+            laser_condition: self.laser_condition,
+            start_vertex: self.start_vertex.map(|[x, y]|Point::new(x, y)),
+        }
+    }
+}
+
+/// Entities that are moved together. See [`EntityGroup`](crate::sheet::EntityGroup).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectGroup {
+    /// Indices into [`ProjectSheet::entities`].
+    pub members: Vec<usize>,
+    /// The group's transform, saved the same way as [`ProjectEntity`]'s.
+    pub x: f64,
+    pub y: f64,
+    pub angle: f64,
+    pub scale: f64,
+}
+
+fn default_global_scale()->f64 {1.0}
+
+fn default_rotary_diameter()->f64 {DEFAULT_ROTARY_DIAMETER}
+
+#[cfg(test)]
+mod tests {
+    use geo::LineString;
+    use crate::model::Model;
+    use super::*;
+
+
+    #[test]
+    fn groups_round_trip() {
+        let mut store = ConditionStore::default();
+        let mut remap = HashMap::new();
+        store.merge(
+            ron::from_str(r#"(conditions: {(0): (id: (0), color: (r: 1.0, g: 0.2, b: 0.2), name: "Cut", sequence: [])})"#).unwrap(),
+            &mut remap,
+        );
+        let condition = *remap.values().next().unwrap();
+        let store = Rc::new(RefCell::new(store));
+
+        let models = ModelStore::new();
+        let square = models.add(Model::new(
+            vec![LineString::from(vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0), (0.0, 0.0)])],
+            "square".into(),
+        ));
+        let mut sheet = Sheet::new(models.clone(), store.clone(), Rc::default(), Rc::default());
+        let ids = sheet.add_model_from_handle(square, 3, condition);
+        sheet.group(vec![ids[0], ids[2]]).unwrap();
+        sheet.rotate_scale_entity(ids[0], Rotation::from_angle(0.5), 2.0);
+
+        let saved = ProjectSheet::new("Sheet", &sheet, StdPath::new(""));
+        let saved = ron::from_str::<ProjectSheet>(&ron::to_string(&saved).unwrap()).unwrap();
+        let loaded = saved.to_sheet(&models, store, Rc::default(), Rc::default()).unwrap();
+
+        assert_eq!(loaded.groups.len(), 1);
+        let group = &loaded.groups[0];
+        let labels = group.members.iter()
+            .map(|id|loaded.entity_label(*id))
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec![sheet.entity_label(ids[0]), sheet.entity_label(ids[2])]);
+
+        let original = &sheet.groups[0].transform;
+        assert!((group.transform.translation - original.translation).mag() < 1e-9);
+        assert!((group.transform.scale - original.scale).abs() < 1e-9);
+        assert!((rotation_angle(group.transform.rotation) - rotation_angle(original.rotation)).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,365 @@
+use iced::Theme;
+use serde::{Serialize, Deserialize};
+use time::OffsetDateTime;
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+        Result as FmtResult,
+    },
+    path::PathBuf,
+};
+use crate::model::DEFAULT_ARC_TOLERANCE;
+
+
+/// Program wide settings. These are shared between all sheets.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Settings {
+    #[serde(default)]
+    pub theme: AppTheme,
+    /// The units lengths are shown and entered in. Everything is stored in mm, so changing this
+    /// never changes the geometry.
+    #[serde(default)]
+    pub units: Units,
+    /// How much each scroll zooms in or out. `0.1` is 10%.
+    #[serde(default = "default_zoom_step")]
+    pub zoom_step: f64,
+    /// How far the arrow keys move an entity in screen pixels.
+    #[serde(default = "default_nudge_distance")]
+    pub nudge_distance: f64,
+    /// Line up the bounding box edges of dragged entities with other entities' edges.
+    #[serde(default = "default_entity_snap")]
+    pub entity_snap: bool,
+    /// How close edges have to be to snap in screen pixels.
+    #[serde(default = "default_snap_distance")]
+    pub snap_distance: f64,
+    /// The resolution of exported PNG images.
+    #[serde(default = "default_image_dpi")]
+    pub image_dpi: f64,
+    #[serde(default)]
+    pub canvas: CanvasStyle,
+    /// The folder GCODE was last saved in. GCODE save dialogs start here.
+    #[serde(default)]
+    pub gcode_dir: Option<PathBuf>,
+    /// The suggested GCODE file name without the extension. See [`Settings::gcode_file_name`].
+    #[serde(default = "default_gcode_file_name")]
+    pub gcode_file_name: String,
+    /// Used without asking when [`Settings::ask_import_options`] is off.
+    #[serde(default)]
+    pub import_options: ImportOptions,
+    /// Show the import options before loading DXF files.
+    #[serde(default = "default_ask_import_options")]
+    pub ask_import_options: bool,
+    /// Warn about imported models with segments shorter than this in mm.
+    #[serde(default = "default_min_feature_size")]
+    pub min_feature_size: f64,
+    /// Ask before fitting an entity to the sheet scales it up more than this.
+    #[serde(default = "default_max_fit_scale")]
+    pub max_fit_scale: f64,
+}
+impl Default for Settings {
+    fn default()->Self {
+        Settings {
+            theme: AppTheme::default(),
+            units: Units::default(),
+            zoom_step: default_zoom_step(),
+            nudge_distance: default_nudge_distance(),
+            entity_snap: default_entity_snap(),
+            snap_distance: default_snap_distance(),
+            image_dpi: default_image_dpi(),
+            canvas: CanvasStyle::default(),
+            gcode_dir: None,
+            gcode_file_name: default_gcode_file_name(),
+            import_options: ImportOptions::default(),
+            ask_import_options: default_ask_import_options(),
+            min_feature_size: default_min_feature_size(),
+            max_fit_scale: default_max_fit_scale(),
+        }
+    }
+}
+impl Settings {
+    pub fn load()->Self {
+        let config_path = directories::BaseDirs::new()
+            .unwrap()
+            .config_dir()
+            .to_path_buf()
+            .join("laser_cam")
+            .join("settings.ron");
+
+        if config_path.exists() {
+            let s = std::fs::read_to_string(config_path).expect("Could not read the settings");
+
+            match ron::from_str::<Settings>(&s) {
+                Ok(settings)=>{
+                    eprintln!("Loaded settings");
+                    return settings;
+                },
+                Err(e)=>eprintln!("Error loading settings: {e}"),
+            }
+        }
+
+        return Self::default();
+    }
+
+    pub fn save(&self) {
+        use ron::ser::PrettyConfig;
+
+        let config_path = directories::BaseDirs::new()
+            .unwrap()
+            .config_dir()
+            .to_path_buf()
+            .join("laser_cam");
+        std::fs::create_dir_all(&config_path).unwrap();
+        let config_path = config_path.join("settings.ron");
+
+        let mut pc = PrettyConfig::default();
+        pc.struct_names = false;
+
+        let s = ron::ser::to_string_pretty(self, pc).unwrap();
+        std::fs::write(config_path, s).expect("Could not write settings");
+
+        eprintln!("Saved settings");
+    }
+
+    /// Fill in the placeholders of the file name template: `{sheet}`, `{date}` (`YYYY-MM-DD`),
+    /// `{material}`, and `{condition}`. Characters that can't be in a file name are replaced with
+    /// `_`, and the sheet's name is used if nothing is left.
+    pub fn gcode_file_name(&self, sheet: &str, material: &str, condition: &str)->String {
+        let now = OffsetDateTime::now_local()
+            .unwrap_or(OffsetDateTime::now_utc());
+        let date = format!("{}-{:02}-{:02}", now.year(), now.month() as u8, now.day());
+
+        let name = self.gcode_file_name
+            .replace("{sheet}", sheet)
+            .replace("{date}", &date)
+            .replace("{material}", material)
+            .replace("{condition}", condition);
+        let name = clean_file_name(&name);
+
+        if name.is_empty() {
+            return sheet.to_string();
+        }
+
+        return name;
+    }
+}
+
+/// Replace the characters that can't be in a file name with `_` and trim the whitespace.
+pub fn clean_file_name(name: &str)->String {
+    name.chars()
+        .map(|c|if matches!(c, '/'|'\\'|':'|'*'|'?'|'"'|'<'|'>'|'|') {'_'} else {c})
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// How DXF files are imported.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImportOptions {
+    /// The units the drawing is in. We don't read the units from the file, so this is up to the
+    /// user.
+    pub units: Units,
+    /// Multiplied with the units.
+    pub scale: f64,
+    /// Place one of each model on the active sheet instead of only adding it to the model list.
+    pub add_to_sheet: bool,
+    /// How far (in the drawing's units) the lines made from arcs and circles may be from the
+    /// real curve.
+    pub arc_tolerance: f64,
+}
+impl Default for ImportOptions {
+    fn default()->Self {
+        ImportOptions {
+            units: Units::Millimeters,
+            scale: 1.0,
+            add_to_sheet: true,
+            arc_tolerance: DEFAULT_ARC_TOLERANCE,
+        }
+    }
+}
+impl ImportOptions {
+    /// The scale entities of the imported models are added with.
+    pub fn entity_scale(&self)->f64 {
+        self.units.in_mm(self.scale)
+    }
+}
+
+/// How the sheet canvas is drawn. Colors that aren't set use the theme.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CanvasStyle {
+    pub background: Option<[u8; 3]>,
+    pub sheet_color: Option<[u8; 3]>,
+    pub sheet_width: f32,
+    pub entity_width: f32,
+    /// The outline of the selected entities.
+    pub selected_width: f32,
+    pub order_text_size: f32,
+    /// Line widths are in mm on the sheet instead of screen pixels, so they get thicker when
+    /// zooming in.
+    pub widths_in_mm: bool,
+}
+impl Default for CanvasStyle {
+    fn default()->Self {
+        CanvasStyle {
+            background: None,
+            sheet_color: None,
+            sheet_width: 2.0,
+            entity_width: 1.0,
+            selected_width: 1.0,
+            order_text_size: 16.0,
+            widths_in_mm: false,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum AppTheme {
+    #[default]
+    Dark,
+    Light,
+}
+impl AppTheme {
+    pub const LIST: &[Self] = &[
+        Self::Dark,
+        Self::Light,
+    ];
+
+    pub fn theme(&self)->Theme {
+        match self {
+            Self::Dark=>Theme::Dark,
+            Self::Light=>Theme::Light,
+        }
+    }
+}
+impl Display for AppTheme {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        match self {
+            Self::Dark=>write!(f, "Dark"),
+            Self::Light=>write!(f, "Light"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum Units {
+    #[default]
+    Millimeters,
+    Inches,
+}
+impl Units {
+    pub const LIST: &[Self] = &[
+        Self::Millimeters,
+        Self::Inches,
+    ];
+
+    /// The short name shown after lengths.
+    pub fn suffix(&self)->&'static str {
+        match self {
+            Self::Millimeters=>"mm",
+            Self::Inches=>"in",
+        }
+    }
+
+    /// How many mm are in one of these units.
+    fn mm_per_unit(&self)->f64 {
+        match self {
+            Self::Millimeters=>1.0,
+            Self::Inches=>MM_PER_INCH,
+        }
+    }
+
+    /// How many decimal places are shown for read-only measurements.
+    fn display_precision(&self)->usize {
+        match self {
+            Self::Millimeters=>1,
+            Self::Inches=>3,
+        }
+    }
+
+    /// How many decimal places are kept in text inputs. This is fine enough to not lose anything
+    /// the machine can do when switching back and forth.
+    fn input_precision(&self)->usize {
+        match self {
+            Self::Millimeters=>3,
+            Self::Inches=>4,
+        }
+    }
+
+    /// Convert a length in mm to these units.
+    pub fn in_units(&self, mm: f64)->f64 {
+        mm / self.mm_per_unit()
+    }
+
+    /// Convert a length in these units to mm.
+    pub fn in_mm(&self, value: f64)->f64 {
+        value * self.mm_per_unit()
+    }
+
+    /// Format a length in mm for a text input in these units. Trailing zeros are removed.
+    pub fn format(&self, mm: f64)->String {
+        let s = format!("{:.prec$}", self.in_units(mm), prec = self.input_precision());
+        let s = s.trim_end_matches('0').trim_end_matches('.');
+
+        // don't show `-0` for tiny negative numbers
+        if s == "-0" {
+            return "0".into();
+        }
+
+        return s.to_string();
+    }
+
+    /// Parse a length typed in these units. Returns mm.
+    pub fn parse(&self, s: &str)->Option<f64> {
+        crate::parse_float(s).map(|v|self.in_mm(v))
+    }
+
+    /// Format a length in mm with these units.
+    pub fn length(&self, mm: f64)->String {
+        format!("{:.prec$} {}", self.in_units(mm), self.suffix(), prec = self.display_precision())
+    }
+
+    /// Format a width and height in mm with these units.
+    pub fn size(&self, w: f64, h: f64)->String {
+        let prec = self.display_precision();
+        format!("{:.prec$}×{:.prec$} {}", self.in_units(w), self.in_units(h), self.suffix())
+    }
+
+    /// Format an area in mm² with these units.
+    pub fn area(&self, mm2: f64)->String {
+        let prec = self.display_precision();
+        let mm_per_unit = self.mm_per_unit();
+        format!("{:.prec$} {}²", mm2 / (mm_per_unit * mm_per_unit), self.suffix())
+    }
+}
+impl Display for Units {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        match self {
+            Self::Millimeters=>write!(f, "Millimeters"),
+            Self::Inches=>write!(f, "Inches"),
+        }
+    }
+}
+
+
+/// The number of mm in an inch.
+pub const MM_PER_INCH: f64 = 25.4;
+
+fn default_zoom_step()->f64 {0.1}
+
+fn default_nudge_distance()->f64 {1.0}
+
+fn default_entity_snap()->bool {true}
+
+fn default_snap_distance()->f64 {5.0}
+
+fn default_image_dpi()->f64 {150.0}
+
+fn default_gcode_file_name()->String {"{sheet}".into()}
+
+fn default_ask_import_options()->bool {true}
+
+fn default_min_feature_size()->f64 {0.1}
+
+fn default_max_fit_scale()->f64 {2.0}
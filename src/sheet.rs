@@ -22,9 +22,11 @@ use iced::{
         key::Named as NamedKey,
         Event as KeyboardEvent,
         Key,
+        Modifiers,
     },
     mouse::{
         Cursor,
+        Interaction,
         Event as MouseEvent,
         Button as MouseButton,
         ScrollDelta,
@@ -42,12 +44,18 @@ use iced_graphics::geometry::{
     Renderer as GeometryRenderer,
     Stroke,
     Style,
-    // Fill,
+    Fill,
     LineCap,
     LineJoin,
+    LineDash,
+};
+use indexmap::{
+    IndexSet,
+    IndexMap,
 };
-use indexmap::IndexSet;
 use time::OffsetDateTime;
+use geo::LineString;
+use serde::{Serialize, Deserialize};
 use anyhow::Result;
 use std::{
     collections::{
@@ -59,12 +67,31 @@ use std::{
         Cell,
     },
     rc::Rc,
+    fs::File,
+    io::{
+        self,
+        BufWriter,
+    },
+    path::Path as StdPath,
+    fmt::{
+        Display,
+        Formatter,
+        Result as FmtResult,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
 };
 use crate::{
     laser::{
+        Condition,
         ConditionId,
         ConditionStore,
     },
+    machine::MachineProfile,
+    settings::Settings,
+    export::SheetImage,
     model::*,
     gcode::*,
     utils::*,
@@ -86,28 +113,74 @@ pub enum SheetMessage {
     RecalcPathsId(EntityId),
     /// Select an entity.
     Select(EntityId),
+    /// Select an entity from outside the canvas, like the entity list.
+    SelectEntity(EntityId),
+    /// The selected entity was double-clicked. The main program opens its settings.
+    Focus(EntityId),
     /// Deselect and entity.
     Deselect(EntityId),
-    /// An amount to pan relative to the previous position.
-    Pan(Translation, Translation),
-    /// An amount to move an entity and its index.
+    /// An amount to pan on the screen relative to the previous position.
+    Pan(Translation),
+    /// An amount to move an entity on the sheet and its index.
     Move(EntityId, Translation),
-    /// An amount to move an entity and its index. Also selects the entity.
+    /// An amount to move an entity on the sheet and its index. Also selects the entity.
     SelectMove(EntityId, Translation),
-    /// Contains the the cursor position.
-    ZoomIn(Point, Point),
-    /// Contains the the cursor position.
-    ZoomOut(Point, Point),
+    /// Contains the the cursor position on the screen.
+    ZoomIn(Point),
+    /// Contains the the cursor position on the screen.
+    ZoomOut(Point),
+    /// Go back to 100% zoom, keeping the middle of the canvas in place.
+    ResetZoom,
+    /// Save the current view with a name. A view with the same name is replaced.
+    SaveBookmark(String),
+    /// Go back to the view at the index in [`Sheet::bookmarks`].
+    RestoreBookmark(usize),
 
     Delete(EntityId),
+    /// Add a copy of the entity next to it.
+    Duplicate(EntityId),
+    /// Move the entity so its bounding box is in the middle of the sheet.
+    MoveToCenter(EntityId),
+    /// Scale the entity to fill the usable part of the sheet and center it there. The main program
+    /// asks first if it would be scaled up a lot.
+    FitToSheet(EntityId),
+    /// Copy the entity to another sheet. The main program asks which sheet.
+    CopyToSheet(EntityId),
+    /// Start cutting at the vertex closest to the point on the sheet.
+    SetStartVertex(EntityId, Point),
 
     StartOrder,
     SetShowOrder(bool),
+    /// Draw every entity's bounding box, not just the selected ones.
+    SetShowBoundingBoxes(bool),
+    /// Draw the cutting direction and start point of every line.
+    SetShowDirection(bool),
+    /// Only show the entities with this condition. `None` shows every entity.
+    SetConditionFilter(Option<ConditionId>),
     AddToOrder(EntityId),
     FinishOrder(EntityId),
+    /// See [`Sheet::reorder_by_position`].
+    ReorderByPosition(bool),
+    /// Cut the entities in the opposite order.
+    InvertOrder,
+
+    /// Add or remove an entity from the multi-selection. Used to make groups.
+    ToggleSelection(EntityId),
+    /// Replace the multi-selection with these entities.
+    SelectMultiple(Vec<EntityId>),
+    /// Make a new group from the entities. They are removed from any groups they are already in.
+    Group(Vec<EntityId>),
+    /// Remove the group. The entities stay where they are.
+    Ungroup(GroupId),
+
+    /// Show the paths the machine takes. Rapid moves use [`SIMULATION_RAPID_COLOR`]. An empty
+    /// list clears the simulation.
+    SimulateGcode(Vec<(Color, Vec<Point>)>),
 }
 
 /// What the current action is for the sheet.
+///
+/// The cursor positions in the pan states are on the screen, and the rest are on the sheet.
 #[derive(Debug, PartialEq)]
 pub enum SheetState {
     /// Delay the selection of an entity.
@@ -119,15 +192,25 @@ pub enum SheetState {
     Move(EntityId, Point),
 
     /// Pan with an entity selected.
-    PanSelected(EntityId, Point, Point),
+    PanSelected(EntityId, Point),
 
     /// Pan the screen.
-    Pan(Point, Point),
+    Pan(Point),
 
     OrderEdit,
     OrderEditSelect(EntityId),
-    OrderEditPan(Point, Point),
-    OrderEditPanSelect(EntityId, Point, Point),
+    OrderEditPan(Point),
+    OrderEditPanSelect(EntityId, Point),
+
+    /// The right-click menu of an entity is open. Contains the screen position of the menu.
+    ContextMenu(EntityId, Point),
+
+    /// Measuring is on, but nothing has been clicked yet. Contains the cursor on the sheet.
+    MeasureStart(Point),
+    /// The start of the measurement and the cursor on the sheet.
+    Measuring(Point, Point),
+    /// A finished measurement. Clicking again starts a new one.
+    Measured(Point, Point),
 
     /// Do nothing
     None(Point),
@@ -145,6 +228,9 @@ pub struct EntityState {
     pub transform: Transform,
     pub flip: bool,
     pub laser_condition: ConditionId,
+    /// A point in model space. The contour with the vertex closest to it starts cutting at that
+    /// vertex instead of its first point.
+    pub start_vertex: Option<Point>,
 }
 impl EntityState {
     pub fn transform(&self, mut point: Point)->Point {
@@ -154,6 +240,579 @@ impl EntityState {
 
         self.transform.transform_vec(point)
     }
+
+    /// The inverse of [`EntityState::transform`]. Maps a point on the sheet to model space.
+    pub fn inverse_transform(&self, point: Point)->Point {
+        let mut point = self.transform.inversed().transform_vec(point);
+        if self.flip {
+            point.y *= -1.0;
+        }
+
+        return point;
+    }
+
+    /// Transform the corners of an AABB in model space and return the `(min, max)` of the AABB
+    /// around them on the sheet. This can be larger than [`Model::bounds`], which uses the hull.
+    pub fn apply_to_bounds(&self, min: Point, max: Point)->(Point, Point) {
+        let corners = [
+            min,
+            Point::new(max.x, min.y),
+            max,
+            Point::new(min.x, max.y),
+        ];
+
+        let mut new_min = Point::new(f64::MAX, f64::MAX);
+        let mut new_max = Point::new(-f64::MAX, -f64::MAX);
+        for corner in corners {
+            let p = self.transform(corner);
+            new_min = new_min.min_by_component(p);
+            new_max = new_max.max_by_component(p);
+        }
+
+        return (new_min, new_max);
+    }
+
+    /// The rotation in radians.
+    pub fn angle(&self)->f64 {
+        rotation_angle(self.transform.rotation)
+    }
+}
+
+/// The angle of a rotation in radians, from -π to π.
+pub fn rotation_angle(rotation: Rotation)->f64 {
+    let mut vec = Vector::new(1.0, 0.0);
+    rotation.normalized().rotate_vec(&mut vec);
+
+    vec.y.atan2(vec.x)
+}
+
+/// An owned snapshot of a sheet for generating GCODE away from the UI.
+pub struct GcodeJob {
+    name: String,
+    sheet_size: Vector,
+    entities: Vec<(ModelHandle, EntityState)>,
+    /// The label of each entity in `entities`.
+    labels: Vec<String>,
+    conditions: HashMap<ConditionId, Condition>,
+    machine: MachineProfile,
+    comment_level: CommentLevel,
+    blank_lines: bool,
+    origin: Origin,
+    /// The cylinder's diameter if the sheet is wrapped around a rotary axis.
+    rotary_diameter: Option<f64>,
+    /// If only some of the sheet's entities are cut, each one's place in the sheet's cutting
+    /// order. See [`Sheet::gcode_job_for`].
+    order_numbers: Option<Vec<usize>>,
+    /// The index of the first entity to cut. The ones before it are left out, but still counted
+    /// in the stats. See [`Sheet::gcode_job_resumed`].
+    resume_from: Option<usize>,
+}
+impl GcodeJob {
+    /// A job for entities that aren't on a sheet. They are cut in the order given and labeled with
+    /// their model's name and their place in the order.
+    pub fn new(
+        name: &str,
+        sheet_size: Vector,
+        entities: &[(ModelHandle, EntityState, Condition)],
+        machine: MachineProfile,
+    )->Self {
+        GcodeJob {
+            name: name.to_string(),
+            sheet_size,
+            labels: entities.iter()
+                .enumerate()
+                .map(|(i, (handle, ..))|format!("{} #{}", handle.name(), i + 1))
+                .collect(),
+            conditions: entities.iter()
+                .map(|(_, mt, condition)|(mt.laser_condition, condition.clone()))
+                .collect(),
+            entities: entities.iter()
+                .map(|(handle, mt, _)|(handle.clone(), *mt))
+                .collect(),
+            machine,
+            comment_level: CommentLevel::default(),
+            blank_lines: false,
+            origin: Origin::default(),
+            rotary_diameter: None,
+            order_numbers: None,
+            resume_from: None,
+        }
+    }
+
+    /// How many entities will be generated.
+    pub fn len(&self)->usize {self.entities.len()}
+
+    /// Generate the GCODE and how many lines were too short to cut. `progress` is called with the
+    /// number of entities done and the total after each entity.
+    pub fn generate(&self, progress: impl FnMut(usize, usize))->(String, usize) {
+        let mut builder = GcodeBuilder::default();
+        self.build(&mut builder, progress);
+        let skipped = builder.skipped_lines();
+
+        return (builder.finish(), skipped);
+    }
+
+    /// Generate the GCODE as blocks instead of text, for checking what it does.
+    #[cfg(test)]
+    pub fn blocks(&self)->Vec<GcodeBlock> {
+        let mut builder = GcodeBuilder::default();
+        self.build(&mut builder, |_, _|{});
+
+        return builder.finish_blocks();
+    }
+
+    /// Generate the GCODE straight into the file without keeping all of it in memory. It is
+    /// written to a temporary file first and renamed when it is done, so a failed write never
+    /// replaces an existing file. Returns how many lines were too short to cut.
+    pub fn write_file(&self, path: &StdPath, progress: impl FnMut(usize, usize))->io::Result<usize> {
+        let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+
+        let file = File::create(&tmp_path)?;
+        let mut builder = GcodeBuilder::write_to(BufWriter::new(file));
+        self.build(&mut builder, progress);
+        let skipped = builder.skipped_lines();
+
+        let res = builder.finish_writer()
+            .and_then(|_|std::fs::rename(&tmp_path, path))
+            .map(|_|skipped);
+        if res.is_err() {
+            let _ = std::fs::remove_file(&tmp_path);
+        }
+
+        return res;
+    }
+
+    fn build(&self, builder: &mut GcodeBuilder, mut progress: impl FnMut(usize, usize)) {
+        builder.set_machine(&self.machine);
+        builder.set_comment_level(self.comment_level);
+        builder.set_blank_lines(self.blank_lines);
+        builder.set_origin(self.origin.position(self.sheet_size), self.origin.axes());
+        builder.set_rotary_diameter(self.rotary_diameter);
+        builder.machine_home(&self.machine);
+        let now = OffsetDateTime::now_local()
+            .unwrap_or(OffsetDateTime::now_utc());
+
+        builder.comment_block(concat!("Gcode generated by LaserCAM ", env!("CARGO_PKG_VERSION")));
+        builder.comment_block(env!("CARGO_PKG_REPOSITORY"));
+
+        // builder.comment_block("NOTE: 0,0 is the \"top left\" of the sheet");
+
+        builder.comment_block(format!("Sheet \"{}\" width: {}; height: {}", self.name, self.sheet_size.x, self.sheet_size.y));
+        builder.comment_block(format!("Origin: {}", self.origin));
+        if let Some(numbers) = &self.order_numbers {
+            builder.comment_block(format!("Only {} selected entities:", numbers.len()));
+            for (number, label) in numbers.iter().zip(self.labels.iter()) {
+                builder.comment_block(format!("  #{number} `{label}`"));
+            }
+        }
+        if let Some(from) = self.resume_from {
+            builder.comment_block(format!(
+                "Resuming at entity #{} `{}`; the {from} entities before it are not cut",
+                from + 1,
+                self.labels.get(from).map(String::as_str).unwrap_or_default(),
+            ));
+        }
+        if let Some(diameter) = self.rotary_diameter {
+            let circumference = std::f64::consts::PI * diameter;
+            builder.comment_block(format!(
+                "Rotary: Y is the A axis in degrees on a {diameter:.3}mm diameter cylinder ({circumference:.3}mm around)",
+            ));
+            if !rotary_height_matches(self.sheet_size.y, diameter) {
+                builder.comment_block(format!(
+                    "WARNING: The sheet height of {:.3}mm is not the cylinder's circumference",
+                    self.sheet_size.y,
+                ));
+            }
+        }
+
+        let stats = SheetStats::new(self.entities.iter(), self.sheet_size);
+        builder.comment_block(format!(
+            "Part area: {:.1}mm²; sheet area: {:.1}mm²; utilization: {:.1}%",
+            stats.part_area,
+            stats.sheet_area,
+            stats.utilization(),
+        ));
+        builder.comment_block(format!("Total cut length: {:.1}mm", stats.cut_length));
+        for (id, count) in stats.condition_counts.iter() {
+            builder.comment_block(format!("Condition \"{}\": {count} entities", self.conditions[id].name));
+        }
+        builder.comment_block(format!(
+            "Generated on {} {}, {} at {}:{}",
+            now.month(),
+            now.day(),
+            now.year(),
+            now.hour(),
+            now.minute(),
+        ));
+
+        builder.machine_header(&self.machine);
+
+        let shapes = if self.machine.subprograms {
+            self.repeated_shapes()
+        } else {
+            Vec::new()
+        };
+        let mut defined = vec![false; shapes.len()];
+
+        let total = self.entities.len();
+        for (i, ((model, mt), label)) in self.entities.iter().zip(self.labels.iter()).enumerate() {
+            match self.resume_from {
+                Some(from) if i < from=>{
+                    progress(i + 1, total);
+                    continue;
+                },
+                Some(from) if i == from=>{
+                    builder.comment_block("==================================================");
+                    builder.comment_block(format!("RESUMING HERE at entity #{} `{label}`", i + 1));
+                    builder.comment_block("==================================================");
+                },
+                _=>{},
+            }
+
+            let condition = &self.conditions[&mt.laser_condition];
+            let shape = (model.clone(), shape_state(mt));
+
+            match shapes.iter().position(|s|*s == shape) {
+                Some(idx)=>{
+                    let id = FIRST_SUBPROGRAM + idx as u16;
+                    if !defined[idx] {
+                        defined[idx] = true;
+                        builder.define_subprogram(id, |sub|model.generate_gcode(&shape.1, sub, condition, None));
+                    }
+
+                    let translation = mt.transform.translation;
+                    builder.comment_block_at(
+                        CommentLevel::Entity,
+                        format!("Entity `{label}` (model `{}`) with sub-program {id}", model.name()),
+                    );
+                    // the sub-program is already moved and flipped by the origin, so the offset is only
+                    // flipped
+                    builder.local_offset()
+                        .offset_x(translation.x)
+                        .offset_y(translation.y)
+                        .eob();
+                    builder.call_subprogram(id).eob();
+                    builder.local_offset()
+                        .offset_x(0.0)
+                        .offset_y(0.0)
+                        .eob();
+                    builder.entity_separator();
+                },
+                None=>model.generate_gcode(mt, builder, condition, Some(label)),
+            }
+
+            progress(i + 1, total);
+        }
+
+        // return to the origin
+        builder.travel_motion()
+            .offset_x(0.0)
+            .offset_y(0.0)
+            .eob();
+    }
+
+    /// The models and states (without the position) that more than one entity uses. Each of these
+    /// gets a sub-program.
+    fn repeated_shapes(&self)->Vec<(ModelHandle, EntityState)> {
+        let mut shapes: Vec<((ModelHandle, EntityState), usize)> = Vec::new();
+
+        for (model, mt) in self.entities.iter() {
+            let shape = (model.clone(), shape_state(mt));
+            match shapes.iter_mut().find(|(s, _)|*s == shape) {
+                Some((_, count))=>*count += 1,
+                None=>shapes.push((shape, 1)),
+            }
+        }
+
+        return shapes.into_iter()
+            .filter(|(_, count)|*count > 1)
+            .map(|(shape, _)|shape)
+            .take(MAX_SUBPROGRAMS)
+            .collect();
+    }
+
+    /// Generate the GCODE for each entity and trace the moves the machine makes. Cuts are in the
+    /// entity's condition color, and rapids are [`SIMULATION_RAPID_COLOR`].
+    pub fn simulate(&self)->Vec<(Color, Vec<Point>)> {
+        let mut out = Vec::new();
+        let mut pos = self.origin.position(self.sheet_size);
+
+        for (model, mt) in self.entities.iter() {
+            let condition = &self.conditions[&mt.laser_condition];
+            let mut cut_color: Color = condition.color.into();
+            cut_color.a = 0.5;
+
+            // each entity gets its own GCODE so we know what color the cuts are
+            let mut builder = GcodeBuilder::default();
+            model.generate_gcode(mt, &mut builder, condition, None);
+            let gcode = builder.finish();
+
+            for (kind, points) in trace(&gcode, pos) {
+                pos = *points.last().unwrap();
+                match kind {
+                    TraceMove::Rapid=>out.push((SIMULATION_RAPID_COLOR, points)),
+                    TraceMove::Cut=>out.push((cut_color, points)),
+                }
+            }
+        }
+
+        // the GCODE returns to the origin at the end
+        out.push((SIMULATION_RAPID_COLOR, vec![pos, self.origin.position(self.sheet_size)]));
+
+        return out;
+    }
+}
+
+/// How an entity that is bigger than the sheet can be made to fit. See [`Sheet::oversize_fix`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OversizeFix {
+    /// What to multiply the entity's scale by.
+    pub scale: f64,
+    /// If turning the entity 90° would make it fit without scaling it.
+    pub rotate_fits: bool,
+}
+
+/// The diameter of a new sheet's rotary cylinder in mm.
+pub const DEFAULT_ROTARY_DIAMETER: f64 = 80.0;
+
+/// How far the sheet height can be from the rotary cylinder's circumference in mm.
+const ROTARY_TOLERANCE: f64 = 0.01;
+
+/// The fractions of the condition's power for each square of a test burn.
+pub const TEST_BURN_FRACTIONS: [f64; 5] = [0.2, 0.4, 0.6, 0.8, 1.0];
+
+/// The size of the test burn squares in mm.
+const TEST_BURN_SQUARE_SIZE: f64 = 10.0;
+
+/// The space between the test burn squares in mm.
+const TEST_BURN_SPACING: f64 = 5.0;
+
+/// The number of the first sub-program. Lower numbers are often used by the controller.
+const FIRST_SUBPROGRAM: u16 = 1000;
+
+/// Sub-program numbers only go up to 9999. Any other repeated entities are cut normally.
+const MAX_SUBPROGRAMS: usize = 9000;
+
+/// The entity's state at the origin. Entities with the same model and shape state are cut the same
+/// way, just in different places.
+fn shape_state(mt: &EntityState)->EntityState {
+    let mut mt = *mt;
+    mt.transform.translation = Translation::zero();
+
+    return mt;
+}
+
+/// The length of the direction arrows on screen.
+const ARROW_SIZE: f64 = 8.0;
+
+/// The radius of the dots where each line starts cutting on screen.
+const START_DOT_RADIUS: f64 = 3.0;
+
+/// The space between the lines of the margin and keep-out zones on screen.
+const HATCH_SPACING: f64 = 8.0;
+
+/// The length of the origin's axis arrows on screen.
+const ORIGIN_AXIS_LENGTH: f64 = 40.0;
+
+/// The color of rapid moves in a simulation.
+pub const SIMULATION_RAPID_COLOR: Color = Color::from_rgb(0.5, 0.5, 0.5);
+
+/// The width of the right-click menu.
+const CONTEXT_MENU_WIDTH: f32 = 150.0;
+
+/// The height of each item in the right-click menu.
+const CONTEXT_MENU_ITEM_HEIGHT: f32 = 24.0;
+
+/// The most time between two clicks on an entity for them to be a double-click.
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+
+/// The items in the right-click menu of an entity.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ContextMenuItem {
+    Duplicate,
+    Delete,
+    MoveToCenter,
+    FitToSheet,
+    CopyToSheet,
+    SetStartVertex,
+}
+impl ContextMenuItem {
+    pub const LIST: &[Self] = &[
+        Self::Duplicate,
+        Self::Delete,
+        Self::MoveToCenter,
+        Self::FitToSheet,
+        Self::CopyToSheet,
+        Self::SetStartVertex,
+    ];
+
+    /// Where the menu is drawn when opened at `pos` on the screen. It is moved to stay inside the
+    /// canvas.
+    fn menu_bounds(pos: Point, size: Size)->Rectangle {
+        let height = CONTEXT_MENU_ITEM_HEIGHT * Self::LIST.len() as f32;
+        let x = (pos.x as f32).min(size.width - CONTEXT_MENU_WIDTH).max(0.0);
+        let y = (pos.y as f32).min(size.height - height).max(0.0);
+
+        return Rectangle::new(iced::Point::new(x, y), Size::new(CONTEXT_MENU_WIDTH, height));
+    }
+
+    /// The item under the point on the screen.
+    fn at(menu: Rectangle, point: iced::Point)->Option<Self> {
+        if !menu.contains(point) {
+            return None;
+        }
+
+        let index = ((point.y - menu.y) / CONTEXT_MENU_ITEM_HEIGHT) as usize;
+        return Self::LIST.get(index).copied();
+    }
+
+    /// The message for this item. `pos` is where the menu was opened on the sheet.
+    fn message(&self, id: EntityId, pos: Point)->SheetMessage {
+        match self {
+            Self::Duplicate=>SheetMessage::Duplicate(id),
+            Self::Delete=>SheetMessage::Delete(id),
+            Self::MoveToCenter=>SheetMessage::MoveToCenter(id),
+            Self::FitToSheet=>SheetMessage::FitToSheet(id),
+            Self::CopyToSheet=>SheetMessage::CopyToSheet(id),
+            Self::SetStartVertex=>SheetMessage::SetStartVertex(id, pos),
+        }
+    }
+}
+impl Display for ContextMenuItem {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        match self {
+            Self::Duplicate=>write!(f, "Duplicate"),
+            Self::Delete=>write!(f, "Delete"),
+            Self::MoveToCenter=>write!(f, "Move to center"),
+            Self::FitToSheet=>write!(f, "Fit to sheet"),
+            Self::CopyToSheet=>write!(f, "Copy to sheet..."),
+            Self::SetStartVertex=>write!(f, "Set start vertex"),
+        }
+    }
+}
+
+/// Progress of a [`GcodeJob`] running in the background.
+#[derive(Debug, Clone)]
+pub enum GcodeProgress {
+    /// `(entities done, total entities)`
+    Progress(usize, usize),
+    /// Contains how many lines were too short to cut, or the error message if the file could not
+    /// be written.
+    Done(Result<usize, String>),
+}
+
+/// How much of the sheet the parts use and how much cutting there is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SheetStats {
+    /// The area of every entity without its holes.
+    pub part_area: f64,
+    pub sheet_area: f64,
+    /// The cutting length of a single pass of every entity.
+    pub cut_length: f64,
+    /// How many entities use each condition, in the order the conditions are first used.
+    pub condition_counts: IndexMap<ConditionId, usize>,
+}
+impl SheetStats {
+    fn new<'a>(entities: impl Iterator<Item = &'a (ModelHandle, EntityState)>, sheet_size: Vector)->Self {
+        let mut stats = SheetStats {
+            part_area: 0.0,
+            sheet_area: sheet_size.x * sheet_size.y,
+            cut_length: 0.0,
+            condition_counts: IndexMap::new(),
+        };
+
+        for (handle, mt) in entities {
+            stats.part_area += handle.part_area(mt);
+            stats.cut_length += handle.path_length(mt);
+            *stats.condition_counts.entry(mt.laser_condition).or_default() += 1;
+        }
+
+        return stats;
+    }
+
+    /// The part area as a percentage of the sheet area.
+    pub fn utilization(&self)->f64 {
+        if self.sheet_area <= 0.0 {
+            return 0.0;
+        }
+
+        self.part_area / self.sheet_area * 100.0
+    }
+}
+
+/// Which point of the sheet is the machine's (0, 0). The axes point into the sheet from the
+/// corners, so coordinates are never negative.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum Origin {
+    #[default]
+    BottomLeft,
+    TopLeft,
+    BottomRight,
+    TopRight,
+    Center,
+}
+impl Origin {
+    pub const LIST: &[Self] = &[
+        Self::BottomLeft,
+        Self::TopLeft,
+        Self::BottomRight,
+        Self::TopRight,
+        Self::Center,
+    ];
+
+    /// Where the origin is on the sheet.
+    pub fn position(&self, sheet_size: Vector)->Point {
+        match self {
+            Self::BottomLeft=>Point::zero(),
+            Self::TopLeft=>Point::new(0.0, sheet_size.y),
+            Self::BottomRight=>Point::new(sheet_size.x, 0.0),
+            Self::TopRight=>sheet_size,
+            Self::Center=>sheet_size / 2.0,
+        }
+    }
+
+    /// The direction of the machine's X and Y axes on the sheet. Each is `1.0` or `-1.0`.
+    pub fn axes(&self)->Vector {
+        match self {
+            Self::BottomLeft|Self::Center=>Vector::new(1.0, 1.0),
+            Self::TopLeft=>Vector::new(1.0, -1.0),
+            Self::BottomRight=>Vector::new(-1.0, 1.0),
+            Self::TopRight=>Vector::new(-1.0, -1.0),
+        }
+    }
+
+    /// Map a point on the sheet to the machine's coordinates.
+    pub fn sheet_to_machine(&self, point: Point, sheet_size: Vector)->Point {
+        (point - self.position(sheet_size)) * self.axes()
+    }
+
+    /// Map a point in the machine's coordinates to the sheet.
+    pub fn machine_to_sheet(&self, point: Point, sheet_size: Vector)->Point {
+        point * self.axes() + self.position(sheet_size)
+    }
+}
+impl Display for Origin {
+    fn fmt(&self, f: &mut Formatter)->FmtResult {
+        match self {
+            Self::BottomLeft=>write!(f, "Bottom left"),
+            Self::TopLeft=>write!(f, "Top left"),
+            Self::BottomRight=>write!(f, "Bottom right"),
+            Self::TopRight=>write!(f, "Top right"),
+            Self::Center=>write!(f, "Center"),
+        }
+    }
+}
+
+/// Entities that are moved, rotated, and scaled together.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityGroup {
+    pub id: GroupId,
+    pub members: Vec<EntityId>,
+    /// Every transform applied to the group since it was made. It is applied on top of each
+    /// member's own transform when the group is changed.
+    pub transform: Transform,
 }
 
 /// A sheet to nest the models in. Has a sheet size to display an outline and handles displaying
@@ -162,84 +821,190 @@ pub struct Sheet {
     pub active_models: HashMap<ModelHandle, HashSet<EntityId>>,
     pub entities: HashMap<EntityId, (ModelHandle, EntityState)>,
     pub sheet_size: Vector,
+    /// How far from each edge of the sheet entities have to be in mm.
+    pub margin: f64,
+    /// The machine's (0, 0). GCODE, entity positions, and the cursor position use this.
+    pub origin: Origin,
+    /// The scale of the whole layout. See [`Sheet::set_global_scale`].
+    pub global_scale: f64,
+    /// Wrap the sheet around a cylinder on a rotary axis. The height is the cylinder's
+    /// circumference and Y is written as the A axis. See [`Sheet::set_rotary`].
+    pub rotary: bool,
+    /// The diameter of the cylinder in mm.
+    pub rotary_diameter: f64,
+    /// What the sheet is made of. This is only used in file names.
+    pub material: String,
+    /// Rectangles on the sheet that entities can't be in, as `(min, max)` corners.
+    pub keep_outs: Vec<(Point, Point)>,
 
     pub laser_conditions: Rc<RefCell<ConditionStore>>,
+    pub machine: Rc<RefCell<MachineProfile>>,
+    pub settings: Rc<RefCell<Settings>>,
 
     models: ModelStore,
     paths: HashMap<EntityId, (Color, ModelPaths)>,
+    /// The cutting length of each entity. See [`Sheet::path_length_for_entity`].
+    path_lengths: HashMap<EntityId, f64>,
+    /// The name of each entity. See [`Sheet::entity_label`].
+    labels: HashMap<EntityId, String>,
+    /// Entities that are partly or completely outside the margin or in a keep-out zone. See
+    /// [`Sheet::out_of_bounds_entities`].
+    out_of_bounds: HashSet<EntityId>,
+    /// Cleared whenever the paths are recalculated. See [`Sheet::stats`].
+    stats: RefCell<Option<SheetStats>>,
     cached_models: HashMap<EntityId, Cache>,
+    /// Maps the sheet (after projecting to Y down) to the screen. See [`Sheet::world_to_screen`].
     view: Transform,
-    world: Transform,
+    /// Saved views and their names.
+    pub bookmarks: Vec<(String, Transform)>,
+    /// The size of the canvas the last time it was drawn.
+    canvas_size: Cell<Size>,
     sheet_cache: Cache,
-    window_height: Cell<f64>,
-    height_change: Cell<bool>,
 
     recent_clicks: RefCell<HashSet<EntityId>>,
+    /// The entity the last click selected and when. Used to find double-clicks.
+    last_click: Cell<Option<(EntityId, Instant)>>,
+    /// The edges the entity being dragged snapped to, on the sheet.
+    snap_lines: RefCell<Vec<(Point, Point)>>,
+    /// Where the cursor is on the sheet, if it is over the canvas.
+    cursor_pos: Cell<Option<Point>>,
+    /// An entity from [`SheetMessage::SelectEntity`]. The canvas selects it on the next event.
+    pending_select: Cell<Option<EntityId>>,
+    /// The entity a left click would select. See [`Sheet::click_candidate`].
+    hovered: Cell<Option<EntityId>>,
 
     order: IndexSet<EntityId>,
 
+    /// The paths from [`SheetMessage::SimulateGcode`].
+    pub simulation: Vec<(Color, Vec<Point>)>,
+    simulation_cache: Cache,
+
+    pub groups: Vec<EntityGroup>,
+    /// Entities selected with shift+click.
+    pub selection: IndexSet<EntityId>,
+    modifiers: Cell<Modifiers>,
+
     pub show_order: bool,
+    pub show_bounding_boxes: bool,
+    /// Draw which way each line is cut and where it starts.
+    pub show_direction: bool,
+    /// Entities with other conditions are faded and can't be clicked. This doesn't change the
+    /// GCODE.
+    pub condition_filter: Option<ConditionId>,
     pub reorder: bool,
-    pub grbl_comments: bool,
+    pub comment_level: CommentLevel,
+    /// Put a blank line between each entity in the GCODE.
+    pub blank_lines: bool,
 }
 impl Sheet {
-    pub fn new(models: ModelStore, laser_conditions: Rc<RefCell<ConditionStore>>)->Self {
+    pub fn new(
+        models: ModelStore,
+        laser_conditions: Rc<RefCell<ConditionStore>>,
+        machine: Rc<RefCell<MachineProfile>>,
+        settings: Rc<RefCell<Settings>>,
+    )->Self {
         Sheet {
             models,
             active_models: HashMap::new(),
             entities: HashMap::new(),
             paths: HashMap::new(),
+            path_lengths: HashMap::new(),
+            out_of_bounds: HashSet::new(),
+            stats: RefCell::new(None),
+            labels: HashMap::new(),
             cached_models: HashMap::new(),
             view: Transform::new(Translation::zero(), Rotation::from_angle(0.0), 1.0),
-            world: Transform::new(Translation::zero(), Rotation::from_angle(0.0), 1.0),
+            bookmarks: Vec::new(),
+            canvas_size: Cell::new(Size::ZERO),
             sheet_size: Vector::new(300.0, 300.0),
+            margin: 0.0,
+            keep_outs: Vec::new(),
+            origin: Origin::default(),
+            global_scale: 1.0,
+            rotary: false,
+            rotary_diameter: DEFAULT_ROTARY_DIAMETER,
+            material: String::new(),
             sheet_cache: Cache::new(),
             laser_conditions,
-            window_height: Cell::new(1000.0),
-            height_change: Cell::new(false),
+            machine,
+            settings,
 
             recent_clicks: RefCell::new(HashSet::new()),
+            last_click: Cell::new(None),
+            snap_lines: RefCell::new(Vec::new()),
+            cursor_pos: Cell::new(None),
+            pending_select: Cell::new(None),
+            hovered: Cell::new(None),
 
             order: IndexSet::new(),
 
+            simulation: Vec::new(),
+            simulation_cache: Cache::new(),
+
+            groups: Vec::new(),
+            selection: IndexSet::new(),
+            modifiers: Cell::new(Modifiers::default()),
+
             show_order: false,
+            show_bounding_boxes: false,
+            show_direction: false,
+            condition_filter: None,
             reorder: false,
-            grbl_comments: false,
+            comment_level: CommentLevel::default(),
+            blank_lines: false,
         }
     }
 
-    pub fn generate_gcode(&self, name: &str)->String {
-        let mut builder = GcodeBuilder::default();
-        if self.grbl_comments {
-            builder.set_grbl_mode();
-        }
-        let now = OffsetDateTime::now_local()
-            .unwrap_or(OffsetDateTime::now_utc());
+    pub fn generate_gcode(&self, name: &str)->(String, usize) {
+        self.gcode_job(name).generate(|_, _|{})
+    }
 
-        builder.comment_block(concat!("Gcode generated by LaserCAM ", env!("CARGO_PKG_VERSION")));
-        builder.comment_block(env!("CARGO_PKG_REPOSITORY"));
+    /// A row of squares starting at (0, 0) for finding the right power on scrap. Each square is cut
+    /// with the next fraction of the condition's power in [`TEST_BURN_FRACTIONS`].
+    pub fn generate_test_burn_gcode(&self, condition: ConditionId)->String {
+        let store = self.laser_conditions.borrow();
+        let condition = store.get_or_default(condition);
+        let machine = self.machine.borrow();
 
-        // builder.comment_block("NOTE: 0,0 is the \"top left\" of the sheet");
+        let size = TEST_BURN_SQUARE_SIZE;
+        let square = Model::new(
+            vec![LineString::from(vec![(0.0, 0.0), (size, 0.0), (size, size), (0.0, size), (0.0, 0.0)])],
+            "Test square".into(),
+        );
 
-        builder.comment_block(format!("Sheet \"{}\" width: {}; height: {}", name, self.sheet_size.x, self.sheet_size.y));
+        let mut builder = GcodeBuilder::default();
+        builder.set_machine(&machine);
+        builder.set_comment_level(self.comment_level);
+        builder.machine_home(&machine);
+
+        builder.comment_block(concat!("Test burn generated by LaserCAM ", env!("CARGO_PKG_VERSION")));
         builder.comment_block(format!(
-            "Generated on {} {}, {} at {}:{}",
-            now.month(),
-            now.day(),
-            now.year(),
-            now.hour(),
-            now.minute(),
+            "Squares for laser condition `{}` at {} of its power",
+            condition.name,
+            TEST_BURN_FRACTIONS.map(|f|format!("{:.0}%", f * 100.0)).join(", "),
         ));
-        builder.default_header();
+        builder.machine_header(&machine);
 
-        let store = self.laser_conditions.borrow();
-        for (model, mt) in self.entities.values() {
-            let condition = store.get(mt.laser_condition);
-            model.generate_gcode(mt, &mut builder, condition);
+        for (i, fraction) in TEST_BURN_FRACTIONS.iter().enumerate() {
+            let mut scaled = condition.clone();
+            for seq in scaled.sequence.iter_mut() {
+                *seq = seq.with_power_fraction(*fraction);
+            }
+
+            let mt = EntityState {
+                transform: Transform::new(
+                    Translation::new(i as f64 * (size + TEST_BURN_SPACING), 0.0),
+                    Rotation::identity(),
+                    1.0,
+                ),
+                flip: false,
+                laser_condition: condition.id,
+                start_vertex: None,
+            };
+            square.generate_gcode(&mt, &mut builder, &scaled, Some(&format!("{:.0}% power", fraction * 100.0)));
         }
-        drop(store);
 
-        builder.rapid_motion()
+        builder.travel_motion()
             .x(0.0)
             .y(0.0)
             .eob();
@@ -247,18 +1012,115 @@ impl Sheet {
         return builder.finish();
     }
 
+    /// The total area of the entities on the sheet. Each entity uses the area of its convex hull, so
+    /// this is how much material the parts use, not counting the space between them.
+    pub fn cut_area(&self)->f64 {
+        self.entities.values()
+            .map(|(handle, mt)|handle.hull_area(mt))
+            .sum()
+    }
+
+    /// The material use and cutting totals. These are only recalculated after the paths change.
+    pub fn stats(&self)->SheetStats {
+        self.stats.borrow_mut()
+            .get_or_insert_with(||SheetStats::new(self.entities.values(), self.sheet_size))
+            .clone()
+    }
+
+    /// Take a snapshot of the sheet for exporting as an image. Each entity's lines are in its
+    /// condition's color, and the cutting order numbers are included if `show_order` is set.
+    pub fn image(&self, show_order: bool)->SheetImage {
+        let store = self.laser_conditions.borrow();
+        let mut lines = Vec::new();
+        let mut labels = Vec::new();
+
+        for (i, (id, (handle, mt))) in self.ordered_entities().enumerate() {
+            let color = store.get_or_default(mt.laser_condition).color;
+            for points in handle.cut_lines(mt) {
+                lines.push((color, points));
+            }
+
+            if show_order {
+                labels.push((i + 1, self.paths[&id].1.display_center));
+            }
+        }
+
+        SheetImage {
+            sheet_size: self.sheet_size,
+            lines,
+            labels,
+        }
+    }
+
+    /// Take a snapshot of everything needed to generate the GCODE so it can be done on another
+    /// thread.
+    pub fn gcode_job(&self, name: &str)->GcodeJob {
+        return self.gcode_job_filtered(name, |_|true);
+    }
+
+    /// A job with only these entities, in the sheet's cutting order. The header lists them with
+    /// their place in the whole order, for re-cutting parts that didn't finish.
+    pub fn gcode_job_for(&self, name: &str, ids: &[EntityId])->GcodeJob {
+        let mut job = self.gcode_job_filtered(name, |id|ids.contains(&id));
+        job.order_numbers = Some(self.ordered_entities()
+            .enumerate()
+            .filter(|(_, (id, _))|ids.contains(id))
+            .map(|(i, _)|i + 1)
+            .collect());
+
+        return job;
+    }
+
+    /// A job that starts at the entity at `from` in the cutting order, for finishing a sheet after
+    /// the machine stopped part way through. The header is the same as the whole sheet's.
+    pub fn gcode_job_resumed(&self, name: &str, from: usize)->GcodeJob {
+        let mut job = self.gcode_job(name);
+        job.resume_from = Some(from);
+
+        return job;
+    }
+
+    fn gcode_job_filtered(&self, name: &str, include: impl Fn(EntityId)->bool)->GcodeJob {
+        let store = self.laser_conditions.borrow();
+
+        let entities = self.ordered_entities()
+            .filter(|(id, _)|include(*id))
+            .map(|(_, (handle, mt))|(handle.clone(), *mt, store.get_or_default(mt.laser_condition).clone()))
+            .collect::<Vec<_>>();
+
+        let mut job = GcodeJob::new(name, self.sheet_size, &entities, self.machine.borrow().clone());
+        job.labels = self.ordered_entities()
+            .filter(|(id, _)|include(*id))
+            .map(|(id, _)|self.entity_label(id).to_string())
+            .collect();
+        job.comment_level = self.comment_level;
+        job.blank_lines = self.blank_lines;
+        job.origin = self.origin;
+        job.rotary_diameter = self.rotary.then_some(self.rotary_diameter);
+
+        return job;
+    }
+
+    /// The entities in the order they are cut. Anything not in the order yet (we might be in the
+    /// middle of reordering) is cut last.
+    pub fn ordered_entities(&self)->impl Iterator<Item = (EntityId, &(ModelHandle, EntityState))> {
+        self.order.iter()
+            .chain(self.entities.keys().filter(|id|!self.order.contains(*id)))
+            .map(|id|(*id, &self.entities[id]))
+    }
+
     /// Add a model with a quantity.
     #[inline]
     #[allow(unused)]
     pub fn add_model(&mut self, path: &str, qty: usize, laser_condition: ConditionId)->Result<()> {
         let transform = Transform::new(Translation::zero(), Rotation::from_angle(0.0), 1.0);
 
-        self.add_model_with_transform(path, EntityState {transform, flip: false, laser_condition}, qty)
+        self.add_model_with_transform(path, EntityState {transform, flip: false, laser_condition, start_vertex: None}, qty)
     }
 
     /// Add a model with a transform and quantity.
     pub fn add_model_with_transform(&mut self, path: &str, transform: EntityState, qty: usize)->Result<()> {
-        let model = Model::load(path)?;
+        let model = Model::load(path, self.settings.borrow().import_options.arc_tolerance)?;
 
         let handle = self.models.add(model);
 
@@ -268,20 +1130,26 @@ impl Sheet {
     }
 
     /// Add a model from the given ID
-    pub fn add_model_from_handle(&mut self, handle: ModelHandle, qty: usize, laser_condition: ConditionId) {
-        let transform = Transform::new(Translation::zero(), Rotation::from_angle(0.0), 1.0);
+    pub fn add_model_from_handle(&mut self, handle: ModelHandle, qty: usize, laser_condition: ConditionId)->Vec<EntityId> {
+        let transform = Transform::new(Translation::zero(), Rotation::from_angle(0.0), handle.import_scale);
 
-        self.add_model_from_handle_with_transform(handle, EntityState {transform, flip:false, laser_condition}, qty)
+        return self.add_model_from_handle_with_transform(handle, EntityState {transform, flip:false, laser_condition, start_vertex: None}, qty);
     }
 
-    /// Add a model from the given ID and transform
-    pub fn add_model_from_handle_with_transform(&mut self, handle: ModelHandle, mut transform: EntityState, qty: usize) {
+    /// Add a model from the given ID and transform. Returns the new entities. Each one is labeled
+    /// with the model's name and the next unused number.
+    pub fn add_model_from_handle_with_transform(&mut self, handle: ModelHandle, mut transform: EntityState, qty: usize)->Vec<EntityId> {
         let model_entity_list = self.active_models
             .entry(handle.clone())
             .or_default();
+        let mut used_labels = self.labels.values()
+            .cloned()
+            .collect::<HashSet<_>>();
+        let mut label_number = 1;
+        let mut ids = Vec::new();
 
         let store = self.laser_conditions.borrow();
-        let color = store.get(transform.laser_condition).color;
+        let color = store.get_or_default(transform.laser_condition).color;
         drop(store);
 
         for _ in 0..qty {
@@ -289,12 +1157,44 @@ impl Sheet {
             model_entity_list.insert(id);
             self.entities.insert(id, (handle.clone(), transform));
             self.order.insert(id);
-            self.paths.insert(id, (color.into(), handle.paths(transform, self.window_height.get())));
+            self.paths.insert(id, (color.into(), handle.paths(transform)));
+            self.path_lengths.insert(id, handle.path_length(&transform));
             self.cached_models.insert(id, Cache::new());
             transform.transform.translation += Point::new(5.0, 5.0);
+
+            let label = loop {
+                let label = format!("{} #{label_number}", handle.name());
+                label_number += 1;
+                if !used_labels.contains(&label) {
+                    break label;
+                }
+            };
+            used_labels.insert(label.clone());
+            self.labels.insert(id, label);
+            ids.push(id);
         }
 
         self.recalc_paths();
+
+        return ids;
+    }
+
+    /// The entity's name. See [`Sheet::add_model_from_handle_with_transform`] for the default.
+    pub fn entity_label(&self, id: EntityId)->&str {
+        self.labels.get(&id)
+            .map(|label|label.as_str())
+            .unwrap_or_default()
+    }
+
+    pub fn set_entity_label(&mut self, id: EntityId, label: String) {
+        if !self.entities.contains_key(&id) {
+            return;
+        }
+
+        self.labels.insert(id, label);
+        if let Some(cache) = self.cached_models.get(&id) {
+            cache.clear();
+        }
     }
 
     pub fn main_view(&self)->Element<SheetMessage> {
@@ -305,21 +1205,59 @@ impl Sheet {
     }
 
     pub fn main_update(&mut self, msg: SheetMessage)->Task<SheetMessage> {
-        // If the height has changed, then recalc the paths.
-        if self.height_change.take() {
-            self.recalc_paths();
-        }
-
         match msg {
             SheetMessage::RecalcPaths=>self.recalc_paths(),
             SheetMessage::RecalcPathsId(id)=>self.recalc_paths_id(id),
-            SheetMessage::Select(id)=>self.clear_cache_id(id),
+            SheetMessage::Select(_)=>{
+                self.selection.clear();
+                self.clear_cache();
+            },
+            SheetMessage::SelectEntity(id)=>{
+                self.selection.clear();
+                self.pending_select.set(Some(id));
+                self.clear_cache();
+            },
+            SheetMessage::ToggleSelection(id)=>{
+                if !self.selection.shift_remove(&id) {
+                    self.selection.insert(id);
+                }
+                self.clear_cache();
+            },
+            SheetMessage::SelectMultiple(ids)=>{
+                self.selection = ids.into_iter().collect();
+                self.clear_cache();
+            },
+            SheetMessage::Group(ids)=>{
+                self.group(ids);
+                self.selection.clear();
+                self.clear_cache();
+            },
+            SheetMessage::Ungroup(id)=>{
+                eprintln!("Ungroup {id:?}");
+                self.groups.retain(|g|g.id != id);
+                self.clear_cache();
+            },
             SheetMessage::Delete(id)=>self.delete_entity(id),
+            SheetMessage::Duplicate(id)=>self.duplicate_entity(id),
+            SheetMessage::MoveToCenter(id)=>self.move_to_center(id),
+            // the main program checks how much it is scaled
+            SheetMessage::FitToSheet(_)=>{},
+            // the main program handles this since it has the other sheets
+            SheetMessage::CopyToSheet(_)=>{},
+            // the main program opens the entity settings
+            SheetMessage::Focus(_)=>{},
+            SheetMessage::SetStartVertex(id, pos)=>self.set_start_vertex(id, pos),
+            SheetMessage::SimulateGcode(paths)=>{
+                eprintln!("Simulating {} paths", paths.len());
+                self.simulation = paths;
+                self.simulation_cache.clear();
+            },
             SheetMessage::StartOrder=>{
                 if self.entities.len() > 0 {
                     self.order.clear();
                     eprintln!("Start order");
                     self.reorder = true;
+                    self.clear_cache();
                 } else {
                     eprintln!("No entities. Not starting order");
                 }
@@ -331,87 +1269,80 @@ impl Sheet {
                 } else {
                     eprintln!("Hiding entities");
                 }
+                self.clear_cache();
+            },
+            SheetMessage::SetShowBoundingBoxes(b)=>{
+                self.show_bounding_boxes = b;
+                self.clear_cache();
+            },
+            SheetMessage::SetShowDirection(b)=>{
+                self.show_direction = b;
+                self.clear_cache();
+            },
+            SheetMessage::SetConditionFilter(filter)=>{
+                let old_filter = std::mem::replace(&mut self.condition_filter, filter);
+
+                // only redraw the entities that are shown differently
+                for (id, (_, mt)) in self.entities.iter() {
+                    let was_visible = old_filter.is_none_or(|c|c == mt.laser_condition);
+                    if was_visible != self.condition_visible(mt) {
+                        self.clear_cache_id(*id);
+                    }
+                }
             },
-            SheetMessage::Deselect(id)=>{
+            SheetMessage::Deselect(_)=>{
                 self.recent_clicks.borrow_mut().clear();
+                self.selection.clear();
 
-                self.clear_cache_id(id);
+                self.clear_cache();
             },
             SheetMessage::Move(id, delta)=>{
                 self.recent_clicks.borrow_mut().clear();
 
-                self.entities
-                    .get_mut(&id)
-                    .unwrap()
-                    .1.transform
-                    .translation += delta / self.world.scale;
-
-                self.recalc_paths_id(id);
+                self.translate_entity(id, delta);
             },
             SheetMessage::SelectMove(id, delta)=>{
-                self.clear_cache_id(id);
+                self.clear_cache();
                 self.recent_clicks.borrow_mut().clear();
 
-                self.entities
-                    .get_mut(&id)
-                    .unwrap()
-                    .1.transform
-                    .translation += delta / self.world.scale;
-
-                self.recalc_paths_id(id);
+                self.translate_entity(id, delta);
             },
-            SheetMessage::Pan(delta, w_delta)=>{
+            SheetMessage::Pan(delta)=>{
                 self.recent_clicks.borrow_mut().clear();
 
                 self.view.translation += delta;
-                self.world.translation += w_delta;
 
                 self.clear_cache();
             },
-            SheetMessage::ZoomIn(mouse_pos, w_mouse_pos)=>{
-                const ZOOM: f64 = 1.1;
-
+            SheetMessage::ZoomIn(mouse_pos)=>{
                 self.recent_clicks.borrow_mut().clear();
-
-                let mouse_offset = self.view.translation - mouse_pos;
-                let offset = (mouse_offset * ZOOM) - mouse_offset;
-
-                self.view.translation.x += offset.x;
-                self.view.translation.y += offset.y;
-
-                self.view.scale *= ZOOM;
-
-                let mouse_offset = self.world.translation - w_mouse_pos;
-                let offset = (mouse_offset * ZOOM) - mouse_offset;
-
-                self.world.translation.x += offset.x;
-                self.world.translation.y += offset.y;
-
-                self.world.scale *= ZOOM;
-
+                let step = self.settings.borrow().zoom_step;
+                self.zoom_at(1.0 + step, mouse_pos);
                 self.clear_cache();
             },
-            SheetMessage::ZoomOut(mouse_pos, w_mouse_pos)=>{
-                const ZOOM: f64 = 0.9;
-
+            SheetMessage::ZoomOut(mouse_pos)=>{
                 self.recent_clicks.borrow_mut().clear();
-
-                let mouse_offset = self.view.translation - mouse_pos;
-                let offset = (mouse_offset * ZOOM) - mouse_offset;
-
-                self.view.translation.x += offset.x;
-                self.view.translation.y += offset.y;
-
-                self.view.scale *= ZOOM;
-
-                let mouse_offset = self.world.translation - w_mouse_pos;
-                let offset = (mouse_offset * ZOOM) - mouse_offset;
-
-                self.world.translation.x += offset.x;
-                self.world.translation.y += offset.y;
-
-                self.world.scale *= ZOOM;
-
+                let step = self.settings.borrow().zoom_step;
+                self.zoom_at(1.0 - step, mouse_pos);
+                self.clear_cache();
+            },
+            SheetMessage::ResetZoom=>{
+                self.recent_clicks.borrow_mut().clear();
+                let size = self.canvas_size.get();
+                let center = Point::new(size.width as f64, size.height as f64) / 2.0;
+                self.zoom_at(1.0 / self.view.scale, center);
+                self.clear_cache();
+            },
+            SheetMessage::SaveBookmark(name)=>{
+                eprintln!("Save view `{name}`");
+                match self.bookmarks.iter_mut().find(|(n, _)|*n == name) {
+                    Some((_, view))=>*view = self.view,
+                    None=>self.bookmarks.push((name, self.view)),
+                }
+            },
+            SheetMessage::RestoreBookmark(i)=>if let Some((_, view)) = self.bookmarks.get(i) {
+                self.recent_clicks.borrow_mut().clear();
+                self.view = *view;
                 self.clear_cache();
             },
             SheetMessage::AddToOrder(id)=>{
@@ -419,6 +1350,7 @@ impl Sheet {
                     self.order.shift_remove(&id);
                 }
                 self.order.insert(id);
+                self.clear_cache();
                 eprintln!("Add entity to order: {id:?}");
             },
             SheetMessage::FinishOrder(id)=>{
@@ -427,16 +1359,24 @@ impl Sheet {
                 }
                 self.order.insert(id);
                 self.reorder = false;
+                self.clear_cache();
                 eprintln!("Finish order with entity: {id:?}");
             },
+            SheetMessage::ReorderByPosition(horizontal_first)=>self.reorder_by_position(horizontal_first),
+            SheetMessage::InvertOrder=>{
+                self.order = self.order.iter().copied().rev().collect::<IndexSet<_>>();
+                self.clear_cache();
+                eprintln!("Reverse order");
+            },
         }
 
         Task::none()
     }
 
-    fn clear_cache(&self) {
+    pub fn clear_cache(&self) {
         self.cached_models.values().for_each(Cache::clear);
         self.sheet_cache.clear();
+        self.simulation_cache.clear();
     }
 
     fn clear_cache_id(&self, id: EntityId) {
@@ -451,8 +1391,77 @@ impl Sheet {
 
         let store = self.laser_conditions.borrow();
         for (id, (handle, mt)) in self.entities.iter() {
-            let condition = store.get(mt.laser_condition);
-            self.paths.insert(*id, (condition.color.into(), handle.paths(*mt, self.window_height.get())));
+            let condition = store.get_or_default(mt.laser_condition);
+            self.paths.insert(*id, (condition.color.into(), handle.paths(*mt)));
+            self.path_lengths.insert(*id, handle.path_length(mt));
+        }
+        drop(store);
+
+        self.update_out_of_bounds();
+        self.stats.take();
+    }
+
+    /// Give the entities using a replaced model the new handle. Their transforms, conditions, and
+    /// order are kept. See [`ModelStore::replace`].
+    pub fn replace_model(&mut self, handle: ModelHandle) {
+        let Some(ids) = self.active_models.remove(&handle) else {return};
+
+        for id in ids.iter() {
+            if let Some((entity_handle, _)) = self.entities.get_mut(id) {
+                *entity_handle = handle.clone();
+            }
+        }
+        self.active_models.insert(handle, ids);
+
+        self.recalc_paths();
+    }
+
+    /// Cut the entities in rows from the top of the sheet down, each row left to right, by the
+    /// centers of their bounding boxes. If `horizontal_first`, they are cut in columns from the
+    /// left instead, each column top to bottom. This stops a manual reorder.
+    pub fn reorder_by_position(&mut self, horizontal_first: bool) {
+        let mut centers = self.entities.keys()
+            .filter_map(|id|{
+                let (min, max) = self.entity_bounds(*id)?;
+                Some((*id, (min + max) / 2.0))
+            })
+            .collect::<Vec<_>>();
+
+        // Y is up, so the top of the sheet is the biggest Y
+        if horizontal_first {
+            centers.sort_by(|(_, a), (_, b)|a.x.total_cmp(&b.x).then(b.y.total_cmp(&a.y)));
+        } else {
+            centers.sort_by(|(_, a), (_, b)|b.y.total_cmp(&a.y).then(a.x.total_cmp(&b.x)));
+        }
+
+        self.order = centers.into_iter()
+            .map(|(id, _)|id)
+            .collect();
+        self.reorder = false;
+        self.clear_cache();
+    }
+
+    /// Remove IDs from the cutting order that aren't entities on the sheet. Returns the removed
+    /// IDs.
+    pub fn prune_order(&mut self)->Vec<EntityId> {
+        let removed = self.order.iter()
+            .filter(|id|!self.entities.contains_key(id))
+            .copied()
+            .collect::<Vec<_>>();
+        self.order.retain(|id|self.entities.contains_key(id));
+
+        return removed;
+    }
+
+    /// Recalculate the paths of only the entities using the condition.
+    pub fn recalc_paths_condition(&mut self, condition: ConditionId) {
+        let ids = self.entities.iter()
+            .filter(|(_, (_, mt))|mt.laser_condition == condition)
+            .map(|(id, _)|*id)
+            .collect::<Vec<_>>();
+
+        for id in ids {
+            self.recalc_paths_id(id);
         }
     }
 
@@ -462,16 +1471,200 @@ impl Sheet {
 
         let store = self.laser_conditions.borrow();
         if let Some((handle, mt)) = self.entities.get(&id) {
-            let condition = store.get(mt.laser_condition);
-            self.paths.insert(id, (condition.color.into(), handle.paths(*mt, self.window_height.get())));
+            let condition = store.get_or_default(mt.laser_condition);
+            self.paths.insert(id, (condition.color.into(), handle.paths(*mt)));
+            self.path_lengths.insert(id, handle.path_length(mt));
+        }
+        drop(store);
+
+        if let Some((handle, mt)) = self.entities.get(&id).cloned() {
+            self.set_out_of_bounds(id, &handle, &mt);
         }
+        self.stats.take();
+    }
+
+    /// The entities that are partly or completely outside the margin or in a keep-out zone, in cut
+    /// order.
+    pub fn out_of_bounds_entities(&self)->Vec<EntityId> {
+        self.ordered_entities()
+            .map(|(id, _)|id)
+            .filter(|id|self.out_of_bounds.contains(id))
+            .collect()
+    }
+
+    /// Check every entity against the sheet outline.
+    fn update_out_of_bounds(&mut self) {
+        self.out_of_bounds = self.entities.iter()
+            .filter(|(_, (handle, mt))|self.is_out_of_bounds(handle, mt))
+            .map(|(id, _)|*id)
+            .collect();
+    }
+
+    fn set_out_of_bounds(&mut self, id: EntityId, handle: &ModelHandle, mt: &EntityState) {
+        if self.is_out_of_bounds(handle, mt) {
+            self.out_of_bounds.insert(id);
+        } else {
+            self.out_of_bounds.remove(&id);
+        }
+    }
+
+    /// If any part of the entity's bounding box is past the sheet's margin or its hull touches a
+    /// keep-out zone.
+    fn is_out_of_bounds(&self, handle: &ModelHandle, mt: &EntityState)->bool {
+        let (min, max) = handle.bounds(mt);
+
+        let outside = min.x < self.margin ||
+            min.y < self.margin ||
+            max.x > self.sheet_size.x - self.margin ||
+            max.y > self.sheet_size.y - self.margin;
+
+        outside || self.keep_outs.iter()
+            .any(|(ko_min, ko_max)|handle.hull_intersects_rect(mt, *ko_min, *ko_max))
+    }
+
+    /// How to make the entity fit if it is bigger than the usable area of the sheet no matter where
+    /// it is moved. Returns `None` if it fits or doesn't exist.
+    pub fn oversize_fix(&self, id: EntityId)->Option<OversizeFix> {
+        let (handle, mt) = self.entities.get(&id)?;
+        let (min, max) = handle.bounds(mt);
+        let size = max - min;
+        let usable = self.sheet_size - Vector::broadcast(self.margin * 2.0);
+
+        if (size.x <= usable.x && size.y <= usable.y) || usable.x <= 0.0 || usable.y <= 0.0 {
+            return None;
+        }
+
+        return Some(OversizeFix {
+            scale: (usable.x / size.x).min(usable.y / size.y),
+            rotate_fits: size.y <= usable.x && size.x <= usable.y,
+        });
+    }
+
+    /// Apply the fix from [`Sheet::oversize_fix`] and move the entity to the middle of the sheet.
+    /// The rest of its group is changed with it.
+    pub fn fit_entity(&mut self, id: EntityId, rotate: bool) {
+        let Some(fix) = self.oversize_fix(id) else {return};
+
+        if rotate {
+            self.rotate_scale_entity(id, Rotation::from_angle(std::f64::consts::FRAC_PI_2), 1.0);
+        } else {
+            self.rotate_scale_entity(id, Rotation::identity(), fix.scale);
+        }
+        self.move_to_center(id);
+    }
+
+    /// The total length of every line of the entity after its transform is applied. This is how
+    /// far the laser travels while cutting it in a single pass. Returns `0.0` if the entity
+    /// doesn't exist.
+    pub fn path_length_for_entity(&self, id: EntityId)->f64 {
+        self.path_lengths.get(&id)
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// Make a new group from the entities. Groups need at least 2 entities.
+    pub fn group(&mut self, ids: Vec<EntityId>)->Option<GroupId> {
+        if ids.len() < 2 {
+            return None;
+        }
+
+        for group in self.groups.iter_mut() {
+            group.members.retain(|id|!ids.contains(id));
+        }
+        self.groups.retain(|g|g.members.len() >= 2);
+
+        let id = next_group_id();
+        eprintln!("Group {ids:?} as {id:?}");
+        self.groups.push(EntityGroup {
+            id,
+            members: ids,
+            transform: Transform::identity(),
+        });
+
+        return Some(id);
+    }
+
+    /// The group the entity is in, if any.
+    pub fn group_of(&self, id: EntityId)->Option<&EntityGroup> {
+        self.groups.iter().find(|g|g.members.contains(&id))
+    }
+
+    /// The entity and every other entity in its group.
+    pub fn group_members(&self, id: EntityId)->Vec<EntityId> {
+        match self.group_of(id) {
+            Some(group)=>group.members.clone(),
+            None=>vec![id],
+        }
+    }
+
+    /// Apply `delta` on top of the transform of the entity. If it is in a group, `delta` is added
+    /// to the group's transform instead, and the new group transform is applied on top of each
+    /// member's transform within the group.
+    pub fn transform_entity(&mut self, id: EntityId, delta: Transform) {
+        let Some(group) = self.groups.iter_mut().find(|g|g.members.contains(&id)) else {
+            if let Some((_, mt)) = self.entities.get_mut(&id) {
+                mt.transform.append_similarity(delta);
+            }
+            self.recalc_paths_id(id);
+            return;
+        };
+
+        let old = group.transform.inversed();
+        group.transform.append_similarity(delta);
+        let new = group.transform;
+
+        for member in group.members.clone() {
+            if let Some((_, mt)) = self.entities.get_mut(&member) {
+                // take off the old group transform to get the member's own, then put the new one on
+                mt.transform.append_similarity(old);
+                mt.transform.append_similarity(new);
+            }
+            self.recalc_paths_id(member);
+        }
+    }
+
+    /// Move the entity and its group.
+    pub fn translate_entity(&mut self, id: EntityId, delta: Translation) {
+        self.transform_entity(id, Transform::new(delta, Rotation::identity(), 1.0));
+    }
+
+    /// Rotate and scale around the entity's position. The rest of its group rotates and scales
+    /// around the same point.
+    pub fn rotate_scale_entity(&mut self, id: EntityId, rotation: Rotation, scale: f64) {
+        let Some((_, mt)) = self.entities.get(&id) else {return};
+        let center = mt.transform.translation;
+
+        let mut offset = center * scale;
+        rotation.rotate_vec(&mut offset);
+
+        self.transform_entity(id, Transform::new(center - offset, rotation, scale));
+    }
+
+    /// Rotate the entity to the angle in radians. The rest of its group rotates with it.
+    pub fn set_entity_angle(&mut self, id: EntityId, angle: f64) {
+        let Some((_, mt)) = self.entities.get(&id) else {return};
+        let delta = Rotation::from_angle(angle) * mt.transform.rotation.normalized().reversed();
+
+        self.rotate_scale_entity(id, delta, 1.0);
     }
 
     pub fn delete_entity(&mut self, id: EntityId) {
+        let Some((model, _)) = self.entities.remove(&id) else {
+            eprintln!("Tried to delete entity {id:?}, but it doesn't exist");
+            return;
+        };
         eprintln!("Delete entity: {id:?}");
-        let (model, _) = self.entities.remove(&id).unwrap();
         self.order.shift_remove(&id);
+        self.selection.shift_remove(&id);
+        for group in self.groups.iter_mut() {
+            group.members.retain(|m|*m != id);
+        }
+        self.groups.retain(|g|g.members.len() >= 2);
         self.paths.remove(&id);
+        self.path_lengths.remove(&id);
+        self.labels.remove(&id);
+        self.out_of_bounds.remove(&id);
+        self.stats.take();
         self.cached_models.remove(&id);
 
         if let Some(entities) = self.active_models.get_mut(&model) {
@@ -481,40 +1674,859 @@ impl Sheet {
             }
         }
 
-        if self.show_order {
-            self.clear_cache();
-        }
+        if self.show_order {
+            self.clear_cache();
+        }
+    }
+
+    /// Add a copy of the entity offset a little so it can be seen.
+    pub fn duplicate_entity(&mut self, id: EntityId) {
+        let Some((handle, mut mt)) = self.entities.get(&id).cloned() else {return};
+        eprintln!("Duplicate entity: {id:?}");
+
+        mt.transform.translation += Point::new(5.0, 5.0);
+        self.add_model_from_handle_with_transform(handle, mt, 1);
+    }
+
+    /// Move the entity (and its group) so the entity's bounding box is in the middle of the sheet.
+    pub fn move_to_center(&mut self, id: EntityId) {
+        let Some((min, max)) = self.entity_bounds(id) else {return};
+        let center = (min + max) / 2.0;
+
+        self.translate_entity(id, self.sheet_size / 2.0 - center);
+    }
+
+    /// The horizontal and vertical gaps between the bounding boxes of `a` and `b`, on the side of
+    /// `a` that `b` is on. Negative gaps are overlaps.
+    pub fn entity_gap(&self, a: EntityId, b: EntityId)->Option<Vector> {
+        let (a_min, a_max) = self.entity_bounds(a)?;
+        let (b_min, b_max) = self.entity_bounds(b)?;
+
+        return Some(Vector::new(
+            axis_gap(a_min.x, a_max.x, b_min.x, b_max.x).0,
+            axis_gap(a_min.y, a_max.y, b_min.y, b_max.y).0,
+        ));
+    }
+
+    /// Move `b` (and its group) so the horizontal or vertical gap between the bounding boxes of
+    /// `a` and `b` is `gap` mm. `b` stays on the same side of `a`. Nothing happens if they are in
+    /// the same group.
+    pub fn set_entity_gap(&mut self, a: EntityId, b: EntityId, gap: f64, vertical: bool) {
+        if self.group_members(a).contains(&b) {
+            return;
+        }
+        let Some((a_min, a_max)) = self.entity_bounds(a) else {return};
+        let Some((b_min, b_max)) = self.entity_bounds(b) else {return};
+
+        if vertical {
+            let (current, side) = axis_gap(a_min.y, a_max.y, b_min.y, b_max.y);
+            self.translate_entity(b, Translation::new(0.0, (gap - current) * side));
+        } else {
+            let (current, side) = axis_gap(a_min.x, a_max.x, b_min.x, b_max.x);
+            self.translate_entity(b, Translation::new((gap - current) * side, 0.0));
+        }
+    }
+
+    /// The scale and new center for [`Sheet::fit_to_sheet`]. The entity goes in whichever empty
+    /// part of the sheet between the margin and keep-out zones lets it be the biggest. Returns
+    /// `None` if there is no room or the entity doesn't exist.
+    pub fn fit_to_sheet_target(&self, id: EntityId)->Option<(f64, Point)> {
+        let (min, max) = self.entity_bounds(id)?;
+        let size = max - min;
+        if size.x <= 0.0 && size.y <= 0.0 {
+            return None;
+        }
+
+        return self.free_areas()
+            .into_iter()
+            .map(|(area_min, area_max)|{
+                let area = area_max - area_min;
+                let scale = match (size.x > 0.0, size.y > 0.0) {
+                    (true, true)=>(area.x / size.x).min(area.y / size.y),
+                    (true, false)=>area.x / size.x,
+                    _=>area.y / size.y,
+                };
+
+                (scale, (area_min + area_max) / 2.0)
+            })
+            .max_by(|a, b|a.0.total_cmp(&b.0));
+    }
+
+    /// Scale the entity (and its group) around its bounding box's center so it fills the sheet,
+    /// then move it to the middle of the empty area. This uses the rotated bounding box, so the
+    /// entity's angle is kept.
+    pub fn fit_to_sheet(&mut self, id: EntityId) {
+        let Some((scale, target)) = self.fit_to_sheet_target(id) else {return};
+        let Some((min, max)) = self.entity_bounds(id) else {return};
+        let center = (min + max) / 2.0;
+        self.transform_entity(id, Transform::new(target - center * scale, Rotation::identity(), scale));
+    }
+
+    /// The largest rectangles inside the sheet's margin that don't overlap a keep-out zone, as
+    /// `(min, max)` corners. They can overlap each other.
+    fn free_areas(&self)->Vec<(Point, Point)> {
+        let mut areas = vec![(
+            Point::broadcast(self.margin),
+            self.sheet_size - Vector::broadcast(self.margin),
+        )];
+
+        for (ko_min, ko_max) in self.keep_outs.iter() {
+            let mut split = Vec::new();
+            for (min, max) in areas {
+                if ko_max.x <= min.x || ko_min.x >= max.x || ko_max.y <= min.y || ko_min.y >= max.y {
+                    split.push((min, max));
+                    continue;
+                }
+
+                // the parts left, right, below, and above the keep-out
+                if ko_min.x > min.x {
+                    split.push((min, Point::new(ko_min.x, max.y)));
+                }
+                if ko_max.x < max.x {
+                    split.push((Point::new(ko_max.x, min.y), max));
+                }
+                if ko_min.y > min.y {
+                    split.push((min, Point::new(max.x, ko_min.y)));
+                }
+                if ko_max.y < max.y {
+                    split.push((Point::new(min.x, ko_max.y), max));
+                }
+            }
+            areas = split;
+        }
+
+        areas.retain(|(min, max)|max.x > min.x && max.y > min.y);
+        return areas;
+    }
+
+    /// Start cutting the entity at the vertex closest to `pos`, which is on the sheet.
+    pub fn set_start_vertex(&mut self, id: EntityId, pos: Point) {
+        let Some((_, mt)) = self.entities.get_mut(&id) else {return};
+        eprintln!("Set start vertex of {id:?}");
+
+        mt.start_vertex = Some(mt.inverse_transform(pos));
+    }
+
+    pub fn change_width(&mut self, width: f64) {
+        self.sheet_size.x = width;
+        self.sheet_cache.clear();
+        self.update_out_of_bounds();
+        self.stats.take();
+    }
+
+    pub fn change_height(&mut self, height: f64) {
+        self.sheet_size.y = height;
+        self.sheet_cache.clear();
+        self.update_out_of_bounds();
+        self.stats.take();
+    }
+
+    /// Turn the rotary mode on or off with the cylinder's diameter. Turning it on makes the sheet
+    /// height the circumference.
+    pub fn set_rotary(&mut self, rotary: bool, diameter: f64) {
+        self.rotary = rotary;
+        self.rotary_diameter = diameter;
+        if rotary {
+            self.change_height(std::f64::consts::PI * diameter);
+        }
+    }
+
+    /// If the rotary mode is on, but the sheet height isn't the cylinder's circumference.
+    pub fn rotary_height_mismatch(&self)->bool {
+        self.rotary && !rotary_height_matches(self.sheet_size.y, self.rotary_diameter)
+    }
+
+    pub fn set_origin(&mut self, origin: Origin) {
+        self.origin = origin;
+        self.sheet_cache.clear();
+        self.simulation.clear();
+        self.simulation_cache.clear();
+    }
+
+    /// The position of the entity in the machine's coordinates. See [`Sheet::origin`].
+    pub fn entity_position(&self, id: EntityId)->Option<Point> {
+        let (_, mt) = self.entities.get(&id)?;
+
+        return Some(self.origin.sheet_to_machine(mt.transform.translation, self.sheet_size));
+    }
+
+    /// Move the entity (and its group) to the position in the machine's coordinates.
+    pub fn set_entity_position(&mut self, id: EntityId, pos: Point) {
+        let Some((_, mt)) = self.entities.get(&id) else {return};
+        let target = self.origin.machine_to_sheet(pos, self.sheet_size);
+
+        self.translate_entity(id, target - mt.transform.translation);
+    }
+
+    pub fn change_margin(&mut self, margin: f64) {
+        self.margin = margin.max(0.0);
+        self.sheet_cache.clear();
+        self.update_out_of_bounds();
+    }
+
+    /// Scale the whole layout around the sheet's (0, 0) so nested entities stay nested. Every
+    /// entity's scale is multiplied by `scale / global_scale`.
+    pub fn set_global_scale(&mut self, scale: f64) {
+        // a scale of 0 can't be undone
+        if scale <= 0.0 {
+            return;
+        }
+
+        let delta = Transform::new(Translation::zero(), Rotation::identity(), scale / self.global_scale);
+        self.global_scale = scale;
+
+        for (_, mt) in self.entities.values_mut() {
+            mt.transform.append_similarity(delta);
+        }
+        for group in self.groups.iter_mut() {
+            group.transform.append_similarity(delta);
+        }
+
+        self.recalc_paths();
+    }
+
+    /// Add a keep-out zone with any two opposite corners.
+    pub fn add_keep_out(&mut self, a: Point, b: Point) {
+        self.keep_outs.push((a.min_by_component(b), a.max_by_component(b)));
+        self.sheet_cache.clear();
+        self.update_out_of_bounds();
+    }
+
+    pub fn remove_keep_out(&mut self, index: usize) {
+        if index < self.keep_outs.len() {
+            self.keep_outs.remove(index);
+            self.sheet_cache.clear();
+            self.update_out_of_bounds();
+        }
+    }
+
+    /// Fill a rectangle on the sheet with diagonal lines. The lines line up between rectangles, so
+    /// touching rectangles look like one area.
+    fn draw_hatched_rect(&self, frame: &mut Frame, min: Point, max: Point, color: Color, height: f64) {
+        let spacing = HATCH_SPACING / self.view.scale;
+        if max.x <= min.x || max.y <= min.y {
+            return;
+        }
+
+        // each line is `y = x - c`
+        let mut builder = PathBuilder::new();
+        let mut c = ((min.x - max.y) / spacing).floor() * spacing;
+        while c <= max.x - min.y {
+            let x0 = min.x.max(min.y + c);
+            let x1 = max.x.min(max.y + c);
+            if x0 < x1 {
+                builder.move_to(Point::new(x0, x0 - c).to_ydown(height).to_iced());
+                builder.line_to(Point::new(x1, x1 - c).to_ydown(height).to_iced());
+            }
+            c += spacing;
+        }
+
+        self.draw_line(frame, &builder.build(), color, 1.0);
+    }
+
+    /// Draw arrows along the machine's X and Y axes from the origin, labeled on the screen so the
+    /// text is the same size at any zoom.
+    fn draw_origin(&self, frame: &mut Frame, theme: &Theme, size: Size, height: f64) {
+        let palette = theme.palette();
+        let origin = self.origin.position(self.sheet_size);
+        let axes = self.origin.axes();
+        let length = ORIGIN_AXIS_LENGTH / self.view.scale;
+
+        for (dir, color, label) in [
+            (Vector::new(axes.x, 0.0), palette.danger, "X"),
+            (Vector::new(0.0, axes.y), palette.success, "Y"),
+        ] {
+            let tip = origin + dir * length;
+
+            frame.with_save(|frame|{
+                self.transform_frame(frame, size);
+
+                let start = origin.to_ydown(height);
+                let end = tip.to_ydown(height);
+                self.draw_line(frame, &Path::line(start.to_iced(), end.to_iced()), color, 2.0);
+
+                // the arrow's head is at the tip
+                let head = ARROW_SIZE / self.view.scale;
+                let dir = (end - start).normalized();
+                self.draw_arrow(frame, end - dir * head * 1.5, end + dir * head * 0.5, color);
+            });
+
+            let mut text = CanvasText::from(label);
+            text.position = self.world_to_screen(origin + dir * (length + 10.0 / self.view.scale), height).to_iced();
+            text.size = 14.0.into();
+            text.color = color;
+            text.horizontal_alignment = HorizontalAlign::Center;
+            text.vertical_alignment = VerticalAlign::Center;
+            frame.fill_text(text);
+        }
+
+        frame.fill(&Path::circle(self.world_to_screen(origin, height).to_iced(), 3.0), palette.text);
+    }
+
+    /// Draw the margin and keep-out zones as hatched areas.
+    fn draw_keep_outs(&self, frame: &mut Frame, color: Color, height: f64) {
+        let size = self.sheet_size;
+        let m = self.margin.min(size.x / 2.0).min(size.y / 2.0);
+        if m > 0.0 {
+            let strips = [
+                (Point::new(0.0, 0.0), Point::new(size.x, m)),
+                (Point::new(0.0, size.y - m), Point::new(size.x, size.y)),
+                (Point::new(0.0, m), Point::new(m, size.y - m)),
+                (Point::new(size.x - m, m), Point::new(size.x, size.y - m)),
+            ];
+            for (min, max) in strips {
+                self.draw_hatched_rect(frame, min, max, color, height);
+            }
+
+            let inner = Path::rectangle(
+                Point::new(m, size.y - m).to_ydown(height).to_iced(),
+                Size::new((size.x - m * 2.0) as f32, (size.y - m * 2.0) as f32),
+            );
+            self.draw_line(frame, &inner, color, 1.0);
+        }
+
+        for (min, max) in self.keep_outs.iter() {
+            self.draw_hatched_rect(frame, *min, *max, color, height);
+
+            let outline = Path::rectangle(
+                Point::new(min.x, max.y).to_ydown(height).to_iced(),
+                Size::new((max.x - min.x) as f32, (max.y - min.y) as f32),
+            );
+            self.draw_line(frame, &outline, color, 1.0);
+        }
+    }
+
+    fn draw_line(&self, f: &mut Frame, line: &Path, color: Color, width: f32) {
+        let stroke = Stroke {
+            style: Style::Solid(color),
+            width,
+            line_join: LineJoin::Miter,
+            line_cap: LineCap::Square,
+            ..Stroke::default()
+        };
+
+        f.stroke(line, stroke);
+    }
+
+    /// Draw an outline from [`ModelPaths`], which is on the sheet in Y up.
+    fn draw_outline(&self, frame: &mut Frame, outline: &Path, color: Color, width: f32, height: f64) {
+        frame.with_save(|frame|{
+            self.project_frame(frame, height);
+            self.draw_line(frame, outline, color, width);
+        });
+    }
+
+    /// A line width from [`crate::settings::CanvasStyle`]. Strokes are always drawn in screen pixels, so widths in
+    /// mm are scaled by the zoom.
+    fn style_width(&self, width: f32)->f32 {
+        if self.settings.borrow().canvas.widths_in_mm {
+            width * self.view.scale as f32
+        } else {
+            width
+        }
+    }
+
+    /// Draw an outline from [`ModelPaths`] as a thin dashed line.
+    fn draw_dashed_outline(&self, frame: &mut Frame, outline: &Path, color: Color, height: f64) {
+        let dash = [4.0 / self.view.scale as f32; 2];
+        let stroke = Stroke {
+            style: Style::Solid(color),
+            width: 1.0,
+            line_dash: LineDash {
+                segments: &dash,
+                offset: 0,
+            },
+            ..Stroke::default()
+        };
+
+        frame.with_save(|frame|{
+            self.project_frame(frame, height);
+            frame.stroke(outline, stroke);
+        });
+    }
+
+    /// Draw an arrowhead at the middle of the segment pointing from `p1` to `p2`. The points are
+    /// in the frame's space. Segments shorter than the arrow are skipped so curves made of many
+    /// small segments don't turn into a mess of arrows.
+    fn draw_arrow(&self, frame: &mut Frame, p1: Point, p2: Point, color: Color) {
+        let size = ARROW_SIZE / self.view.scale;
+        let delta = p2 - p1;
+        let length = delta.mag();
+        if length < size {
+            return;
+        }
+
+        let dir = delta / length;
+        let normal = Vector::new(-dir.y, dir.x);
+        let mid = (p1 + p2) / 2.0;
+        let tip = mid + dir * (size / 2.0);
+        let base = mid - dir * (size / 2.0);
+
+        let mut builder = PathBuilder::new();
+        builder.move_to(tip.to_iced());
+        builder.line_to((base + normal * (size / 3.0)).to_iced());
+        builder.line_to((base - normal * (size / 3.0)).to_iced());
+        builder.close();
+
+        frame.fill(&builder.build(), Fill {
+            style: Style::Solid(color),
+            ..Fill::default()
+        });
+    }
+
+    /// Draw an arrow halfway along each line pointing the way it is cut and a dot where the laser
+    /// turns on.
+    fn draw_cut_direction(&self, frame: &mut Frame, handle: &ModelHandle, mt: &EntityState, color: Color, start_color: Color, height: f64) {
+        let size = ARROW_SIZE / self.view.scale;
+
+        for points in handle.cut_lines(mt) {
+            let points = points.into_iter()
+                .map(|p|p.to_ydown(height))
+                .collect::<Vec<_>>();
+            let Some(first) = points.first() else {continue};
+
+            let length = points.windows(2)
+                .map(|seg|(seg[1] - seg[0]).mag())
+                .sum::<f64>();
+
+            // find the segment with the middle of the line
+            let mut remaining = length / 2.0;
+            for seg in points.windows(2) {
+                let delta = seg[1] - seg[0];
+                let seg_length = delta.mag();
+                if seg_length > 0.0 && remaining <= seg_length {
+                    let dir = delta / seg_length;
+                    let mid = seg[0] + dir * remaining;
+                    self.draw_arrow(frame, mid - dir * size, mid + dir * size, color);
+                    break;
+                }
+                remaining -= seg_length;
+            }
+
+            frame.fill(&Path::circle(first.to_iced(), (START_DOT_RADIUS / self.view.scale) as f32), start_color);
+        }
+    }
+
+    fn draw_simulation(&self, frame: &mut Frame, height: f64) {
+        for (color, points) in self.simulation.iter() {
+            let mut builder = PathBuilder::new();
+            builder.move_to(points[0].to_ydown(height).to_iced());
+            for point in points.iter().skip(1) {
+                builder.line_to(point.to_ydown(height).to_iced());
+            }
+            let path = builder.build();
+
+            if *color == SIMULATION_RAPID_COLOR {
+                let dash = [4.0 / self.view.scale as f32; 2];
+                let stroke = Stroke {
+                    style: Style::Solid(*color),
+                    width: 1.0,
+                    line_dash: LineDash {
+                        segments: &dash,
+                        offset: 0,
+                    },
+                    ..Stroke::default()
+                };
+                frame.stroke(&path, stroke);
+            } else {
+                self.draw_line(frame, &path, *color, 1.0);
+            }
+        }
+    }
+
+    /// Draw the right-click menu in screen space. The item under the cursor is highlighted.
+    fn draw_context_menu(&self, frame: &mut Frame, theme: &Theme, menu: Rectangle, cursor: Option<iced::Point>) {
+        let palette = theme.palette();
+        let hovered = cursor.and_then(|c|ContextMenuItem::at(menu, c));
+
+        frame.fill_rectangle(menu.position(), menu.size(), palette.background);
+
+        for (i, item) in ContextMenuItem::LIST.iter().enumerate() {
+            let top = menu.y + i as f32 * CONTEXT_MENU_ITEM_HEIGHT;
+
+            if hovered == Some(*item) {
+                frame.fill_rectangle(
+                    iced::Point::new(menu.x, top),
+                    Size::new(menu.width, CONTEXT_MENU_ITEM_HEIGHT),
+                    Color {a: 0.3, ..palette.primary},
+                );
+            }
+
+            let mut text = CanvasText::from(item.to_string());
+            text.position = iced::Point::new(menu.x + 8.0, top + CONTEXT_MENU_ITEM_HEIGHT / 2.0);
+            text.size = 14.0.into();
+            text.color = palette.text;
+            text.vertical_alignment = VerticalAlign::Center;
+
+            frame.fill_text(text);
+        }
+
+        self.draw_line(frame, &Path::rectangle(menu.position(), menu.size()), palette.primary, 1.0);
+    }
+
+    /// Handle events while the right-click menu is open. Any click picks the item under the cursor
+    /// or closes the menu, and every other mouse event is captured so the view stays still.
+    fn context_menu_update(&self, state: &mut SheetState, event: Event, bounds: Rectangle, cursor: Cursor)->(Status, Option<SheetMessage>) {
+        use SheetState as State;
+
+        let State::ContextMenu(id, pos) = *state else {
+            return (Status::Ignored, None);
+        };
+        let height = bounds.height as f64;
+        let sheet_pos = self.screen_to_world(pos, height);
+
+        match event {
+            Event::Mouse(MouseEvent::ButtonPressed(button))=>{
+                let menu = ContextMenuItem::menu_bounds(pos, bounds.size());
+                let item = cursor.position_in(bounds)
+                    .filter(|_|button == MouseButton::Left)
+                    .and_then(|c|ContextMenuItem::at(menu, c));
+
+                *state = match item {
+                    Some(ContextMenuItem::Delete)=>State::None(sheet_pos),
+                    _=>State::Select(id, sheet_pos),
+                };
+
+                return (Status::Captured, item.map(|item|item.message(id, sheet_pos)));
+            },
+            Event::Keyboard(KeyboardEvent::KeyPressed{key:Key::Named(NamedKey::Escape),..})=>{
+                *state = State::Select(id, sheet_pos);
+                return (Status::Captured, None);
+            },
+            Event::Mouse(_) if cursor.is_over(bounds)=>return (Status::Captured, None),
+            _=>return (Status::Ignored, None),
+        }
+    }
+
+    /// Handle events while measuring. `M` toggles measuring and Escape stops it. Returns `None` for
+    /// events that work the same as usual, like zooming.
+    fn measure_update(&self, state: &mut SheetState, event: &Event, bounds: Rectangle, cursor: Cursor)->Option<(Status, Option<SheetMessage>)> {
+        use SheetState as State;
+
+        let height = bounds.height as f64;
+        let cursor_pos = cursor.position_in(bounds)
+            .map(|p|self.screen_to_world(p.to_uv(), height));
+
+        match event {
+            Event::Keyboard(KeyboardEvent::KeyPressed{key, ..})=>{
+                let stop = match key {
+                    Key::Named(NamedKey::Escape)=>true,
+                    Key::Character(c)=>c.as_str() == "m",
+                    _=>false,
+                };
+                if !stop {
+                    return None;
+                }
+
+                eprintln!("Stop measuring");
+                let pos = match *state {
+                    State::MeasureStart(pos)|State::Measuring(_, pos)|State::Measured(_, pos)=>pos,
+                    _=>Point::zero(),
+                };
+                *state = State::None(cursor_pos.unwrap_or(pos));
+                return Some((Status::Captured, None));
+            },
+            Event::Mouse(MouseEvent::ButtonPressed(MouseButton::Left))=>{
+                let pos = cursor_pos?;
+                *state = match *state {
+                    State::Measuring(start, _)=>State::Measured(start, pos),
+                    _=>State::Measuring(pos, pos),
+                };
+                return Some((Status::Captured, None));
+            },
+            Event::Mouse(MouseEvent::CursorMoved{..})=>{
+                let pos = cursor_pos?;
+                match state {
+                    State::MeasureStart(cursor)|State::Measuring(_, cursor)=>*cursor = pos,
+                    _=>{},
+                }
+                return Some((Status::Captured, None));
+            },
+            _=>return None,
+        }
+    }
+
+    /// Draw the measurement line with its length above the middle of it.
+    fn draw_measurement(&self, frame: &mut Frame, theme: &Theme, start: Point, end: Point, height: f64) {
+        let palette = theme.palette();
+        let start_screen = self.world_to_screen(start, height);
+        let end_screen = self.world_to_screen(end, height);
+
+        self.draw_line(frame, &Path::line(start_screen.to_iced(), end_screen.to_iced()), palette.danger, 1.0);
+        for point in [start_screen, end_screen] {
+            frame.fill(&Path::circle(point.to_iced(), 3.0), palette.danger);
+        }
+
+        let units = self.settings.borrow().units;
+        let mut text = CanvasText::from(units.length((end - start).mag()));
+        text.position = ((start_screen + end_screen) / 2.0 - Vector::new(0.0, 12.0)).to_iced();
+        text.size = 16.0.into();
+        text.color = palette.text;
+        text.horizontal_alignment = HorizontalAlign::Center;
+        text.vertical_alignment = VerticalAlign::Center;
+
+        frame.fill_text(text);
+    }
+
+    fn transform_frame(&self, frame: &mut Frame, _bounds: Size) {
+        frame.translate(iced::Vector {
+            x: self.view.translation.x as f32,
+            // y: (bounds.height - self.view.translation.y as f32),
+            y: self.view.translation.y as f32,
+        });
+        frame.scale(self.view.scale as f32);
+    }
+
+    /// Map a point on the sheet (Y up) to the screen (Y down). `height` is the height of the
+    /// canvas. This is the same as [`Sheet::transform_frame`] after [`Project2D::to_ydown`].
+    pub fn world_to_screen(&self, point: Point, height: f64)->Point {
+        self.view.transform_vec(point.to_ydown(height))
+    }
+
+    /// Map a point on the screen (Y down) to the sheet (Y up). `height` is the height of the
+    /// canvas. This is the inverse of [`Sheet::world_to_screen`].
+    pub fn screen_to_world(&self, point: Point, height: f64)->Point {
+        self.view.inversed()
+            .transform_vec(point)
+            .to_ydown(height)
+    }
+
+    /// How far the view is zoomed in. `1.0` is 100%.
+    pub fn zoom(&self)->f64 {
+        self.view.scale
+    }
+
+    /// How much more to move the entity (and its group) so one of its bounding box edges lines up
+    /// with an edge of a nearby entity, the sheet, or the margin after moving it by `delta`. Each
+    /// axis snaps separately to the closest edge within the snap distance. The edges it snapped to
+    /// are saved in [`Sheet::snap_lines`] to be drawn. Holding Alt doesn't snap.
+    fn snap_move(&self, id: EntityId, delta: Vector)->Vector {
+        let mut snap_lines = self.snap_lines.borrow_mut();
+        snap_lines.clear();
+
+        let settings = self.settings.borrow();
+        if !settings.entity_snap || self.modifiers.get().alt() {
+            return Vector::zero();
+        }
+        let max_dist = settings.snap_distance / self.view.scale;
+        drop(settings);
+
+        let members = self.group_members(id);
+        let Some((min, max)) = self.entities_bounds(&members) else {return Vector::zero()};
+        let (min, max) = (min + delta, max + delta);
+
+        // `(correction, edge, other min, other max)` for each axis. `edge` is the other entity's
+        // edge that is snapped to.
+        let mut best_x: Option<(f64, f64, Point, Point)> = None;
+        let mut best_y: Option<(f64, f64, Point, Point)> = None;
+
+        // only entities within the moved box's own size of it, so big sheets stay fast
+        let reach = (max - min).component_max() + max_dist;
+        let others = self.paths.iter()
+            .filter(|(other_id, _)|!members.contains(other_id))
+            .map(|(_, (_, paths))|(paths.min, paths.max))
+            .filter(|(other_min, other_max)|{
+                other_min.x <= max.x + reach && other_max.x >= min.x - reach &&
+                    other_min.y <= max.y + reach && other_max.y >= min.y - reach
+            });
+        let margin = Vector::broadcast(self.margin);
+        let borders = [
+            (Point::zero(), self.sheet_size),
+            (margin, self.sheet_size - margin),
+        ];
+        let border_count = if self.margin > 0.0 {2} else {1};
+
+        for (other_min, other_max) in others.chain(borders.into_iter().take(border_count)) {
+            for (edge, other_edge) in edge_pairs(min.x, max.x, other_min.x, other_max.x) {
+                let correction = other_edge - edge;
+                if correction.abs() <= max_dist && best_x.is_none_or(|(c, ..)|correction.abs() < c.abs()) {
+                    best_x = Some((correction, other_edge, other_min, other_max));
+                }
+            }
+            for (edge, other_edge) in edge_pairs(min.y, max.y, other_min.y, other_max.y) {
+                let correction = other_edge - edge;
+                if correction.abs() <= max_dist && best_y.is_none_or(|(c, ..)|correction.abs() < c.abs()) {
+                    best_y = Some((correction, other_edge, other_min, other_max));
+                }
+            }
+        }
+
+        let snap = Vector::new(
+            best_x.map(|(c, ..)|c).unwrap_or(0.0),
+            best_y.map(|(c, ..)|c).unwrap_or(0.0),
+        );
+        let (min, max) = (min + snap, max + snap);
+
+        // a line along the shared edge, covering both boxes
+        if let Some((_, x, other_min, other_max)) = best_x {
+            snap_lines.push((
+                Point::new(x, min.y.min(other_min.y)),
+                Point::new(x, max.y.max(other_max.y)),
+            ));
+        }
+        if let Some((_, y, other_min, other_max)) = best_y {
+            snap_lines.push((
+                Point::new(min.x.min(other_min.x), y),
+                Point::new(max.x.max(other_max.x), y),
+            ));
+        }
+
+        return snap;
+    }
+
+    /// The `(min, max)` corners of the bounding box around all of the entities.
+    fn entities_bounds(&self, ids: &[EntityId])->Option<(Point, Point)> {
+        ids.iter()
+            .filter_map(|id|self.entity_bounds(*id))
+            .reduce(|(min, max), (other_min, other_max)|(min.min_by_component(other_min), max.max_by_component(other_max)))
+    }
+
+    /// The `(min, max)` corners of the entity's bounding box from its cached paths.
+    fn entity_bounds(&self, id: EntityId)->Option<(Point, Point)> {
+        self.paths.get(&id)
+            .map(|(_, paths)|(paths.min, paths.max))
+    }
+
+    /// Returns the selected entity if this click is the second click of a double-click on it.
+    fn double_click(&self, state: &SheetState, pos: Point)->Option<EntityId> {
+        let (last_id, time) = self.last_click.take()?;
+        let SheetState::Select(id, _) = state else {return None};
+
+        if *id != last_id || time.elapsed() > DOUBLE_CLICK_TIME {
+            return None;
+        }
+
+        let (model, mt) = self.entities.get(id)?;
+        return model.point_within(mt.inverse_transform(pos)).then_some(*id);
+    }
+
+    /// Tab and Shift+Tab select the entity after or before `current` in the cut order, and Ctrl+A
+    /// adds every entity to the multi-selection. The app handles these keys, so they aren't used
+    /// while a text input has focus.
+    pub fn selection_shortcut(&self, current: Option<EntityId>, key: &Key, modifiers: Modifiers)->Option<SheetMessage> {
+        if self.reorder {
+            return None;
+        }
+
+        match key {
+            Key::Named(NamedKey::Tab)=>{
+                let ids = self.ordered_entities()
+                    .map(|(id, _)|id)
+                    .collect::<Vec<_>>();
+                if ids.is_empty() {
+                    return None;
+                }
+
+                let len = ids.len();
+                let back = modifiers.shift();
+                let idx = match current.and_then(|current|ids.iter().position(|id|*id == current)) {
+                    Some(i) if back=>(i + len - 1) % len,
+                    Some(i)=>(i + 1) % len,
+                    None if back=>len - 1,
+                    None=>0,
+                };
+
+                return Some(SheetMessage::SelectEntity(ids[idx]));
+            },
+            Key::Character(c) if modifiers.command() && c.as_str() == "a"=>{
+                let ids = self.ordered_entities()
+                    .map(|(id, _)|id)
+                    .collect();
+
+                return Some(SheetMessage::SelectMultiple(ids));
+            },
+            _=>return None,
+        }
+    }
+
+    /// Zoom in or out by `zoom` keeping the point on the screen under the cursor in the same place.
+    fn zoom_at(&mut self, zoom: f64, cursor: Point) {
+        let mouse_offset = self.view.translation - cursor;
+        let offset = (mouse_offset * zoom) - mouse_offset;
+
+        self.view.translation += offset;
+        self.view.scale *= zoom;
+    }
+
+    /// If the entity passes the condition filter. See [`Sheet::condition_filter`].
+    fn condition_visible(&self, mt: &EntityState)->bool {
+        self.condition_filter.is_none_or(|c|c == mt.laser_condition)
+    }
+
+    /// Find the first entity under the point on the sheet.
+    fn entity_at(&self, pos: Point)->Option<EntityId> {
+        for (id, (_, mt)) in self.entities.iter() {
+            if !self.condition_visible(mt) {
+                continue;
+            }
+
+            if self.entity_contains(*id, pos) {
+                return Some(*id);
+            }
+        }
+
+        return None;
     }
 
-    pub fn change_width(&mut self, width: f64) {
-        self.sheet_size.x = width;
-        self.sheet_cache.clear();
-    }
+    /// If the point on the sheet is inside the entity. The cached bounding box is checked first, so
+    /// most entities are skipped without transforming the point.
+    fn entity_contains(&self, id: EntityId, pos: Point)->bool {
+        let Some((handle, mt)) = self.entities.get(&id) else {return false};
+        let Some((min, max)) = self.entity_bounds(id) else {return false};
 
-    pub fn change_height(&mut self, height: f64) {
-        self.sheet_size.y = height;
-        self.sheet_cache.clear();
+        if pos.x < min.x || pos.y < min.y || pos.x > max.x || pos.y > max.y {
+            return false;
+        }
+
+        return handle.point_within(mt.inverse_transform(pos));
     }
 
-    fn draw_line(&self, f: &mut Frame, line: &Path, color: Color, width: f32) {
-        let stroke = Stroke {
-            style: Style::Solid(color),
-            width,
-            line_join: LineJoin::Miter,
-            line_cap: LineCap::Square,
-            ..Stroke::default()
+    /// The entity a left click at `pos` would select. Like clicking, the selected entity and the
+    /// ones already cycled through are only picked when nothing else is under the cursor.
+    fn click_candidate(&self, state: &SheetState, pos: Point)->Option<EntityId> {
+        let selected = match state {
+            SheetState::Select(id, _)|SheetState::DelaySelect(id, ..)|SheetState::OrderEditSelect(id)=>Some(*id),
+            _=>None,
         };
+        let recent_clicks = self.recent_clicks.borrow();
 
-        f.stroke(line, stroke);
+        let mut fallback = None;
+        for (id, (_, mt)) in self.entities.iter() {
+            if !self.condition_visible(mt) || !self.entity_contains(*id, pos) {
+                continue;
+            }
+
+            if selected == Some(*id) || (selected.is_some() && recent_clicks.contains(id)) {
+                fallback = Some(*id);
+            } else {
+                return Some(*id);
+            }
+        }
+
+        return fallback;
     }
 
-    fn transform_frame(&self, frame: &mut Frame, _bounds: Size) {
-        frame.translate(iced::Vector {
-            x: self.view.translation.x as f32,
-            // y: (bounds.height - self.view.translation.y as f32),
-            y: self.view.translation.y as f32,
-        });
-        frame.scale(self.view.scale as f32);
+    /// Project the frame from the sheet's Y up to the screen's Y down. This is the same as
+    /// [`Project2D::to_ydown`].
+    fn project_frame(&self, frame: &mut Frame, height: f64) {
+        frame.translate(iced::Vector::new(0.0, height as f32));
+        frame.scale_nonuniform(iced::Vector::new(1.0, -1.0));
+    }
+
+    /// Transform the frame from model space to the sheet. This is the same as
+    /// [`EntityState::transform`] followed by projecting to Y down.
+    fn transform_entity_frame(&self, frame: &mut Frame, mt: &EntityState, height: f64) {
+        let translation = mt.transform.translation;
+
+        self.project_frame(frame, height);
+
+        frame.translate(iced::Vector::new(translation.x as f32, translation.y as f32));
+        frame.rotate(mt.angle() as f32);
+        frame.scale(mt.transform.scale as f32);
+
+        if mt.flip {
+            frame.scale_nonuniform(iced::Vector::new(1.0, -1.0));
+        }
     }
 }
 impl CanvasProgram<SheetMessage> for Sheet {
@@ -526,13 +2538,20 @@ impl CanvasProgram<SheetMessage> for Sheet {
         renderer: &Renderer,
         theme: &Theme,
         bounds: Rectangle,
-        _cursor: Cursor,
+        cursor: Cursor,
     ) -> Vec<<Renderer as GeometryRenderer>::Geometry> {
+        let style = self.settings.borrow().canvas;
         let text_color = theme.palette().text;
         let outline_color = theme.palette().primary;
-        let sheet_fg_color = theme.palette().primary;
+        let sheet_fg_color = style.sheet_color
+            .map(|[r, g, b]|Color::from_rgb8(r, g, b))
+            .unwrap_or(theme.palette().primary);
+        let start_color = theme.palette().success;
+        let entity_width = self.style_width(style.entity_width);
+        let selected_width = self.style_width(style.selected_width);
         let mut ret = Vec::new();
 
+        self.canvas_size.set(bounds.size());
         let height = bounds.height as f64;
 
         assert!(self.entities.len() == self.paths.len());
@@ -543,6 +2562,12 @@ impl CanvasProgram<SheetMessage> for Sheet {
             height: bounds.height,
         };
 
+        if let Some([r, g, b]) = style.background {
+            let mut frame = Frame::new(renderer, size);
+            frame.fill_rectangle(iced::Point::ORIGIN, size, Color::from_rgb8(r, g, b));
+            ret.push(frame.into_geometry());
+        }
+
         // draw the sheet first
         ret.push(self.sheet_cache.draw(
             renderer,
@@ -586,7 +2611,9 @@ impl CanvasProgram<SheetMessage> for Sheet {
                 // );
 
                 // do the outline of the sheet
-                self.draw_line(frame, &path, sheet_fg_color, 2.0);
+                self.draw_line(frame, &path, sheet_fg_color, self.style_width(style.sheet_width));
+
+                self.draw_keep_outs(frame, sheet_fg_color.scale_alpha(0.4), height);
             },
         ));
 
@@ -596,6 +2623,7 @@ impl CanvasProgram<SheetMessage> for Sheet {
             let index = self.order.get_index_of(id)
                 .map(|i|format!("#{}", i + 1))
                 .unwrap_or(String::from("??"));
+            let index = format!("{index}\n{}", self.entity_label(*id));
             ret.push(cache.draw(
                 renderer,
                 Size {
@@ -605,12 +2633,11 @@ impl CanvasProgram<SheetMessage> for Sheet {
                 |frame|{
                     use SheetState as State;
 
-                    self.transform_frame(frame, size);
-
+                    // the label is on the screen so it is the same size at any zoom
                     if self.show_order || self.reorder {
                         let mut text = CanvasText::from(index);
-                        text.position = paths.display_center;
-                        text.size = (16.0 / self.view.scale as f32).into();
+                        text.position = self.world_to_screen(paths.display_center, height).to_iced();
+                        text.size = style.order_text_size.into();
                         text.color = text_color;
                         text.horizontal_alignment = HorizontalAlign::Center;
                         text.vertical_alignment = VerticalAlign::Center;
@@ -618,9 +2645,28 @@ impl CanvasProgram<SheetMessage> for Sheet {
                         frame.fill_text(text);
                     }
 
+                    self.transform_frame(frame, size);
+
                     // Do the main path before the outline so the outline shows over the paths
-                    for path in paths.lines.iter() {
-                        self.draw_line(frame, &path, *color, 1.0);
+                    let (handle, mt) = &self.entities[id];
+                    let color = match self.condition_visible(mt) {
+                        true=>*color,
+                        false=>color.scale_alpha(0.15),
+                    };
+                    frame.with_save(|frame|{
+                        self.transform_entity_frame(frame, mt, height);
+
+                        for path in handle.local_paths() {
+                            self.draw_line(frame, path, color, entity_width);
+                        }
+                    });
+
+                    if self.show_bounding_boxes {
+                        self.draw_dashed_outline(frame, &paths.outline, text_color.scale_alpha(0.4), height);
+                    }
+
+                    if self.show_direction {
+                        self.draw_cut_direction(frame, handle, mt, text_color, start_color, height);
                     }
 
                     // do the outline
@@ -630,17 +2676,117 @@ impl CanvasProgram<SheetMessage> for Sheet {
                             State::PanSelected(idx, ..)|
                             State::DelaySelect(idx, ..)|
                             State::OrderEditSelect(idx)|
-                            State::OrderEditPanSelect(idx, ..)=>{
-                                if id == idx {
-                                    self.draw_line(frame, &paths.outline, outline_color, 1.0);
+                            State::OrderEditPanSelect(idx, ..)|
+                            State::ContextMenu(idx, _)=>{
+                                let in_group = self.group_of(*idx)
+                                    .map(|g|g.members.contains(id))
+                                    .unwrap_or(false);
+                                if id == idx || in_group {
+                                    self.draw_outline(frame, &paths.outline, outline_color, selected_width, height);
                                 }
                             },
                         _=>{},
                     }
+
+                    if self.selection.contains(id) {
+                        self.draw_outline(frame, &paths.outline, outline_color, selected_width, height);
+                    }
+
+                    // show which way the laser goes around the selected entity
+                    if let State::Select(idx, _) = state {
+                        if id == idx {
+                            for points in handle.cut_lines(mt) {
+                                for seg in points.windows(2) {
+                                    self.draw_arrow(
+                                        frame,
+                                        seg[0].to_ydown(height),
+                                        seg[1].to_ydown(height),
+                                        outline_color,
+                                    );
+                                }
+                            }
+                        }
+                    }
+                },
+            ));
+        }
+
+        // the simulation goes over everything else
+        if !self.simulation.is_empty() {
+            ret.push(self.simulation_cache.draw(
+                renderer,
+                size,
+                |frame|{
+                    self.transform_frame(frame, size);
+                    self.draw_simulation(frame, height);
                 },
             ));
         }
 
+        // the snap lines change with every move, so they aren't cached
+        let snap_lines = self.snap_lines.borrow();
+        if !snap_lines.is_empty() {
+            let mut frame = Frame::new(renderer, size);
+            let color = theme.extended_palette().success.base.color;
+            for (start, end) in snap_lines.iter() {
+                let line = Path::line(
+                    self.world_to_screen(*start, height).to_iced(),
+                    self.world_to_screen(*end, height).to_iced(),
+                );
+                self.draw_line(&mut frame, &line, color, 1.0);
+            }
+            ret.push(frame.into_geometry());
+        }
+
+        // the outline of the entity a click would select, unless it is already selected
+        let hovered = self.hovered.get()
+            .filter(|id|!matches!(state, SheetState::Select(selected, _)|SheetState::OrderEditSelect(selected) if selected == id))
+            .and_then(|id|self.paths.get(&id));
+        if let Some((_, paths)) = hovered {
+            let mut frame = Frame::new(renderer, size);
+            frame.with_save(|frame|{
+                self.transform_frame(frame, size);
+                self.draw_outline(frame, &paths.outline, text_color.scale_alpha(0.4), 1.0, height);
+            });
+            ret.push(frame.into_geometry());
+        }
+
+        // the origin is drawn on the screen, so it can't share the sheet's transformed frame
+        let mut frame = Frame::new(renderer, size);
+        self.draw_origin(&mut frame, theme, size, height);
+        ret.push(frame.into_geometry());
+
+        // the cursor position in the machine's coordinates
+        if let Some(pos) = self.cursor_pos.get() {
+            let mut frame = Frame::new(renderer, size);
+            let units = self.settings.borrow().units;
+            let pos = self.origin.sheet_to_machine(pos, self.sheet_size);
+
+            let mut text = CanvasText::from(format!("X: {}  Y: {}", units.length(pos.x), units.length(pos.y)));
+            text.position = iced::Point::new(8.0, size.height - 8.0);
+            text.size = 14.0.into();
+            text.color = text_color;
+            text.vertical_alignment = VerticalAlign::Bottom;
+            frame.fill_text(text);
+
+            ret.push(frame.into_geometry());
+        }
+
+        // the measurement follows the cursor, so it isn't cached either
+        if let SheetState::Measuring(start, end)|SheetState::Measured(start, end) = state {
+            let mut frame = Frame::new(renderer, size);
+            self.draw_measurement(&mut frame, theme, *start, *end, height);
+            ret.push(frame.into_geometry());
+        }
+
+        // the menu changes with the cursor, so it isn't cached
+        if let SheetState::ContextMenu(_, pos) = state {
+            let mut frame = Frame::new(renderer, size);
+            let menu = ContextMenuItem::menu_bounds(*pos, size);
+            self.draw_context_menu(&mut frame, theme, menu, cursor.position_in(bounds));
+            ret.push(frame.into_geometry());
+        }
+
         return ret;
     }
 
@@ -655,31 +2801,73 @@ impl CanvasProgram<SheetMessage> for Sheet {
         use SheetState as State;
 
         let height = bounds.height as f64;
-        let old_height = self.window_height.get();
 
-        self.window_height.set(height);
-        self.height_change.set(old_height == height);
+        self.cursor_pos.set(cursor.position_in(bounds).map(|p|self.screen_to_world(p.to_uv(), height)));
 
         if self.reorder {
             match state {
-                State::OrderEdit|State::OrderEditSelect(_)=>{},
+                State::OrderEdit|State::OrderEditSelect(_)|State::ContextMenu(..)=>{},
                 State::Select(id, ..)|State::DelaySelect(id, ..)=>*state = State::OrderEditSelect(*id),
                 _=>*state = State::OrderEdit,
             }
         }
 
+        if let Event::Keyboard(KeyboardEvent::ModifiersChanged(modifiers)) = event {
+            self.modifiers.set(modifiers);
+        }
+
+        if let Some(id) = self.pending_select.take() {
+            if self.entities.contains_key(&id) && !self.reorder {
+                *state = State::Select(id, self.cursor_pos.get().unwrap_or_default());
+            }
+        }
+
+        // nothing is hovered while dragging
+        if let Event::Mouse(_) = event {
+            let hovered = match state {
+                State::Move(..)|
+                    State::DelaySelect(..)|
+                    State::Pan(_)|
+                    State::PanSelected(..)|
+                    State::OrderEditPan(_)|
+                    State::OrderEditPanSelect(..)|
+                    State::ContextMenu(..)|
+                    State::MeasureStart(_)|
+                    State::Measuring(..)|
+                    State::Measured(..)=>None,
+                _=>self.cursor_pos.get().and_then(|pos|self.click_candidate(state, pos)),
+            };
+            self.hovered.set(hovered);
+        }
+
+        if let State::ContextMenu(..) = state {
+            return self.context_menu_update(state, event, bounds, cursor);
+        }
+
+        if let State::MeasureStart(..)|State::Measuring(..)|State::Measured(..) = state {
+            if let Some(res) = self.measure_update(state, &event, bounds, cursor) {
+                return res;
+            }
+        }
+
         if cursor.is_over(bounds) {
             let cursor_pos = cursor.position_in(bounds)
                 .unwrap()
                 .to_uv();
-            let move_pos = cursor.position_in(bounds)
-                .unwrap()
-                .to_yup(height);
+            let move_pos = self.screen_to_world(cursor_pos, height);
 
             match event {
+                Event::Keyboard(KeyboardEvent::KeyPressed{key:Key::Character(c), modifiers, ..})
+                    if c.as_str() == "m" && modifiers.is_empty() && !self.reorder=>
+                {
+                    eprintln!("Start measuring");
+                    *state = State::MeasureStart(move_pos);
+                    return (Status::Captured, None);
+                },
                 Event::Keyboard(e)=>{
                     // let movement = (1.0 / self.view.scale.sqrt()).min(5.0);
-                    let movement = 1.0;
+                    // the nudge distance is in pixels on the screen
+                    let movement = self.settings.borrow().nudge_distance / self.view.scale;
                     let id = match state {
                         State::Select(id, _)=>*id,
                         State::OrderEditSelect(id)=>match e {
@@ -738,7 +2926,22 @@ impl CanvasProgram<SheetMessage> for Sheet {
                 },
                 Event::Mouse(e)=>{
                     match e {
+                        MouseEvent::ButtonPressed(MouseButton::Left) if self.modifiers.get().shift() && !self.reorder=>{
+                            if let Some(id) = self.entity_at(move_pos) {
+                                return (Status::Captured, Some(SheetMessage::ToggleSelection(id)));
+                            }
+
+                            return (Status::Captured, None);
+                        },
                         MouseEvent::ButtonPressed(MouseButton::Left)=>{
+                            // a quick second click on the selected entity opens its settings
+                            // instead of cycling to the entity under it
+                            if let Some(id) = self.double_click(state, move_pos) {
+                                eprintln!("Double-click {id:?}");
+                                *state = State::Move(id, move_pos);
+                                return (Status::Captured, Some(SheetMessage::Focus(id)));
+                            }
+
                             let mut fallback_id = None;
                             let mut found_id = None;
 
@@ -746,42 +2949,12 @@ impl CanvasProgram<SheetMessage> for Sheet {
 
                             let mut cleared = None;
 
-                            for (id, (model, mt)) in self.entities.iter() {
-                                // let mut model_tr = mt.transform;
-                                // model_tr.append_similarity(self.view);
-                                // let inv_model_view = model_tr.inversed();
-                                // let mut model_point = inv_model_view
-                                //     .transform_vec(cursor_pos)
-                                //     .to_ydown(height);
-
-                                // let view_point = inv_view.transform_vec(move_pos);
-                                let mut view_point = move_pos;
-                                let t = self.world.translation;
-
-                                view_point.x = view_point.x - t.x;
-                                view_point.y = view_point.y - t.y;
-
-                                view_point /= self.world.scale;
-
-                                let inv_model = mt.transform.inversed();
-                                let mut model_point = inv_model.transform_vec(view_point);
-
-                                // dbg!(
-                                //     self.world.translation,
-                                //     self.view.translation,
-                                //     self.world.scale,
-                                //     move_pos,
-                                //     cursor_pos,
-                                //     view_point,
-                                //     model_point,
-                                // );
-                                // eprintln!();
-
-                                if mt.flip {
-                                    model_point.y *= -1.0;
+                            for (id, (_, mt)) in self.entities.iter() {
+                                if !self.condition_visible(mt) {
+                                    continue;
                                 }
 
-                                if model.point_within(model_point) {
+                                if self.entity_contains(*id, move_pos) {
                                     match state {
                                         State::Select(id2, _)|State::DelaySelect(id2, ..)|State::OrderEditSelect(id2)=>{
                                             if id == id2 || rc.contains(id) {
@@ -829,6 +3002,7 @@ impl CanvasProgram<SheetMessage> for Sheet {
                             if let Some(id) = found_id.or(fallback_id) {
                                 eprintln!("Select and start move {id:?}");
                                 rc.insert(id);
+                                self.last_click.set(Some((id, Instant::now())));
                                 match state {
                                     State::Select(current_id, ..) if fallback_id.is_some()=>{
                                         eprintln!("Delay selection incase of move");
@@ -856,7 +3030,7 @@ impl CanvasProgram<SheetMessage> for Sheet {
                                     },
                                     State::OrderEditPan(..)|State::OrderEditPanSelect(..)=>{
                                         eprintln!("Deselect {id:?}");
-                                        *state = State::OrderEditPan(cursor_pos, move_pos);
+                                        *state = State::OrderEditPan(cursor_pos);
                                         return (Status::Captured, Some(SheetMessage::Deselect(id)));
                                     },
                                     _=>{
@@ -876,7 +3050,7 @@ impl CanvasProgram<SheetMessage> for Sheet {
                                 State::OrderEditPanSelect(id, ..)=>{
                                     let id = *id;
                                     eprintln!("Deselect {id:?}");
-                                    *state = State::OrderEditPan(cursor_pos, move_pos);
+                                    *state = State::OrderEditPan(cursor_pos);
                                     return (Status::Captured, Some(SheetMessage::Deselect(id)));
                                 },
                                 State::Select(id, _)|State::DelaySelect(id, ..)=>{
@@ -891,6 +3065,8 @@ impl CanvasProgram<SheetMessage> for Sheet {
                             return (Status::Captured, None);
                         },
                         MouseEvent::ButtonReleased(MouseButton::Left)=>{
+                            self.snap_lines.borrow_mut().clear();
+
                             match state {
                                 State::Move(id, _)=>{
                                     eprintln!("Stop move {id:?}");
@@ -908,28 +3084,37 @@ impl CanvasProgram<SheetMessage> for Sheet {
                             return (Status::Captured, None);
                         },
                         MouseEvent::ButtonPressed(MouseButton::Right)=>{
+                            // right-clicking an entity opens its menu instead of panning
+                            if let State::Select(..)|State::None(_) = state {
+                                if let Some(id) = self.entity_at(move_pos) {
+                                    eprintln!("Open context menu for {id:?}");
+                                    *state = State::ContextMenu(id, cursor_pos);
+                                    return (Status::Captured, Some(SheetMessage::Select(id)));
+                                }
+                            }
+
                             match state {
                                 State::Select(id, _)=>{
                                     eprintln!("Start pan with selection {id:?}");
-                                    *state = State::PanSelected(*id, cursor_pos, move_pos);
+                                    *state = State::PanSelected(*id, cursor_pos);
                                 },
                                 State::None(_)=>{
-                                    *state = State::Pan(cursor_pos, move_pos);
+                                    *state = State::Pan(cursor_pos);
                                     eprintln!("Start pan");
                                 },
-                                State::OrderEdit=>*state = State::OrderEditPan(cursor_pos, move_pos),
-                                State::OrderEditSelect(id)=>*state = State::OrderEditPanSelect(*id, cursor_pos, move_pos),
+                                State::OrderEdit=>*state = State::OrderEditPan(cursor_pos),
+                                State::OrderEditSelect(id)=>*state = State::OrderEditPanSelect(*id, cursor_pos),
                                 _=>{},
                             }
                             return (Status::Captured, None);
                         },
                         MouseEvent::ButtonReleased(MouseButton::Right)=>{
                             match state {
-                                State::Pan(_, _)=>{
+                                State::Pan(_)=>{
                                     *state = State::None(move_pos);
                                     eprintln!("Stop pan");
                                 },
-                                State::PanSelected(id, _, _)=>{
+                                State::PanSelected(id, _)=>{
                                     eprintln!("Stop pan with selection {id:?}");
                                     *state = State::Select(*id, move_pos);
                                 },
@@ -941,43 +3126,46 @@ impl CanvasProgram<SheetMessage> for Sheet {
                         },
                         MouseEvent::CursorMoved{..}=>{
                             match state {
-                                State::Pan(prev, w_prev)|
-                                    State::PanSelected(_, prev, w_prev)|
-                                    State::OrderEditPan(prev, w_prev)|
-                                    State::OrderEditPanSelect(_, prev, w_prev)=>{
+                                State::Pan(prev)|
+                                    State::PanSelected(_, prev)|
+                                    State::OrderEditPan(prev)|
+                                    State::OrderEditPanSelect(_, prev)=>{
                                     let delta = cursor_pos - *prev;
                                     *prev = cursor_pos;
 
-                                    let w_delta = move_pos - *w_prev;
-                                    *w_prev = move_pos;
-
                                     if delta.mag_sq() >= 8.0 {
                                         self.recent_clicks.borrow_mut().clear();
                                     }
 
                                     return (
                                         Status::Captured,
-                                        Some(SheetMessage::Pan(delta, w_delta)),
+                                        Some(SheetMessage::Pan(delta)),
                                     );
                                 },
                                 State::Move(id, prev)|State::DelaySelect(id, _, prev)=>{
                                     let id = *id;
                                     let delta = move_pos - *prev;
 
-                                    if delta.mag_sq() >= 8.0 {
+                                    if (delta * self.view.scale).mag_sq() >= 8.0 {
                                         self.recent_clicks.borrow_mut().clear();
                                     }
 
+                                    // The previous position is offset by the snap, so the next
+                                    // move starts from where the entity would be without it.
+                                    let snap = self.snap_move(id, delta);
+                                    let delta = delta + snap;
+                                    let prev = move_pos + snap;
+
                                     match state {
                                         State::DelaySelect(..)=>{
-                                            *state = State::Move(id, move_pos);
+                                            *state = State::Move(id, prev);
                                             return (
                                                 Status::Captured,
                                                 Some(SheetMessage::SelectMove(id, delta)),
                                             );
                                         },
                                         _=>{
-                                            *state = State::Move(id, move_pos);
+                                            *state = State::Move(id, prev);
                                             return (
                                                 Status::Captured,
                                                 Some(SheetMessage::Move(id, delta)),
@@ -988,18 +3176,23 @@ impl CanvasProgram<SheetMessage> for Sheet {
                                 State::Select(_, prev)|State::None(prev)=>{
                                     let delta = move_pos - *prev;
                                     *prev = move_pos;
-                                    if delta.mag_sq() >= 8.0 {
+                                    if (delta * self.view.scale).mag_sq() >= 8.0 {
                                         self.recent_clicks.borrow_mut().clear();
                                     }
                                 },
-                                State::OrderEdit|State::OrderEditSelect(_)=>{},
+                                State::OrderEdit|
+                                    State::OrderEditSelect(_)|
+                                    State::ContextMenu(..)|
+                                    State::MeasureStart(_)|
+                                    State::Measuring(..)|
+                                    State::Measured(..)=>{},
                             }
                         },
                         MouseEvent::WheelScrolled{delta:ScrollDelta::Lines{y,..}}=>{
                             let msg = if y > 0.0 {
-                                SheetMessage::ZoomIn(cursor_pos, move_pos)
+                                SheetMessage::ZoomIn(cursor_pos)
                             } else {
-                                SheetMessage::ZoomOut(cursor_pos, move_pos)
+                                SheetMessage::ZoomOut(cursor_pos)
                             };
                             return (Status::Captured, Some(msg));
                         },
@@ -1012,12 +3205,67 @@ impl CanvasProgram<SheetMessage> for Sheet {
 
         (Status::Ignored, None)
     }
+
+    fn mouse_interaction(&self, state: &SheetState, bounds: Rectangle, cursor: Cursor)->Interaction {
+        use SheetState as State;
+
+        if !cursor.is_over(bounds) {
+            return Interaction::default();
+        }
+
+        match state {
+            State::Move(..)|
+                State::DelaySelect(..)|
+                State::Pan(_)|
+                State::PanSelected(..)|
+                State::OrderEditPan(_)|
+                State::OrderEditPanSelect(..)=>Interaction::Grabbing,
+            State::ContextMenu(..)=>Interaction::Idle,
+            _ if self.hovered.get().is_none()=>Interaction::Crosshair,
+            // entities are only picked while reordering
+            _ if self.reorder=>Interaction::Pointer,
+            _=>Interaction::Grab,
+        }
+    }
+}
+
+/// The gap between two ranges on an axis and which side of `a` that `b` is on, `1.0` for after
+/// and `-1.0` for before. The side is from the ranges' centers.
+fn axis_gap(a_min: f64, a_max: f64, b_min: f64, b_max: f64)->(f64, f64) {
+    if b_min + b_max >= a_min + a_max {
+        (b_min - a_max, 1.0)
+    } else {
+        (a_min - b_max, -1.0)
+    }
+}
+
+/// Every pairing of an edge of one box with an edge of another box on one axis.
+fn edge_pairs(min: f64, max: f64, other_min: f64, other_max: f64)->[(f64, f64); 4] {
+    [
+        (min, other_min),
+        (min, other_max),
+        (max, other_min),
+        (max, other_max),
+    ]
 }
 
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
 #[repr(transparent)]
 pub struct EntityId(usize);
 
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct GroupId(usize);
+
+fn next_group_id()->GroupId {
+    use std::sync::atomic::{
+        Ordering,
+        AtomicUsize,
+    };
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    GroupId(COUNT.fetch_add(1, Ordering::SeqCst))
+}
+
 fn next_entity_id()->EntityId {
     use std::sync::atomic::{
         Ordering,
@@ -1026,3 +3274,734 @@ fn next_entity_id()->EntityId {
     static COUNT: AtomicUsize = AtomicUsize::new(0);
     EntityId(COUNT.fetch_add(1, Ordering::SeqCst))
 }
+
+
+/// If the sheet height is the circumference of a cylinder with the diameter.
+fn rotary_height_matches(height: f64, diameter: f64)->bool {
+    (height - std::f64::consts::PI * diameter).abs() <= ROTARY_TOLERANCE
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::LineString;
+    use crate::gcode::GcodeInstruction as Ins;
+    use super::*;
+
+
+    /// A model from lines given as points.
+    fn model(name: &str, lines: &[&[(f64, f64)]])->ModelHandle {
+        let lines = lines.iter()
+            .map(|points|LineString::from(points.to_vec()))
+            .collect();
+
+        ModelStore::new().add(Model::new(lines, name.into()))
+    }
+
+    fn square()->ModelHandle {
+        model("square", &[&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0), (0.0, 0.0)]])
+    }
+
+    fn plate_with_hole()->ModelHandle {
+        model("plate", &[
+            &[(0.0, 0.0), (40.0, 0.0), (40.0, 20.0), (0.0, 20.0), (0.0, 0.0)],
+            &[(15.0, 5.0), (25.0, 5.0), (25.0, 15.0), (15.0, 15.0), (15.0, 5.0)],
+        ])
+    }
+
+    /// A condition with the sequence items written the same as in `laser_conditions.ron`.
+    fn condition(sequence: &str)->Condition {
+        ron::from_str(&format!(
+            r#"(id: (0), color: (r: 1.0, g: 0.2, b: 0.2), name: "Cut", sequence: [{sequence}])"#,
+        )).unwrap()
+    }
+
+    fn at(x: f64, y: f64, condition: &Condition)->EntityState {
+        EntityState {
+            transform: Transform::new(Translation::new(x, y), Rotation::identity(), 1.0),
+            flip: false,
+            laser_condition: condition.id,
+            start_vertex: None,
+        }
+    }
+
+    /// An empty sheet and the ID of the condition from [`condition`].
+    fn test_sheet()->(Sheet, ConditionId) {
+        let sheet = Sheet::new(ModelStore::new(), Rc::default(), Rc::default(), Rc::default());
+        (sheet, condition("").id)
+    }
+
+    fn blocks(entities: &[(ModelHandle, EntityState, Condition)])->Vec<GcodeBlock> {
+        GcodeJob::new("test", Vector::new(100.0, 100.0), entities, MachineProfile::default())
+            .blocks()
+    }
+
+    /// The GCODE without comments, since the header has the date.
+    fn snapshot(blocks: &[GcodeBlock])->String {
+        blocks.iter()
+            .filter(|block|block.len() > 0)
+            .map(|block|{
+                block.codes()
+                    .iter()
+                    .map(|code|format!("{code:.3}"))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The laser is never on during a rapid or at the end, the power is never above `max_power`,
+    /// and the program ends with `M30`.
+    fn assert_safe(blocks: &[GcodeBlock], max_power: u16) {
+        let mut laser_on = false;
+
+        for block in blocks.iter() {
+            for code in block.codes() {
+                match code {
+                    Ins::M(3|4)=>laser_on = true,
+                    Ins::M(5)=>laser_on = false,
+                    Ins::G(0)=>assert!(!laser_on, "rapid move with the laser on"),
+                    Ins::S(power)=>assert!(*power <= max_power, "power {power} is over {max_power}"),
+                    _=>{},
+                }
+            }
+        }
+
+        assert!(!laser_on, "the laser is on at the end");
+        assert_eq!(blocks.last().map(|block|block.codes()), Some([Ins::M(30)].as_slice()));
+    }
+
+
+    #[test]
+    fn square_snapshot() {
+        let condition = condition("GrblConst(passes: 1, power: 500, feed: 1000)");
+        let blocks = blocks(&[(square(), at(10.0, 20.0, &condition), condition.clone())]);
+
+        assert_safe(&blocks, 1000);
+        assert_eq!(snapshot(&blocks), "\
+            G54 G17 G21 G90 G94\n\
+            G0 X10.000 Y20.000\n\
+            G1 S500 F1000 M3\n\
+            G1 X20.000 Y20.000\n\
+            G1 X20.000 Y30.000\n\
+            G1 X10.000 Y30.000\n\
+            G1 X10.000 Y20.000\n\
+            G1 S0 M5\n\
+            G0 X0.000 Y0.000\n\
+            M30\
+        ");
+    }
+
+    #[test]
+    fn plate_with_hole_snapshot() {
+        let condition = condition("GrblDyn(passes: 1, power: 700, feed: 800)");
+        let blocks = blocks(&[(plate_with_hole(), at(5.0, 5.0, &condition), condition.clone())]);
+
+        assert_safe(&blocks, 1000);
+        assert_eq!(snapshot(&blocks), "\
+            G54 G17 G21 G90 G94\n\
+            G0 X20.000 Y10.000\n\
+            G1 S700 F800 M4\n\
+            G1 X20.000 Y20.000\n\
+            G1 X30.000 Y20.000\n\
+            G1 X30.000 Y10.000\n\
+            G1 X20.000 Y10.000\n\
+            G1 S0 M5\n\
+            G0 X5.000 Y5.000\n\
+            G1 S700 F800 M4\n\
+            G1 X45.000 Y5.000\n\
+            G1 X45.000 Y25.000\n\
+            G1 X5.000 Y25.000\n\
+            G1 X5.000 Y5.000\n\
+            G1 S0 M5\n\
+            G0 X0.000 Y0.000\n\
+            M30\
+        ");
+    }
+
+    #[test]
+    fn multi_pass_snapshot() {
+        let condition = condition("GrblConst(passes: 2, power: 800, feed: 600), GrblDyn(passes: 1, power: 300, feed: 1200)");
+        let blocks = blocks(&[(square(), at(0.0, 0.0, &condition), condition.clone())]);
+
+        assert_safe(&blocks, 1000);
+        assert_eq!(snapshot(&blocks), "\
+            G54 G17 G21 G90 G94\n\
+            G0 X0.000 Y0.000\n\
+            G1 S800 F600 M3\n\
+            G1 X10.000 Y0.000\n\
+            G1 X10.000 Y10.000\n\
+            G1 X0.000 Y10.000\n\
+            G1 X0.000 Y0.000\n\
+            G1 S0 M5\n\
+            G0 X0.000 Y0.000\n\
+            G1 S800 F600 M3\n\
+            G1 X10.000 Y0.000\n\
+            G1 X10.000 Y10.000\n\
+            G1 X0.000 Y10.000\n\
+            G1 X0.000 Y0.000\n\
+            G1 S0 M5\n\
+            G0 X0.000 Y0.000\n\
+            G1 S300 F1200 M4\n\
+            G1 X10.000 Y0.000\n\
+            G1 X10.000 Y10.000\n\
+            G1 X0.000 Y10.000\n\
+            G1 X0.000 Y0.000\n\
+            G1 S0 M5\n\
+            G0 X0.000 Y0.000\n\
+            M30\
+        ");
+    }
+
+    #[test]
+    fn travel_feed_snapshot() {
+        let condition = condition("GrblConst(passes: 1, power: 500, feed: 1000)");
+        let machine = MachineProfile {
+            travel_feed: Some(3000),
+            ..MachineProfile::default()
+        };
+        let blocks = GcodeJob::new(
+            "test",
+            Vector::new(100.0, 100.0),
+            &[(square(), at(10.0, 20.0, &condition), condition.clone())],
+            machine,
+        ).blocks();
+
+        assert_safe(&blocks, 1000);
+        assert_eq!(snapshot(&blocks), "\
+            G54 G17 G21 G90 G94\n\
+            G1 F3000 X10.000 Y20.000\n\
+            G1 S500 F1000 M3\n\
+            G1 X20.000 Y20.000\n\
+            G1 X20.000 Y30.000\n\
+            G1 X10.000 Y30.000\n\
+            G1 X10.000 Y20.000\n\
+            G1 S0 M5\n\
+            G1 F3000 X0.000 Y0.000\n\
+            M30\
+        ");
+    }
+
+    #[test]
+    fn no_comments_after_header() {
+        let condition = condition("GrblConst(passes: 2, power: 100, feed: 1000, min_power: 200), GrblConst(passes: 1, power: 500, feed: 1000, enabled: false)");
+        let machine = MachineProfile {
+            subprograms: true,
+            ..MachineProfile::default()
+        };
+        let mut job = GcodeJob::new(
+            "test",
+            Vector::new(100.0, 100.0),
+            &[
+                (plate_with_hole(), at(0.0, 0.0, &condition), condition.clone()),
+                (square(), at(50.0, 0.0, &condition), condition.clone()),
+                (square(), at(50.0, 50.0, &condition), condition.clone()),
+            ],
+            machine,
+        );
+        job.comment_level = CommentLevel::None;
+        let blocks = job.blocks();
+
+        let header_end = blocks.iter()
+            .position(|block|block.codes().contains(&Ins::G(54)))
+            .unwrap();
+        for block in blocks[header_end..].iter() {
+            let text = format!("{block:.3}");
+            assert!(!text.contains(['(', ')', ';']), "`{text}` has a comment");
+        }
+    }
+
+    #[test]
+    fn grbl_comments_fit() {
+        let condition = condition("GrblConst(passes: 1, power: 100, feed: 1000, min_power: 200)");
+        let machine = MachineProfile {
+            grbl_comments: true,
+            ..MachineProfile::default()
+        };
+        let mut job = GcodeJob::new(
+            "test (µm)",
+            Vector::new(100.0, 100.0),
+            &[(square(), at(0.0, 0.0, &condition), condition.clone())],
+            machine,
+        );
+        job.labels[0] = "a long (label) ".repeat(10);
+
+        for line in job.generate(|_, _|{}).0.lines() {
+            assert!(line.len() <= GRBL_LINE_LENGTH, "`{line}` is too long");
+            assert!(line.is_ascii(), "`{line}` isn't ASCII");
+            assert!(line.matches('(').count() <= 1, "`{line}` has a nested comment");
+        }
+    }
+
+    #[test]
+    fn power_is_limited() {
+        let condition = condition("GrblConst(passes: 1, power: 1500, feed: 1000)");
+        let blocks = blocks(&[(square(), at(0.0, 0.0, &condition), condition.clone())]);
+
+        assert_safe(&blocks, 1000);
+        assert!(blocks.iter().any(|block|block.codes().contains(&Ins::S(1000))));
+    }
+
+    #[test]
+    fn degenerate_lines_are_skipped() {
+        let condition = condition("GrblConst(passes: 1, power: 500, feed: 1000)");
+        let with_dot = model("square with a dot", &[
+            &[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0), (0.0, 0.0)],
+            &[(5.0, 5.0), (5.0, 5.0)],
+        ]);
+        let mut tiny = at(50.0, 50.0, &condition);
+        tiny.transform.scale = 0.0001;
+
+        let expected = snapshot(&blocks(&[(square(), at(0.0, 0.0, &condition), condition.clone())]));
+        let blocks = blocks(&[
+            (with_dot, at(0.0, 0.0, &condition), condition.clone()),
+            (square(), tiny, condition.clone()),
+        ]);
+
+        assert_eq!(snapshot(&blocks), expected);
+    }
+
+    /// The X and Y of every cutting move, without rounding.
+    fn cut_points(blocks: &[GcodeBlock])->Vec<(f64, f64)> {
+        blocks.iter()
+            .filter(|block|block.codes().contains(&Ins::G(1)))
+            .filter_map(|block|{
+                let x = block.codes().iter().find_map(|code|match code {Ins::X(x)=>Some(*x), _=>None})?;
+                let y = block.codes().iter().find_map(|code|match code {Ins::Y(y)=>Some(*y), _=>None})?;
+                Some((x, y))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn closed_lines_end_at_the_start() {
+        let condition = condition("GrblConst(passes: 1, power: 500, feed: 1000)");
+        let explicit = model("explicit", &[&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0), (0.0, 0.0)]]);
+        // the last point is within the closed tolerance, but not the same as the first
+        let implicit = model("implicit", &[&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0), (0.0, 0.0005)]]);
+
+        let expected = vec![(10.0, 0.0), (10.0, 10.0), (0.0, 10.0), (0.0, 0.0)];
+        let explicit = blocks(&[(explicit, at(0.0, 0.0, &condition), condition.clone())]);
+        assert_eq!(cut_points(&explicit), expected);
+
+        let implicit = blocks(&[(implicit, at(0.0, 0.0, &condition), condition.clone())]);
+        let points = cut_points(&implicit);
+        assert_eq!(points.first(), Some(&(10.0, 0.0)));
+        assert_eq!(points.last(), Some(&(0.0, 0.0)));
+    }
+
+    #[test]
+    fn open_lines_are_not_closed() {
+        let condition = condition("GrblConst(passes: 1, power: 500, feed: 1000)");
+        let open = model("open", &[&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]]);
+
+        let blocks = blocks(&[(open, at(0.0, 0.0, &condition), condition.clone())]);
+        assert_eq!(cut_points(&blocks), vec![(10.0, 0.0), (10.0, 10.0)]);
+    }
+
+    #[test]
+    fn overcut_follows_the_line() {
+        let mut condition = condition("GrblConst(passes: 1, power: 500, feed: 1000)");
+        condition.overcut_mm = 15.0;
+        let open = model("open", &[&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]]);
+
+        let cut = blocks(&[(square(), at(0.0, 0.0, &condition), condition.clone())]);
+        assert_eq!(cut_points(&cut), vec![
+            (10.0, 0.0), (10.0, 10.0), (0.0, 10.0), (0.0, 0.0),
+            (10.0, 0.0), (10.0, 5.0),
+        ]);
+
+        let open = blocks(&[(open, at(0.0, 0.0, &condition), condition.clone())]);
+        assert_eq!(cut_points(&open), vec![(10.0, 0.0), (10.0, 10.0)]);
+
+        // longer than the whole square
+        condition.overcut_mm = 50.0;
+        let cut = blocks(&[(square(), at(0.0, 0.0, &condition), condition.clone())]);
+        assert_eq!(cut_points(&cut).len(), 4);
+    }
+
+    #[test]
+    fn clockwise_holes_are_holes() {
+        let condition = condition("GrblConst(passes: 1, power: 500, feed: 1000)");
+        let plate = model("plate", &[
+            // the hole comes first and is clockwise, and the outline is clockwise too
+            &[(15.0, 5.0), (15.0, 15.0), (25.0, 15.0), (25.0, 5.0), (15.0, 5.0)],
+            &[(0.0, 0.0), (0.0, 20.0), (40.0, 20.0), (40.0, 0.0), (0.0, 0.0)],
+        ]);
+
+        assert_eq!(plate.part_area(&at(0.0, 0.0, &condition)), 700.0);
+        assert_eq!(snapshot(&blocks(&[(plate, at(5.0, 5.0, &condition), condition.clone())])), snapshot(&blocks(&[
+            (plate_with_hole(), at(5.0, 5.0, &condition), condition.clone()),
+        ])));
+    }
+
+    #[test]
+    fn rotary_snapshot() {
+        let condition = condition("GrblConst(passes: 1, power: 500, feed: 1000)");
+        // one degree for each mm around
+        let diameter = 360.0 / std::f64::consts::PI;
+        let mut job = GcodeJob::new(
+            "test",
+            Vector::new(100.0, 360.0),
+            &[(square(), at(10.0, 20.0, &condition), condition.clone())],
+            MachineProfile::default(),
+        );
+        job.rotary_diameter = Some(diameter);
+
+        assert_eq!(snapshot(&job.blocks()), "\
+            G54 G17 G21 G90 G94\n\
+            G0 X10.000 A20.000\n\
+            G1 S500 F1000 M3\n\
+            G1 X20.000 A20.000\n\
+            G1 X20.000 A30.000\n\
+            G1 X10.000 A30.000\n\
+            G1 X10.000 A20.000\n\
+            G1 S0 M5\n\
+            G0 X0.000 A0.000\n\
+            M30\
+        ");
+        assert!(rotary_height_matches(360.0, diameter));
+    }
+
+    #[test]
+    fn power_is_scaled() {
+        let condition = condition("GrblConst(passes: 1, power: 500, feed: 1000)");
+        let machine = MachineProfile {
+            max_power: 255,
+            s_scale: 0.255,
+            ..MachineProfile::default()
+        };
+        let blocks = GcodeJob::new("test", Vector::new(100.0, 100.0), &[(square(), at(0.0, 0.0, &condition), condition.clone())], machine)
+            .blocks();
+
+        assert_safe(&blocks, 255);
+        assert!(blocks.iter().any(|block|block.codes().contains(&Ins::S(128))));
+    }
+
+    #[test]
+    fn raster_skips_holes() {
+        let condition = condition("RasterEngrave(passes: 1, power: 300, feed: 3000, line_spacing: 8.0)");
+        let blocks = blocks(&[(plate_with_hole(), at(5.0, 5.0, &condition), condition.clone())]);
+
+        assert_safe(&blocks, 1000);
+        // every other line goes back the other way, and the second one goes around the hole
+        assert_eq!(cut_points(&blocks), vec![(45.0, 9.0), (30.0, 17.0), (5.0, 17.0)]);
+    }
+
+    #[test]
+    fn raster_needs_line_spacing() {
+        for spacing in ["0.0", "-1.0", "NaN"] {
+            let condition = condition(&format!("RasterEngrave(passes: 1, power: 300, feed: 3000, line_spacing: {spacing})"));
+            let blocks = blocks(&[(plate_with_hole(), at(5.0, 5.0, &condition), condition.clone())]);
+
+            assert_safe(&blocks, 1000);
+            assert!(cut_points(&blocks).is_empty());
+        }
+    }
+
+    #[test]
+    fn resume_skips_earlier_entities() {
+        let condition = condition("GrblConst(passes: 1, power: 500, feed: 1000)");
+        let mut job = GcodeJob::new(
+            "test",
+            Vector::new(100.0, 100.0),
+            &[
+                (square(), at(0.0, 0.0, &condition), condition.clone()),
+                (square(), at(50.0, 0.0, &condition), condition.clone()),
+            ],
+            MachineProfile::default(),
+        );
+        job.resume_from = Some(1);
+        let blocks = job.blocks();
+
+        assert_safe(&blocks, 1000);
+        assert_eq!(cut_points(&blocks), vec![(60.0, 0.0), (60.0, 10.0), (50.0, 10.0), (50.0, 0.0)]);
+        // the stats still include the skipped entity
+        assert!(blocks.iter().any(|block|block.comment() == Some("Condition \"Cut\": 2 entities")));
+        assert!(blocks.iter().any(|block|block.comment().is_some_and(|c|c.starts_with("RESUMING HERE at entity #2"))));
+    }
+
+    #[test]
+    fn oversized_entities_can_be_fit() {
+        let (mut sheet, condition) = test_sheet();
+        sheet.change_width(300.0);
+        sheet.change_height(500.0);
+
+        let panel = model("panel", &[&[(0.0, 0.0), (800.0, 0.0), (800.0, 200.0), (0.0, 200.0), (0.0, 0.0)]]);
+        let id = sheet.add_model_from_handle(panel, 1, condition)[0];
+        assert_eq!(sheet.oversize_fix(id), Some(OversizeFix {scale: 0.375, rotate_fits: false}));
+        sheet.fit_entity(id, false);
+        assert_eq!(sheet.oversize_fix(id), None);
+        assert!(sheet.out_of_bounds_entities().is_empty());
+
+        let strip = model("strip", &[&[(0.0, 0.0), (400.0, 0.0), (400.0, 100.0), (0.0, 100.0), (0.0, 0.0)]]);
+        let id = sheet.add_model_from_handle(strip, 1, condition)[0];
+        assert_eq!(sheet.oversize_fix(id).map(|fix|fix.rotate_fits), Some(true));
+        sheet.fit_entity(id, true);
+        assert_eq!(sheet.oversize_fix(id), None);
+        assert_eq!(sheet.entities[&id].1.transform.scale, 1.0);
+    }
+
+    #[test]
+    fn fit_to_sheet_avoids_keep_outs() {
+        let (mut sheet, condition) = test_sheet();
+        sheet.change_width(100.0);
+        sheet.change_height(100.0);
+        sheet.margin = 5.0;
+        sheet.add_keep_out(Point::new(0.0, 0.0), Point::new(100.0, 40.0));
+
+        let id = sheet.add_model_from_handle(square(), 1, condition)[0];
+        sheet.set_entity_angle(id, std::f64::consts::FRAC_PI_4);
+        sheet.fit_to_sheet(id);
+
+        // the rotated bounding box fills the height between the keep-out and the margin
+        let (min, max) = sheet.entity_bounds(id).unwrap();
+        assert!((max.y - min.y - 55.0).abs() < 1e-6);
+        assert!((min.y - 40.0).abs() < 1e-6);
+        assert!(((min.x + max.x) / 2.0 - 50.0).abs() < 1e-6);
+        assert!((sheet.entities[&id].1.angle() - std::f64::consts::FRAC_PI_4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn prune_order_removes_missing_entities() {
+        let (mut sheet, condition) = test_sheet();
+        let kept = sheet.add_model_from_handle(square(), 1, condition)[0];
+        let gone = sheet.add_model_from_handle(square(), 1, condition)[0];
+
+        sheet.entities.remove(&gone);
+        assert_eq!(sheet.prune_order(), vec![gone]);
+        assert_eq!(sheet.order.iter().copied().collect::<Vec<_>>(), vec![kept]);
+        assert!(sheet.prune_order().is_empty());
+    }
+
+    #[test]
+    fn moves_snap_to_the_sheet_edge() {
+        let (mut sheet, condition) = test_sheet();
+        sheet.change_width(100.0);
+        sheet.change_height(100.0);
+        let id = sheet.add_model_from_handle(square(), 1, condition)[0];
+        sheet.move_to_center(id);
+
+        let (min, _) = sheet.entity_bounds(id).unwrap();
+        let delta = Vector::new(2.0 - min.x, 0.0);
+        assert_eq!(sheet.snap_move(id, delta), Vector::new(-2.0, 0.0));
+        assert_eq!(sheet.snap_lines.borrow().len(), 1);
+
+        sheet.modifiers.set(Modifiers::ALT);
+        assert_eq!(sheet.snap_move(id, delta), Vector::zero());
+    }
+
+    #[test]
+    fn reorder_by_position_sorts_rows_and_columns() {
+        let (mut sheet, condition) = test_sheet();
+        let ids = [(50.0, 0.0), (0.0, 50.0), (0.0, 0.0), (50.0, 50.0)].map(|(x, y)|{
+            let id = sheet.add_model_from_handle(square(), 1, condition)[0];
+            sheet.translate_entity(id, Translation::new(x, y));
+            id
+        });
+
+        sheet.reorder_by_position(false);
+        assert_eq!(sheet.order.iter().copied().collect::<Vec<_>>(), vec![ids[1], ids[3], ids[2], ids[0]]);
+
+        sheet.reorder_by_position(true);
+        assert_eq!(sheet.order.iter().copied().collect::<Vec<_>>(), vec![ids[1], ids[2], ids[3], ids[0]]);
+    }
+
+    #[test]
+    fn entity_gap_keeps_the_side() {
+        let (mut sheet, condition) = test_sheet();
+        let a = sheet.add_model_from_handle(square(), 1, condition)[0];
+        let b = sheet.add_model_from_handle(square(), 1, condition)[0];
+        sheet.translate_entity(b, Translation::new(-30.0, 4.0));
+        assert_eq!(sheet.entity_gap(a, b), Some(Vector::new(20.0, -6.0)));
+
+        sheet.set_entity_gap(a, b, 2.5, false);
+        sheet.set_entity_gap(a, b, 1.0, true);
+        assert_eq!(sheet.entity_gap(a, b), Some(Vector::new(2.5, 1.0)));
+        // still to the left and above
+        assert!(sheet.entity_bounds(b).unwrap().1.x < sheet.entity_bounds(a).unwrap().0.x);
+        assert!(sheet.entity_bounds(b).unwrap().0.y > sheet.entity_bounds(a).unwrap().1.y);
+    }
+
+    #[test]
+    fn screen_and_world_round_trip() {
+        let (mut sheet, _) = test_sheet();
+        let height = 600.0;
+        // the sheet origin is the bottom left of the canvas
+        assert_eq!(sheet.world_to_screen(Point::zero(), height), Point::new(0.0, height));
+
+        sheet.view.translation += Vector::new(35.0, -20.0);
+        sheet.zoom_at(1.5, Point::new(120.0, 80.0));
+        sheet.zoom_at(0.8, Point::new(-40.0, 310.0));
+
+        for point in [Point::zero(), Point::new(12.5, 300.0), Point::new(-80.0, 45.25)] {
+            let screen = sheet.world_to_screen(point, height);
+            assert!((sheet.screen_to_world(screen, height) - point).mag() < 1e-9, "{point:?}");
+            let world = sheet.screen_to_world(point, height);
+            assert!((sheet.world_to_screen(world, height) - point).mag() < 1e-9, "{point:?}");
+        }
+
+        // zooming keeps the point under the cursor still
+        let cursor = Point::new(200.0, 150.0);
+        let before = sheet.screen_to_world(cursor, height);
+        sheet.zoom_at(2.0, cursor);
+        assert!((sheet.screen_to_world(cursor, height) - before).mag() < 1e-9);
+    }
+
+    #[test]
+    fn model_paths_are_y_up() {
+        let (mut sheet, condition) = test_sheet();
+        let id = sheet.add_model_from_handle(square(), 1, condition)[0];
+        sheet.translate_entity(id, Translation::new(20.0, 30.0));
+
+        // the paths are on the sheet, so they don't depend on the canvas height
+        let (_, paths) = &sheet.paths[&id];
+        assert_eq!((paths.min, paths.max), (Point::new(20.0, 30.0), Point::new(30.0, 40.0)));
+        assert!((paths.display_center - Point::new(25.0, 35.0)).mag() < 1e-9);
+        assert_eq!((paths.min, paths.max), sheet.entity_bounds(id).unwrap());
+
+        // projected to the bottom left of the canvas when drawn
+        let height = 200.0;
+        let center = sheet.world_to_screen(paths.display_center, height);
+        assert!((center - Point::new(25.0, 165.0)).mag() < 1e-9);
+    }
+
+    #[test]
+    fn simulation_starts_at_the_origin() {
+        let condition = condition("");
+        let mut job = GcodeJob::new("test", Vector::new(100.0, 100.0), &[(square(), at(20.0, 20.0, &condition), condition.clone())], MachineProfile::default());
+        job.origin = Origin::TopLeft;
+
+        let paths = job.simulate();
+        assert_eq!(paths.first().unwrap().1[0], Point::new(0.0, 100.0));
+        assert_eq!(*paths.last().unwrap().1.last().unwrap(), Point::new(0.0, 100.0));
+    }
+
+    /// The cut points like [`cut_points`], but with the sub-programs run where they are called
+    /// and moved by the `G52` offset.
+    fn cut_points_with_subprograms(blocks: &[GcodeBlock])->Vec<(f64, f64)> {
+        let coord = |block: &GcodeBlock|(
+            block.codes().iter().find_map(|code|match code {Ins::X(x)=>Some(*x), _=>None}),
+            block.codes().iter().find_map(|code|match code {Ins::Y(y)=>Some(*y), _=>None}),
+        );
+        let end = blocks.iter().position(|block|block.codes() == [Ins::M(30)]).unwrap();
+        let subprogram = |id: u16|{
+            let start = blocks.iter().position(|block|block.codes() == [Ins::O(id)]).unwrap() + 1;
+            let len = blocks[start..].iter().position(|block|block.codes() == [Ins::M(99)]).unwrap();
+            &blocks[start..start + len]
+        };
+
+        let mut points = Vec::new();
+        let mut offset = (0.0, 0.0);
+        for block in blocks[..end].iter() {
+            let codes = block.codes();
+            if codes.contains(&Ins::G(52)) {
+                let (x, y) = coord(block);
+                offset = (x.unwrap(), y.unwrap());
+            } else if codes.contains(&Ins::M(98)) {
+                let Some(Ins::P(id)) = codes.iter().find(|code|matches!(code, Ins::P(_))) else {panic!()};
+                points.extend(cut_points(subprogram(*id)).into_iter().map(|(x, y)|(x + offset.0, y + offset.1)));
+            } else {
+                points.extend(cut_points(std::slice::from_ref(block)));
+            }
+        }
+
+        return points;
+    }
+
+    #[test]
+    fn subprograms_use_the_origin() {
+        let condition = condition("GrblConst(passes: 1, power: 500, feed: 1000)");
+        let entities = [
+            (square(), at(10.0, 20.0, &condition), condition.clone()),
+            (square(), at(60.0, 50.0, &condition), condition.clone()),
+        ];
+        let job = |subprograms: bool|{
+            let machine = MachineProfile {
+                subprograms,
+                ..MachineProfile::default()
+            };
+            let mut job = GcodeJob::new("test", Vector::new(100.0, 100.0), &entities, machine);
+            job.origin = Origin::TopLeft;
+            job.blocks()
+        };
+
+        let expected = cut_points(&job(false));
+        let blocks = job(true);
+        assert!(blocks.iter().any(|block|block.codes().contains(&Ins::M(98))));
+        let points = cut_points_with_subprograms(&blocks);
+        assert_eq!(points.len(), expected.len());
+        for (a, b) in points.iter().zip(expected.iter()) {
+            assert!((a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9, "{a:?} != {b:?}");
+        }
+    }
+
+    #[test]
+    fn bounds_are_transformed() {
+        let condition = condition("");
+        let mut mt = at(5.0, 0.0, &condition);
+        mt.transform.rotation = Rotation::from_angle(std::f64::consts::FRAC_PI_2);
+        mt.flip = true;
+
+        let (min, max) = mt.apply_to_bounds(Point::new(0.0, 0.0), Point::new(10.0, 20.0));
+        assert!((min - Point::new(5.0, 0.0)).mag() < 1e-9, "{min:?}");
+        assert!((max - Point::new(25.0, 10.0)).mag() < 1e-9, "{max:?}");
+        assert!(square().hull_intersects_rect(&mt, Point::new(6.0, 1.0), Point::new(7.0, 2.0)));
+        assert!(!square().hull_intersects_rect(&mt, Point::new(16.0, 1.0), Point::new(17.0, 2.0)));
+    }
+
+    #[test]
+    fn group_transform_is_applied_on_top_of_the_members() {
+        let (mut sheet, condition) = test_sheet();
+        let a = sheet.add_model_from_handle(square(), 1, condition)[0];
+        let b = sheet.add_model_from_handle(square(), 1, condition)[0];
+        sheet.translate_entity(a, Translation::new(10.0, 0.0));
+        sheet.translate_entity(b, Translation::new(30.0, 0.0));
+        let group = sheet.group(vec![a, b]).unwrap();
+
+        sheet.translate_entity(a, Translation::new(5.0, 5.0));
+        sheet.rotate_scale_entity(a, Rotation::from_angle(std::f64::consts::FRAC_PI_2), 2.0);
+
+        let transform = sheet.groups.iter().find(|g|g.id == group).unwrap().transform;
+        for (id, x) in [(a, 10.0), (b, 30.0)] {
+            let mut expected = Transform::new(Translation::new(x, 0.0), Rotation::identity(), 1.0);
+            expected.append_similarity(transform);
+
+            let mt = &sheet.entities[&id].1;
+            assert!((mt.transform.translation - expected.translation).mag() < 1e-9, "{id:?}");
+            assert!((mt.transform.scale - 2.0).abs() < 1e-9);
+        }
+        // b turned around a, which didn't move
+        assert!((sheet.entities[&a].1.transform.translation - Point::new(15.0, 5.0)).mag() < 1e-9);
+        assert!((sheet.entities[&b].1.transform.translation - Point::new(15.0, 45.0)).mag() < 1e-9);
+    }
+
+    #[test]
+    fn tab_selects_in_cut_order() {
+        let (mut sheet, condition) = test_sheet();
+        let ids = sheet.add_model_from_handle(square(), 3, condition);
+        let tab = Key::Named(NamedKey::Tab);
+        let next = |current, modifiers|match sheet.selection_shortcut(current, &tab, modifiers) {
+            Some(SheetMessage::SelectEntity(id))=>id,
+            msg=>panic!("{msg:?}"),
+        };
+
+        assert_eq!(next(None, Modifiers::empty()), ids[0]);
+        assert_eq!(next(Some(ids[0]), Modifiers::empty()), ids[1]);
+        assert_eq!(next(Some(ids[2]), Modifiers::empty()), ids[0]);
+        assert_eq!(next(Some(ids[0]), Modifiers::SHIFT), ids[2]);
+    }
+
+    #[test]
+    fn homing_is_the_first_block() {
+        let condition = condition("GrblConst(passes: 1, power: 500, feed: 1000)");
+        let mut machine = MachineProfile::default();
+        machine.home_at_start = true;
+        machine.homing_command = "$H".into();
+        let blocks = GcodeJob::new("test", Vector::new(100.0, 100.0), &[(square(), at(10.0, 20.0, &condition), condition.clone())], machine)
+            .blocks();
+
+        assert_eq!(blocks[0].to_string(), "$H");
+        assert!(blocks[1..].iter().all(|block|block.to_string() != "$H"));
+    }
+}